@@ -0,0 +1,460 @@
+//! Authentication on Letterboxd with an access token persisted across runs.
+//!
+//! The token is stored in a file of the cache dir, or in the keyring of the operating system after
+//! `auth login`, which keeps the password out of the environment. Each profile selected with
+//! `--profile` has a token of its own. The clients of the commands are created by [`new_client`]
+//! and [`read_only_client`].
+
+use anyhow::{anyhow, Context as _};
+use hmac::{Hmac, Mac};
+use log::{debug, info, warn};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::config::Profile;
+use crate::i18n::Message;
+
+const TOKEN_URL: &str = "https://api.letterboxd.com/api/v0/auth/token";
+
+/// Service and user of the keyring entry holding the token. The user of a profile is suffixed
+/// with its name, like `token:partner`.
+const KEYRING_SERVICE: &str = "letterboxd-sync";
+const KEYRING_USER: &str = "token";
+
+static PROFILE: OnceLock<(String, Profile)> = OnceLock::new();
+
+/// Select the profile whose credentials and token to use, see [`Profile`].
+pub fn set_profile(name: String, profile: Profile) {
+    let _ = PROFILE.set((name, profile));
+}
+
+/// Name of the profile selected with [`set_profile`].
+pub fn profile_name() -> Option<&'static str> {
+    PROFILE.get().map(|(name, _)| name.as_str())
+}
+
+/// The profile selected with [`set_profile`].
+pub fn profile() -> Option<&'static Profile> {
+    PROFILE.get().map(|(_, profile)| profile)
+}
+
+/// Tokens expiring within this many seconds are refreshed before use.
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+/// Access token as stored on disk or in the keyring.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredToken {
+    /// Member the token was issued to.
+    username: String,
+    access_token: String,
+    token_type: String,
+    refresh_token: String,
+    /// Expiry as Unix timestamp.
+    expires_at: i64,
+}
+
+impl StoredToken {
+    fn new(username: &str, token: &letterboxd::AccessToken) -> Self {
+        Self {
+            username: username.to_string(),
+            access_token: token.access_token.clone(),
+            token_type: token.token_type.clone(),
+            refresh_token: token.refresh_token.clone(),
+            expires_at: chrono::Utc::now().timestamp() + token.expires_in as i64,
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires_at - EXPIRY_MARGIN_SECS <= chrono::Utc::now().timestamp()
+    }
+
+    fn to_access_token(&self) -> letterboxd::AccessToken {
+        let expires_in = self.expires_at - chrono::Utc::now().timestamp();
+        letterboxd::AccessToken {
+            access_token: self.access_token.clone(),
+            token_type: self.token_type.clone(),
+            refresh_token: self.refresh_token.clone(),
+            expires_in: expires_in.max(0) as u64,
+        }
+    }
+}
+
+/// Response of the token endpoint.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: String,
+    refresh_token: String,
+    expires_in: u64,
+}
+
+impl From<TokenResponse> for letterboxd::AccessToken {
+    fn from(response: TokenResponse) -> Self {
+        Self {
+            access_token: response.access_token,
+            token_type: response.token_type,
+            refresh_token: response.refresh_token,
+            expires_in: response.expires_in,
+        }
+    }
+}
+
+/// Default location of the stored token: `letterboxd-sync/token.json` in the user's cache dir, or
+/// `token-<profile>.json` for a profile.
+pub fn default_token_path() -> Option<PathBuf> {
+    let file_name = match profile_name() {
+        Some(profile) => format!("token-{}.json", profile),
+        None => "token.json".to_string(),
+    };
+    dirs::cache_dir().map(|dir| dir.join("letterboxd-sync").join(file_name))
+}
+
+/// Where the access token is persisted across runs.
+#[derive(Debug, Clone)]
+pub enum TokenStore {
+    /// A file readable only by the current user, see [`default_token_path`].
+    File(PathBuf),
+    /// The keyring of the operating system, filled by [`login`].
+    Keyring,
+}
+
+impl TokenStore {
+    async fn load(&self) -> anyhow::Result<Option<StoredToken>> {
+        match self {
+            TokenStore::File(path) => load_token(path),
+            TokenStore::Keyring => match read_keyring().await? {
+                Some(secret) => Ok(Some(serde_json::from_str(&secret)?)),
+                None => Ok(None),
+            },
+        }
+    }
+
+    async fn save(&self, token: &StoredToken) -> anyhow::Result<()> {
+        match self {
+            TokenStore::File(path) => save_token(path, token),
+            TokenStore::Keyring => write_keyring(serde_json::to_string(token)?).await,
+        }
+    }
+}
+
+impl fmt::Display for TokenStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenStore::File(path) => write!(f, "{}", path.display()),
+            TokenStore::Keyring => match profile_name() {
+                Some(profile) => write!(f, "the keyring for profile {}", profile),
+                None => write!(f, "the keyring"),
+            },
+        }
+    }
+}
+
+/// Member and expiry as Unix timestamp of the token stored in `store`, if there is one.
+pub async fn token_expiry(store: &TokenStore) -> anyhow::Result<Option<(String, i64)>> {
+    Ok(store
+        .load()
+        .await?
+        .map(|token| (token.username, token.expires_at)))
+}
+
+fn load_token(path: &Path) -> anyhow::Result<Option<StoredToken>> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Run `f` on the keyring entry of the token. The keyring is accessed on a blocking thread, since
+/// its backends block, some of them on a runtime of their own.
+async fn with_keyring<T, F>(f: F) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&keyring::Entry) -> keyring::Result<T> + Send + 'static,
+{
+    let user = match profile_name() {
+        Some(profile) => format!("{}:{}", KEYRING_USER, profile),
+        None => KEYRING_USER.to_string(),
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, &user)?;
+        f(&entry)
+    })
+    .await?;
+    Ok(result?)
+}
+
+async fn read_keyring() -> anyhow::Result<Option<String>> {
+    with_keyring(|entry| match entry.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(err),
+    })
+    .await
+    .context("failed to read the keyring")
+}
+
+async fn write_keyring(secret: String) -> anyhow::Result<()> {
+    with_keyring(move |entry| entry.set_password(&secret))
+        .await
+        .context("failed to write to the keyring")
+}
+
+/// Member whose token is stored in the keyring, if any.
+pub async fn keyring_username() -> anyhow::Result<Option<String>> {
+    Ok(TokenStore::Keyring
+        .load()
+        .await?
+        .map(|token| token.username))
+}
+
+/// Log in with username and password and store the token in the keyring, replacing any token
+/// stored there before. The password itself is not stored.
+pub async fn login(
+    api_key_pair: letterboxd::ApiKeyPair,
+    username: &str,
+    password: &str,
+) -> anyhow::Result<letterboxd::Client> {
+    let client = letterboxd::Client::authenticate(api_key_pair, username, password)
+        .await
+        .context("failed to authenticate on Letterboxd")?;
+    let token = client
+        .token()
+        .ok_or_else(|| anyhow!("Letterboxd issued no access token"))?;
+    TokenStore::Keyring
+        .save(&StoredToken::new(username, token))
+        .await?;
+    Ok(client)
+}
+
+/// Delete the token stored in the keyring, returning whether there was one.
+pub async fn logout() -> anyhow::Result<bool> {
+    with_keyring(|entry| match entry.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(err) => Err(err),
+    })
+    .await
+    .context("failed to delete the token from the keyring")
+}
+
+/// Save the token, readable only by the current user.
+fn save_token(path: &Path, token: &StoredToken) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options.open(path)?;
+    serde_json::to_writer(file, token)?;
+    Ok(())
+}
+
+/// Get a new access token with the refresh token.
+///
+/// The request is signed with the API secret like all requests to the Letterboxd API.
+async fn refresh_token(
+    api_key_pair: &letterboxd::ApiKeyPair,
+    refresh_token: &str,
+) -> anyhow::Result<letterboxd::AccessToken> {
+    let body = format!(
+        "grant_type=refresh_token&refresh_token={}",
+        utf8_percent_encode(refresh_token, NON_ALPHANUMERIC)
+    );
+    let mut url = reqwest::Url::parse(TOKEN_URL).expect("valid url");
+    url.query_pairs_mut()
+        .append_pair("apikey", &api_key_pair.api_key)
+        .append_pair("nonce", &uuid::Uuid::new_v4().to_string())
+        .append_pair("timestamp", &chrono::Utc::now().timestamp().to_string());
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(api_key_pair.api_secret.as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(format!("POST\0{}\0{}", url, body).as_bytes());
+    let signature = hex::encode(mac.finalize().into_bytes());
+    url.query_pairs_mut().append_pair("signature", &signature);
+
+    let body = reqwest::Client::new()
+        .post(url)
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded",
+        )
+        .header(reqwest::header::ACCEPT, "application/json")
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let response: TokenResponse =
+        serde_json::from_str(&body).context("invalid response of the token endpoint")?;
+    Ok(response.into())
+}
+
+/// Authenticate on Letterboxd, reusing the token stored in `token_store` if possible.
+///
+/// An expired token is refreshed. The member logs in with username and password only if there is
+/// no token of this member or the refresh fails, so the password is needed only then. The new
+/// token is stored in `token_store`.
+pub async fn authenticate(
+    api_key_pair: letterboxd::ApiKeyPair,
+    username: &str,
+    password: Option<&str>,
+    token_store: Option<&TokenStore>,
+) -> anyhow::Result<letterboxd::Client> {
+    let stored = match token_store {
+        Some(store) => store.load().await,
+        None => Ok(None),
+    };
+    let stored = match stored {
+        Ok(token) => token,
+        Err(err) => {
+            warn!("failed to read stored token: {:#}", err);
+            None
+        }
+    };
+    let stored = stored.filter(|token| token.username == username);
+
+    let mut token = None;
+    if let Some(stored) = stored {
+        if !stored.is_expired() {
+            debug!("Reusing stored access token.");
+            let token = stored.to_access_token();
+            return Ok(letterboxd::Client::with_token(api_key_pair, token));
+        }
+        match refresh_token(&api_key_pair, &stored.refresh_token).await {
+            Ok(refreshed) => {
+                debug!("Refreshed access token.");
+                token = Some(refreshed);
+            }
+            Err(err) => warn!("failed to refresh access token: {:#}", err),
+        }
+    }
+
+    let client = match token {
+        Some(token) => letterboxd::Client::with_token(api_key_pair, token),
+        None => {
+            let password = password
+                .ok_or_else(|| anyhow!("missing obligatory variable LETTERBOXD_PASSWORD"))?;
+            letterboxd::Client::authenticate(api_key_pair, username, password)
+                .await
+                .context("failed to authenticate on Letterboxd")?
+        }
+    };
+    if let (Some(store), Some(token)) = (token_store, client.token()) {
+        if let Err(err) = store.save(&StoredToken::new(username, token)).await {
+            warn!("failed to store access token in {}: {:#}", store, err);
+        }
+    }
+    Ok(client)
+}
+
+/// API key pair of the selected profile, or else of the environment.
+pub fn api_key_pair() -> anyhow::Result<letterboxd::ApiKeyPair> {
+    if let Some(profile) = profile() {
+        if let (Some(api_key), Some(api_secret)) = (&profile.api_key, &profile.api_secret) {
+            return Ok(letterboxd::ApiKeyPair::new(
+                api_key.clone(),
+                api_secret.clone(),
+            ));
+        }
+    }
+    letterboxd::ApiKeyPair::from_env().ok_or_else(|| {
+        anyhow!(
+            "No API key/secret environment variable found: \
+            check if LETTERBOXD_API_KEY/LETTERBOXD_API_SECRET is set"
+        )
+    })
+}
+
+/// Username and password of the selected profile, or else of the environment.
+pub fn credentials() -> (Option<String>, Option<String>) {
+    match profile() {
+        Some(profile) => (
+            profile.username.clone(),
+            profile
+                .password_env
+                .as_ref()
+                .and_then(|var| env::var(var).ok()),
+        ),
+        None => (
+            env::var("LETTERBOXD_USERNAME").ok(),
+            env::var("LETTERBOXD_PASSWORD").ok(),
+        ),
+    }
+}
+
+/// Authenticate with the token stored in the keyring by `auth login`, or else with the
+/// credentials of the environment or of the selected profile. A username naming another member
+/// than the one of the keyring selects these credentials.
+pub async fn new_client() -> anyhow::Result<letterboxd::Client> {
+    connect(false).await
+}
+
+/// Client for commands which change nothing on Letterboxd, authenticated like [`new_client`] if a
+/// username is known, or else using the API key only, which reads films and public lists.
+pub async fn read_only_client() -> anyhow::Result<letterboxd::Client> {
+    connect(true).await
+}
+
+async fn connect(read_only: bool) -> anyhow::Result<letterboxd::Client> {
+    let api_key_pair = api_key_pair()?;
+    let (env_username, password) = credentials();
+
+    let keyring_username = keyring_username().await.unwrap_or_else(|err| {
+        debug!("Not using the keyring: {:#}", err);
+        None
+    });
+    if let Some(username) = keyring_username {
+        if env_username.as_ref().is_none_or(|env| *env == username) {
+            debug!("Using the access token of {} from the keyring.", username);
+            return authenticate(
+                api_key_pair,
+                &username,
+                password.as_deref(),
+                Some(&TokenStore::Keyring),
+            )
+            .await;
+        }
+    }
+
+    let username = match env_username {
+        Some(username) => username,
+        None if read_only => {
+            info!("{}", Message::AnonymousClient);
+            return Ok(letterboxd::Client::new(api_key_pair));
+        }
+        None => {
+            return Err(match profile_name() {
+                Some(profile) => anyhow!(
+                    "profile {} has no username: set it in the config file or run `auth login \
+                     --profile {}`",
+                    profile,
+                    profile
+                ),
+                None => anyhow!("missing obligatory variable LETTERBOXD_USERNAME"),
+            })
+        }
+    };
+    let token_store = default_token_path().map(TokenStore::File);
+    authenticate(
+        api_key_pair,
+        &username,
+        password.as_deref(),
+        token_store.as_ref(),
+    )
+    .await
+}
@@ -0,0 +1,164 @@
+//! Services the movies of a directory are synced with.
+//!
+//! Letterboxd is synced by [`SyncPlan`] and [`SyncExecutor`], which keep the details, ranks and
+//! notes of its lists. Other services implement [`ListService`], which only adds and removes
+//! films, like [`TraktClient`]. Their lists are synced by [`resolve_service_films`],
+//! [`plan_service_sync`] and [`apply_service_plan`].
+//!
+//! [`SyncPlan`]: crate::sync::SyncPlan
+//! [`SyncExecutor`]: crate::sync::SyncExecutor
+//! [`TraktClient`]: crate::trakt::TraktClient
+
+use anyhow::{anyhow, Context as _};
+use futures_util::{stream, StreamExt};
+use log::warn;
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::cache::{get_cache_filename, load_cache, save_cache};
+use crate::config::Config;
+use crate::i18n::Message;
+use crate::resolve::MovieGuess;
+use crate::scan::Source;
+use crate::sync::SyncPlan;
+use crate::throttle::max_concurrency;
+
+/// Service to sync with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Letterboxd,
+    Trakt,
+}
+
+impl Backend {
+    pub const VARIANTS: &'static [&'static str] = &["letterboxd", "trakt"];
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "letterboxd" => Ok(Backend::Letterboxd),
+            "trakt" => Ok(Backend::Trakt),
+            _ => Err(anyhow!("unknown backend: {}", s)),
+        }
+    }
+}
+
+/// A service with lists of films, which are identified by ids of the service.
+pub trait ListService {
+    /// Name of the service for messages.
+    fn name(&self) -> &'static str;
+
+    /// Id of the film of the movie, if the service knows it.
+    fn resolve(
+        &self,
+        movie: &MovieGuess,
+    ) -> impl Future<Output = anyhow::Result<Option<String>>> + Send;
+
+    /// Titles of the films on the list by film id.
+    fn list_films(
+        &self,
+        list_id: &str,
+    ) -> impl Future<Output = anyhow::Result<HashMap<String, String>>> + Send;
+
+    /// Add and remove films to and from the list.
+    fn update_list(
+        &self,
+        list_id: &str,
+        to_add: &[String],
+        to_remove: &[String],
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+/// Path of the cache of the film ids of the movies of `directory` on `service`. Movies are cached
+/// by service, as the ids of their films differ.
+pub fn service_cache_path<S: ListService>(
+    service: &S,
+    directory: &Source,
+    config: &Config,
+) -> anyhow::Result<PathBuf> {
+    let cache_key = format!("{}:{}", service.name(), config.cache_key(directory));
+    get_cache_filename(config.cache_format, &cache_key)
+}
+
+/// Resolve the films of the movies on `service`, returning the titles of the movies by film id.
+///
+/// The ids are cached at `cache_path`, so that only movies not found before are looked up. Movies
+/// which are not found or fail to resolve are left out.
+pub async fn resolve_service_films<S: ListService>(
+    service: &S,
+    movies: &[MovieGuess],
+    cache_path: &Path,
+    fsync: bool,
+) -> anyhow::Result<HashMap<String, String>> {
+    let mut cache: HashMap<String, String> = load_cache(cache_path)
+        .with_context(|| format!("failed to read cache file at: {}", cache_path.display()))?;
+    let uncached = movies
+        .iter()
+        .filter(|movie| !cache.contains_key(&movie.to_string()))
+        .map(|movie| async move { (movie, service.resolve(movie).await) });
+    let resolved: Vec<_> = stream::iter(uncached)
+        .buffer_unordered(max_concurrency())
+        .collect()
+        .await;
+    let cache_len = cache.len();
+    for (movie, result) in resolved {
+        match result {
+            Ok(Some(id)) => {
+                cache.insert(movie.to_string(), id);
+            }
+            Ok(None) => warn!("{}", Message::MovieNotFound(&movie.title)),
+            Err(err) => warn!("failed to resolve {}: {:#}", movie, err),
+        }
+    }
+    if cache.len() != cache_len {
+        if let Err(err) = save_cache(&cache, cache_path, fsync) {
+            warn!("failed to save cache: {}", err);
+        }
+    }
+
+    let mut titles: HashMap<String, String> = HashMap::new();
+    for movie in movies {
+        if let Some(id) = cache.get(&movie.to_string()) {
+            titles.insert(id.clone(), movie.to_string());
+        }
+    }
+    Ok(titles)
+}
+
+/// Plan the update of the list of `service` to contain exactly the films of `titles`, which has
+/// the titles of the films by id, see [`SyncPlan::for_service`].
+pub async fn plan_service_sync<S: ListService>(
+    service: &S,
+    list_id: &str,
+    titles: HashMap<String, String>,
+    remove_only: bool,
+) -> anyhow::Result<SyncPlan> {
+    let saved = service
+        .list_films(list_id)
+        .await
+        .with_context(|| format!("failed to fetch the {} list", service.name()))?;
+    let ids: HashSet<String> = titles.keys().cloned().collect();
+    let mut plan = SyncPlan::for_service(list_id, saved, &ids, remove_only);
+    plan.titles.extend(titles);
+    plan.to_add.sort();
+    plan.to_remove.sort();
+    Ok(plan)
+}
+
+/// Apply a plan of [`plan_service_sync`] to the list of `service`.
+pub async fn apply_service_plan<S: ListService>(
+    service: &S,
+    plan: &SyncPlan,
+) -> anyhow::Result<()> {
+    service
+        .update_list(&plan.list_id, &plan.to_add, &plan.to_remove)
+        .await
+        .with_context(|| format!("failed to update the {} list", service.name()))
+}
@@ -0,0 +1,1055 @@
+//! Caches of movie ids and film details in the user's cache dir, like `~/.cache/letterboxd-sync`.
+//!
+//! Each scanned directory has its own cache of movie ids, as a sync replaces the cache with the
+//! ids of the movies it found. The film details are cached for all directories together. The
+//! entries of lists are cached by [`crate::sync::fetch_saved_entries`] in `lists`.
+//!
+//! The paths of the files of a directory are cached relative to it, and the cache of a directory
+//! is found by [`crate::config::Config::cache_key`]. Thus, a cache stays valid when the directory
+//! is mounted elsewhere, like on another machine.
+//!
+//! The movie caches are versioned, see [`MovieCache`]. Caches of earlier versions, which only map
+//! movie names to film ids, are still read and are converted when they are saved again.
+
+use anyhow::{anyhow, Context as _};
+use futures_util::{stream, StreamExt};
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead as _, Read as _, Seek as _, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::i18n::Message;
+use crate::progress;
+use crate::resolve::ResolvedFilms;
+use crate::retry::with_retry;
+use crate::scan::MovieFile;
+use crate::throttle::max_concurrency;
+
+/// Version of the format of [`MovieCache`], raised on incompatible changes.
+pub const MOVIE_CACHE_VERSION: u32 = 2;
+
+/// A movie cache of a later version than [`MOVIE_CACHE_VERSION`], written by a newer release.
+/// Such a cache is neither read nor replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NewerCacheVersion(pub u32);
+
+impl fmt::Display for NewerCacheVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cache version {} is newer than supported version {}",
+            self.0, MOVIE_CACHE_VERSION
+        )
+    }
+}
+
+impl std::error::Error for NewerCacheVersion {}
+
+/// Cache of the film ids of the movies of a directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MovieCache {
+    pub version: u32,
+    /// Entries by movie name.
+    pub movies: BTreeMap<String, MovieEntry>,
+}
+
+/// A movie resolved to a film, with the files and metadata it was resolved from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MovieEntry {
+    pub film_id: String,
+    /// Release year guessed from the files.
+    pub year: Option<u16>,
+    /// Confidence of the match between 0 and 1, unknown for caches of earlier versions. Films
+    /// pinned by an override or picked interactively have a confidence of 1.
+    pub confidence: Option<f64>,
+    /// Time of the resolution as a Unix timestamp, unknown for caches of earlier versions.
+    pub resolved_at: Option<i64>,
+    pub files: Vec<FileEntry>,
+}
+
+/// A file of a cached movie as it was when last scanned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Path of the file, relative to the directory when saved, see [`MovieCache::relative_to`].
+    pub path: PathBuf,
+    pub size: Option<u64>,
+    /// Time of the last modification as a Unix timestamp.
+    pub modified: Option<i64>,
+    /// Fingerprint of the content of a local file, see [`file_fingerprint`].
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+}
+
+impl From<&MovieFile> for FileEntry {
+    fn from(file: &MovieFile) -> Self {
+        Self {
+            path: file.path.clone(),
+            size: file.size,
+            modified: file.modified,
+            fingerprint: file.fingerprint.clone(),
+        }
+    }
+}
+
+impl Default for MovieCache {
+    fn default() -> Self {
+        Self {
+            version: MOVIE_CACHE_VERSION,
+            movies: BTreeMap::new(),
+        }
+    }
+}
+
+impl FileEntry {
+    /// Whether the file has the same size and modification time as when it was cached. Files
+    /// without a modification time are never known to be unchanged.
+    pub fn is_unchanged(&self, file: &MovieFile) -> bool {
+        file.modified.is_some() && self.modified == file.modified && self.size == file.size
+    }
+}
+
+impl MovieEntry {
+    /// Entry of a movie resolved just now.
+    pub fn new(film_id: String, confidence: Option<f64>) -> Self {
+        Self {
+            film_id,
+            year: None,
+            confidence,
+            resolved_at: Some(chrono::Utc::now().timestamp()),
+            files: Vec::new(),
+        }
+    }
+}
+
+impl MovieCache {
+    /// Cache of film ids by movie name, as in the caches of earlier versions.
+    pub fn from_ids(ids: HashMap<String, String>) -> Self {
+        let movies = ids
+            .into_iter()
+            .map(|(movie, film_id)| {
+                let entry = MovieEntry {
+                    film_id,
+                    year: None,
+                    confidence: None,
+                    resolved_at: None,
+                    files: Vec::new(),
+                };
+                (movie, entry)
+            })
+            .collect();
+        Self {
+            movies,
+            ..Self::default()
+        }
+    }
+
+    pub fn film_id(&self, movie: &str) -> Option<&str> {
+        self.movies.get(movie).map(|entry| entry.film_id.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.movies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.movies.is_empty()
+    }
+
+    /// Insert a movie resolved to `film_id`, returning its entry.
+    ///
+    /// An entry of the movie with the same film id is kept, so that it still tells when the film
+    /// was first resolved.
+    pub fn insert(
+        &mut self,
+        movie: String,
+        film_id: &str,
+        confidence: Option<f64>,
+    ) -> &mut MovieEntry {
+        let entry = self
+            .movies
+            .entry(movie)
+            .or_insert_with(|| MovieEntry::new(film_id.to_string(), confidence));
+        if entry.film_id != film_id {
+            *entry = MovieEntry::new(film_id.to_string(), confidence);
+        }
+        if confidence.is_some() {
+            entry.confidence = confidence;
+        }
+        entry
+    }
+
+    /// Add the resolved movies together with their files and release years.
+    pub fn update(&mut self, resolved: &ResolvedFilms) {
+        for (movie, id) in &resolved.ids {
+            let confidence = resolved.confidences.get(movie).copied();
+            let entry = self.insert(movie.clone(), id, confidence);
+            if let Some(year) = resolved.years.get(movie) {
+                entry.year = Some(*year);
+            }
+            if let Some(files) = resolved.files.get(movie) {
+                entry.files = files.iter().map(FileEntry::from).collect();
+            }
+        }
+    }
+
+    /// Cache of only the resolved movies, keeping what is known about them from this cache.
+    pub fn resolved(&self, resolved: &ResolvedFilms) -> Self {
+        let mut cache = Self {
+            version: self.version,
+            movies: self
+                .movies
+                .iter()
+                .filter(|(movie, _)| resolved.ids.contains_key(*movie))
+                .map(|(movie, entry)| (movie.clone(), entry.clone()))
+                .collect(),
+        };
+        cache.update(resolved);
+        cache
+    }
+
+    /// Cache with the paths of the files below `root` relative to it, separated by `/` on all
+    /// systems. Paths of files elsewhere are kept.
+    pub fn relative_to(&self, root: &Path) -> Self {
+        let mut cache = self.clone();
+        if root.as_os_str().is_empty() {
+            return cache;
+        }
+        for file in cache.movies.values_mut().flat_map(|entry| &mut entry.files) {
+            if let Some(path) = relative_path(&file.path, root) {
+                file.path = path;
+            }
+        }
+        cache
+    }
+
+    /// Resolve the relative paths of the files against `root`, the directory the cache was saved
+    /// of. Absolute paths, like the ones of caches saved before paths were relative, are kept.
+    pub fn rooted_at(&mut self, root: &Path) {
+        if root.as_os_str().is_empty() {
+            return;
+        }
+        for file in self.movies.values_mut().flat_map(|entry| &mut entry.files) {
+            if file.path.is_relative() {
+                file.path = root.join(&file.path);
+            }
+        }
+    }
+
+    /// Cached files by path, with the names of their movies.
+    pub fn files(&self) -> HashMap<&Path, (&str, &FileEntry)> {
+        self.movies
+            .iter()
+            .flat_map(|(movie, entry)| {
+                entry
+                    .files
+                    .iter()
+                    .map(move |file| (file.path.as_path(), (movie.as_str(), file)))
+            })
+            .collect()
+    }
+
+    /// Add entries for the movies which are not in the cache, but some of whose files are, by
+    /// their fingerprints. Returns the number of movies found.
+    ///
+    /// Thus, a file which was renamed or moved, and is guessed to be another movie now, is not
+    /// looked up again. Fingerprints of files of different films, like copies of a trailer, are
+    /// ambiguous and ignored.
+    pub fn add_moved(&mut self, files_by_movie: &HashMap<String, Vec<MovieFile>>) -> usize {
+        let mut by_fingerprint: HashMap<&str, Option<&MovieEntry>> = HashMap::new();
+        for entry in self.movies.values() {
+            for fingerprint in entry
+                .files
+                .iter()
+                .filter_map(|file| file.fingerprint.as_deref())
+            {
+                let known = by_fingerprint.entry(fingerprint).or_insert(Some(entry));
+                if known.is_some_and(|known| known.film_id != entry.film_id) {
+                    *known = None;
+                }
+            }
+        }
+        let moved: Vec<(String, MovieEntry)> = files_by_movie
+            .iter()
+            .filter(|(movie, _)| !self.movies.contains_key(*movie))
+            .filter_map(|(movie, files)| {
+                let entry = files
+                    .iter()
+                    .find_map(|file| *by_fingerprint.get(file.fingerprint.as_deref()?)?)?;
+                debug!(
+                    "Found {} in the cache as film {} by the content of its files",
+                    movie, entry.film_id
+                );
+                Some((movie.clone(), entry.clone()))
+            })
+            .collect();
+        let found = moved.len();
+        self.movies.extend(moved);
+        found
+    }
+
+    /// Remove the entries of deleted films and point the entries of merged films to the films
+    /// they were merged into.
+    pub fn apply(&mut self, stale: &[StaleEntry]) {
+        for entry in stale {
+            match entry {
+                StaleEntry::Deleted { movie, .. } => {
+                    self.movies.remove(movie);
+                }
+                StaleEntry::Merged { movie, new_id, .. } => {
+                    if let Some(entry) = self.movies.get_mut(movie) {
+                        entry.film_id = new_id.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove the entries all of whose files no longer exist, returning the names of their movies.
+    ///
+    /// Entries without known files, like the ones of caches of earlier versions, are kept.
+    pub fn prune(&mut self, exists: impl Fn(&Path) -> bool) -> Vec<String> {
+        let pruned: Vec<String> = self
+            .movies
+            .iter()
+            .filter(|(_, entry)| {
+                !entry.files.is_empty() && !entry.files.iter().any(|file| exists(&file.path))
+            })
+            .map(|(movie, _)| movie.clone())
+            .collect();
+        for movie in &pruned {
+            self.movies.remove(movie);
+        }
+        pruned
+    }
+}
+
+/// Path of a file below `root` relative to it and separated by `/`, unless it is not below `root`
+/// or not valid UTF-8.
+fn relative_path(path: &Path, root: &Path) -> Option<PathBuf> {
+    let parts = path
+        .strip_prefix(root)
+        .ok()?
+        .iter()
+        .map(|part| part.to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(PathBuf::from(parts.join("/"))).filter(|path| !path.as_os_str().is_empty())
+}
+
+/// Entry of a cache of movie ids whose film changed on Letterboxd, see [`verify_cache`].
+#[derive(Debug, Clone)]
+pub enum StaleEntry {
+    /// The film was deleted.
+    Deleted { movie: String, film_id: String },
+    /// The film was merged into the film `new_id`, e.g. as a duplicate of it.
+    Merged {
+        movie: String,
+        film_id: String,
+        new_id: String,
+    },
+}
+
+/// Check the film ids of the cache against Letterboxd, returning the entries whose films were
+/// deleted or merged into other films, and the number of films which could not be checked.
+pub async fn verify_cache(
+    cache: &MovieCache,
+    client: &letterboxd::Client,
+) -> (Vec<StaleEntry>, usize) {
+    let bar = progress::bar(cache.len() as u64, Message::VerifyingCache.to_string());
+    let checks = cache.movies.iter().map(|(movie, entry)| async move {
+        let res = with_retry(|| client.film(&entry.film_id)).await;
+        (movie, &entry.film_id, res)
+    });
+    let mut results = stream::iter(checks).buffer_unordered(max_concurrency());
+    let mut stale = Vec::new();
+    let mut failed = 0;
+    while let Some((movie, film_id, res)) = results.next().await {
+        bar.inc(1);
+        match res {
+            Ok(film) if film.id == *film_id => {}
+            Ok(film) => stale.push(StaleEntry::Merged {
+                movie: movie.clone(),
+                film_id: film_id.clone(),
+                new_id: film.id,
+            }),
+            Err(letterboxd::Error::Server { code: 404, .. }) => stale.push(StaleEntry::Deleted {
+                movie: movie.clone(),
+                film_id: film_id.clone(),
+            }),
+            Err(err) => {
+                warn!("failed to verify film {} of {}: {}", film_id, movie, err);
+                failed += 1;
+            }
+        }
+    }
+    bar.finish_and_clear();
+    (stale, failed)
+}
+
+/// Serialization format of the cache files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheFormat {
+    #[default]
+    Json,
+    /// Compact binary format, faster to load for large caches.
+    Bincode,
+}
+
+impl CacheFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            CacheFormat::Json => "json",
+            CacheFormat::Bincode => "bin",
+        }
+    }
+
+    /// Format of a cache file by its extension.
+    pub fn of_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("bin") => CacheFormat::Bincode,
+            _ => CacheFormat::Json,
+        }
+    }
+}
+
+/// Directory of the caches: `letterboxd-sync` in the user's cache dir, which is
+/// `$XDG_CACHE_HOME` or `~/.cache` on Linux.
+pub fn cache_dir() -> anyhow::Result<PathBuf> {
+    dirs::cache_dir()
+        .map(|dir| dir.join("letterboxd-sync"))
+        .ok_or_else(|| anyhow!("no cache directory of the user found"))
+}
+
+/// Path of the cache of movie ids of a directory, given by its path or URL.
+pub fn get_cache_filename(format: CacheFormat, directory: &str) -> anyhow::Result<PathBuf> {
+    let hash = hex::encode(Sha256::digest(directory.as_bytes()));
+    Ok(cache_dir()?
+        .join(format!("movies-{}", &hash[..16]))
+        .with_extension(format.extension()))
+}
+
+pub fn get_film_cache_filename(format: CacheFormat) -> anyhow::Result<PathBuf> {
+    const FILM_CACHE_FILENAME: &str = "films";
+    Ok(cache_dir()?
+        .join(FILM_CACHE_FILENAME)
+        .with_extension(format.extension()))
+}
+
+/// Size of the chunks at the start and the end of a file its fingerprint is computed from. Smaller
+/// files, like `.strm` files or placeholders, are not fingerprinted, as they are not unique enough.
+const FINGERPRINT_CHUNK: u64 = 1 << 20;
+
+/// Cheap fingerprint of the content of a local file, which stays the same when the file is
+/// renamed or moved: a hash of its size and its first and last MiB.
+pub fn file_fingerprint(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut hasher = Sha256::new();
+    hasher.update(size.to_le_bytes());
+    let mut chunk = Vec::new();
+    (&mut file)
+        .take(FINGERPRINT_CHUNK)
+        .read_to_end(&mut chunk)?;
+    hasher.update(&chunk);
+    if size > FINGERPRINT_CHUNK {
+        chunk.clear();
+        file.seek(io::SeekFrom::Start(size - FINGERPRINT_CHUNK))?;
+        file.take(FINGERPRINT_CHUNK).read_to_end(&mut chunk)?;
+        hasher.update(&chunk);
+    }
+    Ok(hex::encode(&hasher.finalize()[..16]))
+}
+
+/// Set the fingerprints of local files of at least [`FINGERPRINT_CHUNK`] bytes. The fingerprints
+/// of files in the cache whose size and modification time did not change are taken from the cache
+/// instead of reading the files.
+pub fn fingerprint_files<'a>(
+    files: impl IntoIterator<Item = &'a mut MovieFile>,
+    cache: &MovieCache,
+) {
+    let known = cache.files();
+    let files: Vec<&mut MovieFile> = files.into_iter().collect();
+    let bar = progress::bar(files.len() as u64, Message::FingerprintingFiles.to_string());
+    for file in files {
+        bar.inc(1);
+        if file.size.is_some_and(|size| size < FINGERPRINT_CHUNK) {
+            file.fingerprint = None;
+            continue;
+        }
+        let unchanged = known
+            .get(file.path.as_path())
+            .map(|(_, known)| *known)
+            .filter(|known| known.is_unchanged(file));
+        file.fingerprint = match unchanged.and_then(|known| known.fingerprint.clone()) {
+            Some(fingerprint) => Some(fingerprint),
+            None => match file_fingerprint(&file.path) {
+                Ok(fingerprint) => Some(fingerprint),
+                Err(err) => {
+                    debug!("Failed to fingerprint {}: {}", file.path.display(), err);
+                    None
+                }
+            },
+        };
+    }
+    bar.finish_and_clear();
+}
+
+/// Load the cache of movie ids at `path`, see [`load_cache`].
+///
+/// If there is no cache yet, the cache in the working directory of earlier versions is loaded
+/// instead, so that it is carried over. Unlike a corrupt cache, a cache of a newer version is not
+/// moved aside but is an error, see [`NewerCacheVersion`].
+pub fn load_movie_cache(path: &Path) -> anyhow::Result<MovieCache> {
+    let format = CacheFormat::of_path(path);
+    let exists = |path: &Path| path.exists() || path.with_extension("json").exists();
+    let legacy_path = env::current_dir()?
+        .join(".movies")
+        .with_extension(format.extension());
+    if !exists(path) && exists(&legacy_path) {
+        info!(
+            "{}",
+            Message::ImportingCache(&legacy_path.display().to_string())
+        );
+        return load_cache_with(&legacy_path, read_movie_cache);
+    }
+    load_cache_with(path, read_movie_cache)
+}
+
+/// Read a cache of movie ids in the format given by its extension, converting a cache of an
+/// earlier version.
+///
+/// A cache of a later version is a [`NewerCacheVersion`] error.
+pub fn read_movie_cache(file: fs::File, format: CacheFormat) -> anyhow::Result<MovieCache> {
+    let mut bytes = Vec::new();
+    io::Read::read_to_end(&mut io::BufReader::new(file), &mut bytes)?;
+    fn parse<T: DeserializeOwned>(bytes: &[u8], format: CacheFormat) -> anyhow::Result<T> {
+        Ok(match format {
+            CacheFormat::Json => serde_json::from_slice(bytes)?,
+            CacheFormat::Bincode => bincode::deserialize(bytes)?,
+        })
+    }
+
+    /// The version of a cache whose entries may be of another format.
+    #[derive(Deserialize)]
+    struct Versioned {
+        version: u32,
+    }
+
+    match parse::<MovieCache>(&bytes, format) {
+        Ok(cache) if cache.version == MOVIE_CACHE_VERSION => Ok(cache),
+        // Binary caches of earlier versions may parse as a cache of another version.
+        res => parse::<HashMap<String, String>>(&bytes, format)
+            .map(MovieCache::from_ids)
+            .map_err(|_| match parse::<Versioned>(&bytes, format) {
+                Ok(Versioned { version }) if version > MOVIE_CACHE_VERSION => {
+                    NewerCacheVersion(version).into()
+                }
+                _ => match res {
+                    Ok(cache) => {
+                        anyhow!("unsupported version of movie cache: {}", cache.version)
+                    }
+                    Err(err) => err,
+                },
+            }),
+    }
+}
+
+/// Read a cache file in the format given by its extension.
+pub fn read_cache<V: DeserializeOwned>(
+    file: fs::File,
+    format: CacheFormat,
+) -> anyhow::Result<HashMap<String, V>> {
+    let reader = io::BufReader::new(file);
+    Ok(match format {
+        CacheFormat::Json => serde_json::from_reader(reader)?,
+        CacheFormat::Bincode => bincode::deserialize_from(reader)?,
+    })
+}
+
+/// Load a cache file, e.g. the cache of movie ids.
+///
+/// A cache which cannot be parsed is moved aside to a backup file and an empty cache is returned,
+/// so that it is rebuilt during the run. If a binary cache does not exist yet, the JSON cache is
+/// loaded instead.
+pub fn load_cache<V: DeserializeOwned>(
+    path: impl AsRef<Path>,
+) -> anyhow::Result<HashMap<String, V>> {
+    load_cache_with(path.as_ref(), read_cache)
+}
+
+/// Load a cache file like [`load_cache`], parsing it with `read`.
+fn load_cache_with<T: Default>(
+    path: &Path,
+    read: fn(fs::File, CacheFormat) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let format = CacheFormat::of_path(path);
+    let file = match fs::File::open(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound && format != CacheFormat::Json => {
+            let json_path = path.with_extension(CacheFormat::Json.extension());
+            if json_path.exists() {
+                info!(
+                    "{}",
+                    Message::ConvertingCache(&json_path.display().to_string())
+                );
+                return load_cache_with(&json_path, read);
+            }
+            Err(err)
+        }
+        file => file,
+    };
+    let entries = match file {
+        Ok(file) => match read(file, format) {
+            Ok(entries) => {
+                debug!("Loaded cache {}.", path.display());
+                entries
+            }
+            Err(err) if err.is::<NewerCacheVersion>() => {
+                return Err(err)
+                    .with_context(|| format!("failed to load cache file: {}", path.display()));
+            }
+            Err(err) => {
+                let mut backup_path = path.as_os_str().to_owned();
+                backup_path.push(".bak");
+                fs::rename(path, &backup_path).with_context(|| {
+                    format!("failed to back up corrupt cache file: {}", path.display())
+                })?;
+                warn!(
+                    "{}",
+                    Message::CorruptCache {
+                        path: &path.display().to_string(),
+                        error: &err.to_string(),
+                        backup: &Path::new(&backup_path).display().to_string(),
+                    }
+                );
+                T::default()
+            }
+        },
+        Err(err) => {
+            if err.kind() == io::ErrorKind::NotFound {
+                T::default()
+            } else {
+                return Err(err.into());
+            }
+        }
+    };
+    Ok(entries)
+}
+
+/// Save a cache file, e.g. a [`MovieCache`].
+///
+/// The cache is written to a temporary file first, which is then renamed to the cache path. Thus,
+/// an interrupted write never leaves a partial cache behind. With `fsync` the data is flushed to
+/// disk before the rename.
+pub fn save_cache<T: Serialize>(
+    entries: &T,
+    path: impl AsRef<Path>,
+    fsync: bool,
+) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let mut writer = io::BufWriter::new(fs::File::create(&tmp_path)?);
+    match CacheFormat::of_path(path) {
+        CacheFormat::Json => serde_json::to_writer_pretty(&mut writer, &entries)?,
+        CacheFormat::Bincode => bincode::serialize_into(&mut writer, &entries)?,
+    }
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+    if fsync {
+        file.sync_all()?;
+    }
+    drop(file);
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Delete a cache file. A missing cache file is not an error.
+pub fn clear_cache(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// Path of the checkpoint of the cache at `cache_path` of a directory, given by its cache key:
+/// the movies searched without finding a film by a sync which did not finish yet, one per line.
+/// Together with the flushed cache, it lets an interrupted sync resume the search where it left
+/// off.
+///
+/// Each directory has its own checkpoint, also if the cache is shared by all directories, see
+/// `--cache-path`, as the movies one directory did not find may be found in another.
+pub fn checkpoint_path(cache_path: &Path, directory: &str) -> PathBuf {
+    let hash = hex::encode(Sha256::digest(directory.as_bytes()));
+    let mut path = cache_path.as_os_str().to_owned();
+    path.push(format!(".{}.checkpoint", &hash[..16]));
+    PathBuf::from(path)
+}
+
+/// Movies of the checkpoint at `path`, which are empty without a checkpoint.
+pub fn read_checkpoint(path: &Path) -> anyhow::Result<HashSet<String>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(err) => return Err(err.into()),
+    };
+    io::BufReader::new(file)
+        .lines()
+        .filter(|line| !line.as_ref().is_ok_and(|line| line.is_empty()))
+        .collect::<io::Result<_>>()
+        .with_context(|| format!("failed to read checkpoint at: {}", path.display()))
+}
+
+/// Add movies searched without finding a film to the checkpoint at `path`.
+fn append_checkpoint(path: &Path, movies: &[String]) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let lines: String = movies.iter().map(|movie| format!("{}\n", movie)).collect();
+    file.write_all(lines.as_bytes())
+}
+
+/// Periodic flushing of the cache while resolving movie ids.
+///
+/// Flushes also add the movies no film was found for to the checkpoint of the directory, see
+/// [`checkpoint_path`], which is removed once the cache is saved at the end of the sync.
+pub struct CacheFlush<'a> {
+    pub path: &'a Path,
+    pub checkpoint: &'a Path,
+    /// Directory the paths of the files are saved relative to, see [`MovieCache::relative_to`].
+    pub root: Option<&'a Path>,
+    /// Flush after this many newly resolved ids.
+    pub every: Option<usize>,
+    /// Flush when this much time has passed since the last flush.
+    pub interval: Option<Duration>,
+    pub fsync: bool,
+}
+
+impl CacheFlush<'_> {
+    /// Save the cache and add the movies not found since the last flush to the checkpoint.
+    pub(crate) fn flush(&self, cache: &MovieCache, not_found: &[String]) -> anyhow::Result<()> {
+        match self.root {
+            Some(root) => save_cache(&cache.relative_to(root), self.path, self.fsync)?,
+            None => save_cache(cache, self.path, self.fsync)?,
+        }
+        append_checkpoint(self.checkpoint, not_found).context("failed to write checkpoint")
+    }
+
+    pub(crate) fn is_due(&self, unflushed: usize, last_flush: Instant) -> bool {
+        unflushed > 0
+            && (self.every.map(|every| unflushed >= every).unwrap_or(false)
+                || self
+                    .interval
+                    .map(|interval| last_flush.elapsed() >= interval)
+                    .unwrap_or(false))
+    }
+}
+
+/// Whether `theirs` is a better match than `ours` for the same movie: of higher confidence, or of
+/// equal confidence and resolved later. Unknown confidences and times count as lowest.
+fn is_better_match(theirs: &MovieEntry, ours: &MovieEntry) -> Option<bool> {
+    let confidence = theirs
+        .confidence
+        .partial_cmp(&ours.confidence)
+        .unwrap_or(Ordering::Equal);
+    match confidence.then(theirs.resolved_at.cmp(&ours.resolved_at)) {
+        Ordering::Greater => Some(true),
+        Ordering::Less => Some(false),
+        Ordering::Equal => None,
+    }
+}
+
+/// Merge the cache file `other` into the cache at `path`.
+///
+/// Of movies present in both caches with different film ids, the entry with the higher confidence
+/// is kept, or else the one resolved later. Entries which are equal in both are taken from
+/// `other` only if `theirs` is set.
+pub fn merge_cache(path: &Path, other: &Path, theirs: bool) -> anyhow::Result<()> {
+    let mut cache = load_cache_with(path, read_movie_cache)
+        .with_context(|| format!("failed to read cache file at: {}", path.display()))?;
+    let file = fs::File::open(other)
+        .with_context(|| format!("failed to open cache file at: {}", other.display()))?;
+    let other_cache = read_movie_cache(file, CacheFormat::of_path(other))
+        .with_context(|| format!("failed to parse cache file at: {}", other.display()))?;
+
+    let mut added = 0;
+    let mut conflicts = 0;
+    for (movie, entry) in other_cache.movies {
+        match cache.movies.get(&movie) {
+            None => {
+                cache.movies.insert(movie, entry);
+                added += 1;
+            }
+            Some(ours) if ours.film_id != entry.film_id => {
+                conflicts += 1;
+                warn!(
+                    "{}",
+                    Message::CacheConflict {
+                        movie: &movie,
+                        ours: &ours.film_id,
+                        theirs: &entry.film_id,
+                    }
+                );
+                if is_better_match(&entry, ours).unwrap_or(theirs) {
+                    cache.movies.insert(movie, entry);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    cache.version = MOVIE_CACHE_VERSION;
+    save_cache(&cache, path, true)?;
+    info!(
+        "{}",
+        Message::MergedCache {
+            added,
+            conflicts,
+            total: cache.len(),
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path of a cache file in the temporary directory, unique to the test.
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("letterboxd-sync-{}-{}", std::process::id(), name))
+    }
+
+    fn sample_cache() -> MovieCache {
+        let mut cache = MovieCache::default();
+        let entry = cache.insert("Heat".to_string(), "2bbs", Some(0.9));
+        entry.year = Some(1995);
+        entry.files.push(FileEntry {
+            path: PathBuf::from("/movies/Heat (1995)/Heat.mkv"),
+            size: Some(1 << 30),
+            modified: Some(1_600_000_000),
+            fingerprint: Some("abc".to_string()),
+        });
+        cache.insert("Alien".to_string(), "2b3e", None);
+        cache
+    }
+
+    fn assert_same(cache: &MovieCache, expected: &MovieCache) {
+        assert_eq!(cache.version, MOVIE_CACHE_VERSION);
+        assert_eq!(
+            cache.movies.keys().collect::<Vec<_>>(),
+            expected.movies.keys().collect::<Vec<_>>()
+        );
+        for (movie, entry) in &expected.movies {
+            let read = &cache.movies[movie];
+            assert_eq!(read.film_id, entry.film_id);
+            assert_eq!(read.year, entry.year);
+            assert_eq!(read.confidence, entry.confidence);
+            assert_eq!(read.resolved_at, entry.resolved_at);
+            assert_eq!(read.files, entry.files);
+        }
+    }
+
+    fn round_trip(format: CacheFormat) {
+        let path = temp_path("round-trip").with_extension(format.extension());
+        let cache = sample_cache();
+        save_cache(&cache, &path, true).unwrap();
+        let read = read_movie_cache(fs::File::open(&path).unwrap(), format).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_same(&read, &cache);
+    }
+
+    #[test]
+    fn json_cache_round_trip() {
+        round_trip(CacheFormat::Json);
+    }
+
+    #[test]
+    fn bincode_cache_round_trip() {
+        round_trip(CacheFormat::Bincode);
+    }
+
+    fn migrate(format: CacheFormat) {
+        let path = temp_path("migrate").with_extension(format.extension());
+        let ids = HashMap::from([("Heat".to_string(), "2bbs".to_string())]);
+        save_cache(&ids, &path, false).unwrap();
+        let cache = read_movie_cache(fs::File::open(&path).unwrap(), format).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(cache.version, MOVIE_CACHE_VERSION);
+        assert_eq!(cache.len(), 1);
+        let entry = &cache.movies["Heat"];
+        assert_eq!(entry.film_id, "2bbs");
+        assert_eq!(entry.confidence, None);
+        assert_eq!(entry.resolved_at, None);
+        assert!(entry.files.is_empty());
+    }
+
+    #[test]
+    fn json_cache_of_first_version_is_migrated() {
+        migrate(CacheFormat::Json);
+    }
+
+    #[test]
+    fn bincode_cache_of_first_version_is_migrated() {
+        migrate(CacheFormat::Bincode);
+    }
+
+    #[test]
+    fn unsupported_version_is_an_error() {
+        let path = temp_path("version").with_extension("json");
+        let cache = MovieCache {
+            version: MOVIE_CACHE_VERSION + 1,
+            ..sample_cache()
+        };
+        save_cache(&cache, &path, false).unwrap();
+        let res = read_movie_cache(fs::File::open(&path).unwrap(), CacheFormat::Json);
+        fs::remove_file(&path).unwrap();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn newer_version_is_not_backed_up() {
+        let path = temp_path("newer").with_extension("json");
+        let cache = MovieCache {
+            version: MOVIE_CACHE_VERSION + 1,
+            ..sample_cache()
+        };
+        save_cache(&cache, &path, false).unwrap();
+        let err = load_movie_cache(&path).unwrap_err();
+        let mut backup_path = path.as_os_str().to_owned();
+        backup_path.push(".bak");
+        let backed_up = Path::new(&backup_path).exists();
+        let kept = path.exists();
+        fs::remove_file(&path).ok();
+        assert_eq!(
+            err.root_cause().downcast_ref::<NewerCacheVersion>(),
+            Some(&NewerCacheVersion(MOVIE_CACHE_VERSION + 1))
+        );
+        assert!(kept && !backed_up);
+    }
+
+    #[test]
+    fn save_leaves_no_temporary_file() {
+        let path = temp_path("atomic").with_extension("json");
+        save_cache(&sample_cache(), &path, false).unwrap();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        assert!(path.exists());
+        assert!(!Path::new(&tmp_path).exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let path = checkpoint_path(&temp_path("checkpoint").with_extension("json"), "/movies");
+        assert!(read_checkpoint(&path).unwrap().is_empty());
+        append_checkpoint(&path, &["Heat".to_string()]).unwrap();
+        append_checkpoint(&path, &["Alien".to_string()]).unwrap();
+        let movies = read_checkpoint(&path).unwrap();
+        clear_cache(&path).unwrap();
+        assert_eq!(
+            movies,
+            HashSet::from(["Heat".to_string(), "Alien".to_string()])
+        );
+    }
+
+    #[test]
+    fn picked_film_wins_merge_over_found_film() {
+        let mut resolved = ResolvedFilms::default();
+        resolved.pick("Heat", "2bbs");
+        let mut picked = MovieCache::default();
+        picked.update(&resolved);
+        // Found with less confidence, but later.
+        let mut found = MovieCache::default();
+        let entry = found.insert("Heat".to_string(), "2bc0", Some(0.9));
+        entry.resolved_at = picked.movies["Heat"].resolved_at.map(|time| time + 60);
+
+        let path = temp_path("merge-picked").with_extension("json");
+        let other = temp_path("merge-found").with_extension("json");
+        save_cache(&picked, &path, false).unwrap();
+        save_cache(&found, &other, false).unwrap();
+        merge_cache(&path, &other, true).unwrap();
+        let merged = load_movie_cache(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&other).unwrap();
+        assert_eq!(merged.movies["Heat"].film_id, "2bbs");
+        assert_eq!(merged.movies["Heat"].confidence, Some(1.0));
+    }
+
+    #[test]
+    fn directories_sharing_a_cache_have_their_own_checkpoints() {
+        let cache_path = temp_path("shared").with_extension("json");
+        let movies = checkpoint_path(&cache_path, "/movies");
+        let series = checkpoint_path(&cache_path, "/series");
+        append_checkpoint(&movies, &["Heat".to_string()]).unwrap();
+        let other = read_checkpoint(&series).unwrap();
+        clear_cache(&movies).unwrap();
+        assert_ne!(movies, series);
+        assert!(other.is_empty());
+    }
+
+    #[test]
+    fn paths_are_saved_relative_to_the_directory() {
+        let cache = sample_cache().relative_to(Path::new("/movies"));
+        assert_eq!(
+            cache.movies["Heat"].files[0].path,
+            PathBuf::from("Heat (1995)/Heat.mkv")
+        );
+        let mut moved = cache.clone();
+        moved.rooted_at(Path::new("/mnt/nas/movies"));
+        assert_eq!(
+            moved.movies["Heat"].files[0].path,
+            PathBuf::from("/mnt/nas/movies/Heat (1995)/Heat.mkv")
+        );
+    }
+
+    #[test]
+    fn paths_outside_the_directory_are_kept() {
+        let cache = sample_cache().relative_to(Path::new("/other"));
+        assert_eq!(
+            cache.movies["Heat"].files[0].path,
+            PathBuf::from("/movies/Heat (1995)/Heat.mkv")
+        );
+        let mut rooted = cache.clone();
+        rooted.rooted_at(Path::new("/mnt"));
+        assert_eq!(
+            rooted.movies["Heat"].files[0].path,
+            PathBuf::from("/movies/Heat (1995)/Heat.mkv")
+        );
+    }
+
+    #[test]
+    fn merge_prefers_the_better_match() {
+        let ours = MovieEntry {
+            confidence: Some(0.6),
+            resolved_at: Some(2),
+            ..MovieEntry::new("a".to_string(), None)
+        };
+        let theirs = MovieEntry {
+            confidence: Some(0.9),
+            resolved_at: Some(1),
+            ..MovieEntry::new("b".to_string(), None)
+        };
+        assert_eq!(is_better_match(&theirs, &ours), Some(true));
+        assert_eq!(is_better_match(&ours, &theirs), Some(false));
+        let newer = MovieEntry {
+            resolved_at: Some(3),
+            ..ours.clone()
+        };
+        assert_eq!(is_better_match(&newer, &ours), Some(true));
+        assert_eq!(is_better_match(&ours, &ours.clone()), None);
+    }
+}
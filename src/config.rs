@@ -0,0 +1,257 @@
+//! Settings read from the config file.
+
+use anyhow::Context as _;
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cache::CacheFormat;
+use crate::notify::NotificationConfig;
+use crate::resolve::compile_pattern;
+use crate::scan::{check_exclude_pattern, Source, DEFAULT_EXTENSIONS};
+use crate::sync::{Target, WATCHLIST};
+
+/// Settings read from the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Regex pattern used to extract the movie names if none is given on the command line.
+    pub pattern: Option<String>,
+    /// Further patterns tried in turn if `pattern` does not match, e.g. `^\d{4} - (.+)\.`.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Extensions of the movie files in all directories without their own [default: mkv, mp4 and
+    /// avi].
+    pub extensions: Option<Vec<String>>,
+    /// Format of the cache files.
+    #[serde(default)]
+    pub cache_format: CacheFormat,
+    /// Directories synced with lists by `sync --all`.
+    #[serde(default)]
+    pub mappings: Vec<Mapping>,
+    /// Letterboxd accounts selected with `--profile` by name, like `[profiles.partner]`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Notifications of syncs which changed lists.
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    /// Names of directories by their path on this machine, like `"/mnt/nas/movies" = "movies"` on
+    /// one machine and `'D:\Movies' = "movies"` on another, so that both use the same caches of
+    /// movie ids. Folders below a directory are mapped too.
+    #[serde(default)]
+    pub path_mappings: BTreeMap<String, String>,
+}
+
+/// Credentials of a Letterboxd account. Profiles whose token is stored in the keyring by
+/// `auth login --profile` need none of them.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Username of the member, instead of `LETTERBOXD_USERNAME`.
+    pub username: Option<String>,
+    /// Name of the environment variable holding the password of the member, so the password itself
+    /// stays out of the config file.
+    pub password_env: Option<String>,
+    /// API key, instead of `LETTERBOXD_API_KEY`.
+    pub api_key: Option<String>,
+    /// API secret, instead of `LETTERBOXD_API_SECRET`.
+    pub api_secret: Option<String>,
+}
+
+/// A directory synced with a list.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mapping {
+    /// The directory to scan movies in, in any form accepted on the command line.
+    pub directory: Source,
+    /// Further directories whose movies are synced with the same list, like the movie folders of
+    /// other disks.
+    #[serde(default)]
+    pub more_directories: Vec<Source>,
+    /// ID of the list to sync the movies with. Not needed for the watchlist.
+    #[serde(default)]
+    pub list_id: String,
+    /// Whether to sync the movies with the list or the watchlist.
+    #[serde(default)]
+    pub target: Target,
+    /// Search for movies in subdirectories too.
+    #[serde(default = "default_recursive")]
+    pub recursive: bool,
+    /// Search for movies at most this many levels below the directories, 1 being the files in the
+    /// directories themselves.
+    pub max_depth: Option<usize>,
+    /// Only add films to the list, never remove any.
+    #[serde(default)]
+    pub add_only: bool,
+    /// Extensions of the movie files [default: mkv, mp4 and avi].
+    pub extensions: Option<Vec<String>>,
+    /// Gitignore-style patterns of files to skip, like `Extras/` or `*sample*`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Profile of the account owning the list. `sync --all` syncs only the mappings of the profile
+    /// given by `--profile`, or the ones without a profile if none is given.
+    pub profile: Option<String>,
+}
+
+fn default_recursive() -> bool {
+    true
+}
+
+impl Mapping {
+    /// ID of the list of the mapping, or [`WATCHLIST`] for the watchlist.
+    pub fn list(&self) -> &str {
+        match self.target {
+            Target::List => &self.list_id,
+            Target::Watchlist => WATCHLIST,
+        }
+    }
+
+    /// The directory and the further directories of the mapping.
+    pub fn directories(&self) -> impl Iterator<Item = &Source> {
+        std::iter::once(&self.directory).chain(&self.more_directories)
+    }
+
+    /// Levels below the directories to search for movies in, with no limit if `None`.
+    pub fn max_depth(&self) -> Option<usize> {
+        if self.recursive {
+            self.max_depth
+        } else {
+            Some(1)
+        }
+    }
+
+    /// Extensions of the movie files, the ones of the mapping or else the ones of the config.
+    pub fn extensions<'a>(&'a self, config: &'a Config) -> Vec<&'a str> {
+        match &self.extensions {
+            Some(extensions) => extensions.iter().map(String::as_str).collect(),
+            None => config.extensions(),
+        }
+    }
+}
+
+impl Config {
+    /// Extensions of the movie files, the configured ones or else the default ones.
+    pub fn extensions(&self) -> Vec<&str> {
+        match &self.extensions {
+            Some(extensions) => extensions.iter().map(String::as_str).collect(),
+            None => DEFAULT_EXTENSIONS.to_vec(),
+        }
+    }
+
+    /// Key of the cache of movie ids of a directory: its path or URL, whose longest prefix in
+    /// [`Config::path_mappings`] is replaced by the name it is mapped to.
+    pub fn cache_key(&self, directory: &Source) -> String {
+        let directory = directory.to_string();
+        let directory = directory.trim_end_matches('/');
+        self.path_mappings
+            .iter()
+            .filter_map(|(prefix, name)| {
+                let prefix = prefix.trim_end_matches(['/', '\\']);
+                let rest = directory.strip_prefix(prefix)?;
+                (rest.is_empty() || rest.starts_with(['/', '\\'])).then_some((prefix, name, rest))
+            })
+            .max_by_key(|(prefix, ..)| prefix.len())
+            .map(|(_, name, rest)| format!("{}{}", name, rest.replace('\\', "/")))
+            .unwrap_or_else(|| directory.to_string())
+    }
+
+    /// Check the config, returning all problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(pattern) = &self.pattern {
+            if let Err(err) = compile_pattern(pattern) {
+                problems.push(format!("pattern: {}", err));
+            }
+        }
+        for (idx, pattern) in self.patterns.iter().enumerate() {
+            if let Err(err) = compile_pattern(pattern) {
+                problems.push(format!("patterns[{}]: {}", idx, err));
+            }
+        }
+        if self.extensions.as_ref().map(Vec::is_empty) == Some(true) {
+            problems.push("extensions are empty".to_string());
+        }
+        for (idx, mapping) in self.mappings.iter().enumerate() {
+            if mapping.target == Target::List && mapping.list_id.is_empty() {
+                problems.push(format!("mappings[{}]: list_id is empty", idx));
+            }
+            if mapping.extensions.as_ref().map(Vec::is_empty) == Some(true) {
+                problems.push(format!("mappings[{}]: extensions are empty", idx));
+            }
+            for pattern in &mapping.exclude {
+                if let Err(err) = check_exclude_pattern(pattern) {
+                    problems.push(format!(
+                        "mappings[{}]: invalid exclude pattern '{}': {}",
+                        idx, pattern, err
+                    ));
+                }
+            }
+            if let Some(profile) = &mapping.profile {
+                if !self.profiles.contains_key(profile) {
+                    problems.push(format!(
+                        "mappings[{}]: profile {} is not defined in [profiles]",
+                        idx, profile
+                    ));
+                }
+            }
+            if mapping.max_depth == Some(0) {
+                problems.push(format!("mappings[{}]: max_depth must be at least 1", idx));
+            }
+            if !mapping.more_directories.is_empty()
+                && mapping.directories().any(Source::lists_movies)
+            {
+                problems.push(format!(
+                    "mappings[{}]: only directories of files can be combined",
+                    idx
+                ));
+            }
+        }
+        if let Some(webhook) = &self.notifications.webhook {
+            if let Err(err) = reqwest::Url::parse(webhook) {
+                problems.push(format!("notifications.webhook: {}", err));
+            }
+        }
+        for (prefix, name) in &self.path_mappings {
+            if name.is_empty() {
+                problems.push(format!("path_mappings.{}: the name is empty", prefix));
+            }
+        }
+        for (name, profile) in &self.profiles {
+            if profile.api_key.is_some() != profile.api_secret.is_some() {
+                problems.push(format!(
+                    "profiles.{}: api_key and api_secret must be given together",
+                    name
+                ));
+            }
+        }
+        problems
+    }
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("letterboxd-sync").join("config.toml"))
+}
+
+/// Load the config file at `path`, or at the default location if no path is given.
+///
+/// A missing config file at the default location yields the default config.
+pub fn load_config(path: Option<&Path>) -> anyhow::Result<Config> {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_config_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+    match fs::read_to_string(&path) {
+        Ok(content) => toml::from_str(&content)
+            .with_context(|| format!("failed to parse config file: {}", path.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !explicit => Ok(Config::default()),
+        Err(err) => {
+            Err(err).with_context(|| format!("failed to read config file: {}", path.display()))
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Titles embedded in the containers of movie files: the segment title of Matroska files and the
+//! title tag of MP4 files.
+//!
+//! Only the few elements leading to the title are parsed, the rest of the file is skipped.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const EBML_HEADER: u64 = 0x1A45_DFA3;
+const MATROSKA_SEGMENT: u64 = 0x1853_8067;
+const MATROSKA_INFO: u64 = 0x1549_A966;
+const MATROSKA_TITLE: u64 = 0x7BA9;
+const MATROSKA_CLUSTER: u64 = 0x1F43_B675;
+
+/// Elements of a Matroska segment read before giving up on finding its info, which usually comes
+/// right after the seek head.
+const MAX_SEGMENT_ELEMENTS: usize = 32;
+
+/// Longest title read, to not read a whole file because of a corrupt size.
+const MAX_TITLE_LEN: u64 = 1024;
+
+/// The title embedded in the Matroska or MP4 file at `path`, if it has one.
+pub fn read_container_title(path: &Path) -> io::Result<Option<String>> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+    let mut file = BufReader::new(File::open(path)?);
+    let title = match extension.as_deref() {
+        Some("mkv") | Some("mk3d") | Some("webm") => read_matroska_title(&mut file)?,
+        Some("mp4") | Some("m4v") | Some("mov") => read_mp4_title(&mut file)?,
+        _ => None,
+    };
+    Ok(title
+        .map(|title| title.trim_matches(char::from(0)).trim().to_string())
+        .filter(|title| !title.is_empty()))
+}
+
+/// Read a variable size integer of EBML, keeping the length marker for ids. Sizes with all bits
+/// set are unknown, which is returned as `u64::MAX`.
+fn read_vint(reader: &mut impl Read, keep_marker: bool) -> io::Result<u64> {
+    let mut first = [0u8; 1];
+    reader.read_exact(&mut first)?;
+    let len = first[0].leading_zeros() as usize + 1;
+    if len > 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid EBML integer",
+        ));
+    }
+    // The bits of the value in the first byte, none for integers of 8 bytes.
+    let mask = (0xFFu16 >> len) as u8;
+    let mut value = if keep_marker {
+        u64::from(first[0])
+    } else {
+        u64::from(first[0] & mask)
+    };
+    let mut all_ones = value == u64::from(mask);
+    for _ in 1..len {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        all_ones &= byte[0] == 0xFF;
+        value = (value << 8) | u64::from(byte[0]);
+    }
+    if !keep_marker && all_ones {
+        return Ok(u64::MAX);
+    }
+    Ok(value)
+}
+
+fn read_string(reader: &mut impl Read, len: u64) -> io::Result<String> {
+    let mut buf = Vec::new();
+    reader.take(len.min(MAX_TITLE_LEN)).read_to_end(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn skip(reader: &mut impl Seek, len: u64) -> io::Result<()> {
+    let len = i64::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "element too large"))?;
+    reader.seek(SeekFrom::Current(len))?;
+    Ok(())
+}
+
+fn read_matroska_title(reader: &mut (impl Read + Seek)) -> io::Result<Option<String>> {
+    if read_vint(reader, true)? != EBML_HEADER {
+        return Ok(None);
+    }
+    let size = read_vint(reader, false)?;
+    skip(reader, size)?;
+    if read_vint(reader, true)? != MATROSKA_SEGMENT {
+        return Ok(None);
+    }
+    read_vint(reader, false)?;
+
+    for _ in 0..MAX_SEGMENT_ELEMENTS {
+        let id = read_vint(reader, true)?;
+        let size = read_vint(reader, false)?;
+        match id {
+            MATROSKA_INFO => {
+                let end = reader.stream_position()?.saturating_add(size);
+                while reader.stream_position()? < end {
+                    let id = read_vint(reader, true)?;
+                    let size = read_vint(reader, false)?;
+                    if id == MATROSKA_TITLE {
+                        return read_string(reader, size).map(Some);
+                    }
+                    skip(reader, size)?;
+                }
+                return Ok(None);
+            }
+            MATROSKA_CLUSTER => return Ok(None),
+            _ => skip(reader, size)?,
+        }
+    }
+    Ok(None)
+}
+
+/// Find the box of the given type between the current position and `end`, returning the end of
+/// its content, which starts at the then current position.
+fn find_mp4_box(
+    reader: &mut (impl Read + Seek),
+    box_type: &[u8; 4],
+    end: u64,
+) -> io::Result<Option<u64>> {
+    while reader.stream_position()? + 8 <= end {
+        let start = reader.stream_position()?;
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let mut size = u64::from(u32::from_be_bytes([
+            header[0], header[1], header[2], header[3],
+        ]));
+        let mut header_len = 8;
+        if size == 1 {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = end - start;
+        }
+        if size < header_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid MP4 box",
+            ));
+        }
+        let box_end = start.saturating_add(size).min(end);
+        if &header[4..] == box_type {
+            return Ok(Some(box_end));
+        }
+        reader.seek(SeekFrom::Start(box_end))?;
+    }
+    Ok(None)
+}
+
+fn read_mp4_title(reader: &mut (impl Read + Seek)) -> io::Result<Option<String>> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let mut end = file_end;
+    for box_type in &[b"moov", b"udta", b"meta"] {
+        end = match find_mp4_box(reader, box_type, end)? {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+    }
+    // The `meta` box of MP4 files has a version and flags, the one of QuickTime files does not.
+    let mut next = [0u8; 8];
+    reader.read_exact(&mut next)?;
+    if &next[4..] == b"hdlr" {
+        reader.seek(SeekFrom::Current(-8))?;
+    } else {
+        reader.seek(SeekFrom::Current(-4))?;
+    }
+    for box_type in &[b"ilst", b"\xA9nam", b"data"] {
+        end = match find_mp4_box(reader, box_type, end)? {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+    }
+    // The value follows its type and locale.
+    skip(reader, 8)?;
+    let len = end.saturating_sub(reader.stream_position()?);
+    read_string(reader, len).map(Some)
+}
@@ -0,0 +1,380 @@
+//! Diagnostics of the setup, run by [`run_checks`] and printed by `doctor`: the config, the
+//! credentials, the stored token, the lists, the caches and the directories, each with a hint how
+//! to fix a problem.
+
+use anyhow::Context as _;
+use log::debug;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::auth::{self, TokenStore};
+use crate::cache::{cache_dir, get_cache_filename, read_movie_cache, CacheFormat};
+use crate::config::{load_config, Config};
+use crate::retry::with_retry;
+use crate::scan::Source;
+use crate::sync::Target;
+
+/// Outcome of a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Works, but may fail later or only in part.
+    Warning,
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Ok => write!(f, "ok"),
+            Status::Warning => write!(f, "warning"),
+            Status::Failed => write!(f, "FAILED"),
+        }
+    }
+}
+
+/// Result of a check, like `API key: ok`.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub status: Status,
+    /// What was checked, like `API key` or `list abc12`.
+    pub check: String,
+    pub detail: String,
+    /// How to fix the problem.
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn ok(check: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            status: Status::Ok,
+            check: check.into(),
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    pub fn warning(
+        check: impl Into<String>,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            status: Status::Warning,
+            hint: Some(hint.into()),
+            ..Self::ok(check, detail)
+        }
+    }
+
+    pub fn failed(
+        check: impl Into<String>,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            status: Status::Failed,
+            hint: Some(hint.into()),
+            ..Self::ok(check, detail)
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<8} {}: {}",
+            self.status.to_string(),
+            self.check,
+            self.detail
+        )?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n{:<8} hint: {}", "", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the token in `store`, which is refreshed before use if it expired.
+pub async fn check_token(store: &TokenStore, username: Option<&str>) -> Diagnostic {
+    const CHECK: &str = "access token";
+    match auth::token_expiry(store).await {
+        Ok(None) => Diagnostic::ok(
+            CHECK,
+            format!("none stored in {}, logging in with the password", store),
+        ),
+        Ok(Some((member, _))) if username.is_some_and(|username| username != member) => {
+            Diagnostic::warning(
+                CHECK,
+                format!("the token in {} is of member {}", store, member),
+                "the token is replaced after logging in with the password of the configured \
+                 member",
+            )
+        }
+        Ok(Some((member, expires_at))) => {
+            let expiry = chrono::DateTime::from_timestamp(expires_at, 0)
+                .map(|expiry| {
+                    expiry
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            if expires_at <= chrono::Utc::now().timestamp() {
+                Diagnostic::warning(
+                    CHECK,
+                    format!("the token of {} expired on {}", member, expiry),
+                    "the token is refreshed on the next run; if that fails, the password is needed",
+                )
+            } else {
+                Diagnostic::ok(
+                    CHECK,
+                    format!("the token of {} is valid until {}", member, expiry),
+                )
+            }
+        }
+        Err(err) => Diagnostic::failed(
+            CHECK,
+            format!("failed to read the token from {}: {:#}", store, err),
+            "run `auth logout` and log in again",
+        ),
+    }
+}
+
+/// Check that the cache of movie ids at `path` can be read and is of a supported version.
+pub fn check_movie_cache(path: &Path) -> Diagnostic {
+    let check = format!("cache {}", path.display());
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Diagnostic::ok(check, "not created yet")
+        }
+        Err(err) => {
+            return Diagnostic::failed(
+                check,
+                err.to_string(),
+                "check the permissions of the cache file",
+            )
+        }
+    };
+    match read_movie_cache(file, CacheFormat::of_path(path)) {
+        Ok(cache) => Diagnostic::ok(check, format!("{} movies", cache.movies.len())),
+        Err(err) => Diagnostic::failed(
+            check,
+            format!("{:#}", err),
+            "delete the cache with `cache clear`, it is rebuilt on the next sync",
+        ),
+    }
+}
+
+/// Check that files can be created in the cache directory `dir`.
+pub fn check_cache_dir(dir: &Path) -> Diagnostic {
+    let check = format!("cache directory {}", dir.display());
+    let probe = dir.join(".doctor");
+    let writable = fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&probe, b""))
+        .and_then(|_| fs::remove_file(&probe));
+    match writable {
+        Ok(()) => Diagnostic::ok(check, "writable"),
+        Err(err) => Diagnostic::failed(
+            check,
+            err.to_string(),
+            "check the permissions of the directory, or set XDG_CACHE_HOME to another one",
+        ),
+    }
+}
+
+/// Check that a local directory can be listed. Other sources are only checked by a scan.
+pub fn check_directory(source: &Source) -> Option<Diagnostic> {
+    let path = match source {
+        Source::Local(path) => path,
+        _ => return None,
+    };
+    let check = format!("directory {}", path.display());
+    Some(match fs::read_dir(path) {
+        Ok(_) => Diagnostic::ok(check, "readable"),
+        Err(err) => Diagnostic::failed(
+            check,
+            err.to_string(),
+            "check the path of the directory and that it is mounted and readable by this user",
+        ),
+    })
+}
+
+/// Run all checks: of the config file at `config_path`, the API key, the credentials and the
+/// stored token, the lists of the mappings and the further `lists`, the caches and the
+/// directories of the mappings.
+pub async fn run_checks(config_path: Option<&Path>, lists: &[String]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let config = match load_config(config_path) {
+        Ok(config) => {
+            let problems = config.validate();
+            if problems.is_empty() {
+                diagnostics.push(Diagnostic::ok("config", "valid"));
+            }
+            for problem in problems {
+                diagnostics.push(Diagnostic::failed(
+                    "config",
+                    problem,
+                    "fix the config file, see `config validate`",
+                ));
+            }
+            config
+        }
+        Err(err) => {
+            diagnostics.push(Diagnostic::failed(
+                "config",
+                format!("{:#}", err),
+                "fix the config file or give another one with --config",
+            ));
+            Config::default()
+        }
+    };
+
+    let api_key_pair = match auth::api_key_pair() {
+        Ok(api_key_pair) => {
+            let request = letterboxd::SearchRequest {
+                cursor: None,
+                per_page: Some(1),
+                input: "Casablanca".to_string(),
+                search_method: Some(letterboxd::SearchMethod::Autocomplete),
+                include: None,
+                contribution_type: None,
+            };
+            match letterboxd::Client::new(api_key_pair.clone())
+                .search(&request)
+                .await
+            {
+                Ok(_) => diagnostics.push(Diagnostic::ok("API key", "accepted by Letterboxd")),
+                Err(err) => diagnostics.push(Diagnostic::failed(
+                    "API key",
+                    format!("search failed: {}", err),
+                    "check LETTERBOXD_API_KEY and LETTERBOXD_API_SECRET, or the api_key and \
+                     api_secret of the profile",
+                )),
+            }
+            Some(api_key_pair)
+        }
+        Err(err) => {
+            diagnostics.push(Diagnostic::failed(
+                "API key",
+                err.to_string(),
+                "set LETTERBOXD_API_KEY and LETTERBOXD_API_SECRET, or the api_key and api_secret \
+                 of the profile",
+            ));
+            None
+        }
+    };
+
+    let (username, password) = auth::credentials();
+    let keyring_username = auth::keyring_username().await.unwrap_or_else(|err| {
+        debug!("Not using the keyring: {:#}", err);
+        None
+    });
+    let keyring = keyring_username
+        .as_ref()
+        .is_some_and(|keyring| username.as_ref().is_none_or(|username| username == keyring));
+    let member = if keyring { keyring_username } else { username };
+    match &member {
+        Some(member) => diagnostics.push(Diagnostic::ok("username", member.as_str())),
+        None => diagnostics.push(Diagnostic::warning(
+            "username",
+            "not given, so only films and public lists can be read",
+            "set LETTERBOXD_USERNAME and LETTERBOXD_PASSWORD, or run `auth login`",
+        )),
+    }
+    let token_store = if keyring {
+        Some(TokenStore::Keyring)
+    } else {
+        auth::default_token_path().map(TokenStore::File)
+    };
+    if let (Some(_), Some(store)) = (&member, &token_store) {
+        let token = check_token(store, member.as_deref()).await;
+        if token.status != Status::Ok && password.is_none() {
+            diagnostics.push(Diagnostic::warning(
+                "password",
+                "not given",
+                "set LETTERBOXD_PASSWORD, or the variable named by password_env of the profile",
+            ));
+        }
+        diagnostics.push(token);
+    }
+
+    let client = match (&api_key_pair, &member) {
+        (Some(_), Some(_)) => {
+            let account = match auth::new_client().await {
+                Ok(client) => with_retry(|| client.me())
+                    .await
+                    .map(|account| (client, account))
+                    .context("failed to fetch the authenticated member"),
+                Err(err) => Err(err),
+            };
+            match account {
+                Ok((client, account)) => {
+                    diagnostics.push(Diagnostic::ok(
+                        "authentication",
+                        format!("authenticated as {}", account.member.username),
+                    ));
+                    Some(client)
+                }
+                Err(err) => {
+                    diagnostics.push(Diagnostic::failed(
+                        "authentication",
+                        format!("{:#}", err),
+                        "check LETTERBOXD_USERNAME and LETTERBOXD_PASSWORD, or run `auth login`",
+                    ));
+                    None
+                }
+            }
+        }
+        (Some(api_key_pair), None) => Some(letterboxd::Client::new(api_key_pair.clone())),
+        (None, _) => None,
+    };
+
+    let list_ids: Vec<&str> = config
+        .mappings
+        .iter()
+        .filter(|mapping| mapping.target == Target::List)
+        .map(|mapping| mapping.list_id.as_str())
+        .chain(lists.iter().map(String::as_str))
+        .collect();
+    if let Some(client) = &client {
+        for list_id in list_ids {
+            let check = format!("list {}", list_id);
+            match with_retry(|| client.list(list_id)).await {
+                Ok(list) => diagnostics.push(Diagnostic::ok(
+                    check,
+                    format!("{} ({} films)", list.name, list.film_count),
+                )),
+                Err(err) => diagnostics.push(Diagnostic::failed(
+                    check,
+                    format!("{:#}", err),
+                    "check the list id, the part after `boxd.it/` of its short URL; private \
+                     lists can only be read with the credentials of their owner",
+                )),
+            }
+        }
+    }
+
+    match cache_dir() {
+        Ok(dir) => diagnostics.push(check_cache_dir(&dir)),
+        Err(err) => diagnostics.push(Diagnostic::failed(
+            "cache directory",
+            err.to_string(),
+            "set XDG_CACHE_HOME to a writable directory",
+        )),
+    }
+    for mapping in &config.mappings {
+        for directory in mapping.directories() {
+            diagnostics.extend(check_directory(directory));
+            if let Ok(path) = get_cache_filename(config.cache_format, &config.cache_key(directory))
+            {
+                diagnostics.push(check_movie_cache(&path));
+            }
+        }
+    }
+
+    diagnostics
+}
@@ -0,0 +1,129 @@
+//! Films with several copies in a library, like a 1080p and a 2160p release of the same movie, to
+//! reclaim disk space.
+
+use anyhow::Context as _;
+use regex::Regex;
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::metadata::{find_release_tag, ReleaseTag};
+use crate::resolve::ResolvedFilms;
+use crate::scan::MovieFile;
+
+/// A film whose movie was found in several copies.
+#[derive(Debug, Serialize)]
+pub struct Duplicate {
+    pub film_id: String,
+    /// Name of the movie as guessed from the files.
+    pub movie: String,
+    /// All files of the film, ordered by path.
+    pub files: Vec<DuplicateFile>,
+}
+
+/// A file of a [`Duplicate`].
+#[derive(Debug, Serialize)]
+pub struct DuplicateFile {
+    pub path: PathBuf,
+    /// Resolution and source in the file name, like `1080p BluRay`.
+    pub quality: Option<String>,
+    /// Size in bytes, unless unknown like on SFTP hosts.
+    pub size: Option<u64>,
+}
+
+/// Row of the duplicates CSV file, one per file.
+#[derive(Debug, Serialize)]
+struct DuplicateRow<'a> {
+    film_id: &'a str,
+    movie: &'a str,
+    path: String,
+    quality: Option<&'a str>,
+    size: Option<u64>,
+}
+
+/// Part number in the name of a file of a multi-part movie, like `CD1` or `part2`.
+pub(crate) fn part_number(path: &Path) -> Option<u32> {
+    static PART: OnceLock<Regex> = OnceLock::new();
+    let part = PART.get_or_init(|| {
+        Regex::new(
+            r"(?i)(?:^|[ ._\-\[(])(?:cd|dvd|dis[ck]|part|pt)[ ._-]?(\d{1,2})(?:$|[ ._\-\])])",
+        )
+        .expect("valid pattern")
+    });
+    let stem = path.file_stem()?.to_str()?;
+    part.captures(stem)?.get(1)?.as_str().parse().ok()
+}
+
+/// Number of copies of a movie in its files. The parts of a multi-part movie make up one copy.
+fn copies(files: &[&MovieFile]) -> usize {
+    let mut whole = 0;
+    let mut parts: HashMap<u32, usize> = HashMap::new();
+    for file in files {
+        match part_number(&file.path) {
+            Some(part) => *parts.entry(part).or_default() += 1,
+            None => whole += 1,
+        }
+    }
+    whole + parts.into_values().max().unwrap_or(0)
+}
+
+fn quality(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let tags: Vec<String> = [ReleaseTag::Resolution, ReleaseTag::Source]
+        .iter()
+        .filter_map(|kind| find_release_tag(name, *kind))
+        .collect();
+    Some(tags.join(" ")).filter(|tags| !tags.is_empty())
+}
+
+/// The resolved films with more than one copy, ordered by movie.
+pub fn find_duplicates(resolved: &ResolvedFilms) -> Vec<Duplicate> {
+    let movies: HashMap<&str, &str> = resolved
+        .ids
+        .iter()
+        .map(|(movie, id)| (id.as_str(), movie.as_str()))
+        .collect();
+    let mut duplicates: Vec<Duplicate> = resolved
+        .files_by_film_id()
+        .into_iter()
+        .filter(|(_, files)| copies(files) > 1)
+        .map(|(id, mut files)| {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            files.dedup_by(|a, b| a.path == b.path);
+            Duplicate {
+                film_id: id.to_string(),
+                movie: movies.get(id).copied().unwrap_or(id).to_string(),
+                files: files
+                    .into_iter()
+                    .map(|file| DuplicateFile {
+                        quality: quality(&file.path),
+                        path: file.path.clone(),
+                        size: file.size,
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    duplicates.sort_by(|a, b| a.movie.cmp(&b.movie));
+    duplicates
+}
+
+/// Write the files of the duplicates to a CSV file at `path`, one row per file.
+pub fn write_duplicates(duplicates: &[Duplicate], path: &Path) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_path(path)
+        .with_context(|| format!("failed to write duplicates: {}", path.display()))?;
+    for duplicate in duplicates {
+        for file in &duplicate.files {
+            writer.serialize(DuplicateRow {
+                film_id: &duplicate.film_id,
+                movie: &duplicate.movie,
+                path: file.path.display().to_string(),
+                quality: file.quality.as_deref(),
+                size: file.size,
+            })?;
+        }
+    }
+    Ok(writer.flush()?)
+}
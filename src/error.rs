@@ -0,0 +1,117 @@
+//! Classification of Letterboxd API errors into actionable messages and exit codes.
+
+use std::fmt;
+
+/// Exit code of failures which are not caused by the Letterboxd API.
+pub const EXIT_FAILURE: i32 = 1;
+
+/// A sync which did not fail but left files unmatched or only updated some of the lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncIncomplete {
+    /// The lists were updated, but some files could not be matched to films.
+    Unmatched(usize),
+    /// More files than allowed could not be matched to films, so the list was not updated.
+    TooManyUnmatched { count: usize, max: usize },
+    /// Some of several mappings failed.
+    MappingsFailed { failed: usize, total: usize },
+    /// The updated list does not show some of the changes, see `--verify`.
+    NotApplied(usize),
+    /// Some movies were not searched as the budget of requests was spent, see `--max-api-calls`,
+    /// so the list was not updated.
+    BudgetSpent(usize),
+}
+
+impl SyncIncomplete {
+    /// Exit code of the process ending with this outcome.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            SyncIncomplete::Unmatched(_) => 2,
+            SyncIncomplete::TooManyUnmatched { .. } => 7,
+            SyncIncomplete::MappingsFailed { .. } => 8,
+            SyncIncomplete::NotApplied(_) => 9,
+            SyncIncomplete::BudgetSpent(_) => 10,
+        }
+    }
+}
+
+impl fmt::Display for SyncIncomplete {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncIncomplete::Unmatched(count) => {
+                write!(f, "{} files could not be matched to films", count)
+            }
+            SyncIncomplete::TooManyUnmatched { count, max } => write!(
+                f,
+                "{} files could not be matched to films, more than the maximum of {}, list was not \
+                 updated",
+                count, max
+            ),
+            SyncIncomplete::MappingsFailed { failed, total } => {
+                write!(f, "{} of {} mappings failed", failed, total)
+            }
+            SyncIncomplete::NotApplied(count) => {
+                write!(f, "{} changes were not applied to the list", count)
+            }
+            SyncIncomplete::BudgetSpent(count) => write!(
+                f,
+                "{} movies were not searched as the budget of requests was spent, list was not \
+                 updated",
+                count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SyncIncomplete {}
+
+/// A failure of the Letterboxd API the user can act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiFailure {
+    /// The API key or secret is invalid.
+    ApiKeyRejected,
+    /// The username or password is wrong.
+    CredentialsRejected,
+    /// The list does not exist or cannot be edited by the authenticated member.
+    ListNotFound,
+    /// Too many requests were sent.
+    RateLimited,
+    /// Letterboxd failed to answer.
+    Unavailable,
+}
+
+impl ApiFailure {
+    /// Find the first Letterboxd API error in the chain of causes of `err` and classify it.
+    pub fn find(err: &anyhow::Error) -> Option<Self> {
+        err.chain()
+            .filter_map(|cause| cause.downcast_ref::<letterboxd::Error>())
+            .find_map(Self::classify)
+    }
+
+    fn classify(err: &letterboxd::Error) -> Option<Self> {
+        match err {
+            letterboxd::Error::Server { code, content } => match code {
+                // OAuth errors name the rejected part of the credentials.
+                400 | 401 if content.contains("invalid_grant") => {
+                    Some(ApiFailure::CredentialsRejected)
+                }
+                400 | 401 if content.contains("invalid_client") => Some(ApiFailure::ApiKeyRejected),
+                401 => Some(ApiFailure::ApiKeyRejected),
+                403 | 404 => Some(ApiFailure::ListNotFound),
+                429 => Some(ApiFailure::RateLimited),
+                500..=599 => Some(ApiFailure::Unavailable),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Exit code of the process failing with this error.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ApiFailure::ApiKeyRejected | ApiFailure::CredentialsRejected => 3,
+            ApiFailure::ListNotFound => 4,
+            ApiFailure::RateLimited => 5,
+            ApiFailure::Unavailable => 6,
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Export of films to other services.
+
+use anyhow::anyhow;
+use log::debug;
+use serde::Serialize;
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::films::FilmMetadata;
+use crate::resolve::{MatchSource, ResolvedFilms};
+
+/// Row of the CSV accepted by the IMDb list import.
+#[derive(Debug, Serialize)]
+pub struct ImdbListRow {
+    #[serde(rename = "Const")]
+    pub imdb_id: Option<String>,
+    #[serde(rename = "Title")]
+    pub title: String,
+    #[serde(rename = "Year")]
+    pub year: Option<u16>,
+}
+
+impl From<&FilmMetadata> for ImdbListRow {
+    fn from(film: &FilmMetadata) -> Self {
+        Self {
+            imdb_id: film.imdb_id.clone(),
+            title: film.name.clone(),
+            year: film.year,
+        }
+    }
+}
+
+/// Rows of the resolved films, one per film, ordered by title. `films` has the details of the
+/// films. IMDb ids unknown to Letterboxd are taken from the NFO files or file names of the movies.
+pub fn imdb_list_rows(
+    resolved: &ResolvedFilms,
+    films: &HashMap<String, FilmMetadata>,
+) -> Vec<ImdbListRow> {
+    let mut seen = HashSet::new();
+    let mut rows: Vec<ImdbListRow> = resolved
+        .ids
+        .iter()
+        .filter(|(_, id)| seen.insert(id.as_str()))
+        .filter_map(|(_, id)| {
+            let mut row = ImdbListRow::from(films.get(id)?);
+            if row.imdb_id.is_none() {
+                row.imdb_id = resolved
+                    .ids
+                    .iter()
+                    .filter(|(_, other)| *other == id)
+                    .find_map(|(movie, _)| resolved.imdb_ids.get(movie))
+                    .cloned();
+            }
+            if row.imdb_id.is_none() {
+                debug!("No IMDb id known for film: {}", row.title);
+            }
+            Some(row)
+        })
+        .collect();
+    rows.sort_by(|a, b| a.title.cmp(&b.title));
+    rows
+}
+
+pub fn write_imdb_list(rows: &[ImdbListRow], path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(writer.flush()?)
+}
+
+/// Row of the CSV accepted by the Letterboxd import, which matches films by the Letterboxd URI, or
+/// else by the IMDb id, or else by title and year.
+#[derive(Debug, Serialize)]
+pub struct LetterboxdImportRow {
+    #[serde(rename = "LetterboxdURI")]
+    pub letterboxd_uri: Option<String>,
+    #[serde(rename = "imdbID")]
+    pub imdb_id: Option<String>,
+    #[serde(rename = "Title")]
+    pub title: String,
+    #[serde(rename = "Year")]
+    pub year: Option<u16>,
+}
+
+impl LetterboxdImportRow {
+    /// Row of a film by its id, whose `boxd.it` short link is its Letterboxd URI.
+    pub fn film(
+        film_id: &str,
+        metadata: Option<&FilmMetadata>,
+        title: &str,
+        year: Option<u16>,
+    ) -> Self {
+        Self {
+            letterboxd_uri: Some(format!("https://boxd.it/{}", film_id)),
+            imdb_id: metadata.and_then(|film| film.imdb_id.clone()),
+            title: metadata.map_or(title, |film| &film.name).to_string(),
+            year: metadata.and_then(|film| film.year).or(year),
+        }
+    }
+}
+
+pub fn write_letterboxd_import(
+    rows: &[LetterboxdImportRow],
+    writer: impl io::Write,
+) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(writer.flush()?)
+}
+
+/// Format of an exported list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub const VARIANTS: &'static [&'static str] = &["csv", "json"];
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(anyhow!("unknown export format: {}", s)),
+        }
+    }
+}
+
+/// An entry of a Letterboxd list.
+#[derive(Debug, Serialize)]
+pub struct ListEntryRow {
+    /// Position of the entry in the list, starting at 1.
+    pub position: usize,
+    pub title: String,
+    pub year: Option<u16>,
+    pub film_id: String,
+    pub url: String,
+}
+
+impl ListEntryRow {
+    /// Row of the entry at the 0-based `index` of the list, at its rank in ranked lists.
+    pub fn new(index: usize, entry: &letterboxd::ListEntry) -> Self {
+        let film = &entry.film;
+        let url = film
+            .links
+            .iter()
+            .find(|link| link.type_ == letterboxd::LinkType::Letterboxd)
+            .map(|link| link.url.clone())
+            .unwrap_or_else(|| format!("https://boxd.it/{}", film.id));
+        Self {
+            position: entry.rank.unwrap_or(index + 1),
+            title: film.name.clone(),
+            year: film.release_year,
+            film_id: film.id.clone(),
+            url,
+        }
+    }
+}
+
+pub fn write_list_entries(
+    rows: &[ListEntryRow],
+    format: ExportFormat,
+    mut writer: impl io::Write,
+) -> anyhow::Result<()> {
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for row in rows {
+                writer.serialize(row)?;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, rows)?;
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Films of two lists, compared by film id.
+#[derive(Debug, Serialize)]
+pub struct ListDiff {
+    pub only_in_a: Vec<ListEntryRow>,
+    pub only_in_b: Vec<ListEntryRow>,
+    /// Films in both lists, at their positions in the first list.
+    pub in_both: Vec<ListEntryRow>,
+}
+
+impl ListDiff {
+    pub fn new(a: &[letterboxd::ListEntry], b: &[letterboxd::ListEntry]) -> Self {
+        let ids_a: HashSet<&str> = a.iter().map(|entry| entry.film.id.as_str()).collect();
+        let ids_b: HashSet<&str> = b.iter().map(|entry| entry.film.id.as_str()).collect();
+        let rows = |entries: &[letterboxd::ListEntry], keep: &dyn Fn(&str) -> bool| {
+            entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| keep(&entry.film.id))
+                .map(|(idx, entry)| ListEntryRow::new(idx, entry))
+                .collect()
+        };
+        Self {
+            only_in_a: rows(a, &|id| !ids_b.contains(id)),
+            only_in_b: rows(b, &|id| !ids_a.contains(id)),
+            in_both: rows(a, &|id| ids_b.contains(id)),
+        }
+    }
+}
+
+/// How a scanned file was matched to a film, written by `sync --write-mapping`.
+#[derive(Debug, Serialize)]
+pub struct FileMappingRow {
+    pub file: String,
+    /// Title of the movie guessed from the file, unless none was found.
+    pub title: Option<String>,
+    /// Release year of the movie guessed from the file.
+    pub year: Option<u16>,
+    /// How the film was found, unless the movie was not resolved.
+    pub source: Option<MatchSource>,
+    pub film_id: Option<String>,
+    pub film_title: Option<String>,
+    pub film_year: Option<u16>,
+    /// Confidence of the match, unless the film was taken from the cache.
+    pub confidence: Option<f64>,
+    /// Why the movie was not resolved.
+    pub reason: Option<String>,
+}
+
+/// Rows of all scanned files of `resolved`, ordered by path. The titles and years of the films
+/// are taken from `films`.
+pub fn file_mapping_rows(
+    resolved: &ResolvedFilms,
+    films: &HashMap<String, FilmMetadata>,
+) -> Vec<FileMappingRow> {
+    let reasons: HashMap<String, String> = resolved.unresolved().into_iter().collect();
+    let reasons = &reasons;
+    let guessed = resolved.files.iter().flat_map(|(movie, files)| {
+        let id = resolved.ids.get(movie);
+        let film = id.and_then(|id| films.get(id));
+        files.iter().map(move |file| FileMappingRow {
+            file: file.path.display().to_string(),
+            title: Some(movie.clone()),
+            year: resolved.years.get(movie).copied(),
+            source: resolved.sources.get(movie).copied(),
+            film_id: id.cloned(),
+            film_title: film.map(|film| film.name.clone()),
+            film_year: film.and_then(|film| film.year),
+            confidence: resolved.confidences.get(movie).copied(),
+            reason: reasons.get(movie).cloned(),
+        })
+    });
+    let unguessed = resolved.unguessed.iter().map(|file| FileMappingRow {
+        file: file.path.display().to_string(),
+        title: None,
+        year: None,
+        source: None,
+        film_id: None,
+        film_title: None,
+        film_year: None,
+        confidence: None,
+        reason: Some("no movie name found".to_string()),
+    });
+    let mut rows: Vec<FileMappingRow> = guessed.chain(unguessed).collect();
+    rows.sort_by(|a, b| a.file.cmp(&b.file));
+    rows
+}
+
+pub fn write_file_mapping(rows: &[FileMappingRow], path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(writer.flush()?)
+}
@@ -0,0 +1,245 @@
+//! Details, ratings and watch status of Letterboxd films.
+
+use anyhow::anyhow;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::retry::with_retry;
+use crate::throttle::max_concurrency;
+
+/// Details of a film, cached to avoid fetching them on every run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilmMetadata {
+    pub name: String,
+    pub year: Option<u16>,
+    #[serde(default)]
+    pub directors: Vec<String>,
+    /// Runtime in minutes.
+    pub runtime: Option<u16>,
+    pub imdb_id: Option<String>,
+}
+
+impl From<letterboxd::Film> for FilmMetadata {
+    fn from(film: letterboxd::Film) -> Self {
+        let imdb_id = film
+            .links
+            .into_iter()
+            .find(|link| link.type_ == letterboxd::LinkType::Imdb)
+            .map(|link| link.id);
+        Self {
+            name: film.name,
+            year: film.release_year,
+            directors: film.directors.into_iter().map(|d| d.name).collect(),
+            runtime: film.run_time,
+            imdb_id,
+        }
+    }
+}
+
+impl FilmMetadata {
+    /// Name of the film with the release year, like `Alien (1979)`.
+    pub fn title(&self) -> String {
+        match self.year {
+            Some(year) => format!("{} ({})", self.name, year),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Fetch the details of the given films which are not in the cache yet, adding them to it.
+pub async fn fetch_film_metadata<'a>(
+    ids: impl IntoIterator<Item = &'a String>,
+    cache: &mut HashMap<String, FilmMetadata>,
+    client: &letterboxd::Client,
+) -> letterboxd::Result<()> {
+    let missing: HashSet<&String> = ids
+        .into_iter()
+        .filter(|id| !cache.contains_key(*id))
+        .collect();
+    let film_requests = missing.into_iter().map(|id| async move {
+        let film = with_retry(|| client.film(id)).await?;
+        Ok((id.clone(), FilmMetadata::from(film)))
+    });
+    let fetched: Vec<(String, FilmMetadata)> = stream::iter(film_requests)
+        .buffer_unordered(max_concurrency())
+        .try_collect()
+        .await?;
+    cache.extend(fetched);
+    Ok(())
+}
+
+/// Fetch the ids of all films the authenticated member has watched.
+pub async fn fetch_watched_films(
+    client: &letterboxd::Client,
+) -> letterboxd::Result<HashSet<String>> {
+    let me = with_retry(|| client.me()).await?;
+    let mut request = letterboxd::FilmsRequest {
+        per_page: Some(100),
+        member: Some(me.member.id),
+        member_relationship: Some(letterboxd::FilmMemberRelationship::Watched),
+        ..Default::default()
+    };
+    let mut ids = HashSet::new();
+    loop {
+        let response = with_retry(|| client.films(&request)).await?;
+        ids.extend(response.items.into_iter().map(|film| film.id));
+        request.cursor = response.next;
+        if request.cursor.is_none() {
+            break;
+        }
+    }
+    Ok(ids)
+}
+
+/// Mark the given films as watched by the authenticated member.
+pub async fn mark_watched(ids: &[String], client: &letterboxd::Client) -> letterboxd::Result<()> {
+    let requests = ids.iter().map(|id| async move {
+        let request = letterboxd::FilmRelationshipUpdateRequest {
+            watched: Some(true),
+            ..Default::default()
+        };
+        with_retry(|| client.update_film_relationship(id, &request)).await
+    });
+    stream::iter(requests)
+        .buffer_unordered(max_concurrency())
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(())
+}
+
+/// Log the given films as watched today by the authenticated member, as diary entries with the
+/// tags given by film id.
+pub async fn log_watched(
+    tags: &HashMap<String, Vec<String>>,
+    client: &letterboxd::Client,
+) -> letterboxd::Result<()> {
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let requests = tags.iter().map(|(id, tags)| {
+        let request = letterboxd::LogEntryCreationRequest {
+            film_id: id.clone(),
+            diary_details: Some(letterboxd::LogEntryCreationRequestDiaryDetails {
+                diary_date: today.clone(),
+                ..Default::default()
+            }),
+            tags: Some(tags.clone()).filter(|tags| !tags.is_empty()),
+            ..Default::default()
+        };
+        async move { with_retry(|| client.create_log_entry(&request)).await }
+    });
+    stream::iter(requests)
+        .buffer_unordered(max_concurrency())
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(())
+}
+
+/// Order of the entries of a synced list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// By average rating of the Letterboxd members, descending.
+    Rating,
+    /// By name, and then by release year.
+    Title,
+    /// By release year, and then by name.
+    Year,
+    /// In the order the films were added to the list, films added by a sync following in the
+    /// order of the modification times of their files.
+    Added,
+    /// By the modification time of the newest file of the film, descending, like a list of
+    /// recently added movies.
+    FileMtime,
+}
+
+impl SortOrder {
+    pub const VARIANTS: &'static [&'static str] =
+        &["rating", "title", "year", "added", "file-mtime"];
+
+    /// Whether sorting needs the details of the films, see [`fetch_film_metadata`].
+    pub fn needs_metadata(self) -> bool {
+        matches!(self, SortOrder::Title | SortOrder::Year)
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "rating" => Ok(SortOrder::Rating),
+            "title" => Ok(SortOrder::Title),
+            "year" => Ok(SortOrder::Year),
+            "added" => Ok(SortOrder::Added),
+            "file-mtime" => Ok(SortOrder::FileMtime),
+            _ => Err(anyhow!("unknown sort order: {}", s)),
+        }
+    }
+}
+
+/// Fetch the average ratings of the given films. Films without rating are left out.
+pub async fn fetch_ratings(
+    ids: impl IntoIterator<Item = String>,
+    client: &letterboxd::Client,
+) -> letterboxd::Result<HashMap<String, f32>> {
+    let statistics_requests = ids.into_iter().map(|id| async move {
+        let statistics = with_retry(|| client.film_statistics(&id)).await?;
+        Ok(statistics.rating.map(|rating| (id, rating)))
+    });
+    stream::iter(statistics_requests)
+        .buffer_unordered(max_concurrency())
+        .filter_map(|res| std::future::ready(res.transpose()))
+        .try_collect()
+        .await
+}
+
+/// Sort the given films in the given order.
+///
+/// `ratings` are the average ratings by film id, needed for [`SortOrder::Rating`], `metadata` the
+/// details of the films, needed for [`SortOrder::Title`] and [`SortOrder::Year`], and `modified`
+/// the modification times of the newest files of the films, needed for [`SortOrder::Added`] and
+/// [`SortOrder::FileMtime`]. Films without the value they are sorted by go last.
+pub fn sort_films(
+    ids: &HashSet<String>,
+    order: SortOrder,
+    ratings: &HashMap<String, f32>,
+    metadata: &HashMap<String, FilmMetadata>,
+    modified: &HashMap<&str, i64>,
+) -> Vec<String> {
+    let mut sorted: Vec<String> = ids.iter().cloned().collect();
+    let name = |id: &String| metadata.get(id).map(|film| film.name.to_lowercase());
+    let year = |id: &String| metadata.get(id).and_then(|film| film.year);
+    let modified = |id: &String| modified.get(id.as_str()).copied();
+    match order {
+        SortOrder::Rating => {
+            sorted.sort_by(|a, b| {
+                let rating_a = ratings.get(a).copied().unwrap_or(f32::MIN);
+                let rating_b = ratings.get(b).copied().unwrap_or(f32::MIN);
+                rating_b.total_cmp(&rating_a).then_with(|| a.cmp(b))
+            });
+        }
+        SortOrder::Title => {
+            sorted.sort_by_cached_key(|id| (name(id).is_none(), name(id), year(id), id.clone()))
+        }
+        SortOrder::Year => {
+            sorted.sort_by_cached_key(|id| (year(id).is_none(), year(id), name(id), id.clone()))
+        }
+        SortOrder::Added => {
+            sorted.sort_by_key(|id| (modified(id).is_none(), modified(id), id.clone()))
+        }
+        SortOrder::FileMtime => {
+            sorted.sort_by_key(|id| (modified(id).is_none(), Reverse(modified(id)), id.clone()))
+        }
+    }
+    sorted
+}
+
+/// Format a film as title with release year for reports.
+pub fn film_title(film: &letterboxd::FilmSummary) -> String {
+    match film.release_year {
+        Some(year) => format!("{} ({})", film.name, year),
+        None => film.name.clone(),
+    }
+}
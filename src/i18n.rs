@@ -0,0 +1,831 @@
+//! Translations of user-facing messages.
+//!
+//! Every message is a variant of [`Message`] and is rendered in the locale selected at startup with
+//! [`set_locale`]. Debug output and error contexts stay in English.
+
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+use crate::error::ApiFailure;
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    En,
+    De,
+}
+
+impl Locale {
+    pub const VARIANTS: &'static [&'static str] = &["en", "de"];
+
+    /// Select the locale from `LC_ALL`, `LC_MESSAGES` or `LANG`, e.g. `de_DE.UTF-8`.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(Locale::En)
+    }
+}
+
+impl FromStr for Locale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s.split(['_', '-', '.']).next().unwrap_or_default();
+        match language.to_lowercase().as_str() {
+            "en" | "c" | "posix" => Ok(Locale::En),
+            "de" => Ok(Locale::De),
+            _ => Err(anyhow!("unsupported locale: {}", s)),
+        }
+    }
+}
+
+/// Set the locale of all messages. Only the first call has an effect.
+pub fn set_locale(locale: Locale) {
+    let _ = LOCALE.set(locale);
+}
+
+fn locale() -> Locale {
+    LOCALE.get().copied().unwrap_or(Locale::En)
+}
+
+/// A user-facing message.
+pub enum Message<'a> {
+    ConvertingCache(&'a str),
+    CorruptCache {
+        path: &'a str,
+        error: &'a str,
+        backup: &'a str,
+    },
+    MovieNotFound(&'a str),
+    PinnedFilmNotFound(&'a str),
+    LowConfidence {
+        movie: &'a str,
+        film: &'a str,
+        confidence: f64,
+    },
+    LanguageNotesLocalOnly,
+    MergedCache {
+        added: usize,
+        conflicts: usize,
+        total: usize,
+    },
+    CacheConflict {
+        movie: &'a str,
+        ours: &'a str,
+        theirs: &'a str,
+    },
+    Owned {
+        count: usize,
+        total: usize,
+    },
+    Missing {
+        count: usize,
+        total: usize,
+    },
+    UpdatingList {
+        list_id: &'a str,
+        to_add: usize,
+        to_remove: usize,
+        reordered: bool,
+        total: usize,
+    },
+    DryRun,
+    ListUpToDate(&'a str),
+    AmbiguousMatch {
+        movie: &'a str,
+        reason: &'a str,
+    },
+    ExportedImdbList {
+        count: usize,
+        path: &'a str,
+    },
+    ExcludingWatched(usize),
+    ConfigValid,
+    ApiFailure(ApiFailure),
+    NoMatch,
+    ToAdd(usize),
+    ToRemove(usize),
+    Authenticated(&'a str),
+    CacheCleared(&'a str),
+    SyncingMapping {
+        directory: &'a str,
+        list_id: &'a str,
+    },
+    RemovalsToConfirm {
+        list_id: &'a str,
+        count: usize,
+    },
+    ConfirmRemovals,
+    RemovalsNotConfirmed(usize),
+    PickFilm(&'a str, &'a str),
+    PickFilmPrompt(usize),
+    NotWatched(&'a str),
+    Watching(usize),
+    CreatingList(&'a str),
+    PlanSaved {
+        count: usize,
+        path: &'a str,
+    },
+    NothingUnresolved,
+    RetriedUnresolved {
+        resolved: usize,
+        remaining: usize,
+        path: &'a str,
+    },
+    ResolveErrors(usize),
+    KeepingRemovals(usize),
+    RetryingRequest {
+        error: &'a str,
+        delay: Duration,
+        attempt: u32,
+        max_attempts: u32,
+    },
+    ScanningDirectory(&'a str),
+    ResolvingMovies {
+        cached: usize,
+        looked_up: usize,
+    },
+    FetchingList {
+        list_id: &'a str,
+        count: usize,
+    },
+    ImportingCache(&'a str),
+    VerifyingCache,
+    CachedFilmDeleted {
+        movie: &'a str,
+        film_id: &'a str,
+    },
+    CachedFilmMerged {
+        movie: &'a str,
+        film_id: &'a str,
+        new_id: &'a str,
+    },
+    VerifiedCache {
+        checked: usize,
+        stale: usize,
+        failed: usize,
+    },
+    PrunedCache {
+        removed: usize,
+        total: usize,
+    },
+    FingerprintingFiles,
+    FoundMovedFiles(usize),
+    ExportedLibrary {
+        count: usize,
+        matched: usize,
+        path: &'a str,
+    },
+    ExportedList {
+        count: usize,
+        path: &'a str,
+    },
+    FoundOnTmdb {
+        movie: &'a str,
+        film: &'a str,
+    },
+    YearMismatch {
+        movie: &'a str,
+        year: u16,
+        film: &'a str,
+    },
+    UsernamePrompt,
+    PasswordPrompt,
+    LoggedIn(&'a str),
+    LoggedOut,
+    NotLoggedIn,
+    ListeningForWebhooks(&'a str),
+    MovieAdded(&'a str),
+    MovieDeleted(&'a str),
+    MovieRenamed(&'a str),
+    NextSync(Duration),
+    StoppingAfterSync,
+    Stopped,
+    OnlyInList(&'a str),
+    InBothLists,
+    MarkingWatched(usize),
+    SettingRatings(usize),
+    StatsFilms {
+        films: usize,
+        unresolved: usize,
+    },
+    StatsDecades,
+    Decade(u16),
+    StatsTopDirectors,
+    StatsRuntime {
+        average: f64,
+        total_hours: u64,
+    },
+    StatsWatched {
+        watched: usize,
+        films: usize,
+    },
+    SkippedEpisodes(usize),
+    LooksLikeEpisode(&'a str),
+    DuplicateFilms(usize),
+    DuplicatesSaved {
+        count: usize,
+        path: &'a str,
+    },
+    ResumingSearch(usize),
+    AppliedChunk {
+        done: usize,
+        total: usize,
+    },
+    VerifyingList(&'a str),
+    Unapplied {
+        list_id: &'a str,
+        count: usize,
+    },
+    MappingWritten {
+        count: usize,
+        path: &'a str,
+    },
+    ReviewPageWritten {
+        count: usize,
+        path: &'a str,
+    },
+    ConfirmUpdate(&'a str),
+    UpdateNotConfirmed(&'a str),
+    UpdateDeclined(&'a str),
+    RestoringSnapshot {
+        list_id: &'a str,
+        taken: &'a str,
+    },
+    ApiCalls(u64),
+    ApiBudgetSpent {
+        max: u64,
+        skipped: usize,
+    },
+    AnonymousClient,
+    DoctorPassed,
+    RenameSuggestions(usize),
+    FilesRenamed(usize),
+    RenamesLocalOnly,
+}
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match locale() {
+            Locale::En => self.fmt_en(f),
+            Locale::De => self.fmt_de(f),
+        }
+    }
+}
+
+impl Message<'_> {
+    fn fmt_en(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::ConvertingCache(path) => {
+                write!(f, "Converting cache {} to binary format.", path)
+            }
+            Message::CorruptCache {
+                path,
+                error,
+                backup,
+            } => write!(
+                f,
+                "Cache file {} is corrupt ({}), moved it to {} and starting with an empty cache.",
+                path, error, backup
+            ),
+            Message::MovieNotFound(title) => write!(f, "Did not find id for movie: {}", title),
+            Message::PinnedFilmNotFound(film) => {
+                write!(f, "Did not find film {} pinned by the overrides.", film)
+            }
+            Message::LowConfidence {
+                movie,
+                film,
+                confidence,
+            } => write!(
+                f,
+                "Skipping {}: best match {} has a confidence of only {:.2}.",
+                movie, film, confidence
+            ),
+            Message::LanguageNotesLocalOnly => {
+                write!(
+                    f,
+                    "Language notes are only supported for local directories."
+                )
+            }
+            Message::MergedCache {
+                added,
+                conflicts,
+                total,
+            } => write!(
+                f,
+                "Merged cache: {} added, {} conflicts, total movies: {}",
+                added, conflicts, total
+            ),
+            Message::CacheConflict {
+                movie,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "Conflicting ids for movie {}: {} (ours), {} (theirs)",
+                movie, ours, theirs
+            ),
+            Message::Owned { count, total } => write!(f, "Owned ({}/{}):", count, total),
+            Message::Missing { count, total } => write!(f, "Missing ({}/{}):", count, total),
+            Message::UpdatingList {
+                list_id,
+                to_add,
+                to_remove,
+                reordered,
+                total,
+            } => write!(
+                f,
+                "Updating list {}: {} to add, {} to remove{}, total movies: {}",
+                list_id,
+                to_add,
+                to_remove,
+                if *reordered { ", reordered" } else { "" },
+                total
+            ),
+            Message::DryRun => write!(f, "Dry run. List was not updated."),
+            Message::ListUpToDate(list_id) => {
+                write!(f, "List {} up to date. Nothing to do.", list_id)
+            }
+            Message::AmbiguousMatch { movie, reason } => {
+                write!(f, "Ambiguous match for movie {}: {}", movie, reason)
+            }
+            Message::ExportedImdbList { count, path } => {
+                write!(f, "Exported {} films to IMDb list: {}", count, path)
+            }
+            Message::ExcludingWatched(count) => write!(f, "Excluding {} watched films.", count),
+            Message::ConfigValid => write!(f, "Config is valid."),
+            Message::NoMatch => write!(f, "(no match)"),
+            Message::ToAdd(count) => write!(f, "To add ({}):", count),
+            Message::ToRemove(count) => write!(f, "To remove ({}):", count),
+            Message::Authenticated(username) => write!(f, "Authenticated as {}.", username),
+            Message::CacheCleared(path) => write!(f, "Deleted cache {}.", path),
+            Message::SyncingMapping { directory, list_id } => {
+                write!(f, "Syncing {} with list {}.", directory, list_id)
+            }
+            Message::RemovalsToConfirm { list_id, count } => write!(
+                f,
+                "{} films on list {} are not in the directory:",
+                count, list_id
+            ),
+            Message::ConfirmRemovals => write!(f, "Remove them from the list? [y/N]"),
+            Message::RemovalsNotConfirmed(count) => write!(
+                f,
+                "Keeping {} films not in the directory: no terminal to confirm their removal.",
+                count
+            ),
+            Message::PickFilm(movie, reason) => write!(f, "Which film is {}? ({})", movie, reason),
+            Message::PickFilmPrompt(count) => write!(f, "Number (1-{}) or Enter to skip:", count),
+            Message::NotWatched(directory) => write!(
+                f,
+                "Not watching {}: only local directories can be watched.",
+                directory
+            ),
+            Message::Watching(count) => write!(f, "Watching {} directories for changes.", count),
+            Message::CreatingList(name) => write!(f, "Creating list {}.", name),
+            Message::PlanSaved { count, path } => {
+                write!(f, "Saved the plans of {} lists to {}.", count, path)
+            }
+            Message::NothingUnresolved => write!(f, "No unresolved movies to retry."),
+            Message::RetriedUnresolved {
+                resolved,
+                remaining,
+                path,
+            } => write!(
+                f,
+                "Resolved {} files, {} remain unresolved in {}.",
+                resolved, remaining, path
+            ),
+            Message::ResolveErrors(count) => write!(
+                f,
+                "Failed to resolve {} movies, they are retried by the next run:",
+                count
+            ),
+            Message::KeepingRemovals(count) => write!(
+                f,
+                "Not removing any films since {} movies failed to resolve.",
+                count
+            ),
+            Message::RetryingRequest {
+                error,
+                delay,
+                attempt,
+                max_attempts,
+            } => write!(
+                f,
+                "Request failed ({}), retrying in {:.1}s (attempt {} of {}).",
+                error,
+                delay.as_secs_f64(),
+                attempt + 1,
+                max_attempts
+            ),
+            Message::ScanningDirectory(directory) => write!(f, "Scanning {}", directory),
+            Message::ResolvingMovies { cached, looked_up } => write!(
+                f,
+                "Resolving movies ({} cached, {} looked up)",
+                cached, looked_up
+            ),
+            Message::FetchingList { list_id, count } => {
+                write!(f, "Fetching list {}: {} entries", list_id, count)
+            }
+            Message::ImportingCache(path) => {
+                write!(f, "Importing cache {} of an earlier version.", path)
+            }
+            Message::VerifyingCache => write!(f, "Verifying cached films"),
+            Message::CachedFilmDeleted { movie, film_id } => write!(
+                f,
+                "Film {} of {} no longer exists on Letterboxd",
+                film_id, movie
+            ),
+            Message::CachedFilmMerged {
+                movie,
+                film_id,
+                new_id,
+            } => write!(
+                f,
+                "Film {} of {} was merged into {}",
+                film_id, movie, new_id
+            ),
+            Message::VerifiedCache {
+                checked,
+                stale,
+                failed,
+            } => write!(
+                f,
+                "Verified {} cached films: {} changed, {} could not be checked",
+                checked, stale, failed
+            ),
+            Message::PrunedCache { removed, total } => write!(
+                f,
+                "Removed {} movies whose files no longer exist, {} remain",
+                removed, total
+            ),
+            Message::FingerprintingFiles => write!(f, "Fingerprinting files"),
+            Message::FoundMovedFiles(count) => {
+                write!(f, "Found {} renamed or moved movies in the cache", count)
+            }
+            Message::ExportedLibrary {
+                count,
+                matched,
+                path,
+            } => write!(
+                f,
+                "Exported {} movies, {} of them matched to films, to {}",
+                count, matched, path
+            ),
+            Message::ExportedList { count, path } => {
+                write!(f, "Exported {} list entries to {}", count, path)
+            }
+            Message::FoundOnTmdb { movie, film } => {
+                write!(f, "Found {} on TMDb as {}", movie, film)
+            }
+            Message::YearMismatch { movie, year, film } => write!(
+                f,
+                "Matched {} from {} to {}, which was released in a different year",
+                movie, year, film
+            ),
+            Message::UsernamePrompt => write!(f, "Letterboxd username: "),
+            Message::PasswordPrompt => write!(f, "Letterboxd password: "),
+            Message::LoggedIn(username) => write!(
+                f,
+                "Logged in as {}, the access token is stored in the keyring.",
+                username
+            ),
+            Message::LoggedOut => write!(f, "Deleted the access token from the keyring."),
+            Message::NotLoggedIn => write!(f, "There is no access token in the keyring."),
+            Message::ListeningForWebhooks(addr) => write!(f, "Listening for webhooks on {}.", addr),
+            Message::MovieAdded(movie) => write!(f, "{} was added.", movie),
+            Message::MovieDeleted(movie) => write!(f, "{} was deleted.", movie),
+            Message::MovieRenamed(movie) => write!(f, "{} was renamed.", movie),
+            Message::NextSync(delay) => {
+                write!(f, "Next sync in {} minutes.", delay.as_secs().div_ceil(60))
+            }
+            Message::StoppingAfterSync => write!(f, "Stopping once the running sync is done."),
+            Message::Stopped => write!(f, "Stopped."),
+            Message::OnlyInList(list) => write!(f, "Only in {}", list),
+            Message::InBothLists => write!(f, "In both lists"),
+            Message::MarkingWatched(count) => write!(f, "Marking {} films as watched.", count),
+            Message::SettingRatings(count) => write!(f, "Setting {} ratings.", count),
+            Message::StatsFilms { films, unresolved } => {
+                write!(f, "{} films, {} files not resolved.", films, unresolved)
+            }
+            Message::StatsDecades => write!(f, "By decade:"),
+            Message::Decade(decade) => write!(f, "{}s", decade),
+            Message::StatsTopDirectors => write!(f, "Top directors:"),
+            Message::StatsRuntime {
+                average,
+                total_hours,
+            } => write!(
+                f,
+                "Average runtime: {:.0} min, {} hours in total.",
+                average, total_hours
+            ),
+            Message::StatsWatched { watched, films } => {
+                write!(f, "Watched: {} of {} films.", watched, films)
+            }
+            Message::SkippedEpisodes(count) => write!(f, "Skipped {} TV episodes.", count),
+            Message::LooksLikeEpisode(path) => write!(f, "File looks like a TV episode: {}", path),
+            Message::DuplicateFilms(count) => write!(f, "{} films have several copies:", count),
+            Message::DuplicatesSaved { count, path } => write!(
+                f,
+                "Saved the files of {} films with several copies to {}.",
+                count, path
+            ),
+            Message::ResumingSearch(count) => write!(
+                f,
+                "Resuming the interrupted search, skipping {} movies it did not find.",
+                count
+            ),
+            Message::AppliedChunk { done, total } => {
+                write!(f, "Applied update {} of {}.", done, total)
+            }
+            Message::VerifyingList(list_id) => write!(f, "Verifying list {}.", list_id),
+            Message::Unapplied { list_id, count } => {
+                write!(f, "{} changes of list {} were not applied:", count, list_id)
+            }
+            Message::MappingWritten { count, path } => {
+                write!(f, "Wrote the films of {} files to {}.", count, path)
+            }
+            Message::ReviewPageWritten { count, path } => write!(
+                f,
+                "Wrote the matches of {} movies to {} for review.",
+                count, path
+            ),
+            Message::ConfirmUpdate(list_id) => write!(f, "Update list {}? [y/N]", list_id),
+            Message::UpdateNotConfirmed(list_id) => write!(
+                f,
+                "List {} was not updated: no terminal to confirm the changes.",
+                list_id
+            ),
+            Message::UpdateDeclined(list_id) => write!(f, "List {} was not updated.", list_id),
+            Message::RestoringSnapshot { list_id, taken } => {
+                write!(f, "Restoring list {} as of {}.", list_id, taken)
+            }
+            Message::ApiCalls(count) => write!(f, "Sent {} requests to Letterboxd.", count),
+            Message::ApiBudgetSpent { max, skipped } => write!(
+                f,
+                "Stopped searching after {} requests to Letterboxd, {} movies were not searched.",
+                max, skipped
+            ),
+            Message::AnonymousClient => write!(
+                f,
+                "No Letterboxd username given, reading public data with the API key only."
+            ),
+            Message::DoctorPassed => write!(f, "All checks passed."),
+            Message::RenameSuggestions(count) => write!(
+                f,
+                "{} files could be renamed, so that their films are found by an exact search:",
+                count
+            ),
+            Message::FilesRenamed(count) => write!(f, "Renamed {} files.", count),
+            Message::RenamesLocalOnly => write!(f, "Only local files can be renamed."),
+            Message::ApiFailure(failure) => match failure {
+                ApiFailure::ApiKeyRejected => write!(
+                    f,
+                    "API key rejected: check LETTERBOXD_API_KEY and LETTERBOXD_API_SECRET."
+                ),
+                ApiFailure::CredentialsRejected => write!(
+                    f,
+                    "Login failed: check LETTERBOXD_USERNAME and LETTERBOXD_PASSWORD."
+                ),
+                ApiFailure::ListNotFound => write!(
+                    f,
+                    "List not found or not writable by this account: check the list id."
+                ),
+                ApiFailure::RateLimited => {
+                    write!(f, "Rate limited by Letterboxd: retry in a few minutes.")
+                }
+                ApiFailure::Unavailable => {
+                    write!(f, "Letterboxd is not available right now: retry later.")
+                }
+            },
+        }
+    }
+
+    fn fmt_de(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::ConvertingCache(path) => {
+                write!(f, "Cache {} wird ins Binärformat umgewandelt.", path)
+            }
+            Message::CorruptCache {
+                path,
+                error,
+                backup,
+            } => write!(
+                f,
+                "Cache-Datei {} ist beschädigt ({}), sie wurde nach {} verschoben und ein leerer Cache wird verwendet.",
+                path, error, backup
+            ),
+            Message::MovieNotFound(title) => write!(f, "Film nicht gefunden: {}", title),
+            Message::PinnedFilmNotFound(film) => {
+                write!(f, "Film {} aus den Overrides nicht gefunden.", film)
+            }
+            Message::LowConfidence {
+                movie,
+                film,
+                confidence,
+            } => write!(
+                f,
+                "{} wird übersprungen: der beste Treffer {} ist nur mit {:.2} sicher.",
+                movie, film, confidence
+            ),
+            Message::LanguageNotesLocalOnly => write!(
+                f,
+                "Sprachnotizen werden nur für lokale Verzeichnisse unterstützt."
+            ),
+            Message::MergedCache {
+                added,
+                conflicts,
+                total,
+            } => write!(
+                f,
+                "Cache zusammengeführt: {} hinzugefügt, {} Konflikte, Filme insgesamt: {}",
+                added, conflicts, total
+            ),
+            Message::CacheConflict {
+                movie,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "Widersprüchliche IDs für Film {}: {} (unsere), {} (deren)",
+                movie, ours, theirs
+            ),
+            Message::Owned { count, total } => write!(f, "Vorhanden ({}/{}):", count, total),
+            Message::Missing { count, total } => write!(f, "Fehlend ({}/{}):", count, total),
+            Message::UpdatingList {
+                list_id,
+                to_add,
+                to_remove,
+                reordered,
+                total,
+            } => write!(
+                f,
+                "Liste {} wird aktualisiert: {} hinzufügen, {} entfernen{}, Filme insgesamt: {}",
+                list_id,
+                to_add,
+                to_remove,
+                if *reordered { ", neu sortiert" } else { "" },
+                total
+            ),
+            Message::DryRun => write!(f, "Probelauf. Die Liste wurde nicht geändert."),
+            Message::ListUpToDate(list_id) => {
+                write!(f, "Liste {} ist aktuell. Nichts zu tun.", list_id)
+            }
+            Message::AmbiguousMatch { movie, reason } => {
+                write!(f, "Mehrdeutiger Treffer für Film {}: {}", movie, reason)
+            }
+            Message::ExportedImdbList { count, path } => {
+                write!(f, "{} Filme in IMDb-Liste exportiert: {}", count, path)
+            }
+            Message::ExcludingWatched(count) => {
+                write!(f, "{} bereits gesehene Filme werden ausgelassen.", count)
+            }
+            Message::ConfigValid => write!(f, "Die Konfiguration ist gültig."),
+            Message::NoMatch => write!(f, "(kein Treffer)"),
+            Message::ToAdd(count) => write!(f, "Hinzuzufügen ({}):", count),
+            Message::ToRemove(count) => write!(f, "Zu entfernen ({}):", count),
+            Message::Authenticated(username) => write!(f, "Angemeldet als {}.", username),
+            Message::CacheCleared(path) => write!(f, "Cache {} gelöscht.", path),
+            Message::SyncingMapping { directory, list_id } => {
+                write!(f, "{} wird mit Liste {} abgeglichen.", directory, list_id)
+            }
+            Message::RemovalsToConfirm { list_id, count } => write!(
+                f,
+                "{} Filme auf Liste {} sind nicht im Verzeichnis:",
+                count, list_id
+            ),
+            Message::ConfirmRemovals => write!(f, "Von der Liste entfernen? [j/N]"),
+            Message::RemovalsNotConfirmed(count) => write!(
+                f,
+                "{} Filme, die nicht im Verzeichnis sind, bleiben auf der Liste: \
+                 kein Terminal zum Bestätigen.",
+                count
+            ),
+            Message::PickFilm(movie, reason) => {
+                write!(f, "Welcher Film ist {}? ({})", movie, reason)
+            }
+            Message::PickFilmPrompt(count) => {
+                write!(f, "Nummer (1-{}) oder Enter zum Überspringen:", count)
+            }
+            Message::NotWatched(directory) => write!(
+                f,
+                "{} wird nicht überwacht: nur lokale Verzeichnisse können überwacht werden.",
+                directory
+            ),
+            Message::Watching(count) => {
+                write!(f, "{} Verzeichnisse werden auf Änderungen überwacht.", count)
+            }
+            Message::CreatingList(name) => write!(f, "Liste {} wird erstellt.", name),
+            Message::PlanSaved { count, path } => write!(f, "Pläne für {} Listen in {} gespeichert.", count, path),
+            Message::NothingUnresolved => write!(f, "Keine ungelösten Filme zum Wiederholen."),
+            Message::RetriedUnresolved { resolved, remaining, path } => write!(f, "{} Dateien aufgelöst, {} bleiben ungelöst in {}.", resolved, remaining, path),
+            Message::ResolveErrors(count) => write!(f, "{} Filme konnten nicht aufgelöst werden, der nächste Lauf versucht es erneut:", count),
+            Message::KeepingRemovals(count) => write!(f, "Es werden keine Filme entfernt, da {} Filme nicht aufgelöst werden konnten.", count),
+            Message::RetryingRequest { error, delay, attempt, max_attempts } => write!(f, "Anfrage fehlgeschlagen ({}), neuer Versuch in {:.1}s (Versuch {} von {}).", error, delay.as_secs_f64(), attempt + 1, max_attempts),
+            Message::ScanningDirectory(directory) => write!(f, "Durchsuche {}", directory),
+            Message::ResolvingMovies { cached, looked_up } => write!(f, "Löse Filme auf ({} aus dem Cache, {} nachgeschlagen)", cached, looked_up),
+            Message::FetchingList { list_id, count } => write!(f, "Lade Liste {}: {} Einträge", list_id, count),
+            Message::ImportingCache(path) => write!(f, "Übernehme Cache {} einer früheren Version.", path),
+            Message::VerifyingCache => write!(f, "Prüfe zwischengespeicherte Filme"),
+            Message::CachedFilmDeleted { movie, film_id } => write!(f, "Film {} von {} existiert auf Letterboxd nicht mehr", film_id, movie),
+            Message::CachedFilmMerged { movie, film_id, new_id } => write!(f, "Film {} von {} wurde mit {} zusammengeführt", film_id, movie, new_id),
+            Message::VerifiedCache { checked, stale, failed } => write!(f, "{} zwischengespeicherte Filme geprüft: {} geändert, {} konnten nicht geprüft werden", checked, stale, failed),
+            Message::PrunedCache { removed, total } => write!(f, "{} Filme entfernt, deren Dateien nicht mehr existieren, {} verbleiben", removed, total),
+            Message::FingerprintingFiles => write!(f, "Berechne Fingerabdrücke der Dateien"),
+            Message::FoundMovedFiles(count) => write!(f, "{} umbenannte oder verschobene Filme im Cache gefunden", count),
+            Message::ExportedLibrary { count, matched, path } => write!(f, "{} Filme exportiert, davon {} zugeordnet, nach {}", count, matched, path),
+            Message::ExportedList { count, path } => write!(f, "{} Listeneinträge nach {} exportiert", count, path),
+            Message::FoundOnTmdb { movie, film } => write!(f, "{} auf TMDb als {} gefunden", movie, film),
+            Message::YearMismatch { movie, year, film } => write!(f, "{} aus {} wurde {} zugeordnet, der in einem anderen Jahr erschien", movie, year, film),
+            Message::UsernamePrompt => write!(f, "Letterboxd-Benutzername: "),
+            Message::PasswordPrompt => write!(f, "Letterboxd-Passwort: "),
+            Message::LoggedIn(username) => write!(f, "Angemeldet als {}, das Zugriffstoken ist im Schlüsselbund gespeichert.", username),
+            Message::LoggedOut => write!(f, "Zugriffstoken aus dem Schlüsselbund gelöscht."),
+            Message::NotLoggedIn => write!(f, "Im Schlüsselbund ist kein Zugriffstoken."),
+            Message::ListeningForWebhooks(addr) => write!(f, "Warte auf Webhooks an {}.", addr),
+            Message::MovieAdded(movie) => write!(f, "{} wurde hinzugefügt.", movie),
+            Message::MovieDeleted(movie) => write!(f, "{} wurde gelöscht.", movie),
+            Message::MovieRenamed(movie) => write!(f, "{} wurde umbenannt.", movie),
+            Message::NextSync(delay) => write!(f, "Nächste Synchronisierung in {} Minuten.", delay.as_secs().div_ceil(60)),
+            Message::StoppingAfterSync => write!(f, "Beende nach der laufenden Synchronisierung."),
+            Message::Stopped => write!(f, "Beendet."),
+            Message::OnlyInList(list) => write!(f, "Nur in {}", list),
+            Message::InBothLists => write!(f, "In beiden Listen"),
+            Message::MarkingWatched(count) => {
+                write!(f, "{} Filme werden als gesehen markiert.", count)
+            }
+            Message::SettingRatings(count) => write!(f, "{} Bewertungen werden gesetzt.", count),
+            Message::StatsFilms { films, unresolved } => {
+                write!(f, "{} Filme, {} Dateien nicht aufgelöst.", films, unresolved)
+            }
+            Message::StatsDecades => write!(f, "Nach Jahrzehnt:"),
+            Message::Decade(decade) => write!(f, "{}er", decade),
+            Message::StatsTopDirectors => write!(f, "Häufigste Regisseure:"),
+            Message::StatsRuntime {
+                average,
+                total_hours,
+            } => write!(
+                f,
+                "Durchschnittliche Laufzeit: {:.0} min, insgesamt {} Stunden.",
+                average, total_hours
+            ),
+            Message::StatsWatched { watched, films } => {
+                write!(f, "Gesehen: {} von {} Filmen.", watched, films)
+            }
+            Message::SkippedEpisodes(count) => write!(f, "{} Serienfolgen übersprungen.", count),
+            Message::LooksLikeEpisode(path) => {
+                write!(f, "Datei sieht nach einer Serienfolge aus: {}", path)
+            }
+            Message::DuplicateFilms(count) => write!(f, "{} Filme sind mehrfach vorhanden:", count),
+            Message::DuplicatesSaved { count, path } => write!(f, "Dateien von {} mehrfach vorhandenen Filmen in {} gespeichert.", count, path),
+            Message::ResumingSearch(count) => write!(f, "Setze die unterbrochene Suche fort, {} dort nicht gefundene Filme werden übersprungen.", count),
+            Message::AppliedChunk { done, total } => write!(f, "Änderung {} von {} übernommen.", done, total),
+            Message::VerifyingList(list_id) => write!(f, "Überprüfe Liste {}.", list_id),
+            Message::Unapplied { list_id, count } => write!(f, "{} Änderungen der Liste {} wurden nicht übernommen:", count, list_id),
+            Message::MappingWritten { count, path } => write!(f, "Filme von {} Dateien in {} geschrieben.", count, path),
+            Message::ReviewPageWritten { count, path } => write!(f, "Zuordnungen von {} Filmen zur Überprüfung in {} geschrieben.", count, path),
+            Message::ConfirmUpdate(list_id) => write!(f, "Liste {} aktualisieren? [j/N]", list_id),
+            Message::UpdateNotConfirmed(list_id) => write!(f, "Liste {} wurde nicht geändert: kein Terminal zum Bestätigen.", list_id),
+            Message::UpdateDeclined(list_id) => write!(f, "Liste {} wurde nicht geändert.", list_id),
+            Message::RestoringSnapshot { list_id, taken } => write!(f, "Liste {} wird auf den Stand vom {} zurückgesetzt.", list_id, taken),
+            Message::ApiCalls(count) => write!(f, "{} Anfragen an Letterboxd gesendet.", count),
+            Message::ApiBudgetSpent { max, skipped } => write!(f, "Suche nach {} Anfragen an Letterboxd beendet, {} Filme wurden nicht gesucht.", max, skipped),
+            Message::AnonymousClient => write!(f, "Kein Letterboxd-Benutzername angegeben, öffentliche Daten werden nur mit dem API-Schlüssel gelesen."),
+            Message::DoctorPassed => write!(f, "Alle Prüfungen bestanden."),
+            Message::RenameSuggestions(count) => write!(f, "{} Dateien könnten umbenannt werden, damit ihre Filme durch eine exakte Suche gefunden werden:", count),
+            Message::FilesRenamed(count) => write!(f, "{} Dateien umbenannt.", count),
+            Message::RenamesLocalOnly => write!(f, "Nur lokale Dateien können umbenannt werden."),
+            Message::ApiFailure(failure) => match failure {
+                ApiFailure::ApiKeyRejected => write!(
+                    f,
+                    "API-Schlüssel abgelehnt: LETTERBOXD_API_KEY und LETTERBOXD_API_SECRET prüfen."
+                ),
+                ApiFailure::CredentialsRejected => write!(
+                    f,
+                    "Anmeldung fehlgeschlagen: LETTERBOXD_USERNAME und LETTERBOXD_PASSWORD prüfen."
+                ),
+                ApiFailure::ListNotFound => write!(
+                    f,
+                    "Liste nicht gefunden oder für dieses Konto nicht bearbeitbar: Listen-ID prüfen."
+                ),
+                ApiFailure::RateLimited => write!(
+                    f,
+                    "Zu viele Anfragen an Letterboxd: in ein paar Minuten erneut versuchen."
+                ),
+                ApiFailure::Unavailable => write!(
+                    f,
+                    "Letterboxd ist gerade nicht erreichbar: später erneut versuchen."
+                ),
+            },
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Movies listed in a file instead of found in a directory, like a spreadsheet of a collection of
+//! discs.
+//!
+//! A CSV file, with the extension `csv`, has the columns title, year and IMDb id, of which only
+//! the title is required. A header row starting with `title` is skipped. Any other file lists one
+//! title per line, optionally followed by the year in parentheses, like `Heat (1995)`. Empty lines
+//! and lines starting with `#` are skipped.
+
+use anyhow::{anyhow, Context as _};
+use regex::Regex;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::resolve::{add_listed_movie, GuessedMovies, MovieGuess};
+use crate::scan::MovieFile;
+
+/// Read the movies listed in the file at `path`, like [`guess_movies`] guesses the movies of
+/// files.
+///
+/// Each entry counts as a file, named after the input file and the line of the entry, like
+/// `collection.csv:12`.
+///
+/// [`guess_movies`]: crate::resolve::guess_movies
+pub fn read_input_file(path: &Path) -> anyhow::Result<GuessedMovies> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let entries = if is_csv {
+        read_csv(path)
+    } else {
+        read_lines(path)
+    }
+    .with_context(|| format!("failed to read input file {}", path.display()))?;
+
+    let mut guessed = GuessedMovies::default();
+    for (line, movie) in entries {
+        let file = MovieFile {
+            path: PathBuf::from(format!("{}:{}", path.display(), line)),
+            size: None,
+            modified: None,
+            fingerprint: None,
+        };
+        add_listed_movie(&mut guessed, movie, vec![file]);
+    }
+    Ok(guessed)
+}
+
+/// Movies of a CSV file by line.
+fn read_csv(path: &Path) -> anyhow::Result<Vec<(usize, MovieGuess)>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record
+            .position()
+            .map_or(0, |position| position.line() as usize);
+        let title = match record.get(0) {
+            Some(title) if !title.is_empty() => title,
+            _ => continue,
+        };
+        if line == 1 && title.eq_ignore_ascii_case("title") {
+            continue;
+        }
+        let year = match record.get(1).filter(|year| !year.is_empty()) {
+            Some(year) => Some(
+                year.parse()
+                    .map_err(|_| anyhow!("invalid year '{}' in line {}", year, line))?,
+            ),
+            None => None,
+        };
+        let mut movie = MovieGuess::new(title.to_string(), year);
+        if let Some(imdb_id) = record.get(2).filter(|id| !id.is_empty()) {
+            if !is_imdb_id(imdb_id) {
+                return Err(anyhow!("invalid IMDb id '{}' in line {}", imdb_id, line));
+            }
+            movie.imdb_id = Some(imdb_id.to_string());
+        }
+        entries.push((line, movie));
+    }
+    Ok(entries)
+}
+
+/// The movie of a title optionally followed by the year in parentheses, like `Heat (1995)`.
+pub(crate) fn parse_title_with_year(entry: &str) -> MovieGuess {
+    static TITLE_WITH_YEAR: OnceLock<Regex> = OnceLock::new();
+    let title_with_year = TITLE_WITH_YEAR.get_or_init(|| {
+        Regex::new(r"^(?P<title>.+?)\s*\((?P<year>\d{4})\)$").expect("valid title pattern")
+    });
+    match title_with_year.captures(entry) {
+        Some(captures) => {
+            MovieGuess::new(captures["title"].to_string(), captures["year"].parse().ok())
+        }
+        None => MovieGuess::new(entry.to_string(), None),
+    }
+}
+
+/// Movies of a file with a title per line by line.
+fn read_lines(path: &Path) -> anyhow::Result<Vec<(usize, MovieGuess)>> {
+    let content = fs::read_to_string(path)?;
+    let entries = content
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, entry)| (line, parse_title_with_year(entry)))
+        .collect();
+    Ok(entries)
+}
+
+fn is_imdb_id(id: &str) -> bool {
+    id.strip_prefix("tt")
+        .is_some_and(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_may_have_a_year() {
+        let movie = parse_title_with_year("Heat (1995)");
+        assert_eq!(movie.title, "Heat");
+        assert_eq!(movie.year, Some(1995));
+
+        let movie = parse_title_with_year("2001: A Space Odyssey");
+        assert_eq!(movie.title, "2001: A Space Odyssey");
+        assert_eq!(movie.year, None);
+    }
+
+    #[test]
+    fn only_the_trailing_year_is_parsed() {
+        let movie = parse_title_with_year("1917 (2019)");
+        assert_eq!(movie.title, "1917");
+        assert_eq!(movie.year, Some(2019));
+
+        let movie = parse_title_with_year("Heat (1995) Director's Cut");
+        assert_eq!(movie.title, "Heat (1995) Director's Cut");
+        assert_eq!(movie.year, None);
+    }
+
+    #[test]
+    fn imdb_ids_are_tt_and_digits() {
+        assert!(is_imdb_id("tt0133093"));
+        assert!(!is_imdb_id("tt"));
+        assert!(!is_imdb_id("0133093"));
+        assert!(!is_imdb_id("tt01330a3"));
+        assert!(!is_imdb_id("nm0000206"));
+    }
+}
@@ -0,0 +1,190 @@
+//! Movies of a Jellyfin or Emby library instead of found in a directory.
+//!
+//! The server already matched the movies to their IMDb and TMDb ids, which resolve to films
+//! exactly. The library is given as `jellyfin://host[:port][/library]`, or `jellyfins://` for
+//! HTTPS, where the library is the name of a movie library, like `jellyfin://nas:8096/Movies`.
+//! Without a library, all movies of the server are read. Emby servers are read the same way. The
+//! API key of the server is taken from `JELLYFIN_API_KEY`.
+
+use anyhow::{anyhow, Context as _};
+use log::debug;
+use percent_encoding::percent_decode_str;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+use crate::resolve::{add_listed_movie, GuessedMovies, MovieGuess};
+use crate::scan::MovieFile;
+
+/// Number of movies requested at once.
+const PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct VirtualFolder {
+    name: String,
+    item_id: String,
+    collection_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Items {
+    #[serde(default)]
+    items: Vec<Item>,
+    total_record_count: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Item {
+    id: String,
+    name: String,
+    production_year: Option<u16>,
+    /// External ids of the movie by provider, like `Imdb` or `Tmdb`.
+    #[serde(default)]
+    provider_ids: HashMap<String, String>,
+    path: Option<String>,
+    /// Versions of the movie, each with its own file.
+    #[serde(default)]
+    media_sources: Vec<MediaSource>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MediaSource {
+    path: Option<String>,
+    size: Option<u64>,
+}
+
+impl Item {
+    fn movie(&self) -> MovieGuess {
+        let mut movie = MovieGuess::new(self.name.clone(), self.production_year);
+        // Provider names differ in case between versions of the servers.
+        for (provider, id) in &self.provider_ids {
+            match provider.to_ascii_lowercase().as_str() {
+                "imdb" if id.starts_with("tt") => movie.imdb_id = Some(id.clone()),
+                "tmdb" if !id.is_empty() => movie.tmdb_id = Some(id.clone()),
+                _ => {}
+            }
+        }
+        movie
+    }
+
+    /// The files of the movie as stored on the server. A movie without any files known to the
+    /// server counts as a file named after its id.
+    fn files(&self) -> Vec<MovieFile> {
+        let file = |path: &str, size| MovieFile {
+            path: PathBuf::from(path),
+            size,
+            modified: None,
+            fingerprint: None,
+        };
+        let mut files: Vec<MovieFile> = self
+            .media_sources
+            .iter()
+            .filter_map(|source| Some(file(source.path.as_ref()?, source.size)))
+            .collect();
+        if files.is_empty() {
+            files.push(file(self.path.as_deref().unwrap_or(&self.id), None));
+        }
+        files
+    }
+}
+
+struct JellyfinClient {
+    client: reqwest::Client,
+    base: reqwest::Url,
+    api_key: Option<String>,
+}
+
+impl JellyfinClient {
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> anyhow::Result<T> {
+        let mut url = self.base.join(path)?;
+        url.query_pairs_mut().extend_pairs(query);
+        let mut request = self.client.get(url);
+        if let Some(api_key) = &self.api_key {
+            // Understood by Emby as well as by Jellyfin.
+            request = request.header("X-Emby-Token", api_key);
+        }
+        let body = request.send().await?.error_for_status()?.text().await?;
+        serde_json::from_str(&body)
+            .with_context(|| format!("invalid Jellyfin response for {}", path))
+    }
+
+    /// Id of the movie library named `library`.
+    async fn library_id(&self, library: &str) -> anyhow::Result<String> {
+        let folders: Vec<VirtualFolder> = self.get("Library/VirtualFolders", &[]).await?;
+        folders
+            .into_iter()
+            .filter(|folder| folder.collection_type.as_deref() == Some("movies"))
+            .find(|folder| folder.name.eq_ignore_ascii_case(library))
+            .map(|folder| folder.item_id)
+            .ok_or_else(|| anyhow!("no Jellyfin movie library '{}'", library))
+    }
+
+    /// The movies below the item `parent_id`, or else of the whole server.
+    async fn movies(&self, parent_id: Option<&str>) -> anyhow::Result<Vec<Item>> {
+        let mut items = Vec::new();
+        loop {
+            let mut query = vec![
+                ("IncludeItemTypes", "Movie".to_string()),
+                ("Recursive", "true".to_string()),
+                ("Fields", "ProviderIds,Path,MediaSources".to_string()),
+                ("StartIndex", items.len().to_string()),
+                ("Limit", PAGE_SIZE.to_string()),
+            ];
+            if let Some(parent_id) = parent_id {
+                query.push(("ParentId", parent_id.to_string()));
+            }
+            let page: Items = self.get("Items", &query).await?;
+            let count = page.items.len();
+            items.extend(page.items);
+            if count < PAGE_SIZE
+                || page
+                    .total_record_count
+                    .is_some_and(|total| items.len() >= total)
+            {
+                break;
+            }
+        }
+        debug!("Found {} movies on the Jellyfin server", items.len());
+        Ok(items)
+    }
+}
+
+/// Read the movies of the Jellyfin or Emby library at `url`, like [`guess_movies`] guesses the
+/// movies of files. The URL has the scheme `http` or `https` and the library as path, if any.
+///
+/// [`guess_movies`]: crate::resolve::guess_movies
+pub async fn read_jellyfin_library(url: &reqwest::Url) -> anyhow::Result<GuessedMovies> {
+    let library = percent_decode_str(url.path().trim_matches('/')).decode_utf8_lossy();
+    let mut base = url.clone();
+    base.set_path("/");
+    let jellyfin = JellyfinClient {
+        client: reqwest::Client::new(),
+        base,
+        api_key: env::var("JELLYFIN_API_KEY").ok(),
+    };
+
+    let parent_id = match library.as_ref() {
+        "" => None,
+        library => Some(jellyfin.library_id(library).await?),
+    };
+    let items = jellyfin
+        .movies(parent_id.as_deref())
+        .await
+        .with_context(|| format!("failed to read the movies of {}", url))?;
+    let mut guessed = GuessedMovies::default();
+    for item in items {
+        add_listed_movie(&mut guessed, item.movie(), item.files());
+    }
+    Ok(guessed)
+}
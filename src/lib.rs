@@ -0,0 +1,59 @@
+//! Synchronizes movies in a folder with a list on Letterboxd.
+//!
+//! A sync consists of these steps:
+//!
+//! 1. [`scan_movies`] lists the movie files in a [`Source`], unless the movies are listed in an
+//!    [`input`] file or a [`plex`] or [`jellyfin`] library.
+//! 2. [`resolve::guess_movies`] extracts the movie names from NFO files and file names.
+//! 3. [`resolve_film_ids`] resolves the movie names to Letterboxd films.
+//! 4. [`SyncPlan`] computes the changes to the list, which a [`SyncExecutor`] applies.
+//!
+//! [`pipeline::scan_and_resolve`] takes the first three steps for a [`config::Mapping`] of
+//! directories to a list, and [`runner::sync_mapping`] all of them.
+
+pub mod auth;
+pub mod backend;
+pub mod cache;
+pub mod config;
+pub mod container;
+pub mod doctor;
+pub mod duplicates;
+pub mod error;
+pub mod export;
+pub mod films;
+pub mod i18n;
+pub mod input;
+pub mod jellyfin;
+pub mod lists;
+pub mod metadata;
+pub mod metrics;
+pub mod notes;
+pub mod notify;
+pub mod overrides;
+pub mod pipeline;
+pub mod plex;
+pub mod progress;
+pub mod ratings;
+pub mod rename;
+pub mod report;
+pub mod resolve;
+pub mod retry;
+pub mod review;
+pub mod runner;
+pub mod scan;
+pub mod snapshot;
+pub mod stats;
+pub mod summary;
+pub mod sync;
+pub mod throttle;
+pub mod tmdb;
+pub mod trakt;
+pub mod watch;
+pub mod webhook;
+
+pub use resolve::{resolve_film_ids, MovieGuess, ResolvedFilms};
+pub use scan::{scan_movies, MovieFile, Source};
+pub use sync::{RemovalPolicy, SyncExecutor, SyncPlan};
+
+/// Default number of concurrent requests.
+const REQUESTS_CONCURRENCY: usize = 16;
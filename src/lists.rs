@@ -0,0 +1,291 @@
+//! Finding lists by their Letterboxd URL or name instead of their id, and the lists named like the
+//! folders of a directory.
+
+use anyhow::{anyhow, Context as _};
+use log::{debug, info};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::i18n::Message;
+use crate::retry::with_retry;
+
+/// Who can see a list created by the sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// Everyone.
+    Public,
+    /// Only the owner of the list.
+    Private,
+}
+
+impl Visibility {
+    pub const VARIANTS: &'static [&'static str] = &["public", "private"];
+}
+
+impl FromStr for Visibility {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "public" => Ok(Visibility::Public),
+            "private" => Ok(Visibility::Private),
+            _ => Err(anyhow!("unknown visibility: {}", s)),
+        }
+    }
+}
+
+/// Create an empty list of the authenticated member, returning its id.
+pub async fn create_list(
+    client: &letterboxd::Client,
+    name: &str,
+    visibility: Visibility,
+) -> letterboxd::Result<String> {
+    let request = letterboxd::ListCreationRequest {
+        published: Some(visibility == Visibility::Public),
+        ..letterboxd::ListCreationRequest::new(name.to_string())
+    };
+    let response = client.create_list(&request).await?;
+    debug!("Created list '{}' with id {}", name, response.data.id);
+    Ok(response.data.id)
+}
+
+/// Fetch the summaries of all lists owned by a member by paging through them.
+pub async fn fetch_member_lists(
+    client: &letterboxd::Client,
+    member_id: &str,
+) -> letterboxd::Result<Vec<letterboxd::ListSummary>> {
+    let mut request = letterboxd::ListsRequest {
+        per_page: Some(100),
+        member: Some(member_id.to_string()),
+        member_relationship: Some(letterboxd::ListMemberRelationship::Owner),
+        ..Default::default()
+    };
+    let mut lists = Vec::new();
+    loop {
+        let response = with_retry(|| client.lists(&request)).await?;
+        lists.extend(response.items);
+        request.cursor = response.next;
+        if request.cursor.is_none() {
+            break;
+        }
+    }
+    Ok(lists)
+}
+
+/// Find the id of the list of the authenticated member with the given name, see
+/// [`find_list_named`].
+pub async fn find_list_by_name(
+    client: &letterboxd::Client,
+    name: &str,
+) -> anyhow::Result<Option<String>> {
+    let lists = fetch_own_lists(client).await?;
+    Ok(find_list_named(&lists, name)?.map(|list| list.id.clone()))
+}
+
+/// Fetch the summaries of all lists owned by the authenticated member.
+pub async fn fetch_own_lists(
+    client: &letterboxd::Client,
+) -> anyhow::Result<Vec<letterboxd::ListSummary>> {
+    let me = with_retry(|| client.me())
+        .await
+        .context("failed to fetch the authenticated member")?;
+    fetch_member_lists(client, &me.member.id)
+        .await
+        .context("failed to fetch the lists of the authenticated member")
+}
+
+/// Find the list with the given name among `lists`.
+///
+/// The name is compared exactly and, if no list has this name, ignoring case. Returns `None` if no
+/// list has the name, and fails if several lists have it.
+pub fn find_list_named<'a>(
+    lists: &'a [letterboxd::ListSummary],
+    name: &str,
+) -> anyhow::Result<Option<&'a letterboxd::ListSummary>> {
+    let mut matches: Vec<&letterboxd::ListSummary> =
+        lists.iter().filter(|list| list.name == name).collect();
+    if matches.is_empty() {
+        matches = lists
+            .iter()
+            .filter(|list| list.name.to_lowercase() == name.to_lowercase())
+            .collect();
+    }
+    match matches.as_slice() {
+        [] => Ok(None),
+        [list] => {
+            debug!("List '{}' has id {}", name, list.id);
+            Ok(Some(list))
+        }
+        lists => {
+            let ids: Vec<&str> = lists.iter().map(|list| list.id.as_str()).collect();
+            Err(anyhow!(
+                "{} lists are named '{}', give the id of one instead: {}",
+                lists.len(),
+                name,
+                ids.join(", ")
+            ))
+        }
+    }
+}
+
+/// Find the id of the list at a Letterboxd URL like `https://letterboxd.com/user/list/slug/` or a
+/// `boxd.it` short link.
+pub async fn find_list_by_url(client: &letterboxd::Client, url: &str) -> anyhow::Result<String> {
+    let path = url
+        .trim()
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.");
+    let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    match parts.as_slice() {
+        ["boxd.it", id] => Ok(id.to_string()),
+        ["letterboxd.com", username, "list", slug, ..] => {
+            let member_id = find_member(client, username).await?;
+            find_member_list_by_slug(client, &member_id, username, slug)
+                .await?
+                .ok_or_else(|| anyhow!("no list found at {}", url))
+        }
+        _ => Err(anyhow!("not the URL of a Letterboxd list: {}", url)),
+    }
+}
+
+/// Find the id of the member with the given username.
+async fn find_member(client: &letterboxd::Client, username: &str) -> anyhow::Result<String> {
+    let me = with_retry(|| client.me())
+        .await
+        .context("failed to fetch the authenticated member")?;
+    if me.member.username.eq_ignore_ascii_case(username) {
+        return Ok(me.member.id);
+    }
+    let request = letterboxd::SearchRequest {
+        cursor: None,
+        per_page: Some(20),
+        input: username.to_string(),
+        search_method: Some(letterboxd::SearchMethod::Autocomplete),
+        include: Some(vec!["MemberSearchItem".to_string()]),
+        contribution_type: None,
+    };
+    let response = with_retry(|| client.search(&request))
+        .await
+        .with_context(|| format!("failed to search for member {}", username))?;
+    response
+        .items
+        .into_iter()
+        .find_map(|item| match item {
+            letterboxd::AbstractSearchItem::MemberSearchItem { member, .. }
+                if member.username.eq_ignore_ascii_case(username) =>
+            {
+                Some(member.id)
+            }
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("no member named {}", username))
+}
+
+/// Turn a list name into the slug of its URL, e.g. `My Collection!` into `my-collection`.
+fn slugify(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Find the id of the list of a member with the given slug in its URL.
+///
+/// Lists cannot be looked up by URL, so the lists whose names fit the slug are fetched to compare
+/// their URLs, and all lists of the member if none fits.
+async fn find_member_list_by_slug(
+    client: &letterboxd::Client,
+    member_id: &str,
+    username: &str,
+    slug: &str,
+) -> anyhow::Result<Option<String>> {
+    let lists = fetch_member_lists(client, member_id)
+        .await
+        .with_context(|| format!("failed to fetch the lists of {}", username))?;
+    // Slugs of lists with the same name get a number appended, like `my-collection-1`.
+    let fits = |list: &&letterboxd::ListSummary| {
+        let name = slugify(&list.name);
+        slug == name
+            || slug
+                .strip_prefix(&name)
+                .is_some_and(|rest| rest.starts_with('-'))
+    };
+    let mut candidates: Vec<&letterboxd::ListSummary> = lists.iter().filter(fits).collect();
+    if candidates.is_empty() {
+        candidates = lists.iter().collect();
+    }
+
+    let path = format!("/{}/list/{}", username, slug).to_lowercase();
+    for candidate in candidates {
+        let list = with_retry(|| client.list(&candidate.id))
+            .await
+            .with_context(|| format!("failed to fetch list {}", candidate.id))?;
+        let has_url = list.links.iter().any(|link| {
+            link.type_ == letterboxd::LinkType::Letterboxd
+                && link
+                    .url
+                    .trim_end_matches('/')
+                    .to_lowercase()
+                    .ends_with(&path)
+        });
+        if has_url {
+            debug!("List {}/{} has id {}", username, slug, list.id);
+            return Ok(Some(list.id));
+        }
+    }
+    Ok(None)
+}
+
+/// The top-level folders of `root` with the ids of the lists of the authenticated member named
+/// like them, ordered by name.
+///
+/// Missing lists are created with `visibility`, except in `dry_run` mode where their folders are
+/// left out.
+pub async fn folder_lists(
+    root: &Path,
+    visibility: Visibility,
+    dry_run: bool,
+    client: &letterboxd::Client,
+) -> anyhow::Result<Vec<(PathBuf, String)>> {
+    let mut folders = Vec::new();
+    let entries =
+        fs::read_dir(root).with_context(|| format!("failed to read {}", root.display()))?;
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read {}", root.display()))?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) if !name.starts_with('.') => name,
+            _ => continue,
+        };
+        if entry.path().is_dir() {
+            folders.push((name, entry.path()));
+        }
+    }
+    if folders.is_empty() {
+        return Err(anyhow!("no folders in {}", root.display()));
+    }
+    folders.sort();
+
+    let lists = fetch_own_lists(client).await?;
+    let mut folder_lists = Vec::new();
+    for (name, path) in folders {
+        let list_id = match find_list_named(&lists, &name)? {
+            Some(list) => list.id.clone(),
+            None => {
+                info!("{}", Message::CreatingList(&name));
+                if dry_run {
+                    info!("{}", Message::DryRun);
+                    continue;
+                }
+                create_list(client, &name, visibility)
+                    .await
+                    .with_context(|| format!("failed to create list '{}'", name))?
+            }
+        };
+        folder_lists.push((path, list_id));
+    }
+    Ok(folder_lists)
+}
@@ -1,275 +1,2304 @@
 use anyhow::{anyhow, Context as _};
-use futures_util::{stream, StreamExt, TryStreamExt};
-use log::{debug, info, warn};
-use regex::Regex;
+use chrono::{TimeZone, Utc};
+use log::{debug, error, info, warn, LevelFilter};
+use structopt::clap::Shell;
 use structopt::StructOpt;
-use walkdir::{DirEntry, WalkDir};
+
+use letterboxd_list_sync::auth::{self, new_client, read_only_client};
+use letterboxd_list_sync::backend::{Backend, ListService};
+use letterboxd_list_sync::cache::{clear_cache, load_movie_cache, merge_cache};
+use letterboxd_list_sync::config::{load_config, Config, Mapping};
+use letterboxd_list_sync::doctor::{self, Status};
+use letterboxd_list_sync::duplicates::{find_duplicates, write_duplicates, Duplicate};
+use letterboxd_list_sync::error::{self, ApiFailure, SyncIncomplete};
+use letterboxd_list_sync::export::{
+    write_letterboxd_import, write_list_entries, ExportFormat, ListDiff, ListEntryRow,
+};
+use letterboxd_list_sync::films::{film_title, SortOrder};
+use letterboxd_list_sync::i18n::{self, Locale, Message};
+use letterboxd_list_sync::lists::{
+    create_list, find_list_by_name, find_list_by_url, folder_lists, Visibility,
+};
+use letterboxd_list_sync::metadata::SourceKind;
+use letterboxd_list_sync::pipeline::{
+    self, cache_path, guess_source_movies, letterboxd_import_rows, library_stats,
+    list_mapping_files, metadata_chain, prune_movie_cache, report_resolve_errors,
+    resolved_film_details, scan_and_resolve, verify_movie_cache, CacheOptions, PickFilms,
+    ResolveSettings, ScanOptions,
+};
+use letterboxd_list_sync::progress;
+use letterboxd_list_sync::rename::DEFAULT_RENAME_TEMPLATE;
+use letterboxd_list_sync::resolve::ResolveOptions;
+use letterboxd_list_sync::retry::{self, RetryPolicy};
+use letterboxd_list_sync::review::write_review_page;
+use letterboxd_list_sync::runner::{
+    self, rename_files, report_duplicates, sync_mappings, sync_single_mapping, write_mapping,
+    Confirm, SyncOptions, SyncedMapping,
+};
+use letterboxd_list_sync::scan::{TvBehavior, WalkOptions};
+use letterboxd_list_sync::snapshot::{load_snapshots, save_snapshots, snapshot_dir};
+use letterboxd_list_sync::summary::{MappingSummary, OutputFormat, RunSummary};
+use letterboxd_list_sync::sync::{fetch_list_entries, load_plans, save_plans, Target};
+use letterboxd_list_sync::throttle;
+use letterboxd_list_sync::trakt::TraktClient;
+use letterboxd_list_sync::{RemovalPolicy, ResolvedFilms, Source, SyncExecutor, SyncPlan};
 
 use std::collections::{HashMap, HashSet};
 use std::env;
+use std::ffi::OsStr;
 use std::fs;
-use std::io;
+use std::io::{self, IsTerminal, Write};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
 
-const REQUESTS_CONCURRENCY: usize = 16;
+mod manpage;
+#[cfg(windows)]
+mod service;
 
 /// Letterboxd Sync.
 ///
 /// Synchronizes movies in a folder with a list on Letterboxd.
+///
+/// Exits with 2 if some files could not be matched to films, 7 if more files than allowed by
+/// `--max-unmatched` could not be matched, 8 if some of several mappings failed, 3 if Letterboxd
+/// rejects the credentials, 4 if the list is not found or not writable, 5 if rate limited, 6 if
+/// Letterboxd is unavailable and 1 on any other error.
 #[derive(Debug, StructOpt)]
 struct Args {
+    /// Path of the config file [default: letterboxd-sync/config.toml in the user's config dir].
+    #[structopt(long, global = true)]
+    config: Option<PathBuf>,
+    /// Language of the output [default: from LANG].
+    #[structopt(long, global = true, possible_values = Locale::VARIANTS)]
+    locale: Option<Locale>,
+    /// Attempts of Letterboxd requests failing due to rate limiting or server errors, which are
+    /// retried with exponential backoff [default: 5].
+    #[structopt(long, global = true, value_name = "N")]
+    max_attempts: Option<u32>,
+    /// Maximum number of concurrent Letterboxd requests. Fewer are sent while Letterboxd rate
+    /// limits, fails or responds slowly [default: 16].
+    #[structopt(long, global = true, value_name = "N")]
+    concurrency: Option<usize>,
+    /// Do not show progress bars.
+    #[structopt(long, global = true)]
+    no_progress: bool,
+    /// Only log errors, and show no progress bars.
+    #[structopt(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Log debug output, or with `-vv` also the one of the libraries [default: from RUST_LOG, or
+    /// else info].
+    #[structopt(short, long, global = true, parse(from_occurrences))]
+    verbose: u8,
+    /// Append the logs with timestamps to the given file instead of writing them to stderr, e.g.
+    /// for unattended runs.
+    #[structopt(long, global = true, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+    /// Letterboxd account to use, with the credentials of `[profiles.<name>]` of the config file or
+    /// the token stored by `auth login --profile <name>` instead of the ones of the environment.
+    #[structopt(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+#[allow(clippy::large_enum_variant)]
+enum Command {
+    /// Synchronize the movies in a directory with a Letterboxd list.
+    Sync(SyncArgs),
+    /// Sync the movies, and again whenever Radarr, Plex or Jellyfin announce added or deleted
+    /// movies by webhook.
+    Serve(ServeArgs),
+    /// List the movies detected in a directory with the metadata guessed from the file names.
+    Scan(ScanArgs),
+    /// Show the films a sync would add to and remove from a list, without updating it.
+    Diff(DiffArgs),
+    /// Update the lists as planned in a plan file written by `sync --dry-run --plan-file`.
+    Apply(ApplyArgs),
+    /// Restore a list as it was before the last sync updated it. Before each update of a list, a
+    /// snapshot of its films in their order is saved, of which the last 10 are kept. Each rollback
+    /// restores the previous one.
+    Rollback(RollbackArgs),
+    /// Report which films of a list are in a directory and which are missing.
+    Compare(CompareArgs),
+    /// List the films of a list which are missing in a directory, with their URLs, without
+    /// changing the list.
+    Missing(MissingArgs),
+    /// Export the movies in a directory as CSV for the Letterboxd import, like a backup of the
+    /// matched films.
+    Export(ExportArgs),
+    /// Export the entries of a Letterboxd list with title, year, film id, URL and position as CSV
+    /// or JSON.
+    ExportList(ExportListArgs),
+    /// Compare two Letterboxd lists, showing the films only in the first, only in the second and
+    /// in both.
+    DiffLists(DiffListsArgs),
+    /// Show statistics of the films in a directory: films by decade, top directors, runtime and
+    /// how many of them were watched.
+    Stats(StatsArgs),
+    /// Retry resolving the movies listed in `unresolved.csv`, adding the resolved ones to the
+    /// cache.
+    Resolve(RetryArgs),
+    /// Manage the cache of movie ids.
+    Cache(CacheCommand),
+    /// Verify the Letterboxd credentials, or log in once and keep the token in the keyring.
+    Auth {
+        #[structopt(subcommand)]
+        command: Option<AuthCommand>,
+    },
+    /// Inspect the config file.
+    Config(ConfigCommand),
+    /// Check the config, the credentials, the stored token, the lists of the mappings, the caches
+    /// and the directories, with hints how to fix the problems found.
+    Doctor(DoctorArgs),
+    /// Write the completions of a shell, or the man page with `--man`, to stdout, like
+    /// `completions bash > /etc/bash_completion.d/letterboxd-list-sync`.
+    Completions(CompletionsArgs),
+    /// Manage the Windows service running the sync periodically.
+    #[cfg(windows)]
+    Service(service::ServiceCommand),
+}
+
+#[derive(Debug, StructOpt)]
+enum AuthCommand {
+    /// Log in with username and password and store the access token in the keyring of the
+    /// operating system, which is used instead of the credentials of the environment from then on.
+    /// The password is not stored.
+    Login {
+        /// Username of the member, prompted for if not given.
+        #[structopt(long)]
+        username: Option<String>,
+    },
+    /// Delete the access token from the keyring.
+    Logout,
+}
+
+#[derive(Debug, StructOpt)]
+enum ConfigCommand {
+    /// Check the config file and report all problems found.
+    Validate,
+}
+
+#[derive(Debug, StructOpt)]
+enum CacheCommand {
+    /// Print the cached movies with their film ids, metadata and files.
+    Show(CacheTarget),
+    /// Delete the cache of movie ids.
+    Clear(CacheTarget),
+    /// Merge another cache file, e.g. from a different machine, into the cache.
+    Merge {
+        /// The cache file to merge.
+        other: PathBuf,
+        /// On conflicting ids, the ones of higher confidence are kept, or else the ones resolved
+        /// later. Of conflicting ids equal in both, prefer the ones from the other cache.
+        #[structopt(long)]
+        theirs: bool,
+        #[structopt(flatten)]
+        cache: CacheTarget,
+    },
+    /// Check the cached film ids against Letterboxd, removing the films which were deleted and
+    /// replacing the ones which were merged into other films.
+    Verify {
+        /// Only report the changed films, without updating the cache.
+        #[structopt(long)]
+        dry_run: bool,
+        #[structopt(flatten)]
+        cache: CacheTarget,
+    },
+    /// Remove the movies whose files no longer exist from the cache.
+    ///
+    /// Files of remote directories are listed to check for them, which needs the directory instead
+    /// of `--cache-path`.
+    Prune {
+        /// Only report the movies to remove, without updating the cache.
+        #[structopt(long)]
+        dry_run: bool,
+        #[structopt(flatten)]
+        cache: CacheTarget,
+    },
+}
+
+/// Options selecting a cache of movie ids.
+#[derive(Debug, StructOpt)]
+struct CacheTarget {
+    /// The directory whose cache of movie ids to use.
+    #[structopt(required_unless = "cache-path")]
+    directory: Option<Source>,
+    /// File of the cache of movie ids instead of the one of a directory.
+    #[structopt(long, value_name = "PATH", conflicts_with = "directory")]
+    cache_path: Option<PathBuf>,
+}
+
+impl CacheTarget {
+    fn path(&self, config: &Config) -> anyhow::Result<PathBuf> {
+        match &self.directory {
+            Some(directory) => cache_path(self.cache_path.as_deref(), directory, config),
+            None => self
+                .cache_path
+                .clone()
+                .ok_or_else(|| anyhow!("no directory given")),
+        }
+    }
+}
+
+/// Options for scanning a directory for movies.
+#[derive(Debug, StructOpt)]
+struct ScanArgs {
     /// Disable recursive search for movies in the given folder.
     #[structopt(long)]
     no_recursive: bool,
-    /// Regex pattern used to extract the movie names.
+    /// Search for movies at most N levels below the directories, 1 being the files in the
+    /// directories themselves, like 2 for a folder per movie.
+    #[structopt(
+        long,
+        value_name = "N",
+        parse(try_from_str = parse_max_depth),
+        conflicts_with = "no-recursive"
+    )]
+    max_depth: Option<usize>,
+    /// Regex pattern used to extract the movie names [default: `pattern` from the config file].
+    /// The `patterns` from the config file are tried next. If no pattern matches, file names are
+    /// parsed as release names like `Some.Movie.2019.1080p.BluRay.x264-GROUP`.
     #[structopt(long)]
-    pattern: String,
-    /// ID of the Letterboxd list to sync the movies with.
-    list_id: String,
-    /// The directory to scan movies in.
-    directory: PathBuf,
-    /// Do NOT update the list at Letterboxd.
+    pattern: Option<String>,
+    /// Extract the movie names from the names of the folders of the files first, and only then
+    /// from the file names. By default, folder names are used if the file name does not match.
     #[structopt(long)]
-    dry_run: bool,
+    prefer_folder_names: bool,
+    /// Treat a folder with a single movie file as the folder of this movie, as in Kodi, Jellyfin
+    /// and Plex libraries: its `movie.nfo` and then the folder name tell the movie, before the
+    /// file name. Extras like `Extras/` or `*-trailer.mkv` are skipped.
+    #[structopt(long)]
+    movie_folders: bool,
+    /// Include the files in the special folders for extras of Plex and Jellyfin libraries, like
+    /// `Extras/`, `Featurettes/`, `Behind The Scenes/`, `Deleted Scenes/`, `Trailers/` and
+    /// `Other/`, which are skipped by default.
+    #[structopt(long)]
+    include_extras: bool,
+    /// Read the title embedded in local MKV and MP4 files whose names have no release year, like
+    /// the ones named after a hash, before parsing the file name as release name.
+    #[structopt(long)]
+    container_titles: bool,
+    /// Comma-separated sources of the movie names, tried in this order until one tells the movie:
+    /// `nfo` files, the `folder` name, the `filename` matched by the patterns, the `container`
+    /// title of MKV and MP4 files, the file name parsed as `release` name and the raw file
+    /// `stem`. Replaces the default order, like `nfo,folder,filename`. IMDb and TMDb ids in file
+    /// names and the overrides apply to the movies of all sources.
+    #[structopt(
+        long,
+        value_name = "SOURCES",
+        require_delimiter = true,
+        possible_values = SourceKind::VARIANTS,
+        conflicts_with_all = &["prefer-folder-names", "movie-folders", "container-titles"]
+    )]
+    metadata_sources: Vec<SourceKind>,
+    /// Follow symbolic links to files and directories in local directories.
+    #[structopt(long)]
+    follow_symlinks: bool,
+    /// Do not descend into directories on other file systems than the one of the local directory,
+    /// like mount points.
+    #[structopt(long)]
+    one_file_system: bool,
+    /// How to handle files which look like TV episodes, named like `S01E02` or `1x02` or in a
+    /// season folder: skip them, keep them with a warning, or include them.
+    #[structopt(long, default_value = "skip", possible_values = TvBehavior::VARIANTS)]
+    tv_behavior: TvBehavior,
+    /// Skip files matching the gitignore-style pattern, like `Extras/` or `*sample*`. Can be given
+    /// multiple times. Local directories may contain a `.lbignore` file with such patterns too.
+    #[structopt(long, value_name = "GLOB", number_of_values = 1)]
+    exclude: Vec<String>,
+    /// Skip files smaller than the given size, like samples. The size is in bytes or, with the
+    /// suffix K, M, G or T, in multiples of 1024, e.g. `100M`. Sizes of files on SFTP hosts are
+    /// unknown, so they are never skipped.
+    #[structopt(long, value_name = "SIZE", parse(try_from_str = parse_size))]
+    min_size: Option<u64>,
+    /// Comma-separated extensions of the movie files, like `mkv,mp4,strm,iso` [default:
+    /// `extensions` from the config file or else mkv, mp4 and avi]. Overrides the extensions of
+    /// the mappings with `sync --all`.
+    #[structopt(long, value_name = "EXT", use_delimiter = true)]
+    extensions: Vec<String>,
+    /// The directory to scan movies in: a local path, `sftp://[user@]host[:port]/path`,
+    /// `webdav[s]://[user:password@]host/path`, `s3://bucket/prefix` or `rclone:remote:path`. The
+    /// movies of a Plex library are read with `plex[s]://host[:port][/library]` and the token in
+    /// PLEX_TOKEN, the ones of a Jellyfin or Emby library with
+    /// `jellyfin[s]://host[:port][/library]` and the API key in JELLYFIN_API_KEY.
+    #[structopt(required_unless_one = &[
+        "all", "list-url", "list-by-name", "target", "map", "per-folder-lists", "input-file",
+        "stdin",
+    ])]
+    directory: Option<Source>,
+    /// Further directories whose movies are scanned together with the ones of the directory, like
+    /// the movie folders of other disks.
+    #[structopt(value_name = "DIRECTORY")]
+    more_directories: Vec<Source>,
+    /// File listing the movies to use instead of a directory: a CSV file with the columns title,
+    /// year and IMDb id, of which only the title is required, or else a title per line, like
+    /// `Heat (1995)`. Same as the directory `input:<PATH>`.
+    #[structopt(
+        long,
+        value_name = "PATH",
+        parse(from_os_str = parse_input_file),
+        conflicts_with = "directory"
+    )]
+    input_file: Option<Source>,
+    /// Read the paths of the movie files from stdin, one per line, instead of scanning a
+    /// directory, like `find /movies -name '*.mkv' | letterboxd-list-sync sync --stdin <list>`.
+    /// Same as the directory `-`.
+    #[structopt(long, conflicts_with_all = &["directory", "input-file"])]
+    stdin: bool,
+}
+
+/// The directory of `--stdin`.
+static STDIN: Source = Source::Stdin;
+
+fn parse_input_file(path: &OsStr) -> Source {
+    Source::InputFile(PathBuf::from(path))
 }
 
-/// List all movie files in a dir.
-fn list_movie_files(path: PathBuf, recursively: bool) -> walkdir::Result<Vec<DirEntry>> {
-    const ACCEPTED_EXTENSIONS: &[&str] = &["mkv", "mp4", "avi"];
+impl ScanArgs {
+    /// The directory or input file given on the command line, which is only missing with
+    /// `sync --all`.
+    fn directory(&self) -> anyhow::Result<&Source> {
+        self.directory
+            .as_ref()
+            .or(self.input_file.as_ref())
+            .or(Some(&STDIN).filter(|_| self.stdin))
+            .ok_or_else(|| anyhow!("no directory given"))
+    }
 
-    fn is_hidden(entry: &DirEntry) -> bool {
-        entry
-            .file_name()
-            .to_str()
-            .map(|s| s != "." && s.starts_with('.'))
-            .unwrap_or(false)
+    /// Mapping of the directories given on the command line to the list `list_id`.
+    fn mapping(&self, list_id: &str) -> anyhow::Result<Mapping> {
+        Ok(Mapping {
+            more_directories: self.more_directories.clone(),
+            ..self.mapping_of(self.directory()?, list_id)
+        })
     }
 
-    fn is_accepted_file(entry: &DirEntry) -> bool {
-        !entry.file_type().is_file()
-            || entry
-                .path()
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .map(|ext| ACCEPTED_EXTENSIONS.contains(&ext))
-                .unwrap_or(false)
+    fn options(&self) -> ScanOptions {
+        ScanOptions {
+            pattern: self.pattern.clone(),
+            prefer_folder_names: self.prefer_folder_names,
+            movie_folders: self.movie_folders,
+            include_extras: self.include_extras,
+            container_titles: self.container_titles,
+            metadata_sources: self.metadata_sources.clone(),
+            walk: WalkOptions {
+                follow_symlinks: self.follow_symlinks,
+                one_file_system: self.one_file_system,
+            },
+            tv_behavior: self.tv_behavior,
+            exclude: self.exclude.clone(),
+            min_size: self.min_size,
+            extensions: self.extensions.clone(),
+        }
     }
 
-    let mut walker = WalkDir::new(path);
-    if !recursively {
-        walker = walker.max_depth(0);
+    /// Mapping of `directory` to the list `list_id` with the options given on the command line.
+    fn mapping_of(&self, directory: &Source, list_id: &str) -> Mapping {
+        Mapping {
+            directory: directory.clone(),
+            more_directories: Vec::new(),
+            list_id: list_id.to_string(),
+            target: Target::List,
+            recursive: !self.no_recursive,
+            max_depth: self.max_depth,
+            add_only: false,
+            extensions: None,
+            exclude: Vec::new(),
+            profile: None,
+        }
     }
-    walker
-        .into_iter()
-        .filter_entry(|e| !is_hidden(e) && is_accepted_file(e))
-        .filter_map(|res| {
-            res.map(|e| Some(e).filter(|e| e.file_type().is_file()))
-                .transpose()
+}
+
+/// Options of `serve`.
+///
+/// Webhooks are accepted at any path, like `http://host:8787/?token=...`. If `WEBHOOK_TOKEN` is
+/// set, only requests with this token as `token` query parameter are accepted.
+#[derive(Debug, StructOpt)]
+struct ServeArgs {
+    /// Address to accept webhooks on.
+    #[structopt(long, value_name = "ADDR", default_value = "127.0.0.1:8787")]
+    listen: SocketAddr,
+    /// Hours between syncs of all mappings, which catch the changes no webhook announced.
+    #[structopt(long, value_name = "HOURS", default_value = "24")]
+    reconcile_interval: u64,
+    #[structopt(flatten)]
+    sync: SyncArgs,
+}
+
+/// Options for resolving movie names to films.
+#[derive(Debug, StructOpt)]
+struct ResolveArgs {
+    /// Do not resolve movies with multiple close candidates or a release year differing by more
+    /// than a year from the year captured by the `year` group of the pattern. A sync stops before
+    /// updating the list then.
+    #[structopt(long)]
+    strict: bool,
+    /// Pick the film of ambiguous and low confidence matches among the search results. The picked
+    /// films are remembered in the cache.
+    #[structopt(long)]
+    interactive: bool,
+    /// Compare several search results with the movie name and year and take the best one only if
+    /// its confidence is at least the given value between 0 and 1. Movies with less confident
+    /// matches are reported and not added.
+    #[structopt(long, value_name = "CONFIDENCE", parse(try_from_str = parse_confidence))]
+    min_confidence: Option<f64>,
+    /// File pinning file names or glob patterns to Letterboxd film ids or URLs [default:
+    /// overrides.toml next to the default config file].
+    #[structopt(long, value_name = "PATH")]
+    overrides: Option<PathBuf>,
+    /// Search TMDb for the movies Letterboxd finds no film for, which is more forgiving of foreign
+    /// titles and typos, and take the film of the best match. Needs a TMDb API key in
+    /// TMDB_API_KEY.
+    #[structopt(long)]
+    tmdb_fallback: bool,
+    /// Search the movie names without diacritics first, like `Amelie` for `Amélie`, and the names
+    /// as guessed only if Letterboxd finds no film. Names which find no film are always searched
+    /// without diacritics too.
+    #[structopt(long)]
+    fold_diacritics: bool,
+    /// Search no more movies once N requests were sent to Letterboxd in this run, to share the API
+    /// key with other tools. The movies found so far are kept in the cache, so the next run
+    /// searches the others. A sync does not update the lists then.
+    #[structopt(long, value_name = "N")]
+    max_api_calls: Option<u64>,
+}
+
+impl ResolveArgs {
+    fn options(&self) -> anyhow::Result<ResolveOptions> {
+        let tmdb_api_key = if self.tmdb_fallback {
+            let api_key = env::var("TMDB_API_KEY")
+                .map_err(|_| anyhow!("missing variable TMDB_API_KEY for --tmdb-fallback"))?;
+            Some(api_key)
+        } else {
+            None
+        };
+        Ok(ResolveOptions {
+            // Ambiguous matches are picked from in interactive mode.
+            strict: self.strict || self.interactive,
+            min_confidence: self.min_confidence,
+            tmdb_api_key,
+            fold_diacritics: self.fold_diacritics,
+            max_api_calls: self.max_api_calls,
+        })
+    }
+
+    fn settings(&self) -> anyhow::Result<ResolveSettings> {
+        Ok(ResolveSettings {
+            options: self.options()?,
+            overrides: self.overrides.clone(),
+            pick: Some(pick_films as PickFilms).filter(|_| self.interactive),
+            strict: self.strict,
         })
-        .collect()
+    }
 }
 
-/// Search movie on letterbox.
-async fn search_movie(
-    client: &letterboxd::Client,
-    movie: String,
-) -> letterboxd::Result<letterboxd::SearchResponse> {
-    let request = letterboxd::SearchRequest {
-        cursor: None,
-        per_page: Some(1),
-        input: movie,
-        search_method: Some(letterboxd::SearchMethod::Autocomplete),
-        include: None,
-        contribution_type: None,
+fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let s = s.trim();
+    let digits = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit: u64 = match s[digits.len()..].to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        unit => return Err(anyhow!("unknown unit of size: {}", unit)),
     };
-    client.search(&request).await
+    let size: f64 = digits.trim().parse()?;
+    if size < 0.0 {
+        return Err(anyhow!("the size must not be negative"));
+    }
+    if !size.is_finite() {
+        return Err(anyhow!("the size is too large"));
+    }
+    Ok((size * unit as f64) as u64)
 }
 
-/// Extract movie names from file names with given pattern.
-fn extract_movie(pattern: &Regex, file_name: &str) -> Option<String> {
-    let matches = pattern.captures(file_name)?;
-    Some(matches.get(1)?.as_str().to_string())
+fn parse_max_depth(s: &str) -> anyhow::Result<usize> {
+    match s.parse()? {
+        0 => Err(anyhow!("the depth must be at least 1")),
+        depth => Ok(depth),
+    }
 }
 
-/// Get film ids response of list entries request.
-fn film_id_set_from_response(entries: Vec<letterboxd::ListEntry>) -> HashSet<String> {
-    entries.into_iter().map(|entry| entry.film.id).collect()
+/// Parse a duration like `90s`, `30m`, `6h` or `1d`. Numbers without unit are seconds.
+fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let s = s.trim();
+    let digits = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit: u64 = match s[digits.len()..].to_ascii_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" | "min" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        unit => return Err(anyhow!("unknown unit of duration: {}", unit)),
+    };
+    let duration: f64 = digits.trim().parse()?;
+    let duration = Duration::try_from_secs_f64(duration * unit as f64)?;
+    if duration.is_zero() {
+        return Err(anyhow!("duration must not be zero"));
+    }
+    Ok(duration)
 }
 
-async fn fetch_saved_films(
-    list_id: &str,
-    client: &letterboxd::Client,
-) -> letterboxd::Result<HashSet<String>> {
-    let mut request = letterboxd::ListEntriesRequest {
-        per_page: Some(100),
-        ..Default::default()
-    };
-    let mut entries: HashSet<String> = HashSet::new();
-    loop {
-        let response = client.list_entries(list_id, &request).await?;
-        entries.extend(film_id_set_from_response(response.items));
-        request.cursor = response.next;
-        if request.cursor.is_none() {
-            break;
+/// Parse a `DIR=LIST_ID` pair. The directory may contain `=` itself, list ids never do.
+fn parse_pair(s: &str) -> anyhow::Result<(Source, String)> {
+    let (directory, list_id) = s
+        .rsplit_once('=')
+        .filter(|(directory, list_id)| !directory.is_empty() && !list_id.is_empty())
+        .ok_or_else(|| anyhow!("expected DIR=LIST_ID"))?;
+    Ok((directory.parse()?, list_id.to_string()))
+}
+
+fn parse_confidence(s: &str) -> anyhow::Result<f64> {
+    let confidence: f64 = s.parse()?;
+    if !(0.0..=1.0).contains(&confidence) {
+        return Err(anyhow!("confidence must be between 0 and 1"));
+    }
+    Ok(confidence)
+}
+
+/// Options selecting a list by its URL or name instead of its id.
+#[derive(Debug, StructOpt)]
+struct ListArgs {
+    /// URL of the list instead of its id, like `https://letterboxd.com/user/list/my-collection/`.
+    #[structopt(long, value_name = "URL", conflicts_with = "list-by-name")]
+    list_url: Option<String>,
+    /// Name of a list of the authenticated member instead of its id.
+    #[structopt(long, value_name = "NAME")]
+    list_by_name: Option<String>,
+}
+
+/// Without a list id, like with `--list-url` or `--per-folder-lists`, the positional arguments are
+/// the directories, of which the argument parser takes the first for the list id though.
+fn shift_directory(list_id: &mut Option<String>, scan: &mut ScanArgs) -> anyhow::Result<()> {
+    if let Some(directory) = list_id.take() {
+        if let Some(second) = scan.directory.replace(directory.parse()?) {
+            scan.more_directories.insert(0, second);
         }
     }
-    Ok(entries)
+    Ok(())
 }
 
-fn get_cache_filename() -> anyhow::Result<std::path::PathBuf> {
-    const CACHE_FILENAME: &str = ".movies.json";
-    Ok(env::current_dir()?.join(CACHE_FILENAME))
+impl ListArgs {
+    /// Whether the list is given by URL or name.
+    fn is_given(&self) -> bool {
+        self.list_url.is_some() || self.list_by_name.is_some()
+    }
+
+    /// Id of the list given by `list_id`, or else by URL or name.
+    ///
+    /// Returns `None` if no list has the given name.
+    async fn find_list_id(
+        &self,
+        list_id: Option<&str>,
+        client: &letterboxd::Client,
+    ) -> anyhow::Result<Option<String>> {
+        if let Some(list_id) = list_id {
+            return Ok(Some(list_id.to_string()));
+        }
+        if let Some(url) = &self.list_url {
+            return find_list_by_url(client, url).await.map(Some);
+        }
+        if let Some(name) = &self.list_by_name {
+            return find_list_by_name(client, name).await;
+        }
+        Err(anyhow!("no list given"))
+    }
+
+    /// Id of the list given by `list_id`, or else by URL or name, which must exist.
+    async fn list_id(
+        &self,
+        list_id: Option<&str>,
+        client: &letterboxd::Client,
+    ) -> anyhow::Result<String> {
+        self.find_list_id(list_id, client).await?.ok_or_else(|| {
+            anyhow!(
+                "no list named '{}'",
+                self.list_by_name.as_deref().unwrap_or_default()
+            )
+        })
+    }
+}
+
+/// Options for the cache of movie ids while resolving film ids.
+#[derive(Debug, StructOpt)]
+struct CacheArgs {
+    /// Flush the cache to disk before replacing the previous cache file.
+    #[structopt(long)]
+    fsync_cache: bool,
+    /// Save the cache after every N newly searched movies.
+    #[structopt(long, value_name = "N")]
+    cache_flush_every: Option<usize>,
+    /// Save the cache every N seconds while resolving movie ids [default: 60 unless
+    /// --cache-flush-every is given]. A sync interrupted while searching movies resumes the search
+    /// from the saved cache.
+    #[structopt(long, value_name = "N")]
+    cache_flush_interval: Option<u64>,
+    /// Search the movies which an interrupted sync did not find again, instead of resuming the
+    /// search of the interrupted sync.
+    #[structopt(long)]
+    no_resume: bool,
+    /// File of the cache of movie ids, shared by all directories [default: a file per directory in
+    /// the user's cache dir, like ~/.cache/letterboxd-sync].
+    #[structopt(long, value_name = "PATH")]
+    cache_path: Option<PathBuf>,
+    /// Resolve the movies of the files matching the gitignore-style pattern again instead of
+    /// taking their film ids from the cache, like `*Heat*`. Can be given multiple times.
+    #[structopt(long, value_name = "GLOB", number_of_values = 1)]
+    refresh: Vec<String>,
+    /// Guess the movies of all files again, also of the ones whose size and modification time did
+    /// not change since the last sync. Needed after changing the patterns to guess movies with.
+    #[structopt(long)]
+    no_incremental: bool,
+}
+
+/// Seconds between saves of the cache while resolving movie ids, unless configured otherwise.
+const DEFAULT_FLUSH_INTERVAL: u64 = 60;
+
+impl CacheArgs {
+    fn options(&self) -> CacheOptions {
+        let flush_interval = match (self.cache_flush_every, self.cache_flush_interval) {
+            (Some(_), None) => None,
+            (_, interval) => Some(interval.unwrap_or(DEFAULT_FLUSH_INTERVAL)),
+        };
+        CacheOptions {
+            path: self.cache_path.clone(),
+            fsync: self.fsync_cache,
+            flush_every: self.cache_flush_every,
+            flush_interval: flush_interval.map(Duration::from_secs),
+            resume: !self.no_resume,
+            refresh: self.refresh.clone(),
+            incremental: !self.no_incremental,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct SyncArgs {
+    /// ID of the Letterboxd list to sync the movies with.
+    #[structopt(required_unless_one = &[
+        "all", "list-url", "list-by-name", "target", "map", "per-folder-lists",
+    ])]
+    list_id: Option<String>,
+    #[structopt(flatten)]
+    list: ListArgs,
+    /// Sync the directory with the list of the given id. Can be given multiple times to sync
+    /// several directories in one run, like the mappings of the config file with `--all`.
+    #[structopt(
+        long,
+        value_name = "DIR=LIST_ID",
+        number_of_values = 1,
+        parse(try_from_str = parse_pair),
+        conflicts_with_all = &[
+            "list-id", "list-url", "list-by-name", "target", "directory", "export-imdb",
+            "unwatched-list", "top-rated-list",
+        ]
+    )]
+    map: Vec<(Source, String)>,
+    /// Sync each top-level folder of the directory with the list named like the folder, which is
+    /// created if missing, e.g. `Horror/` with the list "Horror". Only local directories are
+    /// supported, and files outside of the folders are skipped.
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "list-url", "list-by-name", "target", "map", "export-imdb", "unwatched-list",
+            "top-rated-list",
+        ]
+    )]
+    per_folder_lists: bool,
+    /// Sync the movies with a list or with the watchlist of the authenticated member [default:
+    /// list]. The watchlist is neither ranked nor gets notes.
+    #[structopt(long, possible_values = Target::VARIANTS)]
+    target: Option<Target>,
+    /// Service to sync with [default: letterboxd]. With `trakt`, the list id is the slug of a
+    /// list of the member, or `collection` for their collection, and only films are added and
+    /// removed. Needs `TRAKT_CLIENT_ID` and `TRAKT_ACCESS_TOKEN`.
+    #[structopt(
+        long,
+        possible_values = Backend::VARIANTS,
+        conflicts_with_all = &[
+            "list-url", "list-by-name", "per-folder-lists", "target", "watch", "daemon",
+        ]
+    )]
+    backend: Option<Backend>,
+    /// Create the list given by `--list-by-name` if the authenticated member has no list with this
+    /// name.
+    #[structopt(long, requires = "list-by-name")]
+    create_if_missing: bool,
+    /// Who can see the lists created by `--create-if-missing` or `--per-folder-lists`.
+    #[structopt(long, default_value = "public", possible_values = Visibility::VARIANTS)]
+    visibility: Visibility,
+    #[structopt(flatten)]
+    scan: ScanArgs,
+    #[structopt(flatten)]
+    cache: CacheArgs,
+    #[structopt(flatten)]
+    resolve: ResolveArgs,
+    /// Sync all directories with their lists as mapped in the config file.
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "list-id", "list-url", "list-by-name", "target", "map", "per-folder-lists", "directory",
+            "export-imdb", "unwatched-list", "top-rated-list",
+        ]
+    )]
+    all: bool,
+    /// Do NOT update the list at Letterboxd.
+    #[structopt(long)]
+    dry_run: bool,
+    /// Show the changes of each list and update it only once confirmed on the terminal. Without a
+    /// terminal to confirm on, lists are not updated.
+    #[structopt(long, conflicts_with = "dry-run")]
+    confirm: bool,
+    /// Do not ask for confirmation of `--confirm` and `--removals prompt`, but update the lists.
+    #[structopt(long, short = "y")]
+    yes: bool,
+    /// Write the planned changes of all lists as JSON to the given file, to review and edit them
+    /// before updating the lists with `apply`.
+    #[structopt(
+        long,
+        value_name = "PATH",
+        requires = "dry-run",
+        conflicts_with = "watch"
+    )]
+    plan_file: Option<PathBuf>,
+    /// Add and remove at most N films with one update of a list. Larger updates are sent in
+    /// chunks, each checked to be applied before the next one.
+    #[structopt(long, value_name = "N", default_value = "500")]
+    chunk_size: usize,
+    /// Fetch each updated list again and check that it shows all changes, failing with exit code
+    /// 9 if the API dropped some of them.
+    #[structopt(long)]
+    verify: bool,
+    /// Write the files of the films with several copies, like a 1080p and a 2160p release, to the
+    /// given CSV file with their quality and size.
+    #[structopt(long, value_name = "PATH", conflicts_with_all = &["watch", "daemon"])]
+    duplicates_file: Option<PathBuf>,
+    /// Print a summary of the sync as JSON to stdout with `json`: the numbers of scanned files,
+    /// matched movies and cache hits, the unmatched files with the reason, the added and removed
+    /// films, the films with several copies and errors. Logs are written to stderr either way.
+    #[structopt(
+        long,
+        default_value = "text",
+        possible_values = OutputFormat::VARIANTS,
+        conflicts_with = "watch"
+    )]
+    output: OutputFormat,
+    /// Do not update the list if more than N files could not be matched to films.
+    #[structopt(long, value_name = "N")]
+    max_unmatched: Option<usize>,
+    /// Do not update the list if any file could not be matched to a film, like
+    /// `--max-unmatched 0`.
+    #[structopt(long, conflicts_with = "max-unmatched")]
+    fail_on_unmatched: bool,
+    /// Export the resolved films as CSV in the IMDb list import format to the given file.
+    #[structopt(long)]
+    export_imdb: Option<PathBuf>,
+    /// Write how each scanned file was matched to a film to the given CSV file: the guessed title
+    /// and year, how the film was found (cache, override, external id, search, TMDb or picked),
+    /// and the id, title, year and match confidence of the film.
+    #[structopt(long, value_name = "PATH", conflicts_with_all = &["watch", "daemon"])]
+    write_mapping: Option<PathBuf>,
+    /// Write a page linking the movies of the scanned files to their films on Letterboxd, to
+    /// review new matches before syncing without --dry-run. The page is Markdown if the file name
+    /// ends with `.md`, and HTML otherwise.
+    #[structopt(long, value_name = "PATH", conflicts_with_all = &["watch", "daemon"])]
+    review_page: Option<PathBuf>,
+    /// Suggest names like `Title (Year).mkv` for the files whose films were not found by an exact
+    /// search, like by a fuzzy search, TMDb, an override or by picking, so that later syncs find
+    /// them by an exact search.
+    #[structopt(long, conflicts_with_all = &["watch", "daemon"])]
+    suggest_renames: bool,
+    /// Rename the files as suggested by `--suggest-renames`. Only local files are renamed, and
+    /// never to the name of an existing file.
+    #[structopt(long, conflicts_with_all = &["watch", "daemon", "dry-run"])]
+    apply_renames: bool,
+    /// Template of the suggested file names, without extension. Placeholders: {title}, {year} and
+    /// {director}.
+    #[structopt(long, value_name = "TEMPLATE", default_value = DEFAULT_RENAME_TEMPLATE)]
+    rename_template: String,
+    /// Template for the notes of added entries, like "{resolution} {source}, added {added}".
+    /// Placeholders: {title}, {year}, {director}, {runtime} (in minutes), {languages} (audio and
+    /// subtitle languages from ffprobe) and of the movie file {resolution}, {source} (like
+    /// BluRay or Remux), {path} (relative to the directory), {tags} (the folders of the file, see
+    /// `--path-tags`) and {added} (date of the last modification).
+    #[structopt(long, value_name = "TEMPLATE")]
+    entry_notes: Option<String>,
+    /// Add the audio and subtitle languages of the movie files, as reported by ffprobe, to the
+    /// notes of added entries. Shorthand for `--entry-notes {languages}`.
+    #[structopt(long)]
+    language_notes: bool,
+    /// Add tags from the folders of the movie files to the notes of added entries, like `horror,
+    /// slasher` for `Horror/Slasher/Movie.mkv`, as list entries on Letterboxd have no tags of
+    /// their own. Folders of a single movie, named with a release year, make no tags. Shorthand
+    /// for `--entry-notes {tags}`, combined with `--language-notes`.
+    #[structopt(long)]
+    path_tags: bool,
+    /// Only remove films from the list which are not in the directory, never add any.
+    #[structopt(long)]
+    remove_only: bool,
+    /// Whether to remove films from the list which are not in the directory. With `prompt`, the
+    /// films are listed and removed only once confirmed.
+    #[structopt(long, default_value = "always", possible_values = RemovalPolicy::VARIANTS)]
+    removals: RemovalPolicy,
+    /// Keep running and sync again whenever movie files are added, renamed or deleted. Only local
+    /// directories are watched.
+    #[structopt(long)]
+    watch: bool,
+    /// Seconds without further changes or webhooks to wait for before syncing in watch mode or by
+    /// `serve`.
+    #[structopt(long, value_name = "SECS", default_value = "10")]
+    debounce: u64,
+    /// Minimum number of seconds between two syncs in watch mode.
+    #[structopt(long, value_name = "SECS", default_value = "300")]
+    min_interval: u64,
+    /// Serve metrics of the syncs in the format of Prometheus at `http://ADDR/metrics` in watch
+    /// mode, daemon mode or by `serve`, like `0.0.0.0:9595`.
+    #[structopt(long, value_name = "ADDR")]
+    metrics: Option<SocketAddr>,
+    /// Keep running and sync every `--interval`, keeping the login between the syncs. Ctrl-C or
+    /// SIGTERM stop it once the running sync is done.
+    #[structopt(long, requires = "interval", conflicts_with = "watch")]
+    daemon: bool,
+    /// Time between the starts of two syncs in daemon mode, like `6h`, `30m` or `1d`.
+    #[structopt(long, value_name = "DURATION", parse(try_from_str = parse_duration))]
+    interval: Option<Duration>,
+    /// Delay each sync in daemon mode by a random time up to this duration, so syncs of several
+    /// machines do not start at the same time.
+    #[structopt(long, value_name = "DURATION", parse(try_from_str = parse_duration))]
+    jitter: Option<Duration>,
+    /// Rename the list. By default, the name of the list is kept.
+    #[structopt(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = &["all", "map", "per-folder-lists"]
+    )]
+    list_name: Option<String>,
+    /// Replace the description of the list. By default, the description of the list is kept.
+    #[structopt(long, conflicts_with_all = &["all", "map", "per-folder-lists"])]
+    description: Option<String>,
+    /// Leave films already watched on Letterboxd out of the list.
+    #[structopt(long)]
+    exclude_watched: bool,
+    /// Mark the films with files in this folder of the directory as watched on Letterboxd, like
+    /// `Watched` for movies moved there after watching them. No diary entries are logged, unless
+    /// `--log-tags` is given.
+    #[structopt(long, value_name = "FOLDER")]
+    mark_watched: Option<PathBuf>,
+    /// Log the films marked as watched as diary entries of today instead, tagged with the folders
+    /// of their files below the watched folder, like `criterion` and `4k` for
+    /// `Watched/Criterion/4K/Movie.mkv`. Folders of a single movie, named with a release year,
+    /// make no tags.
+    #[structopt(long, requires = "mark-watched")]
+    log_tags: bool,
+    /// Set the ratings of the films on Letterboxd from this CSV file, with rows like
+    /// `Heat (1995),4.5` or `<file name>,4.5`. Defaults to `ratings.csv` in a local directory, if
+    /// there is one. Only ratings which changed since the last sync are set.
+    #[structopt(long, value_name = "FILE")]
+    ratings: Option<PathBuf>,
+    /// ID of a second list to sync with the movies not yet watched on Letterboxd.
+    #[structopt(long, value_name = "LIST_ID")]
+    unwatched_list: Option<String>,
+    /// Add the films removed from the synced list to this list, like an archive of sold movies.
+    /// Films back in the directory are removed from it again.
+    #[structopt(long, value_name = "LIST_ID")]
+    archive_removals_to: Option<String>,
+    /// Keep the entries of the synced lists ranked in the given order. With `added`, the entries
+    /// keep their rank and added films follow, and `file-mtime` ranks the films with the newest
+    /// files first.
+    #[structopt(long, possible_values = SortOrder::VARIANTS)]
+    sort: Option<SortOrder>,
+    /// ID of a second list to sync with the movies rated at least `--min-rating` on average.
+    #[structopt(long, value_name = "LIST_ID")]
+    top_rated_list: Option<String>,
+    /// Minimum average rating of the movies in the top rated list.
+    #[structopt(long, default_value = "3.5")]
+    min_rating: f32,
 }
 
-fn load_ids_list_from_cache(path: impl AsRef<Path>) -> anyhow::Result<HashMap<String, String>> {
-    let file = fs::File::open(path);
-    let ids = match file {
-        Ok(file) => {
-            let ids: HashMap<String, String> = serde_json::from_reader(file)?;
-            debug!("Loaded {} movie ids from cache.", ids.len());
-            ids
+impl SyncArgs {
+    /// Template for the notes of added entries, if any.
+    fn notes_template(&self) -> Option<String> {
+        if let Some(template) = &self.entry_notes {
+            return Some(template.clone());
+        }
+        let placeholders: Vec<&str> = [
+            ("{languages}", self.language_notes),
+            ("{tags}", self.path_tags),
+        ]
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(placeholder, _)| *placeholder)
+        .collect();
+        Some(placeholders.join("\n")).filter(|template| !template.is_empty())
+    }
+
+    /// Maximum number of files which may stay unmatched for the list to be updated, if any.
+    fn max_unmatched(&self) -> Option<usize> {
+        if self.fail_on_unmatched {
+            Some(0)
+        } else {
+            self.max_unmatched
         }
-        Err(err) => {
-            if err.kind() == io::ErrorKind::NotFound {
-                HashMap::new()
+    }
+
+    fn options(&self) -> anyhow::Result<SyncOptions> {
+        // With --yes, the removals are not asked for.
+        let removals = match self.removals {
+            RemovalPolicy::Prompt if self.yes => RemovalPolicy::Always,
+            removals => removals,
+        };
+        Ok(SyncOptions {
+            scan: self.scan.options(),
+            cache: self.cache.options(),
+            resolve: self.resolve.settings()?,
+            dry_run: self.dry_run,
+            confirm_update: Some(confirm_update as Confirm).filter(|_| self.confirm && !self.yes),
+            confirm_removals: Some(confirm_removals),
+            chunk_size: self.chunk_size,
+            verify: self.verify,
+            max_unmatched: self.max_unmatched(),
+            export_imdb: self.export_imdb.clone(),
+            remove_only: self.remove_only,
+            removals,
+            list_name: self.list_name.clone(),
+            description: self.description.clone(),
+            notes_template: self.notes_template(),
+            exclude_watched: self.exclude_watched,
+            mark_watched: self.mark_watched.clone(),
+            log_tags: self.log_tags,
+            ratings: self.ratings.clone(),
+            unwatched_list: self.unwatched_list.clone(),
+            archive_removals_to: self.archive_removals_to.clone(),
+            sort: self.sort,
+            top_rated_list: self.top_rated_list.clone(),
+            min_rating: self.min_rating,
+            debounce: Duration::from_secs(self.debounce),
+        })
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct CompareArgs {
+    /// ID of any public Letterboxd list to compare the movies with.
+    #[structopt(required_unless_one = &["list-url", "list-by-name"])]
+    list_id: Option<String>,
+    #[structopt(flatten)]
+    list: ListArgs,
+    #[structopt(flatten)]
+    scan: ScanArgs,
+    #[structopt(flatten)]
+    cache: CacheArgs,
+    #[structopt(flatten)]
+    resolve: ResolveArgs,
+}
+
+#[derive(Debug, StructOpt)]
+struct MissingArgs {
+    #[structopt(flatten)]
+    compare: CompareArgs,
+    /// Print the missing entries with their position in the list as CSV or JSON instead of text.
+    #[structopt(long, possible_values = ExportFormat::VARIANTS)]
+    format: Option<ExportFormat>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ExportArgs {
+    /// File to write the CSV to [default: stdout].
+    #[structopt(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Only take the films from the cache, which needs no Letterboxd credentials. The other movies
+    /// are exported by title and year, for the import to match them.
+    #[structopt(long)]
+    offline: bool,
+    #[structopt(flatten)]
+    scan: ScanArgs,
+    #[structopt(flatten)]
+    cache: CacheArgs,
+    #[structopt(flatten)]
+    resolve: ResolveArgs,
+}
+
+#[derive(Debug, StructOpt)]
+struct ExportListArgs {
+    /// ID of any public Letterboxd list to export.
+    #[structopt(required_unless_one = &["list-url", "list-by-name"])]
+    list_id: Option<String>,
+    #[structopt(flatten)]
+    list: ListArgs,
+    /// File to write the entries to [default: stdout].
+    #[structopt(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Format of the export: CSV with a header row, or a JSON array.
+    #[structopt(long, default_value = "csv", possible_values = ExportFormat::VARIANTS)]
+    format: ExportFormat,
+}
+
+#[derive(Debug, StructOpt)]
+struct DiffListsArgs {
+    /// ID or URL of the first list.
+    list_a: String,
+    /// ID or URL of the second list.
+    list_b: String,
+    /// Format of the output: lines of title and URL, or JSON.
+    #[structopt(long, default_value = "text", possible_values = OutputFormat::VARIANTS)]
+    format: OutputFormat,
+}
+
+#[derive(Debug, StructOpt)]
+struct CompletionsArgs {
+    /// Shell to write the completions for.
+    #[structopt(
+        possible_values = &Shell::variants(),
+        case_insensitive = true,
+        required_unless = "man"
+    )]
+    shell: Option<Shell>,
+    /// Write the man page in roff instead, like `completions --man > letterboxd-list-sync.1`.
+    #[structopt(long, conflicts_with = "shell")]
+    man: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct DoctorArgs {
+    /// Ids of further lists to check, besides the ones of the mappings of the config file.
+    lists: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct StatsArgs {
+    /// Number of directors with the most films to show.
+    #[structopt(long, default_value = "10", value_name = "N")]
+    top: usize,
+    /// Only count the films in the cache, which needs no Letterboxd credentials. Details of films
+    /// are only known if fetched before, and watched films are not counted.
+    #[structopt(long)]
+    offline: bool,
+    /// Format of the output: text or JSON.
+    #[structopt(long, default_value = "text", possible_values = OutputFormat::VARIANTS)]
+    format: OutputFormat,
+    #[structopt(flatten)]
+    scan: ScanArgs,
+    #[structopt(flatten)]
+    cache: CacheArgs,
+    #[structopt(flatten)]
+    resolve: ResolveArgs,
+}
+
+#[derive(Debug, StructOpt)]
+struct ApplyArgs {
+    /// The plan file to apply.
+    plan_file: PathBuf,
+    /// Add and remove at most N films with one update of a list. Larger updates are sent in
+    /// chunks, each checked to be applied before the next one.
+    #[structopt(long, value_name = "N", default_value = "500")]
+    chunk_size: usize,
+    /// Show the changes of each list and update it only once confirmed on the terminal.
+    #[structopt(long)]
+    confirm: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct RollbackArgs {
+    /// ID of the Letterboxd list to restore.
+    #[structopt(required_unless_one = &["list-url", "list-by-name"])]
+    list_id: Option<String>,
+    #[structopt(flatten)]
+    list: ListArgs,
+    /// Only show the changes restoring the list, without updating it.
+    #[structopt(long)]
+    dry_run: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct DiffArgs {
+    /// ID of the Letterboxd list to compute the changes for.
+    #[structopt(required_unless_one = &["list-url", "list-by-name"])]
+    list_id: Option<String>,
+    #[structopt(flatten)]
+    list: ListArgs,
+    #[structopt(flatten)]
+    scan: ScanArgs,
+    #[structopt(flatten)]
+    cache: CacheArgs,
+    #[structopt(flatten)]
+    resolve: ResolveArgs,
+    /// Only show films to remove, as with `sync --remove-only`.
+    #[structopt(long)]
+    remove_only: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct RetryArgs {
+    #[structopt(flatten)]
+    cache: CacheArgs,
+    #[structopt(flatten)]
+    resolve: ResolveArgs,
+}
+
+/// Retry resolving the movies in the report of unresolved movies, adding the resolved ones to the
+/// cache and removing them from the report.
+async fn retry_unresolved(args: &RetryArgs, config: &Config) -> anyhow::Result<()> {
+    let client = read_only_client().await?;
+    let resolve = args.resolve.settings()?;
+    pipeline::retry_unresolved(&resolve, &args.cache.options(), config, &client).await
+}
+
+/// Let the user pick the films of ambiguous and low confidence matches among the search results.
+///
+/// Picked films are resolved, skipped movies stay unresolved.
+fn pick_films(resolved: &mut ResolvedFilms) -> anyhow::Result<()> {
+    let movies: Vec<(String, String)> = resolved
+        .unresolved()
+        .into_iter()
+        .filter(|(movie, _)| resolved.candidates.contains_key(movie))
+        .collect();
+    if movies.is_empty() {
+        return Ok(());
+    }
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        return Err(anyhow!("no terminal to pick films in interactive mode"));
+    }
+
+    for (movie, reason) in movies {
+        let candidates = match resolved.candidates.get(&movie) {
+            Some(candidates) if !candidates.is_empty() => candidates,
+            _ => continue,
+        };
+        println!("{}", Message::PickFilm(&movie, &reason));
+        for (idx, film) in candidates.iter().enumerate() {
+            let directors: Vec<&str> = film.directors.iter().map(|d| d.name.as_str()).collect();
+            if directors.is_empty() {
+                println!("  {}) {}", idx + 1, film_title(film));
             } else {
-                return Err(err.into());
+                println!(
+                    "  {}) {} – {}",
+                    idx + 1,
+                    film_title(film),
+                    directors.join(", ")
+                );
             }
         }
+        let picked = loop {
+            print!("{} ", Message::PickFilmPrompt(candidates.len()));
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            if stdin.read_line(&mut answer)? == 0 || answer.trim().is_empty() {
+                break None;
+            }
+            match answer.trim().parse::<usize>() {
+                Ok(n) if (1..=candidates.len()).contains(&n) => break Some(&candidates[n - 1]),
+                _ => continue,
+            }
+        };
+        if let Some(film) = picked {
+            debug!("Picked {} for {}", film.id, movie);
+            let id = film.id.clone();
+            resolved.pick(&movie, &id);
+        }
+    }
+    Ok(())
+}
+
+async fn scan(args: &ScanArgs, config: &Config) -> anyhow::Result<()> {
+    let mapping = args.mapping("")?;
+    let directory = &mapping.directory;
+    let options = args.options();
+    let files = list_mapping_files(&mapping, &options, config).await?;
+    let metadata = metadata_chain(&mapping, &files, &options, config)?;
+    let mut paths: Vec<PathBuf> = files.iter().map(|file| file.path.clone()).collect();
+    // Files of the same movie, like the parts of a multi-part movie, show the same movie.
+    let (movies, files_by_movie) = guess_source_movies(directory, files, &metadata).await?;
+    if directory.lists_movies() {
+        paths = files_by_movie
+            .values()
+            .flatten()
+            .map(|file| file.path.clone())
+            .collect();
+    }
+    let mut movies_by_path: HashMap<&Path, String> = HashMap::new();
+    for movie in &movies {
+        for file in &files_by_movie[&movie.title] {
+            movies_by_path.insert(&file.path, movie.to_string());
+        }
+    }
+    let mut lines: Vec<(&Path, String)> = paths
+        .iter()
+        .map(|path| {
+            let movie = movies_by_path
+                .remove(path.as_path())
+                .unwrap_or_else(|| Message::NoMatch.to_string());
+            (path.as_path(), movie)
+        })
+        .collect();
+    lines.sort();
+    for (path, movie) in lines {
+        println!("{}\t{}", movie, path.display());
+    }
+    Ok(())
+}
+
+async fn diff(args: &DiffArgs, config: &Config) -> anyhow::Result<()> {
+    let client = read_only_client().await?;
+    let list_id = args.list.list_id(args.list_id.as_deref(), &client).await?;
+    let mapping = args.scan.mapping(&list_id)?;
+    let resolved = scan_and_resolve(
+        &mapping,
+        &args.scan.options(),
+        &args.cache.options(),
+        &args.resolve.settings()?,
+        config,
+        Some(&client),
+    )
+    .await?;
+    report_resolve_errors([&resolved]);
+    let ids: HashSet<String> = resolved.ids.values().cloned().collect();
+    let mut plan = SyncPlan::new(&client, &list_id, &ids, None, args.remove_only)
+        .await
+        .context("failed to fetch the list")?;
+    plan.to_add.sort();
+    plan.to_remove.sort();
+
+    let movies_by_id: HashMap<&str, &str> = resolved
+        .ids
+        .iter()
+        .map(|(movie, id)| (id.as_str(), movie.as_str()))
+        .collect();
+    println!("{}", Message::ToAdd(plan.to_add.len()));
+    for id in &plan.to_add {
+        println!("  + {} ({})", movies_by_id[id.as_str()], id);
+    }
+    println!("{}", Message::ToRemove(plan.to_remove.len()));
+    for id in &plan.to_remove {
+        println!("  - {} ({})", plan.title(id), id);
+    }
+    Ok(())
+}
+
+/// Prompt for the credentials and store the token in the keyring.
+async fn login(username: Option<String>) -> anyhow::Result<()> {
+    let api_key_pair = auth::api_key_pair()?;
+    let username = username.or_else(|| auth::profile()?.username.clone());
+    let username = match username {
+        Some(username) => username,
+        None => {
+            eprint!("{}", Message::UsernamePrompt);
+            io::stderr().flush()?;
+            let mut username = String::new();
+            io::stdin()
+                .read_line(&mut username)
+                .context("failed to read the username")?;
+            username.trim().to_string()
+        }
     };
-    Ok(ids)
+    if username.is_empty() {
+        return Err(anyhow!("no username given"));
+    }
+    let password = rpassword::prompt_password(Message::PasswordPrompt.to_string())
+        .context("failed to read the password")?;
+    auth::login(api_key_pair, &username, &password).await?;
+    info!("{}", Message::LoggedIn(&username));
+    Ok(())
 }
 
-fn save_ids_list_to_cache(
-    ids: &HashMap<String, String>,
-    path: impl AsRef<Path>,
-) -> anyhow::Result<()> {
-    let file = fs::File::create(path)?;
-    Ok(serde_json::to_writer_pretty(file, &ids)?)
+async fn logout() -> anyhow::Result<()> {
+    if auth::logout().await? {
+        info!("{}", Message::LoggedOut);
+    } else {
+        info!("{}", Message::NotLoggedIn);
+    }
+    Ok(())
 }
 
-/// Resolve movie ids from movie names by first looking in the given cache, and then, if not found,
-/// by making a request through letterboxd api.
-async fn resolve_film_ids(
-    movie_names: impl IntoIterator<Item = String>,
-    film_ids_cache: &HashMap<String, String>,
-    client: &letterboxd::Client,
-) -> letterboxd::Result<HashMap<String, String>> {
-    let film_id_requests = movie_names.into_iter().map(|movie| async {
-        if let Some(id) = film_ids_cache.get(&movie) {
-            Ok(Some((movie, id.clone())))
-        } else {
-            let response = search_movie(&client, movie.clone()).await?;
-            let first_item = response.items.into_iter().next();
-            match first_item {
-                Some(letterboxd::AbstractSearchItem::FilmSearchItem { film, .. }) => {
-                    debug!("Resolved id of {}: {}", movie, film.id);
-                    Ok(Some((movie, film.id)))
-                }
-                _ => {
-                    warn!("Did not find id for movie: {}", movie);
-                    Ok(None)
+async fn auth() -> anyhow::Result<()> {
+    let client = new_client().await?;
+    let account = client
+        .me()
+        .await
+        .context("failed to fetch the authenticated member")?;
+    info!("{}", Message::Authenticated(&account.member.username));
+    Ok(())
+}
+
+/// Run the checks of `doctor`, printing a diagnostic for each. Only failed checks fail.
+async fn doctor(args: &DoctorArgs, config_path: Option<&Path>) -> anyhow::Result<()> {
+    let diagnostics = doctor::run_checks(config_path, &args.lists).await;
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    let failed = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.status == Status::Failed)
+        .count();
+    if failed == 0 {
+        info!("{}", Message::DoctorPassed);
+        Ok(())
+    } else {
+        Err(anyhow!("{} of {} checks failed", failed, diagnostics.len()))
+    }
+}
+
+/// Print the cached movies, one per line with the film id, the guessed year, the confidence of
+/// the match, the date of the resolution and the files, separated by tabs.
+fn show_cache(path: &Path) -> anyhow::Result<()> {
+    let cache = load_movie_cache(path)
+        .with_context(|| format!("failed to read cache file at: {}", path.display()))?;
+    for (movie, entry) in &cache.movies {
+        let year = entry.year.map(|year| year.to_string()).unwrap_or_default();
+        let confidence = entry
+            .confidence
+            .map(|confidence| format!("{:.2}", confidence))
+            .unwrap_or_default();
+        let resolved_at = entry
+            .resolved_at
+            .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        let files: Vec<_> = entry
+            .files
+            .iter()
+            .map(|file| file.path.display().to_string())
+            .collect();
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            movie,
+            entry.film_id,
+            year,
+            confidence,
+            resolved_at,
+            files.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Export the movies of the directory for the Letterboxd import: the resolved ones as films, the
+/// others by the guessed title and year.
+async fn export(args: ExportArgs, config: &Config) -> anyhow::Result<()> {
+    let client = if args.offline {
+        None
+    } else {
+        Some(read_only_client().await?)
+    };
+    let mapping = args.scan.mapping("")?;
+    let resolved = scan_and_resolve(
+        &mapping,
+        &args.scan.options(),
+        &args.cache.options(),
+        &args.resolve.settings()?,
+        config,
+        client.as_ref(),
+    )
+    .await?;
+    report_resolve_errors([&resolved]);
+
+    let rows = letterboxd_import_rows(&resolved, config)?;
+
+    match &args.output {
+        Some(path) => {
+            let file = fs::File::create(path)
+                .with_context(|| format!("failed to create export file: {}", path.display()))?;
+            write_letterboxd_import(&rows, file)?;
+            info!(
+                "{}",
+                Message::ExportedLibrary {
+                    count: rows.len(),
+                    matched: resolved.ids.len(),
+                    path: &path.display().to_string(),
                 }
-            }
+            );
         }
-    });
+        None => write_letterboxd_import(&rows, io::stdout())?,
+    }
+    Ok(())
+}
 
-    stream::iter(film_id_requests)
-        .buffer_unordered(REQUESTS_CONCURRENCY)
-        .filter_map(|res| std::future::ready(res.transpose()))
-        .try_collect()
+/// Export the entries of a list in the order of the list.
+async fn export_list(args: &ExportListArgs) -> anyhow::Result<()> {
+    let client = read_only_client().await?;
+    let list_id = args.list.list_id(args.list_id.as_deref(), &client).await?;
+    let entries = fetch_list_entries(&list_id, &client)
         .await
+        .context("failed to fetch the list")?;
+    let rows: Vec<ListEntryRow> = entries
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| ListEntryRow::new(idx, entry))
+        .collect();
+    match &args.output {
+        Some(path) => {
+            let file = fs::File::create(path)
+                .with_context(|| format!("failed to create export file: {}", path.display()))?;
+            write_list_entries(&rows, args.format, file)?;
+            info!(
+                "{}",
+                Message::ExportedList {
+                    count: rows.len(),
+                    path: &path.display().to_string(),
+                }
+            );
+        }
+        None => write_list_entries(&rows, args.format, io::stdout())?,
+    }
+    Ok(())
 }
 
-async fn new_client() -> anyhow::Result<letterboxd::Client> {
-    let username = env::var("LETTERBOXD_USERNAME")
-        .map_err(|_| anyhow!("missing obligatory variable LETTERBOXD_USERNAME"))?;
-    let password = env::var("LETTERBOXD_PASSWORD")
-        .map_err(|_| anyhow!("missing obligatory variable LETTERBOXD_PASSWORD"))?;
-
-    let api_key_pair = letterboxd::ApiKeyPair::from_env().ok_or_else(|| {
-        anyhow!(
-            "No API key/secret environment variable found: \
-            check if LETTERBOXD_API_KEY/LETTERBOXD_API_SECRET is set"
-        )
-    })?;
-    // TODO: cache token
-    letterboxd::Client::authenticate(api_key_pair, &username, &password)
-        .await
-        .context("failed to authenticate on Letterboxd")
+/// Id of the list given by id or URL.
+async fn list_id_or_url(list: &str, client: &letterboxd::Client) -> anyhow::Result<String> {
+    if list.starts_with("https://") || list.starts_with("http://") {
+        find_list_by_url(client, list).await
+    } else {
+        Ok(list.to_string())
+    }
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = Args::from_args();
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-    dotenv::dotenv().ok();
+fn print_entry_rows(rows: &[ListEntryRow]) {
+    for row in rows {
+        match row.year {
+            Some(year) => println!("  {} ({})\t{}", row.title, year, row.url),
+            None => println!("  {}\t{}", row.title, row.url),
+        }
+    }
+}
 
-    let cache_path = get_cache_filename().context("failed to resolve cache path")?;
+async fn diff_lists(args: &DiffListsArgs) -> anyhow::Result<()> {
+    let client = read_only_client().await?;
+    let mut entries = Vec::new();
+    for list in [&args.list_a, &args.list_b] {
+        let list_id = list_id_or_url(list, &client).await?;
+        let list_entries = fetch_list_entries(&list_id, &client)
+            .await
+            .with_context(|| format!("failed to fetch list {}", list))?;
+        entries.push(list_entries);
+    }
+    let diff = ListDiff::new(&entries[0], &entries[1]);
+    if args.format == OutputFormat::Json {
+        serde_json::to_writer_pretty(io::stdout(), &diff).context("failed to write the diff")?;
+        println!();
+        return Ok(());
+    }
+    let sections = [
+        (Message::OnlyInList(&args.list_a), &diff.only_in_a),
+        (Message::OnlyInList(&args.list_b), &diff.only_in_b),
+        (Message::InBothLists, &diff.in_both),
+    ];
+    for (heading, rows) in &sections {
+        println!("{} ({}):", heading, rows.len());
+        print_entry_rows(rows);
+    }
+    Ok(())
+}
 
-    let files = list_movie_files(args.directory.clone(), !args.no_recursive)
-        .with_context(|| format!("failed to list files in '{}'", args.directory.display()))?;
-    log::debug!("Found {} movie files", files.len());
+async fn stats(args: &StatsArgs, config: &Config) -> anyhow::Result<()> {
+    let client = if args.offline {
+        None
+    } else {
+        Some(read_only_client().await?)
+    };
+    let mapping = args.scan.mapping("")?;
+    let resolved = scan_and_resolve(
+        &mapping,
+        &args.scan.options(),
+        &args.cache.options(),
+        &args.resolve.settings()?,
+        config,
+        client.as_ref(),
+    )
+    .await?;
+    report_resolve_errors([&resolved]);
+    let stats = library_stats(
+        &resolved,
+        args.top,
+        args.cache.fsync_cache,
+        config,
+        client.as_ref(),
+    )
+    .await?;
 
-    let client = new_client().await?;
+    if args.format == OutputFormat::Json {
+        serde_json::to_writer_pretty(io::stdout(), &stats)
+            .context("failed to write the statistics")?;
+        println!();
+        return Ok(());
+    }
+    println!(
+        "{}",
+        Message::StatsFilms {
+            films: stats.films,
+            unresolved: stats.unresolved_files,
+        }
+    );
+    if let Some(watched) = stats.watched {
+        println!(
+            "{}",
+            Message::StatsWatched {
+                watched,
+                films: stats.films,
+            }
+        );
+    }
+    if let Some(average) = stats.average_runtime {
+        println!(
+            "{}",
+            Message::StatsRuntime {
+                average,
+                total_hours: stats.total_runtime / 60,
+            }
+        );
+    }
+    if !stats.decades.is_empty() {
+        println!("{}", Message::StatsDecades);
+        for (decade, count) in &stats.decades {
+            println!("  {}\t{}", Message::Decade(*decade), count);
+        }
+    }
+    if !stats.top_directors.is_empty() {
+        println!("{}", Message::StatsTopDirectors);
+        for (director, count) in &stats.top_directors {
+            println!("  {}\t{}", director, count);
+        }
+    }
+    Ok(())
+}
 
-    // Collect all movie names
-    let re = Regex::new(&args.pattern)?;
-    let movie_names = files
-        .into_iter()
-        .filter_map(|entry| extract_movie(&re, entry.file_name().to_str()?));
+/// The entries of the list of `args` and the ids of the films resolved in its directory.
+async fn list_and_directory(
+    args: &CompareArgs,
+    config: &Config,
+) -> anyhow::Result<(Vec<letterboxd::ListEntry>, HashSet<String>)> {
+    let client = read_only_client().await?;
+    let list_id = args.list.list_id(args.list_id.as_deref(), &client).await?;
+    let mapping = args.scan.mapping(&list_id)?;
+    let resolved = scan_and_resolve(
+        &mapping,
+        &args.scan.options(),
+        &args.cache.options(),
+        &args.resolve.settings()?,
+        config,
+        Some(&client),
+    )
+    .await?;
+    report_resolve_errors([&resolved]);
+    let ids = resolved.ids.into_values().collect();
 
-    // Resolve movie ids either from cache or by requesting these
-    let film_ids_cache = load_ids_list_from_cache(&cache_path)
-        .with_context(|| format!("failed to read cache file at: {}", cache_path.display()))?;
-    let film_ids = resolve_film_ids(movie_names, &film_ids_cache, &client)
+    let entries = fetch_list_entries(&list_id, &client)
         .await
-        .context("failed to resolve film ids")?;
+        .context("failed to fetch the list")?;
+    Ok((entries, ids))
+}
 
-    // Fetch ids for films already on list.
-    let saved_film_ids = fetch_saved_films(&args.list_id, &client)
-        .await
-        .context("failed to fetch ids already on the list")?;
+async fn compare(args: CompareArgs, config: &Config) -> anyhow::Result<()> {
+    let (entries, ids) = list_and_directory(&args, config).await?;
+    let (owned, missing): (Vec<_>, Vec<_>) = entries
+        .iter()
+        .map(|entry| &entry.film)
+        .partition(|film| ids.contains(&film.id));
 
-    if let Err(err) = save_ids_list_to_cache(&film_ids, cache_path) {
-        warn!("failed to save film ids to cache: {}", err);
+    println!(
+        "{}",
+        Message::Owned {
+            count: owned.len(),
+            total: entries.len(),
+        }
+    );
+    for film in owned {
+        println!("  {}", film_title(film));
     }
+    println!(
+        "{}",
+        Message::Missing {
+            count: missing.len(),
+            total: entries.len(),
+        }
+    );
+    for film in missing {
+        println!("  {}", film_title(film));
+    }
+    Ok(())
+}
+
+/// Print the entries of the list whose films are not in the directory.
+async fn missing(args: &MissingArgs, config: &Config) -> anyhow::Result<()> {
+    let (entries, ids) = list_and_directory(&args.compare, config).await?;
+    let missing: Vec<ListEntryRow> = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| !ids.contains(&entry.film.id))
+        .map(|(idx, entry)| ListEntryRow::new(idx, entry))
+        .collect();
+    if let Some(format) = args.format {
+        return write_list_entries(&missing, format, io::stdout());
+    }
+    println!(
+        "{}",
+        Message::Missing {
+            count: missing.len(),
+            total: entries.len(),
+        }
+    );
+    print_entry_rows(&missing);
+    Ok(())
+}
 
-    // Get disjunction of films to save and films to remove.
-    let ids: HashSet<String> = film_ids.values().cloned().collect();
-    let to_add: Vec<String> = ids.difference(&saved_film_ids).cloned().collect();
-    let to_remove: Vec<String> = saved_film_ids.difference(&ids).cloned().collect();
+/// Ask on the terminal whether to remove the films of the plan from the list.
+///
+/// Without a terminal to ask on, the films are kept.
+fn confirm_removals(plan: &SyncPlan) -> anyhow::Result<bool> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        warn!("{}", Message::RemovalsNotConfirmed(plan.to_remove.len()));
+        return Ok(false);
+    }
+    println!(
+        "{}",
+        Message::RemovalsToConfirm {
+            list_id: &plan.list_id,
+            count: plan.to_remove.len(),
+        }
+    );
+    for id in &plan.to_remove {
+        println!("  - {}", plan.title(id));
+    }
+    print!("{} ", Message::ConfirmRemovals);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    stdin.read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "y" | "yes" | "j" | "ja"
+    ))
+}
 
-    // Update film list.
-    let list_name = "Collection".to_string();
-    let list_id = args.list_id.clone();
-    if !to_remove.is_empty() || !to_add.is_empty() {
-        let request = letterboxd::ListUpdateRequest {
-            entries: to_add
-                .into_iter()
-                .map(letterboxd::ListUpdateEntry::new)
+/// Ask on the terminal whether to update the list with the changes of the plan, which were shown
+/// already.
+///
+/// Without a terminal to ask on, the list is not updated.
+fn confirm_update(plan: &SyncPlan) -> anyhow::Result<bool> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        warn!("{}", Message::UpdateNotConfirmed(&plan.list_id));
+        return Ok(false);
+    }
+    print!("{} ", Message::ConfirmUpdate(&plan.list_id));
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    stdin.read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_lowercase().as_str(),
+        "y" | "yes" | "j" | "ja"
+    ))
+}
+
+/// The mappings of the config file with `--all` or the ones of `--map`, if any of them are given.
+fn given_mappings(args: &SyncArgs, config: &Config) -> anyhow::Result<Option<Vec<Mapping>>> {
+    if args.all {
+        // The mappings of other accounts are synced with their profiles.
+        let mappings: Vec<Mapping> = config
+            .mappings
+            .iter()
+            .filter(|mapping| mapping.profile.as_deref() == auth::profile_name())
+            .cloned()
+            .collect();
+        if mappings.is_empty() {
+            return Err(match auth::profile_name() {
+                Some(profile) => anyhow!("no mappings of profile {} in the config file", profile),
+                None => anyhow!("no mappings in the config file"),
+            });
+        }
+        Ok(Some(mappings))
+    } else if !args.map.is_empty() {
+        Ok(Some(
+            args.map
+                .iter()
+                .map(|(directory, list_id)| args.scan.mapping_of(directory, list_id))
                 .collect(),
-            films_to_remove: to_remove,
-            ..letterboxd::ListUpdateRequest::new(list_name)
+        ))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The mappings to sync, as given on the command line or, with `--all`, in the config file. Returns
+/// `None` if there is no list to sync with, since its creation was declined.
+async fn sync_targets(
+    args: &SyncArgs,
+    config: &Config,
+    client: &letterboxd::Client,
+) -> anyhow::Result<Option<Vec<Mapping>>> {
+    let mappings = if let Some(mappings) = given_mappings(args, config)? {
+        mappings
+    } else if args.per_folder_lists {
+        folder_mappings(args, client).await?
+    } else if args.target == Some(Target::Watchlist) {
+        if args.list.is_given() {
+            return Err(anyhow!("the watchlist cannot be given by URL or name"));
+        }
+        vec![Mapping {
+            target: Target::Watchlist,
+            ..args.scan.mapping("")?
+        }]
+    } else {
+        let list_id = match args
+            .list
+            .find_list_id(args.list_id.as_deref(), client)
+            .await?
+        {
+            Some(list_id) => list_id,
+            None => match create_missing_list(args, client).await? {
+                Some(list_id) => list_id,
+                None => return Ok(None),
+            },
         };
+        vec![args.scan.mapping(&list_id)?]
+    };
+    Ok(Some(mappings))
+}
+
+/// Sync the mappings given on the command line or, with `--all`, in the config file with a list
+/// of another service than Letterboxd.
+async fn sync_with_service<S: ListService>(
+    args: &SyncArgs,
+    config: &Config,
+    service: &S,
+) -> anyhow::Result<()> {
+    let mappings = match given_mappings(args, config)? {
+        Some(mappings) => mappings,
+        None => {
+            let list_id = args
+                .list_id
+                .as_deref()
+                .ok_or_else(|| anyhow!("no list id given"))?;
+            vec![args.scan.mapping(list_id)?]
+        }
+    };
+    let options = args.options()?;
+    let mut failed = 0;
+    for mapping in &mappings {
+        if let Err(err) = runner::sync_service_mapping(&options, mapping, config, service).await {
+            if mappings.len() == 1 {
+                return Err(err);
+            }
+            error!("{}: {:#}", mapping.directory, err);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!(
+            "failed to sync {} of {} mappings",
+            failed,
+            mappings.len()
+        ));
+    }
+    Ok(())
+}
+
+async fn sync(args: &SyncArgs, config: &Config) -> anyhow::Result<()> {
+    if args.backend == Some(Backend::Trakt) {
+        let trakt = TraktClient::from_env()?;
+        return sync_with_service(args, config, &trakt).await;
+    }
+    let options = args.options()?;
+    let client = new_client().await?;
+    let mappings = match sync_targets(args, config, &client).await? {
+        Some(mappings) => mappings,
+        None => return Ok(()),
+    };
+    let mappings = &mappings;
+    if args.watch || args.daemon {
+        runner::spawn_metrics_server(args.metrics);
+    }
+    if args.watch {
+        let min_interval = Duration::from_secs(args.min_interval);
+        return runner::watch(&options, mappings, min_interval, config, &client).await;
+    }
+    if args.daemon {
+        let interval = args
+            .interval
+            .ok_or_else(|| anyhow!("daemon mode needs --interval"))?;
+        return runner::daemon(&options, mappings, interval, args.jitter, config, &client).await;
+    }
+    let single = !args.all && args.map.is_empty() && !args.per_folder_lists;
+    let mappings: Vec<&Mapping> = mappings.iter().collect();
+    let results = if single {
+        vec![sync_single_mapping(&options, mappings[0], config, &client).await]
+    } else {
+        sync_mappings(&options, &mappings, config, &client).await
+    };
+    report_resolve_errors(results.iter().flatten().map(|synced| &synced.resolved));
+    let duplicates: Vec<Duplicate> = results
+        .iter()
+        .flatten()
+        .flat_map(|synced| find_duplicates(&synced.resolved))
+        .collect();
+    report_duplicates(&duplicates);
+    if args.write_mapping.is_some()
+        || args.review_page.is_some()
+        || args.suggest_renames
+        || args.apply_renames
+    {
+        let resolved: Vec<&ResolvedFilms> = results
+            .iter()
+            .flatten()
+            .map(|synced| &synced.resolved)
+            .collect();
+        let films = resolved_film_details(&resolved, config, &client).await?;
+        if let Some(path) = &args.write_mapping {
+            write_mapping(&resolved, &films, path)?;
+        }
+        if let Some(path) = &args.review_page {
+            let count = write_review_page(&resolved, &films, path)?;
+            info!(
+                "{}",
+                Message::ReviewPageWritten {
+                    count,
+                    path: &path.display().to_string(),
+                }
+            );
+        }
+        if args.suggest_renames || args.apply_renames {
+            let synced: Vec<&SyncedMapping> = results.iter().flatten().collect();
+            rename_files(&synced, &films, &args.rename_template, args.apply_renames);
+        }
+    }
+    if let Some(path) = &args.duplicates_file {
+        write_duplicates(&duplicates, path)?;
         info!(
-            "Updating list: {} to add, {} to remove, total movies: {}",
-            request.entries.len(),
-            request.films_to_remove.len(),
-            ids.len()
+            "{}",
+            Message::DuplicatesSaved {
+                count: duplicates.len(),
+                path: &path.display().to_string(),
+            }
         );
+    }
 
-        if !args.dry_run {
-            client
-                .update_list(&list_id, &request)
-                .await
-                .context("failed to update the list")?;
-        } else {
-            info!("Dry run. List was not updated.");
-        }
+    if let Some(path) = &args.plan_file {
+        let plans: Vec<SyncPlan> = results
+            .iter()
+            .flatten()
+            .flat_map(|synced| synced.plans.iter().cloned())
+            .collect();
+        save_plans(&plans, path)?;
+        info!(
+            "{}",
+            Message::PlanSaved {
+                count: plans.len(),
+                path: &path.display().to_string(),
+            }
+        );
+    }
+    if args.output == OutputFormat::Json {
+        let summary = RunSummary {
+            mappings: mappings
+                .iter()
+                .zip(&results)
+                .map(|(mapping, result)| match result {
+                    Ok(synced) => MappingSummary::new(mapping, &synced.resolved, &synced.plans),
+                    Err(err) => MappingSummary::failed(mapping, err),
+                })
+                .collect(),
+        };
+        serde_json::to_writer_pretty(io::stdout(), &summary)
+            .context("failed to write the summary")?;
+        println!();
+    }
+
+    let unmatched: usize = results
+        .iter()
+        .flatten()
+        .map(|synced| synced.resolved.unresolved_files())
+        .sum();
+    if single {
+        results
+            .into_iter()
+            .next()
+            .expect("result of the single mapping")?;
     } else {
-        info!("List up to date. Nothing to do.");
+        let failed = results.iter().filter(|result| result.is_err()).count();
+        if failed > 0 {
+            return Err(SyncIncomplete::MappingsFailed {
+                failed,
+                total: mappings.len(),
+            }
+            .into());
+        }
+    }
+    if unmatched > 0 {
+        return Err(SyncIncomplete::Unmatched(unmatched).into());
+    }
+    Ok(())
+}
+
+/// Mappings of the top-level folders of the directory to the lists named like them.
+///
+/// Missing lists are created, see [`folder_lists`].
+async fn folder_mappings(
+    args: &SyncArgs,
+    client: &letterboxd::Client,
+) -> anyhow::Result<Vec<Mapping>> {
+    let root = match args.scan.directory()? {
+        Source::Local(root) => root,
+        source => {
+            return Err(anyhow!(
+                "lists per folder are only supported for local directories, not {}",
+                source
+            ))
+        }
+    };
+    let folder_lists = folder_lists(root, args.visibility, args.dry_run, client).await?;
+    Ok(folder_lists
+        .into_iter()
+        .map(|(path, list_id)| args.scan.mapping_of(&Source::Local(path), &list_id))
+        .collect())
+}
+
+/// Create the list given by `--list-by-name`, which does not exist, if `--create-if-missing` is
+/// set.
+///
+/// Returns the id of the new list, or `None` in dry run mode where the list is not created.
+async fn create_missing_list(
+    args: &SyncArgs,
+    client: &letterboxd::Client,
+) -> anyhow::Result<Option<String>> {
+    let name = args.list.list_by_name.as_deref().unwrap_or_default();
+    if !args.create_if_missing {
+        return Err(anyhow!("no list named '{}'", name));
+    }
+    info!("{}", Message::CreatingList(name));
+    if args.dry_run {
+        info!("{}", Message::DryRun);
+        return Ok(None);
+    }
+    let list_id = create_list(client, name, args.visibility)
+        .await
+        .with_context(|| format!("failed to create list '{}'", name))?;
+    Ok(Some(list_id))
+}
+
+/// Sync the mappings, and again whenever a webhook announces an added, deleted or renamed movie and
+/// every `--reconcile-interval` hours.
+///
+/// Webhooks are collected until there were none for `--debounce` seconds, and only the mappings
+/// whose directories may hold the movies are synced again. Movies in none of the directories, e.g.
+/// since Radarr sees other paths, cause a sync of all mappings.
+async fn serve(args: &ServeArgs, config: &Config) -> anyhow::Result<()> {
+    let options = args.sync.options()?;
+    let client = new_client().await?;
+    let mappings = match sync_targets(&args.sync, config, &client).await? {
+        Some(mappings) => mappings,
+        None => return Ok(()),
+    };
+    runner::spawn_metrics_server(args.sync.metrics);
+    runner::serve(
+        &options,
+        &mappings,
+        args.listen,
+        env::var("WEBHOOK_TOKEN").ok(),
+        Duration::from_secs(args.reconcile_interval.max(1) * 60 * 60),
+        config,
+        &client,
+    )
+    .await
+}
+
+/// Update the lists as planned in the plan file.
+async fn apply(args: &ApplyArgs) -> anyhow::Result<()> {
+    let plans = load_plans(&args.plan_file)?;
+    let client = new_client().await?;
+    let mut executor = SyncExecutor::new(&client, false)
+        .with_chunk_size(args.chunk_size)
+        .with_snapshots(snapshot_dir()?);
+    if args.confirm {
+        executor = executor.with_confirmation(confirm_update);
+    }
+    for plan in &plans {
+        executor
+            .execute(plan)
+            .await
+            .with_context(|| format!("failed to update list {}", plan.list_id))?;
+    }
+    Ok(())
+}
+
+/// Restore a list from its last snapshot, which is dropped once the list is restored.
+async fn rollback(args: &RollbackArgs) -> anyhow::Result<()> {
+    let client = new_client().await?;
+    let list_id = args.list.list_id(args.list_id.as_deref(), &client).await?;
+    let dir = snapshot_dir()?;
+    let mut snapshots = load_snapshots(&dir, &list_id)?;
+    let snapshot = snapshots
+        .pop()
+        .ok_or_else(|| anyhow!("no snapshot of list {}", list_id))?;
+    info!(
+        "{}",
+        Message::RestoringSnapshot {
+            list_id: &list_id,
+            taken: &snapshot.taken_at(),
+        }
+    );
+    let mut plan = SyncPlan::for_snapshot(&client, &snapshot)
+        .await
+        .context("failed to fetch the list")?;
+    plan.to_add.sort();
+    plan.to_remove.sort();
+    let updated = SyncExecutor::new(&client, args.dry_run)
+        .execute(&plan)
+        .await
+        .context("failed to restore the list")?;
+    if updated || (plan.is_empty() && !args.dry_run) {
+        save_snapshots(&dir, &list_id, snapshots)?;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(err) = run(Args::from_args()).await {
+        if let Some(incomplete) = err.downcast_ref::<SyncIncomplete>() {
+            eprintln!("Error: {}", incomplete);
+            process::exit(incomplete.exit_code());
+        }
+        let exit_code = match ApiFailure::find(&err) {
+            Some(failure) => {
+                debug!("{:?}", err);
+                eprintln!("Error: {}", Message::ApiFailure(failure));
+                failure.exit_code()
+            }
+            None => {
+                eprintln!("Error: {:?}", err);
+                error::EXIT_FAILURE
+            }
+        };
+        process::exit(exit_code);
     }
+}
 
+/// Initialize the logger with the verbosity given on the command line.
+fn init_logger(args: &Args) -> anyhow::Result<()> {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    match (args.quiet, args.verbose) {
+        (true, _) => builder.filter_level(LevelFilter::Error),
+        (false, 0) => &mut builder,
+        (false, 1) => builder.filter_module(env!("CARGO_CRATE_NAME"), LevelFilter::Debug),
+        (false, _) => builder.filter_level(LevelFilter::Trace),
+    };
+    if let Some(path) = &args.log_file {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open log file: {}", path.display()))?;
+        builder
+            .target(env_logger::Target::Pipe(Box::new(file)))
+            .write_style(env_logger::WriteStyle::Never)
+            .format_timestamp_millis();
+    }
+    builder.init();
     Ok(())
 }
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    match &args.command {
+        #[cfg(windows)]
+        Command::Service(service::ServiceCommand::Run { .. }) => service::init_logging()?,
+        _ => init_logger(&args)?,
+    }
+    dotenv::dotenv().ok();
+    i18n::set_locale(args.locale.unwrap_or_else(Locale::from_env));
+    let policy = RetryPolicy::default();
+    retry::set_retry_policy(RetryPolicy {
+        max_attempts: args.max_attempts.unwrap_or(policy.max_attempts).max(1),
+        ..policy
+    });
+    if let Some(concurrency) = args.concurrency {
+        throttle::set_concurrency(concurrency);
+    }
+    progress::set_enabled(!args.no_progress && !args.quiet);
+    if let Some(name) = &args.profile {
+        let config = load_config(args.config.as_deref())?;
+        let profile = config.profiles.get(name).cloned().unwrap_or_default();
+        auth::set_profile(name.clone(), profile);
+    }
+
+    match args.command {
+        Command::Sync(mut sync_args) => {
+            if sync_args.list.is_given()
+                || sync_args.target == Some(Target::Watchlist)
+                || sync_args.per_folder_lists
+            {
+                shift_directory(&mut sync_args.list_id, &mut sync_args.scan)?;
+            }
+            let config = load_config(args.config.as_deref())?;
+            sync(&sync_args, &config).await
+        }
+        Command::Serve(mut serve_args) => {
+            if serve_args.sync.list.is_given()
+                || serve_args.sync.target == Some(Target::Watchlist)
+                || serve_args.sync.per_folder_lists
+            {
+                shift_directory(&mut serve_args.sync.list_id, &mut serve_args.sync.scan)?;
+            }
+            let config = load_config(args.config.as_deref())?;
+            serve(&serve_args, &config).await
+        }
+        Command::Scan(scan_args) => {
+            let config = load_config(args.config.as_deref())?;
+            scan(&scan_args, &config).await
+        }
+        Command::Diff(mut diff_args) => {
+            if diff_args.list.is_given() {
+                shift_directory(&mut diff_args.list_id, &mut diff_args.scan)?;
+            }
+            let config = load_config(args.config.as_deref())?;
+            diff(&diff_args, &config).await
+        }
+        Command::Apply(apply_args) => apply(&apply_args).await,
+        Command::Rollback(rollback_args) => rollback(&rollback_args).await,
+        Command::Resolve(retry_args) => {
+            let config = load_config(args.config.as_deref())?;
+            retry_unresolved(&retry_args, &config).await
+        }
+        Command::Compare(mut compare_args) => {
+            if compare_args.list.is_given() {
+                shift_directory(&mut compare_args.list_id, &mut compare_args.scan)?;
+            }
+            let config = load_config(args.config.as_deref())?;
+            compare(compare_args, &config).await
+        }
+        Command::Missing(mut missing_args) => {
+            let compare_args = &mut missing_args.compare;
+            if compare_args.list.is_given() {
+                shift_directory(&mut compare_args.list_id, &mut compare_args.scan)?;
+            }
+            let config = load_config(args.config.as_deref())?;
+            missing(&missing_args, &config).await
+        }
+        Command::Export(export_args) => {
+            let config = load_config(args.config.as_deref())?;
+            export(export_args, &config).await
+        }
+        Command::ExportList(export_args) => export_list(&export_args).await,
+        Command::DiffLists(diff_args) => diff_lists(&diff_args).await,
+        Command::Stats(stats_args) => {
+            let config = load_config(args.config.as_deref())?;
+            stats(&stats_args, &config).await
+        }
+        Command::Cache(command) => {
+            let config = load_config(args.config.as_deref())?;
+            match command {
+                CacheCommand::Show(cache) => show_cache(&cache.path(&config)?),
+                CacheCommand::Clear(cache) => {
+                    let cache_path = cache.path(&config)?;
+                    clear_cache(&cache_path).with_context(|| {
+                        format!("failed to delete cache file: {}", cache_path.display())
+                    })?;
+                    info!(
+                        "{}",
+                        Message::CacheCleared(&cache_path.display().to_string())
+                    );
+                    Ok(())
+                }
+                CacheCommand::Merge {
+                    other,
+                    theirs,
+                    cache,
+                } => merge_cache(&cache.path(&config)?, &other, theirs),
+                CacheCommand::Verify { dry_run, cache } => {
+                    let client = read_only_client().await?;
+                    verify_movie_cache(&cache.path(&config)?, dry_run, &client).await
+                }
+                CacheCommand::Prune { dry_run, cache } => {
+                    let path = cache.path(&config)?;
+                    prune_movie_cache(&path, cache.directory.as_ref(), &config, dry_run).await
+                }
+            }
+        }
+        Command::Auth { command } => match command {
+            None => auth().await,
+            Some(AuthCommand::Login { username }) => login(username).await,
+            Some(AuthCommand::Logout) => logout().await,
+        },
+        Command::Completions(completions_args) => {
+            let mut app = Args::clap();
+            let name = app.get_name().to_string();
+            match completions_args.shell {
+                Some(shell) if !completions_args.man => {
+                    app.gen_completions_to(name, shell, &mut io::stdout())
+                }
+                _ => manpage::write_man_page(&app, &mut io::stdout())
+                    .context("failed to write the man page")?,
+            }
+            Ok(())
+        }
+        Command::Doctor(doctor_args) => doctor(&doctor_args, args.config.as_deref()).await,
+        Command::Config(ConfigCommand::Validate) => {
+            let config = load_config(args.config.as_deref())?;
+            let problems = config.validate();
+            for problem in &problems {
+                error!("{}", problem);
+            }
+            if problems.is_empty() {
+                info!("{}", Message::ConfigValid);
+                Ok(())
+            } else {
+                Err(anyhow!("found {} problems in config", problems.len()))
+            }
+        }
+        #[cfg(windows)]
+        Command::Service(command) => service::run_command(&command, args.config.as_deref()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_have_binary_units() {
+        assert_eq!(parse_size("700").unwrap(), 700);
+        assert_eq!(parse_size("700B").unwrap(), 700);
+        assert_eq!(parse_size("100k").unwrap(), 100 << 10);
+        assert_eq!(parse_size("100 MB").unwrap(), 100 << 20);
+        assert_eq!(parse_size("1.5GiB").unwrap(), 3 << 29);
+        assert_eq!(parse_size("2t").unwrap(), 2 << 40);
+    }
+
+    #[test]
+    fn invalid_sizes_are_errors() {
+        assert!(parse_size("100X").is_err());
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("").is_err());
+        assert!(parse_size("-5M").is_err());
+        assert!(parse_size("1e400").is_err());
+    }
+
+    #[test]
+    fn durations_default_to_seconds() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(
+            parse_duration("30min").unwrap(),
+            Duration::from_secs(30 * 60)
+        );
+        assert_eq!(
+            parse_duration("1.5h").unwrap(),
+            Duration::from_secs(90 * 60)
+        );
+        assert_eq!(
+            parse_duration("1d").unwrap(),
+            Duration::from_secs(24 * 60 * 60)
+        );
+    }
+
+    #[test]
+    fn invalid_durations_are_errors() {
+        assert!(parse_duration("0").is_err());
+        assert!(parse_duration("-5m").is_err());
+        assert!(parse_duration("5w").is_err());
+        assert!(parse_duration("h").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+//! Man page of the command line interface, written by `completions --man`.
+//!
+//! clap 2 cannot write man pages, so the page is put together from the help texts of the program
+//! and of each of its commands.
+
+use structopt::clap::{App, ErrorKind};
+
+use std::io::{self, Write};
+
+/// Help text of the command at `path` below the program, as printed by `--help`.
+fn help_text(app: &App, path: &[&str]) -> String {
+    let args = std::iter::once(app.get_name())
+        .chain(path.iter().copied())
+        .chain(std::iter::once("--help"));
+    match app.clone().get_matches_from_safe(args) {
+        Err(err) if err.kind == ErrorKind::HelpDisplayed => err.message,
+        _ => String::new(),
+    }
+}
+
+/// Escape text for roff, so that lines starting with a dot or quote are no requests.
+fn escape(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e");
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Write a section with the help of each command below `app`, which is at `path`, and of their
+/// commands.
+fn write_commands(
+    root: &App,
+    app: &App,
+    path: &mut Vec<String>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    // clap 2 has no accessor of the commands of an app.
+    for command in &app.p.subcommands {
+        path.push(command.get_name().to_string());
+        let names: Vec<&str> = path.iter().map(String::as_str).collect();
+        writeln!(out, ".SS \"{} {}\"", root.get_name(), names.join(" "))?;
+        writeln!(
+            out,
+            ".nf\n{}\n.fi",
+            escape(help_text(root, &names).trim_end())
+        )?;
+        write_commands(root, command, path, out)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Write the man page of `app` in roff to `out`.
+pub fn write_man_page(app: &App, out: &mut impl Write) -> io::Result<()> {
+    let name = app.get_name();
+    writeln!(
+        out,
+        ".TH {} 1 \"\" \"{} {}\"",
+        name.to_uppercase(),
+        name,
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(
+        out,
+        ".SH NAME\n{} \\- {}",
+        name,
+        app.p.meta.about.unwrap_or_default()
+    )?;
+    writeln!(
+        out,
+        ".SH DESCRIPTION\n.nf\n{}\n.fi",
+        escape(help_text(app, &[]).trim_end())
+    )?;
+    writeln!(out, ".SH COMMANDS")?;
+    write_commands(app, app, &mut Vec::new(), out)
+}
@@ -0,0 +1,544 @@
+//! Sources telling the movie in a file: NFO files, the file name, the folder name and the title
+//! embedded in the file.
+
+use anyhow::anyhow;
+use log::debug;
+use regex::Regex;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::container::read_container_title;
+use crate::overrides::Overrides;
+use crate::resolve::{add_embedded_ids, extract_movie, MovieGuess};
+use crate::scan::{MovieFile, Source};
+
+/// A way to tell the movie in a file. Files are guessed in parallel, see [`guess_movies`].
+///
+/// [`guess_movies`]: crate::resolve::guess_movies
+pub trait MetadataSource: Sync {
+    /// Guess the movie in the file, if this source knows it.
+    fn guess(&self, file: &MovieFile) -> Option<MovieGuess>;
+}
+
+/// The movie described by a Kodi or Jellyfin style NFO file next to the movie file.
+///
+/// The NFO file is named like the movie file, or `movie.nfo` in a folder per movie.
+pub struct NfoFile;
+
+impl MetadataSource for NfoFile {
+    fn guess(&self, file: &MovieFile) -> Option<MovieGuess> {
+        nfo_paths(&file.path).into_iter().find_map(|path| {
+            let content = fs::read_to_string(&path).ok()?;
+            let movie = parse_nfo(&content);
+            match &movie {
+                Some(movie) => debug!("Read {} from {}", movie, path.display()),
+                None => debug!("No movie title in {}", path.display()),
+            }
+            movie
+        })
+    }
+}
+
+fn nfo_paths(path: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![path.with_extension("nfo")];
+    if let Some(dir) = path.parent() {
+        paths.push(dir.join("movie.nfo"));
+    }
+    paths
+}
+
+/// Parse the title, release year and IMDb and TMDb ids of an NFO file.
+///
+/// Returns `None` if the file is not an XML document with a `movie` root and a title, e.g. an NFO
+/// file of a release group.
+pub fn parse_nfo(content: &str) -> Option<MovieGuess> {
+    let doc = roxmltree::Document::parse(content.trim_start_matches('\u{feff}')).ok()?;
+    let movie = doc.root_element();
+    if movie.tag_name().name() != "movie" {
+        return None;
+    }
+    let child_text = |name: &str| {
+        movie
+            .children()
+            .find(|n| n.tag_name().name() == name)
+            .and_then(|n| n.text())
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+    };
+    let unique_id = |kind: &str| {
+        movie
+            .children()
+            .filter(|n| n.tag_name().name() == "uniqueid")
+            .find(|n| n.attribute("type") == Some(kind))
+            .and_then(|n| n.text())
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+    };
+
+    let title = child_text("title")?;
+    let year = child_text("year")
+        .and_then(|year| year.parse().ok())
+        .or_else(|| child_text("premiered")?.get(..4)?.parse().ok());
+    // Older files only have an `id`, newer Jellyfin ones `imdbid` and `tmdbid` besides the
+    // `uniqueid`s.
+    let imdb_id = unique_id("imdb")
+        .or_else(|| child_text("imdbid"))
+        .or_else(|| child_text("id").filter(|id| id.starts_with("tt")));
+    let tmdb_id = unique_id("tmdb").or_else(|| child_text("tmdbid"));
+    Some(MovieGuess {
+        title: title.to_string(),
+        year,
+        imdb_id: imdb_id.map(str::to_string),
+        tmdb_id: tmdb_id.map(str::to_string),
+        pinned: None,
+    })
+}
+
+/// The movie captured by the first matching of the patterns in the file name, see
+/// [`extract_movie`].
+pub struct FileNamePatterns(pub Vec<Regex>);
+
+impl MetadataSource for FileNamePatterns {
+    fn guess(&self, file: &MovieFile) -> Option<MovieGuess> {
+        let name = file.path.file_name()?.to_string_lossy();
+        self.0
+            .iter()
+            .find_map(|pattern| extract_movie(pattern, &name))
+    }
+}
+
+/// The movie in the name of the folder of the file, for libraries with a folder per movie like
+/// `Title (Year)/release-name.mkv`.
+pub struct FolderName {
+    /// Patterns capturing the movie, tried in turn, see [`extract_movie`]. Without patterns, the
+    /// folder name is parsed as release name, see [`parse_release_name`].
+    pub patterns: Vec<Regex>,
+    /// Folder scanned for movies, whose name is not the one of a movie.
+    pub root: Option<PathBuf>,
+}
+
+impl MetadataSource for FolderName {
+    fn guess(&self, file: &MovieFile) -> Option<MovieGuess> {
+        let folder = file.path.parent()?;
+        if self.root.as_deref() == Some(folder) {
+            return None;
+        }
+        let name = folder.file_name()?.to_string_lossy();
+        if self.patterns.is_empty() {
+            return parse_release_name(&name);
+        }
+        self.patterns
+            .iter()
+            .find_map(|pattern| extract_movie(pattern, &name))
+    }
+}
+
+/// The movie in the title embedded in a local Matroska or MP4 file, parsed as release name, see
+/// [`read_container_title`].
+///
+/// The embedded title is only read if the file name is inconclusive, i.e. has no release year,
+/// like a file named after a hash.
+pub struct ContainerTitle;
+
+impl MetadataSource for ContainerTitle {
+    fn guess(&self, file: &MovieFile) -> Option<MovieGuess> {
+        let stem = file.path.file_stem()?.to_string_lossy();
+        if parse_release_name(&stem).is_some_and(|movie| movie.year.is_some()) {
+            return None;
+        }
+        let title = match read_container_title(&file.path) {
+            Ok(title) => title?,
+            Err(err) => {
+                debug!("Failed to read title of {}: {}", file.path.display(), err);
+                return None;
+            }
+        };
+        let movie = parse_release_name(&title);
+        if let Some(movie) = &movie {
+            debug!("Read {} from {}", movie, file.path.display());
+        }
+        movie
+    }
+}
+
+/// The movie in the file name without extension, parsed as release name, see
+/// [`parse_release_name`].
+pub struct ReleaseName;
+
+impl MetadataSource for ReleaseName {
+    fn guess(&self, file: &MovieFile) -> Option<MovieGuess> {
+        parse_release_name(&file.path.file_stem()?.to_string_lossy())
+    }
+}
+
+/// The file name without extension as title, with dots and underscores as spaces, for files named
+/// just like the movie.
+pub struct FileStem;
+
+impl MetadataSource for FileStem {
+    fn guess(&self, file: &MovieFile) -> Option<MovieGuess> {
+        let stem = file.path.file_stem()?.to_string_lossy();
+        let title = stem.replace(['.', '_'], " ");
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+        Some(title)
+            .filter(|title| !title.is_empty())
+            .map(|title| MovieGuess::new(title, None))
+    }
+}
+
+/// Kind of a [`MetadataSource`], to give the order of the sources with `--metadata-sources`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    /// [`NfoFile`]
+    Nfo,
+    /// [`FolderName`]
+    Folder,
+    /// [`FileNamePatterns`]
+    Filename,
+    /// [`ContainerTitle`]
+    Container,
+    /// [`ReleaseName`]
+    Release,
+    /// [`FileStem`]
+    Stem,
+}
+
+impl SourceKind {
+    pub const VARIANTS: &'static [&'static str] =
+        &["nfo", "folder", "filename", "container", "release", "stem"];
+}
+
+impl FromStr for SourceKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "nfo" => Ok(SourceKind::Nfo),
+            "folder" => Ok(SourceKind::Folder),
+            "filename" => Ok(SourceKind::Filename),
+            "container" => Ok(SourceKind::Container),
+            "release" => Ok(SourceKind::Release),
+            "stem" => Ok(SourceKind::Stem),
+            _ => Err(anyhow!("unknown metadata source: {}", s)),
+        }
+    }
+}
+
+/// Lowercase tags in release names, by kind.
+const RELEASE_TAGS: &[&str] = &[
+    // Resolution
+    "480p 576p 720p 1080p 1080i 2160p 4k uhd",
+    // Source
+    "bluray blu-ray bdrip brrip bdremux remux web web-dl webdl webrip hdrip dvdrip dvd dvdscr hdtv \
+     hdcam amzn nf dsnp hmax",
+    // Codec
+    "x264 x265 h264 h265 hevc avc xvid divx av1 10bit hdr hdr10 dv",
+    // Audio
+    "aac ac3 dts dts-hd truehd atmos ddp5 dd5 flac mp3",
+    // Edition
+    "extended unrated remastered theatrical uncut criterion imax proper repack limited internal \
+     multi dubbed subbed",
+];
+
+fn is_release_tag(token: &str) -> bool {
+    let token = token.to_lowercase();
+    let is_tag = |tag: &str| {
+        RELEASE_TAGS
+            .iter()
+            .flat_map(|tags| tags.split_whitespace())
+            .any(|known| known == tag)
+    };
+    // Tags may carry the release group, e.g. `x264-GROUP`.
+    is_tag(&token) || matches!(token.rsplit_once('-'), Some((tag, _)) if is_tag(tag))
+}
+
+/// Kind of the tags of a release name, indexing [`RELEASE_TAGS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReleaseTag {
+    Resolution = 0,
+    Source = 1,
+}
+
+/// The first tag of the given kind in a release name, as written there, like `2160p` or `Remux`.
+pub fn find_release_tag(name: &str, kind: ReleaseTag) -> Option<String> {
+    let tags = RELEASE_TAGS[kind as usize];
+    let is_tag = |tag: &str| tags.split_whitespace().any(|known| known == tag);
+    name.split(|c: char| c == '.' || c == '_' || c == '(' || c == ')' || c.is_whitespace())
+        .find_map(|token| {
+            if is_tag(&token.to_lowercase()) {
+                return Some(token);
+            }
+            let (tag, _) = token.rsplit_once('-')?;
+            Some(tag).filter(|tag| is_tag(&tag.to_lowercase()))
+        })
+        .map(str::to_string)
+}
+
+fn parse_year(token: &str) -> Option<u16> {
+    let year: u16 = token.parse().ok()?;
+    Some(year).filter(|year| (1888..=2099).contains(year))
+}
+
+/// Parse a release name like `Some.Movie.2019.1080p.BluRay.x264-GROUP`.
+///
+/// The title ends at the release year or at the first tag of the resolution, source, codec, audio
+/// format or edition, after which the release year and tags are dropped together with the release
+/// group. Bracketed parts like `[GROUP]` or `{imdb-tt0133093}` are dropped too.
+pub fn parse_release_name(name: &str) -> Option<MovieGuess> {
+    static BRACKETED: OnceLock<Regex> = OnceLock::new();
+    let bracketed =
+        BRACKETED.get_or_init(|| Regex::new(r"\[[^\]]*\]|\{[^}]*\}").expect("valid pattern"));
+    let name = bracketed.replace_all(name, " ");
+    let tokens: Vec<&str> = name
+        .split(|c: char| c == '.' || c == '_' || c == '(' || c == ')' || c.is_whitespace())
+        .filter(|token| token.chars().any(char::is_alphanumeric))
+        .collect();
+
+    // The first token is part of the title even if it looks like a year or tag, as in `1917`.
+    let end = tokens
+        .iter()
+        .skip(1)
+        .position(|token| is_release_tag(token))
+        .map_or(tokens.len(), |idx| idx + 1);
+    let year_idx = (1..end)
+        .rev()
+        .find(|idx| parse_year(tokens[*idx]).is_some());
+    let title = tokens[..year_idx.unwrap_or(end)].join(" ");
+    if title.is_empty() {
+        return None;
+    }
+    Some(MovieGuess::new(
+        title,
+        year_idx.and_then(|idx| parse_year(tokens[idx])),
+    ))
+}
+
+/// Sources asked in turn until one knows the movie.
+///
+/// IMDb and TMDb ids embedded in the file name complete the ids of the movie, see
+/// [`add_embedded_ids`], and overrides pin the film of the movie.
+pub struct MetadataChain {
+    sources: Vec<Box<dyn MetadataSource>>,
+    overrides: Overrides,
+}
+
+impl MetadataChain {
+    /// Chain the given sources.
+    pub fn new(sources: Vec<Box<dyn MetadataSource>>) -> Self {
+        Self {
+            sources,
+            overrides: Overrides::default(),
+        }
+    }
+
+    /// Pin the films of the files matched by the overrides.
+    pub fn with_overrides(self, overrides: Overrides) -> Self {
+        Self { overrides, ..self }
+    }
+
+    /// Sources for the files of `source`: NFO files, then `patterns` in turn on the file name and
+    /// on the folder name, then with `container_titles` the titles embedded in the files, and then
+    /// the file name parsed as release name.
+    ///
+    /// NFO files and embedded titles are only read from local directories. With
+    /// `prefer_folder_names`, the folder name is tried before the file name, and parsed as
+    /// release name if there are no patterns.
+    pub fn for_source(
+        source: &Source,
+        patterns: Vec<Regex>,
+        prefer_folder_names: bool,
+        container_titles: bool,
+    ) -> Self {
+        let mut sources: Vec<Box<dyn MetadataSource>> = Vec::new();
+        let root = match source {
+            Source::Local(root) => {
+                sources.push(Box::new(NfoFile));
+                Some(root.clone())
+            }
+            Source::Stdin => {
+                sources.push(Box::new(NfoFile));
+                None
+            }
+            _ => None,
+        };
+        let has_patterns = !patterns.is_empty();
+        let file_name = FileNamePatterns(patterns.clone());
+        let folder_name = FolderName { patterns, root };
+        if prefer_folder_names {
+            sources.push(Box::new(folder_name));
+            sources.push(Box::new(file_name));
+        } else if has_patterns {
+            sources.push(Box::new(file_name));
+            sources.push(Box::new(folder_name));
+        }
+        if container_titles && source.is_local() {
+            sources.push(Box::new(ContainerTitle));
+        }
+        sources.push(Box::new(ReleaseName));
+        Self::new(sources)
+    }
+
+    /// Sources of the given kinds in this order for the files of `source`. `patterns` are the
+    /// patterns of [`FileNamePatterns`] and [`FolderName`], which parses folder names as release
+    /// names without patterns. NFO files and embedded titles are only read from local
+    /// directories, so these kinds are left out for other sources.
+    pub fn from_kinds(kinds: &[SourceKind], source: &Source, patterns: Vec<Regex>) -> Self {
+        let local = source.is_local() || matches!(source, Source::Stdin);
+        let root = match source {
+            Source::Local(root) => Some(root.clone()),
+            _ => None,
+        };
+        let sources = kinds
+            .iter()
+            .filter_map(|kind| -> Option<Box<dyn MetadataSource>> {
+                match kind {
+                    SourceKind::Nfo if local => Some(Box::new(NfoFile)),
+                    SourceKind::Folder => Some(Box::new(FolderName {
+                        patterns: patterns.clone(),
+                        root: root.clone(),
+                    })),
+                    SourceKind::Filename => Some(Box::new(FileNamePatterns(patterns.clone()))),
+                    SourceKind::Container if source.is_local() => Some(Box::new(ContainerTitle)),
+                    SourceKind::Release => Some(Box::new(ReleaseName)),
+                    SourceKind::Stem => Some(Box::new(FileStem)),
+                    SourceKind::Nfo | SourceKind::Container => None,
+                }
+            })
+            .collect();
+        Self::new(sources)
+    }
+
+    /// Sources for the files of `source` in a library with a folder per movie: the files alone in
+    /// one of the `folders`, see [`movie_folders`], are guessed from the NFO file and then from the
+    /// folder name first, the other files as with [`MetadataChain::for_source`].
+    ///
+    /// [`movie_folders`]: crate::scan::movie_folders
+    pub fn for_movie_folders(
+        source: &Source,
+        patterns: Vec<Regex>,
+        prefer_folder_names: bool,
+        container_titles: bool,
+        folders: HashSet<PathBuf>,
+    ) -> Self {
+        let folder_sources = Self::for_source(source, patterns.clone(), true, container_titles);
+        let file_sources =
+            Self::for_source(source, patterns, prefer_folder_names, container_titles);
+        Self::new(vec![Box::new(MovieFolders {
+            folders,
+            folder_sources,
+            file_sources,
+        })])
+    }
+
+    /// Sources for the files of several directories scanned together: each file is guessed by the
+    /// sources of the directory it is in.
+    pub fn for_directories(chains: Vec<(&Source, MetadataChain)>) -> Self {
+        let chains = chains
+            .into_iter()
+            .map(|(source, chain)| (source.root(), chain))
+            .collect();
+        Self::new(vec![Box::new(Directories(chains))])
+    }
+}
+
+/// Sources for the files of each directory by the root of the directory.
+struct Directories(Vec<(PathBuf, MetadataChain)>);
+
+impl MetadataSource for Directories {
+    fn guess(&self, file: &MovieFile) -> Option<MovieGuess> {
+        let (_, chain) = self
+            .0
+            .iter()
+            .find(|(root, _)| file.path.starts_with(root))?;
+        chain.guess(file)
+    }
+}
+
+/// Sources for the files in a folder per movie and for the other files.
+struct MovieFolders {
+    folders: HashSet<PathBuf>,
+    folder_sources: MetadataChain,
+    file_sources: MetadataChain,
+}
+
+impl MetadataSource for MovieFolders {
+    fn guess(&self, file: &MovieFile) -> Option<MovieGuess> {
+        let in_movie_folder = file
+            .path
+            .parent()
+            .is_some_and(|folder| self.folders.contains(folder));
+        if in_movie_folder {
+            self.folder_sources.guess(file)
+        } else {
+            self.file_sources.guess(file)
+        }
+    }
+}
+
+impl MetadataSource for MetadataChain {
+    fn guess(&self, file: &MovieFile) -> Option<MovieGuess> {
+        let mut movie = self.sources.iter().find_map(|source| source.guess(file))?;
+        if let Some(file_name) = file.path.file_name() {
+            let file_name = file_name.to_string_lossy();
+            add_embedded_ids(&mut movie, &file_name);
+            movie.pinned = self.overrides.film_for(&file_name).map(str::to_string);
+        }
+        Some(movie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_names_are_parsed() {
+        let cases: &[(&str, &str, Option<u16>)] = &[
+            ("Heat.1995.1080p.BluRay.x264-GRP", "Heat", Some(1995)),
+            ("Heat (1995) [1080p]", "Heat", Some(1995)),
+            ("Heat_1995_720p_WEB-DL", "Heat", Some(1995)),
+            ("Heat.1080p.BluRay", "Heat", None),
+            ("Heat", "Heat", None),
+            // Years in the title
+            (
+                "Blade.Runner.2049.2017.2160p.UHD.BluRay.x265-GRP",
+                "Blade Runner 2049",
+                Some(2017),
+            ),
+            (
+                "2001.A.Space.Odyssey.1968.1080p.BluRay",
+                "2001 A Space Odyssey",
+                Some(1968),
+            ),
+            ("1917.2019.1080p.WEB-DL", "1917", Some(2019)),
+            ("1917", "1917", None),
+            // Multi-part names
+            (
+                "Kill.Bill.Vol.1.2003.1080p.BluRay",
+                "Kill Bill Vol 1",
+                Some(2003),
+            ),
+            ("Heat.1995.CD1", "Heat", Some(1995)),
+            ("Heat.1995.DVDRip.XviD-GRP.CD2", "Heat", Some(1995)),
+        ];
+        for (name, title, year) in cases {
+            let movie = parse_release_name(name).unwrap();
+            assert_eq!(
+                (movie.title.as_str(), movie.year),
+                (*title, *year),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn release_name_without_title_is_not_parsed() {
+        assert!(parse_release_name("[GRP]").is_none());
+        assert!(parse_release_name("").is_none());
+    }
+}
@@ -0,0 +1,159 @@
+//! Metrics of long running syncs in the text format of Prometheus, served at `/metrics`.
+
+use anyhow::Context as _;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, StatusCode};
+
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use crate::summary::MappingSummary;
+
+static FILES_SCANNED: AtomicU64 = AtomicU64::new(0);
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static API_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static API_ERRORS: AtomicU64 = AtomicU64::new(0);
+static UNRESOLVED: AtomicU64 = AtomicU64::new(0);
+static FILMS_ADDED: AtomicU64 = AtomicU64::new(0);
+static FILMS_REMOVED: AtomicU64 = AtomicU64::new(0);
+static SYNCS: AtomicU64 = AtomicU64::new(0);
+static FAILED_SYNCS: AtomicU64 = AtomicU64::new(0);
+static LAST_SYNC: AtomicI64 = AtomicI64::new(0);
+static LAST_SUCCESS: AtomicI64 = AtomicI64::new(0);
+
+/// Count a request to Letterboxd, including every retry.
+pub fn record_request(failed: bool) {
+    API_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    if failed {
+        API_ERRORS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn count(counter: &AtomicU64, n: usize) {
+    counter.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+/// Count the successful sync of a mapping.
+pub fn record_sync(summary: &MappingSummary) {
+    count(&FILES_SCANNED, summary.files);
+    count(&CACHE_HITS, summary.cache_hits);
+    count(&UNRESOLVED, summary.unmatched.len());
+    count(&FILMS_ADDED, summary.added.len());
+    count(&FILMS_REMOVED, summary.removed.len());
+    count(&SYNCS, 1);
+    let now = chrono::Utc::now().timestamp();
+    LAST_SYNC.store(now, Ordering::Relaxed);
+    LAST_SUCCESS.store(now, Ordering::Relaxed);
+}
+
+/// Count the failed sync of a mapping.
+pub fn record_failed_sync() {
+    count(&SYNCS, 1);
+    count(&FAILED_SYNCS, 1);
+    LAST_SYNC.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+}
+
+/// Name, help and value of the counters.
+static COUNTERS: [(&str, &str, &AtomicU64); 9] = [
+    (
+        "files_scanned_total",
+        "Movie files scanned.",
+        &FILES_SCANNED,
+    ),
+    (
+        "cache_hits_total",
+        "Movies resolved from the cache.",
+        &CACHE_HITS,
+    ),
+    (
+        "api_requests_total",
+        "Requests sent to Letterboxd.",
+        &API_REQUESTS,
+    ),
+    (
+        "api_errors_total",
+        "Failed requests to Letterboxd.",
+        &API_ERRORS,
+    ),
+    (
+        "unresolved_total",
+        "Movies not resolved to films.",
+        &UNRESOLVED,
+    ),
+    ("films_added_total", "Films added to lists.", &FILMS_ADDED),
+    (
+        "films_removed_total",
+        "Films removed from lists.",
+        &FILMS_REMOVED,
+    ),
+    ("syncs_total", "Syncs of mappings.", &SYNCS),
+    (
+        "failed_syncs_total",
+        "Failed syncs of mappings.",
+        &FAILED_SYNCS,
+    ),
+];
+
+/// Name, help and value of the gauges.
+static GAUGES: [(&str, &str, &AtomicI64); 2] = [
+    (
+        "last_sync_timestamp_seconds",
+        "Time of the last sync.",
+        &LAST_SYNC,
+    ),
+    (
+        "last_success_timestamp_seconds",
+        "Time of the last successful sync.",
+        &LAST_SUCCESS,
+    ),
+];
+
+/// The metrics in the text format of Prometheus.
+pub fn render() -> String {
+    let mut out = String::new();
+    let counters = COUNTERS.iter().map(|(name, help, value)| {
+        (
+            *name,
+            *help,
+            "counter",
+            value.load(Ordering::Relaxed) as i64,
+        )
+    });
+    let gauges = GAUGES
+        .iter()
+        .map(|(name, help, value)| (*name, *help, "gauge", value.load(Ordering::Relaxed)));
+    for (name, help, kind, value) in counters.chain(gauges) {
+        let _ = writeln!(out, "# HELP letterboxd_sync_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE letterboxd_sync_{} {}", name, kind);
+        let _ = writeln!(out, "letterboxd_sync_{} {}", name, value);
+    }
+    out
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = if request.uri().path() == "/metrics" {
+        Response::builder()
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "text/plain; version=0.0.4; charset=utf-8",
+            )
+            .body(Body::from(render()))
+    } else {
+        Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+    };
+    Ok(response.expect("valid response"))
+}
+
+/// Serve the metrics at `/metrics` on `addr`.
+pub async fn serve_metrics(addr: SocketAddr) -> anyhow::Result<()> {
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+    hyper::Server::try_bind(&addr)
+        .with_context(|| format!("failed to listen on {}", addr))?
+        .serve(make_service)
+        .await
+        .context("metrics server failed")
+}
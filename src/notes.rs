@@ -0,0 +1,280 @@
+//! Notes of list entries.
+
+use anyhow::{anyhow, Context as _};
+use futures_util::{stream, StreamExt};
+use log::warn;
+use regex::Regex;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::films::{fetch_film_metadata, FilmMetadata};
+use crate::i18n::Message;
+use crate::metadata::{find_release_tag, parse_release_name, ReleaseTag};
+use crate::resolve::ResolvedFilms;
+use crate::scan::{MovieFile, Source};
+use crate::REQUESTS_CONCURRENCY;
+
+/// Probe the audio and subtitle languages of a movie file with `ffprobe`.
+///
+/// Returns a summary like "EN/JP audio, EN subs", or `None` if no languages are tagged.
+pub async fn probe_languages(path: &Path) -> anyhow::Result<Option<String>> {
+    #[derive(Debug, Deserialize)]
+    struct ProbeOutput {
+        #[serde(default)]
+        streams: Vec<ProbeStream>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct ProbeStream {
+        codec_type: Option<String>,
+        #[serde(default)]
+        tags: HashMap<String, String>,
+    }
+
+    let output = tokio::process::Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "stream=codec_type:stream_tags=language",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .output()
+        .await
+        .context("failed to run ffprobe")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffprobe failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let probe: ProbeOutput =
+        serde_json::from_slice(&output.stdout).context("invalid output of ffprobe")?;
+
+    let languages_of = |codec_type: &str| {
+        let mut languages: Vec<String> = Vec::new();
+        for stream in &probe.streams {
+            if stream.codec_type.as_deref() != Some(codec_type) {
+                continue;
+            }
+            let language = match stream.tags.get("language") {
+                Some(language) if language != "und" => short_language_code(language),
+                _ => continue,
+            };
+            if !languages.contains(&language) {
+                languages.push(language);
+            }
+        }
+        languages.join("/")
+    };
+    let audio = languages_of("audio");
+    let subtitles = languages_of("subtitle");
+
+    let summary = match (audio.is_empty(), subtitles.is_empty()) {
+        (true, true) => return Ok(None),
+        (false, true) => format!("{} audio", audio),
+        (true, false) => format!("{} subs", subtitles),
+        (false, false) => format!("{} audio, {} subs", audio, subtitles),
+    };
+    Ok(Some(summary))
+}
+
+/// Convert an ISO 639-2 language code as used in media containers to a short upper case code.
+fn short_language_code(code: &str) -> String {
+    const LANGUAGES: &[(&str, &str)] = &[
+        ("ara", "AR"),
+        ("chi", "ZH"),
+        ("zho", "ZH"),
+        ("cze", "CS"),
+        ("ces", "CS"),
+        ("dan", "DA"),
+        ("dut", "NL"),
+        ("nld", "NL"),
+        ("eng", "EN"),
+        ("fin", "FI"),
+        ("fre", "FR"),
+        ("fra", "FR"),
+        ("ger", "DE"),
+        ("deu", "DE"),
+        ("gre", "EL"),
+        ("ell", "EL"),
+        ("heb", "HE"),
+        ("hin", "HI"),
+        ("hun", "HU"),
+        ("ita", "IT"),
+        ("jpn", "JP"),
+        ("kor", "KO"),
+        ("nor", "NO"),
+        ("pol", "PL"),
+        ("por", "PT"),
+        ("rus", "RU"),
+        ("spa", "ES"),
+        ("swe", "SV"),
+        ("tha", "TH"),
+        ("tur", "TR"),
+    ];
+    let code = code.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(long, _)| *long == code)
+        .map(|(_, short)| short.to_string())
+        .unwrap_or_else(|| code.to_uppercase())
+}
+
+/// Notes with the languages of the movie files of the given films, by film id.
+pub async fn language_notes(films: HashMap<&str, Vec<&MovieFile>>) -> HashMap<String, String> {
+    let probes = films.into_iter().filter_map(|(id, files)| {
+        let file = files.into_iter().next()?;
+        Some(async move {
+            match probe_languages(&file.path).await {
+                Ok(summary) => summary.map(|summary| (id.to_string(), summary)),
+                Err(err) => {
+                    warn!("failed to probe {}: {:#}", file.path.display(), err);
+                    None
+                }
+            }
+        })
+    });
+    stream::iter(probes)
+        .buffer_unordered(REQUESTS_CONCURRENCY)
+        .filter_map(std::future::ready)
+        .collect()
+        .await
+}
+
+/// Render the notes of a list entry by replacing the `{name}` placeholders in `template` with the
+/// given values. Unknown placeholders are kept as they are.
+pub fn render_notes(template: &str, values: &HashMap<&str, String>) -> Option<String> {
+    let placeholder = Regex::new(r"\{(\w+)\}").expect("valid regex");
+    let notes = placeholder.replace_all(template, |caps: &regex::Captures| {
+        values
+            .get(&caps[1])
+            .cloned()
+            .unwrap_or_else(|| caps[0].to_string())
+    });
+    let notes = notes.trim();
+    Some(notes.to_string()).filter(|notes| !notes.is_empty())
+}
+
+/// Tags of a movie file from the names of its folders below the directory of `source`, like
+/// `horror` and `slasher` of `Horror/Slasher/Movie.mkv`. Folders of a single movie, named with a
+/// release year like `Halloween (1978)`, make no tags.
+pub fn path_tags(file: &MovieFile, source: &Source) -> Vec<String> {
+    let root = source.root();
+    let folders = match file.path.parent().map(|folder| folder.strip_prefix(&root)) {
+        Some(Ok(folders)) => folders,
+        _ => return Vec::new(),
+    };
+    let mut tags: Vec<String> = Vec::new();
+    for name in folders.iter().map(|name| name.to_string_lossy()) {
+        if parse_release_name(&name).is_some_and(|movie| movie.year.is_some()) {
+            continue;
+        }
+        let tag = name.trim().to_lowercase();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Values of the placeholders describing the movie file of a film: `{resolution}` and `{source}`
+/// from the release name of the file or its folder, `{path}` relative to the directory of
+/// `source`, `{tags}` from its folders, see [`path_tags`], and `{added}`, the date the file was
+/// last modified.
+fn file_values(file: &MovieFile, source: &Source) -> Vec<(&'static str, String)> {
+    let names: Vec<&str> = [
+        file.path.file_stem(),
+        file.path.parent().and_then(Path::file_name),
+    ]
+    .iter()
+    .flatten()
+    .filter_map(|name| name.to_str())
+    .collect();
+    let tag = |kind| names.iter().find_map(|name| find_release_tag(name, kind));
+    let path = match source {
+        Source::Local(root) => file.path.strip_prefix(root).unwrap_or(&file.path),
+        _ => &file.path,
+    };
+    let added = file
+        .modified
+        .and_then(|modified| chrono::DateTime::from_timestamp(modified, 0))
+        .map(|date| date.format("%Y-%m-%d").to_string());
+    vec![
+        (
+            "resolution",
+            tag(ReleaseTag::Resolution).unwrap_or_default(),
+        ),
+        ("source", tag(ReleaseTag::Source).unwrap_or_default()),
+        ("path", path.display().to_string()),
+        ("tags", path_tags(file, source).join(", ")),
+        ("added", added.unwrap_or_default()),
+    ]
+}
+
+/// Render the notes of the given films from `template`.
+///
+/// Details of the films missing in `film_cache` are fetched and added to it. The file
+/// placeholders describe the first movie file of each film, see [`file_values`]. The
+/// `{languages}` placeholder needs local movie files, which are probed only for local sources.
+pub async fn entry_notes(
+    template: &str,
+    ids: &[String],
+    resolved: &ResolvedFilms,
+    source: &Source,
+    film_cache: &mut HashMap<String, FilmMetadata>,
+    client: &letterboxd::Client,
+) -> anyhow::Result<HashMap<String, String>> {
+    let files_by_film_id = resolved.files_by_film_id();
+    let mut languages = HashMap::new();
+    if template.contains("{languages}") {
+        if source.is_local() {
+            let files = ids
+                .iter()
+                .filter_map(|id| files_by_film_id.get_key_value(id.as_str()))
+                .map(|(id, files)| (*id, files.clone()))
+                .collect();
+            languages = language_notes(files).await;
+        } else {
+            warn!("{}", Message::LanguageNotesLocalOnly);
+        }
+    }
+    if ["{title}", "{year}", "{director}", "{runtime}"]
+        .iter()
+        .any(|placeholder| template.contains(placeholder))
+    {
+        fetch_film_metadata(ids, film_cache, client)
+            .await
+            .context("failed to fetch film details")?;
+    }
+
+    let mut notes = HashMap::new();
+    for id in ids {
+        let mut values = HashMap::new();
+        if let Some(film) = film_cache.get(id) {
+            values.insert("title", film.name.clone());
+            values.insert("year", film.year.map(|y| y.to_string()).unwrap_or_default());
+            values.insert("director", film.directors.join(", "));
+            values.insert(
+                "runtime",
+                film.runtime.map(|r| r.to_string()).unwrap_or_default(),
+            );
+        }
+        values.insert("languages", languages.remove(id).unwrap_or_default());
+        if let Some(file) = files_by_film_id
+            .get(id.as_str())
+            .and_then(|files| files.first())
+        {
+            values.extend(file_values(file, source));
+        }
+        if let Some(entry_notes) = render_notes(template, &values) {
+            notes.insert(id.clone(), entry_notes);
+        }
+    }
+    Ok(notes)
+}
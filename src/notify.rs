@@ -0,0 +1,125 @@
+//! Notifications about syncs which changed lists, posted to a webhook or shown on the desktop.
+
+use anyhow::Context as _;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::summary::{FilmChange, MappingSummary};
+
+/// Changes worth a notification.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    /// Any added or removed film.
+    #[default]
+    Changes,
+    /// Only removed films, which may have been removed unexpectedly.
+    Removals,
+}
+
+/// Notifications of changed lists, the `[notifications]` table of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// URL to post the changes of each synced list to as JSON, see [`Notification`].
+    pub webhook: Option<String>,
+    /// Show a notification on the desktop.
+    #[serde(default)]
+    pub desktop: bool,
+    #[serde(default)]
+    pub on: NotifyOn,
+}
+
+impl NotificationConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.webhook.is_some() || self.desktop
+    }
+}
+
+/// Body posted to the webhook.
+#[derive(Debug, Serialize)]
+pub struct Notification<'a> {
+    pub directory: &'a str,
+    /// ID of the list, or `watchlist`.
+    pub list_id: &'a str,
+    pub added: &'a [FilmChange],
+    pub removed: &'a [FilmChange],
+}
+
+impl<'a> Notification<'a> {
+    pub fn new(summary: &'a MappingSummary) -> Self {
+        Self {
+            directory: &summary.directory,
+            list_id: &summary.list_id,
+            added: &summary.added,
+            removed: &summary.removed,
+        }
+    }
+
+    fn is_relevant(&self, on: NotifyOn) -> bool {
+        match on {
+            NotifyOn::Changes => !self.added.is_empty() || !self.removed.is_empty(),
+            NotifyOn::Removals => !self.removed.is_empty(),
+        }
+    }
+
+    fn text(&self) -> String {
+        let mut lines = vec![format!(
+            "{}: +{} -{}",
+            self.directory,
+            self.added.len(),
+            self.removed.len()
+        )];
+        let changes = self
+            .added
+            .iter()
+            .map(|film| ('+', film))
+            .chain(self.removed.iter().map(|film| ('-', film)));
+        lines.extend(changes.map(|(sign, film)| format!("{} {}", sign, film.title)));
+        lines.join("\n")
+    }
+}
+
+async fn post(url: &str, notification: &Notification<'_>) -> anyhow::Result<()> {
+    reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(notification)?)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+async fn show_on_desktop(notification: &Notification<'_>) -> anyhow::Result<()> {
+    let body = notification.text();
+    tokio::task::spawn_blocking(move || {
+        notify_rust::Notification::new()
+            .summary("Letterboxd Sync")
+            .body(&body)
+            .show()
+            .map(drop)
+    })
+    .await??;
+    Ok(())
+}
+
+/// Notify about the changes of a sync, if they are worth a notification.
+pub async fn notify(config: &NotificationConfig, summary: &MappingSummary) -> anyhow::Result<()> {
+    let notification = Notification::new(summary);
+    if !notification.is_relevant(config.on) {
+        return Ok(());
+    }
+    debug!("Notifying about the changes of {}", summary.list_id);
+    if let Some(url) = &config.webhook {
+        post(url, &notification)
+            .await
+            .context("failed to post the notification")?;
+    }
+    if config.desktop {
+        show_on_desktop(&notification)
+            .await
+            .context("failed to show the notification")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! Overrides pinning movie files to Letterboxd films, for movies the search does not find.
+//!
+//! The overrides file maps file names or glob patterns to film ids or URLs:
+//!
+//! ```toml
+//! [films]
+//! "La Jetée (1962).mkv" = "https://letterboxd.com/film/la-jetee/"
+//! "Stalker*" = "https://boxd.it/2bbs"
+//! ```
+
+use anyhow::Context as _;
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Films pinned by file name or glob pattern.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Overrides {
+    /// Letterboxd film ids, `boxd.it` short links or film URLs by file name or glob pattern.
+    #[serde(default)]
+    films: BTreeMap<String, String>,
+    #[serde(skip)]
+    patterns: Vec<(glob::Pattern, String)>,
+}
+
+impl Overrides {
+    /// Film id or URL pinned to the file name, if any.
+    ///
+    /// An exact file name takes precedence over patterns, which are tried in alphabetical order.
+    pub fn film_for(&self, file_name: &str) -> Option<&str> {
+        self.films
+            .get(file_name)
+            .or_else(|| {
+                self.patterns
+                    .iter()
+                    .find(|(pattern, _)| pattern.matches(file_name))
+                    .map(|(_, film)| film)
+            })
+            .map(String::as_str)
+    }
+}
+
+/// Default location of the overrides file: `overrides.toml` next to the default config file.
+pub fn default_overrides_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("letterboxd-sync").join("overrides.toml"))
+}
+
+/// Load the overrides file at `path`, or at the default location if no path is given.
+///
+/// A missing overrides file at the default location yields no overrides.
+pub fn load_overrides(path: Option<&Path>) -> anyhow::Result<Overrides> {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_overrides_path() {
+            Some(path) => (path, false),
+            None => return Ok(Overrides::default()),
+        },
+    };
+    let mut overrides: Overrides = match fs::read_to_string(&path) {
+        Ok(content) => toml::from_str(&content)
+            .with_context(|| format!("failed to parse overrides file: {}", path.display()))?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !explicit => {
+            return Ok(Overrides::default())
+        }
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("failed to read overrides file: {}", path.display()))
+        }
+    };
+    // Keys which are no valid patterns, e.g. file names with a single bracket, match exactly only.
+    overrides.patterns = overrides
+        .films
+        .iter()
+        .filter_map(|(pattern, film)| Some((glob::Pattern::new(pattern).ok()?, film.clone())))
+        .collect();
+    Ok(overrides)
+}
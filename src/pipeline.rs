@@ -0,0 +1,837 @@
+//! Scanning the directories of a [`Mapping`] for movies and resolving them to films, the steps of a
+//! sync before the lists are planned, which `diff`, `stats` and `compare` share with `sync`.
+//!
+//! The files unchanged since the last sync keep the movie they were guessed to be then, and the
+//! cache of movie ids is saved along the way, so that an interrupted sync resumes the search.
+//!
+//! The exports and statistics of the resolved films, which read the film cache, and the upkeep of
+//! the cache of movie ids are here as well.
+
+use anyhow::{anyhow, Context as _};
+use log::{debug, error, info, warn};
+use regex::Regex;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cache::{
+    checkpoint_path, clear_cache, fingerprint_files, get_cache_filename, get_film_cache_filename,
+    load_cache, load_movie_cache, read_checkpoint, save_cache, verify_cache, CacheFlush, FileEntry,
+    MovieCache, StaleEntry,
+};
+use crate::config::{Config, Mapping};
+use crate::export::LetterboxdImportRow;
+use crate::films::{fetch_film_metadata, fetch_watched_films, FilmMetadata};
+use crate::i18n::Message;
+use crate::input::read_input_file;
+use crate::jellyfin::read_jellyfin_library;
+use crate::metadata::{MetadataChain, SourceKind};
+use crate::overrides::load_overrides;
+use crate::plex::read_plex_library;
+use crate::progress;
+use crate::report::{
+    get_report_filename, read_report, unresolved_rows, update_report, write_report,
+};
+use crate::resolve::{
+    add_embedded_ids, compile_pattern, guess_movies, resolve_film_ids, GuessedMovies, MovieGuess,
+    ResolveOptions, ResolvedFilms,
+};
+use crate::scan::{
+    file_matcher, in_extras_folder, is_episode, is_extra, movie_folders, scan_movies, MovieFile,
+    Source, TvBehavior, WalkOptions,
+};
+use crate::stats::LibraryStats;
+
+/// Lets the user pick the films of ambiguous and low confidence matches among the search results,
+/// like on the terminal.
+pub type PickFilms = fn(&mut ResolvedFilms) -> anyhow::Result<()>;
+
+/// How to scan the directories of a mapping for movies and guess the movies of the files.
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Pattern extracting the movie names, instead of the `pattern` from the config file.
+    pub pattern: Option<String>,
+    /// Guess the movies from the names of the folders of the files before the file names.
+    pub prefer_folder_names: bool,
+    /// Treat a folder with a single movie file as the folder of this movie, see
+    /// [`MetadataChain::for_movie_folders`].
+    pub movie_folders: bool,
+    /// Keep the files in the special folders for extras.
+    pub include_extras: bool,
+    /// Read the titles embedded in MKV and MP4 files.
+    pub container_titles: bool,
+    /// Sources of the movie names in the order to try them, replacing the default order.
+    pub metadata_sources: Vec<SourceKind>,
+    pub walk: WalkOptions,
+    pub tv_behavior: TvBehavior,
+    /// Gitignore-style patterns of the files to skip, besides the ones of the mapping.
+    pub exclude: Vec<String>,
+    /// Skip files smaller than this many bytes.
+    pub min_size: Option<u64>,
+    /// Extensions of the movie files, replacing the ones of the mapping unless empty.
+    pub extensions: Vec<String>,
+}
+
+impl ScanOptions {
+    /// The extensions replacing the ones of the mappings, if any.
+    fn extensions(&self) -> Option<Vec<&str>> {
+        if self.extensions.is_empty() {
+            return None;
+        }
+        Some(self.extensions.iter().map(String::as_str).collect())
+    }
+}
+
+/// How to use the cache of movie ids while resolving the movies.
+#[derive(Debug, Clone)]
+pub struct CacheOptions {
+    /// File of the cache shared by all directories, instead of a file per directory.
+    pub path: Option<PathBuf>,
+    /// Flush the cache to disk before replacing the previous cache file.
+    pub fsync: bool,
+    /// Save the cache after this many newly searched movies.
+    pub flush_every: Option<usize>,
+    /// Save the cache this often while resolving movies.
+    pub flush_interval: Option<Duration>,
+    /// Leave out the movies which an interrupted sync did not find, instead of searching them
+    /// again.
+    pub resume: bool,
+    /// Gitignore-style patterns of the files whose movies to resolve again.
+    pub refresh: Vec<String>,
+    /// Guess the movies only of the files which changed since the last sync.
+    pub incremental: bool,
+}
+
+impl CacheOptions {
+    /// Path of the cache of movie ids of the directory.
+    pub fn movie_cache_path(&self, directory: &Source, config: &Config) -> anyhow::Result<PathBuf> {
+        cache_path(self.path.as_deref(), directory, config)
+    }
+}
+
+/// `path`, or else the path of the cache of movie ids of the directory.
+pub fn cache_path(
+    path: Option<&Path>,
+    directory: &Source,
+    config: &Config,
+) -> anyhow::Result<PathBuf> {
+    match path {
+        Some(path) => Ok(path.to_path_buf()),
+        None => get_cache_filename(config.cache_format, &config.cache_key(directory))
+            .context("failed to resolve cache path"),
+    }
+}
+
+/// How to resolve the movies of a mapping to films.
+#[derive(Debug, Clone, Default)]
+pub struct ResolveSettings {
+    pub options: ResolveOptions,
+    /// File pinning file names to films, see [`load_overrides`].
+    pub overrides: Option<PathBuf>,
+    /// Picks the films of the movies which were not resolved, in interactive mode.
+    pub pick: Option<PickFilms>,
+    /// Keep the movies which were not picked ambiguous, so that a sync stops, instead of leaving
+    /// them out like movies which were not found.
+    pub strict: bool,
+}
+
+/// Sources of the movies of the files of the directories of the mapping, see
+/// [`MetadataChain::for_directories`].
+pub fn metadata_chain(
+    mapping: &Mapping,
+    files: &[MovieFile],
+    options: &ScanOptions,
+    config: &Config,
+) -> anyhow::Result<MetadataChain> {
+    if mapping.more_directories.is_empty() {
+        return directory_metadata_chain(&mapping.directory, files, options, config);
+    }
+    let chains = mapping
+        .directories()
+        .map(|directory| {
+            let chain = directory_metadata_chain(directory, files, options, config)?;
+            Ok((directory, chain))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(MetadataChain::for_directories(chains))
+}
+
+/// Sources of the movies of the files of `directory`, see [`MetadataChain::from_kinds`],
+/// [`MetadataChain::for_source`] and [`MetadataChain::for_movie_folders`].
+fn directory_metadata_chain(
+    directory: &Source,
+    files: &[MovieFile],
+    options: &ScanOptions,
+    config: &Config,
+) -> anyhow::Result<MetadataChain> {
+    let patterns = movie_patterns(options, config)?;
+    if !options.metadata_sources.is_empty() {
+        return Ok(MetadataChain::from_kinds(
+            &options.metadata_sources,
+            directory,
+            patterns,
+        ));
+    }
+    if !options.movie_folders {
+        return Ok(MetadataChain::for_source(
+            directory,
+            patterns,
+            options.prefer_folder_names,
+            options.container_titles,
+        ));
+    }
+    Ok(MetadataChain::for_movie_folders(
+        directory,
+        patterns,
+        options.prefer_folder_names,
+        options.container_titles,
+        movie_folders(files, directory),
+    ))
+}
+
+/// Patterns extracting the movie names, tried in turn: the one of the options or else the
+/// `pattern` from the config file, followed by the `patterns` from the config file.
+fn movie_patterns(options: &ScanOptions, config: &Config) -> anyhow::Result<Vec<Regex>> {
+    options
+        .pattern
+        .as_ref()
+        .or(config.pattern.as_ref())
+        .into_iter()
+        .chain(&config.patterns)
+        .map(|pattern| compile_pattern(pattern))
+        .collect()
+}
+
+/// List the movie files of `directory`, see [`scan_movies`], with a spinner meanwhile.
+pub async fn list_files(
+    directory: &Source,
+    max_depth: Option<usize>,
+    walk: WalkOptions,
+    extensions: &[&str],
+    exclude: &[String],
+    min_size: Option<u64>,
+) -> anyhow::Result<Vec<MovieFile>> {
+    let spinner = progress::spinner(Message::ScanningDirectory(&directory.to_string()).to_string());
+    let files = scan_movies(directory, max_depth, walk, extensions, exclude, min_size).await;
+    spinner.finish_and_clear();
+    let files = files.with_context(|| format!("failed to list files in '{}'", directory))?;
+    debug!("Found {} movie files", files.len());
+    Ok(files)
+}
+
+/// List the movie files of all directories of the mapping, skipping the ones of [`skip_files`].
+pub async fn list_mapping_files(
+    mapping: &Mapping,
+    options: &ScanOptions,
+    config: &Config,
+) -> anyhow::Result<Vec<MovieFile>> {
+    if !mapping.more_directories.is_empty() && mapping.directories().any(Source::lists_movies) {
+        return Err(anyhow!(
+            "input files and media servers cannot be combined with other directories"
+        ));
+    }
+    let exclude: Vec<String> = mapping
+        .exclude
+        .iter()
+        .chain(&options.exclude)
+        .cloned()
+        .collect();
+    let extensions = match options.extensions() {
+        Some(extensions) => extensions,
+        None => mapping.extensions(config),
+    };
+    let mut files = Vec::new();
+    for directory in mapping.directories() {
+        files.extend(
+            list_files(
+                directory,
+                mapping.max_depth(),
+                options.walk,
+                &extensions,
+                &exclude,
+                options.min_size,
+            )
+            .await?,
+        );
+    }
+    skip_files(&mut files, mapping, options);
+    Ok(files)
+}
+
+/// Skip the files in the special folders for extras unless they are included, the extras of movie
+/// folders and, depending on the [`TvBehavior`], TV episodes.
+fn skip_files(files: &mut Vec<MovieFile>, mapping: &Mapping, options: &ScanOptions) {
+    if !options.include_extras {
+        let count = files.len();
+        files.retain(|file| {
+            !mapping
+                .directories()
+                .any(|directory| in_extras_folder(file, directory))
+        });
+        if files.len() != count {
+            debug!(
+                "Skipped {} files in folders for extras",
+                count - files.len()
+            );
+        }
+    }
+    if options.movie_folders {
+        files.retain(|file| !is_extra(file));
+    }
+    match options.tv_behavior {
+        TvBehavior::Skip => {
+            let count = files.len();
+            files.retain(|file| !is_episode(file));
+            if files.len() != count {
+                info!("{}", Message::SkippedEpisodes(count - files.len()));
+            }
+        }
+        TvBehavior::Warn => {
+            for file in files.iter().filter(|file| is_episode(file)) {
+                warn!(
+                    "{}",
+                    Message::LooksLikeEpisode(&file.path.display().to_string())
+                );
+            }
+        }
+        TvBehavior::Include => {}
+    }
+}
+
+/// Guess the movies of the files of `directory`, or read the movies of an input file or a media
+/// server.
+pub async fn guess_source_movies(
+    directory: &Source,
+    files: Vec<MovieFile>,
+    metadata: &MetadataChain,
+) -> anyhow::Result<GuessedMovies> {
+    match directory {
+        Source::InputFile(path) => read_input_file(path),
+        Source::Plex(url) => read_plex_library(url).await,
+        Source::Jellyfin(url) => read_jellyfin_library(url).await,
+        _ => Ok(guess_movies(files, metadata)),
+    }
+}
+
+/// Scan the directory of the mapping for movies and resolve their film ids, updating the cache.
+pub async fn scan_and_resolve(
+    mapping: &Mapping,
+    scan: &ScanOptions,
+    cache: &CacheOptions,
+    resolve: &ResolveSettings,
+    config: &Config,
+    client: Option<&letterboxd::Client>,
+) -> anyhow::Result<ResolvedFilms> {
+    let cache_path = cache.movie_cache_path(&mapping.directory, config)?;
+
+    let files = list_mapping_files(mapping, scan, config).await?;
+    // The folders per movie are told by all files, also the ones unchanged since the last sync.
+    let metadata = metadata_chain(mapping, &files, scan, config)?;
+
+    // A cache shared by all directories keeps the paths of the files as they are.
+    let root = Some(mapping.directory.root()).filter(|_| cache.path.is_none());
+    let mut film_ids_cache = load_movie_cache(&cache_path)
+        .with_context(|| format!("failed to read cache file at: {}", cache_path.display()))?;
+    if let Some(root) = &root {
+        film_ids_cache.rooted_at(root);
+    }
+    let refresh = file_matcher(&mapping.directory, &cache.refresh)?;
+    let overrides = load_overrides(resolve.overrides.as_deref())?;
+
+    // Files unchanged since the last sync keep the movie they were guessed to be then, unless
+    // they are to be refreshed or are overridden.
+    let cached_files = film_ids_cache.files();
+    let (unchanged, files): (Vec<MovieFile>, Vec<MovieFile>) =
+        files.into_iter().partition(|file| {
+            cache.incremental
+                && cached_files
+                    .get(file.path.as_path())
+                    .is_some_and(|(_, cached)| cached.is_unchanged(file))
+                && !refresh(&file.path)
+                && file
+                    .path
+                    .file_name()
+                    .and_then(|name| overrides.film_for(&name.to_string_lossy()))
+                    .is_none()
+        });
+    let mut unchanged_by_movie: HashMap<String, Vec<MovieFile>> = HashMap::new();
+    for mut file in unchanged {
+        let (movie, entry) = cached_files[file.path.as_path()];
+        file.fingerprint = entry.fingerprint.clone();
+        unchanged_by_movie
+            .entry(movie.to_string())
+            .or_default()
+            .push(file);
+    }
+    debug!(
+        "Skipping {} files unchanged since the last sync",
+        unchanged_by_movie.values().map(Vec::len).sum::<usize>()
+    );
+
+    // Collect all movie names
+    let metadata = metadata.with_overrides(overrides);
+    let (mut movies, mut files_by_movie) =
+        guess_source_movies(&mapping.directory, files.clone(), &metadata).await?;
+    for (movie, files) in unchanged_by_movie {
+        if !files_by_movie.contains_key(&movie) {
+            let year = film_ids_cache
+                .movies
+                .get(&movie)
+                .and_then(|entry| entry.year);
+            movies.push(MovieGuess::new(movie.clone(), year));
+        }
+        files_by_movie.entry(movie).or_default().extend(files);
+    }
+    let years: HashMap<String, u16> = movies
+        .iter()
+        .filter_map(|movie| Some((movie.title.clone(), movie.year?)))
+        .collect();
+    let imdb_ids: HashMap<String, String> = movies
+        .iter()
+        .filter_map(|movie| Some((movie.title.clone(), movie.imdb_id.clone()?)))
+        .collect();
+    let guessed: HashSet<&Path> = files_by_movie
+        .values()
+        .flatten()
+        .map(|file| file.path.as_path())
+        .collect();
+    let unguessed: Vec<MovieFile> = files
+        .iter()
+        .filter(|file| !guessed.contains(file.path.as_path()))
+        .cloned()
+        .collect();
+
+    // Resolve movie ids either from cache or by requesting these
+    // Renamed and moved files are recognized by their content, which is only read locally.
+    if mapping.directories().all(Source::is_local) {
+        fingerprint_files(files_by_movie.values_mut().flatten(), &film_ids_cache);
+        let moved = film_ids_cache.add_moved(&files_by_movie);
+        if moved > 0 {
+            info!("{}", Message::FoundMovedFiles(moved));
+        }
+    }
+    for (movie, files) in &files_by_movie {
+        if files.iter().any(|file| refresh(&file.path))
+            && film_ids_cache.movies.remove(movie).is_some()
+        {
+            debug!("Resolving {} again", movie);
+        }
+    }
+    // Movies an interrupted sync did not find are not searched again. The ones it found are in
+    // the flushed cache.
+    let checkpoint = checkpoint_path(&cache_path, &config.cache_key(&mapping.directory));
+    let flush = CacheFlush {
+        path: &cache_path,
+        checkpoint: &checkpoint,
+        root: root.as_deref(),
+        every: cache.flush_every,
+        interval: cache.flush_interval,
+        fsync: cache.fsync,
+    };
+
+    let not_found = if cache.resume {
+        read_checkpoint(&checkpoint)?
+    } else {
+        HashSet::new()
+    };
+    let (skipped, movies): (Vec<MovieGuess>, Vec<MovieGuess>) = movies
+        .into_iter()
+        .partition(|movie| not_found.contains(&movie.title));
+    if !skipped.is_empty() {
+        info!("{}", Message::ResumingSearch(skipped.len()));
+    }
+    let options = &resolve.options;
+    let mut resolved =
+        resolve_film_ids(movies, &film_ids_cache, client, Some(&flush), options).await;
+    resolved
+        .not_found
+        .extend(skipped.into_iter().map(|movie| movie.title));
+    if let Some(max) = options
+        .max_api_calls
+        .filter(|_| !resolved.skipped.is_empty())
+    {
+        warn!(
+            "{}",
+            Message::ApiBudgetSpent {
+                max,
+                skipped: resolved.skipped.len(),
+            }
+        );
+    }
+    resolved.files = files_by_movie;
+    resolved.unguessed = unguessed;
+    resolved.years = years;
+    resolved.imdb_ids = imdb_ids;
+    if let Some(pick) = resolve.pick {
+        pick(&mut resolved)?;
+        // Without --strict, skipped movies are left out like movies which were not found.
+        if !resolve.strict {
+            resolved.ambiguous.clear();
+        }
+    }
+
+    // A cache shared by all directories keeps the movies of the other directories.
+    let film_ids_cache = if cache.path.is_some() {
+        let mut film_ids_cache = film_ids_cache;
+        film_ids_cache.update(&resolved);
+        film_ids_cache
+    } else {
+        film_ids_cache.resolved(&resolved)
+    };
+    let film_ids_cache = match &root {
+        Some(root) => film_ids_cache.relative_to(root),
+        None => film_ids_cache,
+    };
+    match save_cache(&film_ids_cache, &cache_path, cache.fsync) {
+        Ok(()) => {
+            if let Err(err) = clear_cache(&checkpoint) {
+                warn!("failed to remove checkpoint: {}", err);
+            }
+        }
+        Err(err) => warn!("failed to save film ids to cache: {}", err),
+    }
+    let directory = mapping.directory.to_string();
+    let report = get_report_filename()
+        .and_then(|path| update_report(&path, &directory, unresolved_rows(&directory, &resolved)));
+    if let Err(err) = report {
+        warn!("failed to update report of unresolved movies: {:#}", err);
+    }
+    Ok(resolved)
+}
+
+/// Retry resolving the movies in the report of unresolved movies, adding the resolved ones to the
+/// cache and removing them from the report.
+pub async fn retry_unresolved(
+    resolve: &ResolveSettings,
+    cache: &CacheOptions,
+    config: &Config,
+    client: &letterboxd::Client,
+) -> anyhow::Result<()> {
+    let report_path = get_report_filename().context("failed to resolve report path")?;
+    let rows = read_report(&report_path)?;
+    if rows.is_empty() {
+        info!("{}", Message::NothingUnresolved);
+        return Ok(());
+    }
+
+    let overrides = load_overrides(resolve.overrides.as_deref())?;
+    let mut movies: HashMap<String, MovieGuess> = HashMap::new();
+    for row in &rows {
+        let movie = movies
+            .entry(row.title.clone())
+            .or_insert_with(|| MovieGuess::new(row.title.clone(), row.year));
+        if let Some(file_name) = Path::new(&row.file)
+            .file_name()
+            .and_then(|name| name.to_str())
+        {
+            add_embedded_ids(movie, file_name);
+            if movie.pinned.is_none() {
+                movie.pinned = overrides.film_for(file_name).map(str::to_string);
+            }
+        }
+    }
+
+    let mut resolved = resolve_film_ids(
+        movies.into_values(),
+        &MovieCache::default(),
+        Some(client),
+        None,
+        &resolve.options,
+    )
+    .await;
+    report_resolve_errors([&resolved]);
+    if let Some(pick) = resolve.pick {
+        pick(&mut resolved)?;
+    }
+
+    // Each directory has its own cache of movie ids.
+    let directories: HashSet<&str> = rows.iter().map(|row| row.directory.as_str()).collect();
+    for directory in directories {
+        let source: Source = directory.parse()?;
+        let cache_path = cache.movie_cache_path(&source, config)?;
+        // A cache shared by all directories keeps the paths of the files as they are.
+        let root = Some(source.root()).filter(|_| cache.path.is_none());
+        let mut film_ids_cache = load_movie_cache(&cache_path)
+            .with_context(|| format!("failed to read cache file at: {}", cache_path.display()))?;
+        if let Some(root) = &root {
+            film_ids_cache.rooted_at(root);
+        }
+        for row in rows.iter().filter(|row| row.directory == directory) {
+            if let Some(id) = resolved.ids.get(&row.title) {
+                let confidence = resolved.confidences.get(&row.title).copied();
+                let entry = film_ids_cache.insert(row.title.clone(), id, confidence);
+                entry.year = row.year;
+                let file = FileEntry {
+                    path: PathBuf::from(&row.file),
+                    size: None,
+                    modified: None,
+                    fingerprint: None,
+                };
+                if !entry.files.iter().any(|known| known.path == file.path) {
+                    entry.files.push(file);
+                }
+            }
+        }
+        if let Some(root) = &root {
+            film_ids_cache = film_ids_cache.relative_to(root);
+        }
+        save_cache(&film_ids_cache, &cache_path, cache.fsync)
+            .with_context(|| format!("failed to save cache file: {}", cache_path.display()))?;
+    }
+
+    let reasons: HashMap<String, String> = resolved.unresolved().into_iter().collect();
+    let total = rows.len();
+    let remaining: Vec<_> = rows
+        .into_iter()
+        .filter(|row| !resolved.ids.contains_key(&row.title))
+        .map(|mut row| {
+            if let Some(reason) = reasons.get(&row.title) {
+                row.reason = reason.clone();
+            }
+            row
+        })
+        .collect();
+    write_report(&remaining, &report_path)?;
+    info!(
+        "{}",
+        Message::RetriedUnresolved {
+            resolved: total - remaining.len(),
+            remaining: remaining.len(),
+            path: &report_path.display().to_string(),
+        }
+    );
+    Ok(())
+}
+
+/// Log the movies whose resolution failed together at the end of a run, since their warnings are
+/// easily missed among the logs of the other movies.
+pub fn report_resolve_errors<'a>(resolved: impl IntoIterator<Item = &'a ResolvedFilms>) {
+    let errors: Vec<&(String, String)> = resolved
+        .into_iter()
+        .flat_map(|resolved| &resolved.errors)
+        .collect();
+    if errors.is_empty() {
+        return;
+    }
+    error!("{}", Message::ResolveErrors(errors.len()));
+    for (movie, err) in errors {
+        error!("  {}: {}", movie, err);
+    }
+}
+
+/// Path of the film cache and the films cached in it.
+pub fn load_film_cache(
+    config: &Config,
+) -> anyhow::Result<(PathBuf, HashMap<String, FilmMetadata>)> {
+    let path = get_film_cache_filename(config.cache_format)
+        .context("failed to resolve film cache path")?;
+    let film_cache = load_cache(&path)
+        .with_context(|| format!("failed to read film cache file at: {}", path.display()))?;
+    Ok((path, film_cache))
+}
+
+/// Fetch the details of the films missing from the film cache, saving the cache at `path` if it
+/// grew.
+async fn complete_film_cache(
+    ids: impl IntoIterator<Item = &String>,
+    film_cache: &mut HashMap<String, FilmMetadata>,
+    path: &Path,
+    fsync: bool,
+    client: &letterboxd::Client,
+) -> anyhow::Result<()> {
+    let film_cache_len = film_cache.len();
+    fetch_film_metadata(ids, film_cache, client)
+        .await
+        .context("failed to fetch film details")?;
+    if film_cache.len() != film_cache_len {
+        if let Err(err) = save_cache(film_cache, path, fsync) {
+            warn!("failed to save film details to cache: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Details of the resolved films, from the film cache or else fetched.
+pub async fn resolved_film_details(
+    resolved: &[&ResolvedFilms],
+    config: &Config,
+    client: &letterboxd::Client,
+) -> anyhow::Result<HashMap<String, FilmMetadata>> {
+    let (film_cache_path, mut film_cache) = load_film_cache(config)?;
+    let ids: HashSet<&String> = resolved
+        .iter()
+        .flat_map(|resolved| resolved.ids.values())
+        .collect();
+    complete_film_cache(ids, &mut film_cache, &film_cache_path, false, client).await?;
+    Ok(film_cache)
+}
+
+/// Rows of the Letterboxd import of the resolved movies, sorted by movie: the resolved ones as
+/// films with the details of the film cache, the others by the guessed title and year.
+pub fn letterboxd_import_rows(
+    resolved: &ResolvedFilms,
+    config: &Config,
+) -> anyhow::Result<Vec<LetterboxdImportRow>> {
+    let (_, film_cache) = load_film_cache(config)?;
+    let mut movies: Vec<&String> = resolved.files.keys().collect();
+    movies.sort();
+    Ok(movies
+        .into_iter()
+        .map(|movie| {
+            let year = resolved.years.get(movie).copied();
+            match resolved.ids.get(movie) {
+                Some(id) => LetterboxdImportRow::film(id, film_cache.get(id), movie, year),
+                None => LetterboxdImportRow {
+                    letterboxd_uri: None,
+                    imdb_id: None,
+                    title: movie.clone(),
+                    year,
+                },
+            }
+        })
+        .collect())
+}
+
+/// Statistics of the resolved films with the `top` directors. With a client, the missing details
+/// of the films are fetched and, with credentials, the watched films of the member are counted.
+pub async fn library_stats(
+    resolved: &ResolvedFilms,
+    top: usize,
+    fsync: bool,
+    config: &Config,
+    client: Option<&letterboxd::Client>,
+) -> anyhow::Result<LibraryStats> {
+    let ids: HashSet<String> = resolved.ids.values().cloned().collect();
+    let (film_cache_path, mut film_cache) = load_film_cache(config)?;
+    let watched = match client {
+        Some(client) => {
+            complete_film_cache(&ids, &mut film_cache, &film_cache_path, fsync, client).await?;
+            // Without credentials there is no member whose watched films to count.
+            match client.token() {
+                Some(_) => Some(
+                    fetch_watched_films(client)
+                        .await
+                        .context("failed to fetch watched films")?,
+                ),
+                None => None,
+            }
+        }
+        None => None,
+    };
+    Ok(LibraryStats {
+        unresolved_files: resolved.unresolved_files(),
+        ..LibraryStats::new(&ids, &film_cache, watched.as_ref(), top)
+    })
+}
+
+/// Check the cached film ids against Letterboxd and fix the cache, unless `dry_run` is set.
+pub async fn verify_movie_cache(
+    path: &Path,
+    dry_run: bool,
+    client: &letterboxd::Client,
+) -> anyhow::Result<()> {
+    let mut cache = load_movie_cache(path)
+        .with_context(|| format!("failed to read cache file at: {}", path.display()))?;
+    let (stale, failed) = verify_cache(&cache, client).await;
+    for entry in &stale {
+        match entry {
+            StaleEntry::Deleted { movie, film_id } => {
+                info!("{}", Message::CachedFilmDeleted { movie, film_id })
+            }
+            StaleEntry::Merged {
+                movie,
+                film_id,
+                new_id,
+            } => info!(
+                "{}",
+                Message::CachedFilmMerged {
+                    movie,
+                    film_id,
+                    new_id,
+                }
+            ),
+        }
+    }
+    info!(
+        "{}",
+        Message::VerifiedCache {
+            checked: cache.len(),
+            stale: stale.len(),
+            failed,
+        }
+    );
+    if !dry_run && !stale.is_empty() {
+        cache.apply(&stale);
+        save_cache(&cache, path, true)
+            .with_context(|| format!("failed to save cache file: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Remove the movies whose files no longer exist from the cache at `path`, unless `dry_run` is
+/// set.
+///
+/// Local files are looked up directly, the files of remote directories are listed. Without the
+/// directory of the cache, relative paths cannot be looked up and their files are kept.
+pub async fn prune_movie_cache(
+    path: &Path,
+    directory: Option<&Source>,
+    config: &Config,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let mut cache = load_movie_cache(path)
+        .with_context(|| format!("failed to read cache file at: {}", path.display()))?;
+    let root = directory.map(Source::root);
+    if let Some(root) = &root {
+        cache.rooted_at(root);
+    }
+    let listed: Option<HashSet<PathBuf>> = match directory {
+        Some(directory) if directory.lists_movies() => {
+            let (_, files_by_movie) =
+                guess_source_movies(directory, Vec::new(), &MetadataChain::new(Vec::new())).await?;
+            Some(
+                files_by_movie
+                    .into_values()
+                    .flatten()
+                    .map(|file| file.path)
+                    .collect(),
+            )
+        }
+        Some(directory) if !directory.is_local() => {
+            let files = list_files(
+                directory,
+                None,
+                WalkOptions::default(),
+                &config.extensions(),
+                &[],
+                None,
+            )
+            .await?;
+            Some(files.into_iter().map(|file| file.path).collect())
+        }
+        _ => None,
+    };
+    let pruned = cache.prune(|file| match &listed {
+        Some(listed) => listed.contains(file),
+        None => file.is_relative() || file.exists(),
+    });
+    for movie in &pruned {
+        debug!("Removing {} from the cache", movie);
+    }
+    info!(
+        "{}",
+        Message::PrunedCache {
+            removed: pruned.len(),
+            total: cache.len(),
+        }
+    );
+    if let Some(root) = &root {
+        cache = cache.relative_to(root);
+    }
+    if !dry_run && !pruned.is_empty() {
+        save_cache(&cache, path, true)
+            .with_context(|| format!("failed to save cache file: {}", path.display()))?;
+    }
+    Ok(())
+}
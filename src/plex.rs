@@ -0,0 +1,234 @@
+//! Movies of a Plex library instead of found in a directory.
+//!
+//! Plex already matched the movies to their IMDb and TMDb ids, which resolve to films exactly.
+//! The library is given as `plex://host[:port][/section]`, or `plexs://` for HTTPS, where the
+//! section is the key or the title of a movie library, like `plex://nas:32400/Movies`. Without a
+//! section, all movie libraries are read. The token of the server is taken from `PLEX_TOKEN`.
+
+use anyhow::{anyhow, Context as _};
+use log::debug;
+use percent_encoding::percent_decode_str;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use std::env;
+use std::path::PathBuf;
+
+use crate::resolve::{add_listed_movie, GuessedMovies, MovieGuess};
+use crate::scan::MovieFile;
+
+/// Number of movies requested at once.
+const PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    #[serde(rename = "MediaContainer")]
+    container: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct Sections {
+    #[serde(rename = "Directory", default)]
+    directories: Vec<Section>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Section {
+    key: String,
+    title: String,
+    #[serde(rename = "type")]
+    type_: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Items {
+    total_size: Option<usize>,
+    #[serde(rename = "Metadata", default)]
+    metadata: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    key: String,
+    title: String,
+    year: Option<u16>,
+    /// Id of the agent which matched the movie, like `com.plexapp.agents.imdb://tt0133093?lang=en`
+    /// for the legacy agents.
+    guid: Option<String>,
+    updated_at: Option<i64>,
+    /// External ids of the movie matched by the current agent, like `imdb://tt0133093`.
+    #[serde(rename = "Guid", default)]
+    guids: Vec<Guid>,
+    #[serde(rename = "Media", default)]
+    media: Vec<Media>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Guid {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Media {
+    #[serde(rename = "Part", default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Part {
+    file: Option<String>,
+    size: Option<u64>,
+}
+
+impl Item {
+    fn movie(&self) -> MovieGuess {
+        let mut movie = MovieGuess::new(self.title.clone(), self.year);
+        let legacy = self
+            .guid
+            .as_deref()
+            .and_then(|guid| guid.strip_prefix("com.plexapp.agents."));
+        let ids = self.guids.iter().map(|guid| guid.id.as_str()).chain(legacy);
+        for id in ids {
+            let (agent, id) = match id.split_once("://") {
+                Some((agent, id)) => (agent, id.split('?').next().unwrap_or(id)),
+                None => continue,
+            };
+            match agent {
+                "imdb" if id.starts_with("tt") => movie.imdb_id = Some(id.to_string()),
+                "tmdb" | "themoviedb" => movie.tmdb_id = Some(id.to_string()),
+                _ => {}
+            }
+        }
+        movie
+    }
+
+    /// The files of the movie as stored on the server. A movie without any files known to the
+    /// server counts as a file named after its key, like `/library/metadata/123`.
+    fn files(&self) -> Vec<MovieFile> {
+        let mut files: Vec<MovieFile> = self
+            .media
+            .iter()
+            .flat_map(|media| &media.parts)
+            .filter_map(|part| {
+                Some(MovieFile {
+                    path: PathBuf::from(part.file.as_ref()?),
+                    size: part.size,
+                    modified: self.updated_at,
+                    fingerprint: None,
+                })
+            })
+            .collect();
+        if files.is_empty() {
+            files.push(MovieFile {
+                path: PathBuf::from(&self.key),
+                size: None,
+                modified: self.updated_at,
+                fingerprint: None,
+            });
+        }
+        files
+    }
+}
+
+struct PlexClient {
+    client: reqwest::Client,
+    base: reqwest::Url,
+    token: Option<String>,
+}
+
+impl PlexClient {
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> anyhow::Result<T> {
+        let mut url = self.base.join(path)?;
+        url.query_pairs_mut().extend_pairs(query);
+        let mut request = self
+            .client
+            .get(url)
+            .header(reqwest::header::ACCEPT, "application/json");
+        if let Some(token) = &self.token {
+            request = request.header("X-Plex-Token", token);
+        }
+        let body = request.send().await?.error_for_status()?.text().await?;
+        let response: Response<T> = serde_json::from_str(&body)
+            .with_context(|| format!("invalid Plex response for {}", path))?;
+        Ok(response.container)
+    }
+
+    /// The movie libraries, only the one with the key or title `section` if given.
+    async fn sections(&self, section: Option<&str>) -> anyhow::Result<Vec<Section>> {
+        let sections: Sections = self.get("/library/sections", &[]).await?;
+        let sections: Vec<Section> = sections
+            .directories
+            .into_iter()
+            .filter(|found| found.type_ == "movie")
+            .filter(|found| {
+                section.is_none_or(|section| {
+                    found.key == section || found.title.eq_ignore_ascii_case(section)
+                })
+            })
+            .collect();
+        match section {
+            Some(section) if sections.is_empty() => {
+                Err(anyhow!("no Plex movie library '{}'", section))
+            }
+            _ => Ok(sections),
+        }
+    }
+
+    async fn items(&self, section: &Section) -> anyhow::Result<Vec<Item>> {
+        let path = format!("/library/sections/{}/all", section.key);
+        let mut items = Vec::new();
+        loop {
+            let query = [
+                ("includeGuids", "1".to_string()),
+                ("X-Plex-Container-Start", items.len().to_string()),
+                ("X-Plex-Container-Size", PAGE_SIZE.to_string()),
+            ];
+            let page: Items = self.get(&path, &query).await?;
+            let count = page.metadata.len();
+            items.extend(page.metadata);
+            if count < PAGE_SIZE || page.total_size.is_some_and(|total| items.len() >= total) {
+                break;
+            }
+        }
+        debug!(
+            "Found {} movies in Plex library {}",
+            items.len(),
+            section.title
+        );
+        Ok(items)
+    }
+}
+
+/// Read the movies of the Plex library at `url`, like [`guess_movies`] guesses the movies of
+/// files. The URL has the scheme `http` or `https` and the section as path, if any.
+///
+/// [`guess_movies`]: crate::resolve::guess_movies
+pub async fn read_plex_library(url: &reqwest::Url) -> anyhow::Result<GuessedMovies> {
+    let section = percent_decode_str(url.path().trim_matches('/')).decode_utf8_lossy();
+    let section = Some(section.as_ref()).filter(|section| !section.is_empty());
+    let mut base = url.clone();
+    base.set_path("/");
+    let plex = PlexClient {
+        client: reqwest::Client::new(),
+        base,
+        token: env::var("PLEX_TOKEN").ok(),
+    };
+
+    let mut guessed = GuessedMovies::default();
+    for section in plex.sections(section).await? {
+        let items = plex
+            .items(&section)
+            .await
+            .with_context(|| format!("failed to read Plex library {}", section.title))?;
+        for item in items {
+            add_listed_movie(&mut guessed, item.movie(), item.files());
+        }
+    }
+    Ok(guessed)
+}
@@ -0,0 +1,39 @@
+//! Progress bars of the long running steps, drawn to stderr if it is a terminal.
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enable or disable all progress bars.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Bar of a step with `len` items.
+pub fn bar(len: u64, message: String) -> ProgressBar {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return ProgressBar::hidden();
+    }
+    let style = ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len} ({eta})")
+        .expect("valid template")
+        .progress_chars("=> ");
+    ProgressBar::new(len)
+        .with_style(style)
+        .with_message(message)
+}
+
+/// Spinner of a step with an unknown number of items, ticking until it is finished.
+pub fn spinner(message: String) -> ProgressBar {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return ProgressBar::hidden();
+    }
+    let style = ProgressStyle::with_template("{spinner} {msg}").expect("valid template");
+    let spinner = ProgressBar::new_spinner()
+        .with_style(style)
+        .with_message(message);
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
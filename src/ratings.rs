@@ -0,0 +1,150 @@
+//! Ratings of films kept in a CSV file, like a spreadsheet, and set on Letterboxd during a sync.
+//!
+//! Each row rates a movie by the name of one of its files, like `Heat.1995.1080p.mkv,4.5`, or by
+//! its title and optionally its release year, like `Heat (1995),4.5` or `Heat,1995,4.5`. Ratings
+//! go from 0.5 to 5 in steps of 0.5. A header row starting with `name` or `title` is skipped.
+
+use anyhow::{anyhow, Context as _};
+use futures_util::{stream, StreamExt, TryStreamExt};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::auth::profile_name;
+use crate::cache::{cache_dir, CacheFormat};
+use crate::input::parse_title_with_year;
+use crate::resolve::ResolvedFilms;
+use crate::retry::with_retry;
+use crate::throttle::max_concurrency;
+
+/// Name of the ratings file read from the root of a local directory, unless another file is
+/// given.
+pub const RATINGS_FILENAME: &str = "ratings.csv";
+
+/// Ratings by file name and by title.
+#[derive(Debug, Default)]
+pub struct Ratings {
+    /// Ratings by lowercase file name.
+    by_file_name: HashMap<String, f32>,
+    /// Ratings by lowercase title, with the release year if given.
+    by_title: Vec<(String, Option<u16>, f32)>,
+}
+
+fn parse_rating(rating: &str, line: usize) -> anyhow::Result<f32> {
+    let value: f32 = rating
+        .parse()
+        .map_err(|_| anyhow!("invalid rating '{}' in line {}", rating, line))?;
+    let halves = value * 2.0;
+    if halves.fract() != 0.0 || !(1.0..=10.0).contains(&halves) {
+        return Err(anyhow!(
+            "rating {} in line {} is not between 0.5 and 5 in steps of 0.5",
+            rating,
+            line
+        ));
+    }
+    Ok(value)
+}
+
+/// Read the ratings file at `path`.
+pub fn read_ratings(path: &Path) -> anyhow::Result<Ratings> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("failed to read ratings file {}", path.display()))?;
+    let mut ratings = Ratings::default();
+    for record in reader.records() {
+        let record =
+            record.with_context(|| format!("failed to read ratings file {}", path.display()))?;
+        let line = record
+            .position()
+            .map_or(0, |position| position.line() as usize);
+        let name = match record.get(0) {
+            Some(name) if !name.is_empty() => name.to_lowercase(),
+            _ => continue,
+        };
+        if line == 1 && (name == "name" || name == "title") {
+            continue;
+        }
+        let parsed = match (record.get(1), record.get(2)) {
+            (Some(rating), None) => parse_rating(rating, line).map(|rating| {
+                let movie = parse_title_with_year(&name);
+                ratings.by_title.push((movie.title, movie.year, rating));
+                ratings.by_file_name.insert(name, rating);
+            }),
+            (Some(year), Some(rating)) => {
+                let year = match year {
+                    "" => None,
+                    year => Some(
+                        year.parse()
+                            .map_err(|_| anyhow!("invalid year '{}' in line {}", year, line))?,
+                    ),
+                };
+                parse_rating(rating, line).map(|rating| ratings.by_title.push((name, year, rating)))
+            }
+            _ => Err(anyhow!("missing rating in line {}", line)),
+        };
+        parsed.with_context(|| format!("invalid ratings file {}", path.display()))?;
+    }
+    Ok(ratings)
+}
+
+impl Ratings {
+    /// Ratings of the resolved films by film id. A rating by file name takes precedence over one
+    /// by title.
+    pub fn of_films(&self, resolved: &ResolvedFilms) -> HashMap<String, f32> {
+        let mut ratings = HashMap::new();
+        for (movie, id) in &resolved.ids {
+            let files = resolved.files.get(movie).into_iter().flatten();
+            let by_file_name = files
+                .filter_map(|file| file.path.file_name()?.to_str())
+                .find_map(|name| self.by_file_name.get(&name.to_lowercase()).copied());
+            let year = resolved.years.get(movie).copied();
+            let by_title = || {
+                let title = movie.to_lowercase();
+                self.by_title
+                    .iter()
+                    .find(|(rated, rated_year, _)| {
+                        *rated == title && rated_year.is_none_or(|rated| Some(rated) == year)
+                    })
+                    .map(|(_, _, rating)| *rating)
+            };
+            if let Some(rating) = by_file_name.or_else(by_title) {
+                ratings.insert(id.clone(), rating);
+            }
+        }
+        ratings
+    }
+}
+
+/// Path of the cache of the ratings last set on Letterboxd, so that only changed ratings are set
+/// again. Each profile has its own cache.
+pub fn get_ratings_cache_filename(format: CacheFormat) -> anyhow::Result<PathBuf> {
+    let file_name = match profile_name() {
+        Some(profile) => format!("ratings-{}", profile),
+        None => "ratings".to_string(),
+    };
+    Ok(cache_dir()?
+        .join(file_name)
+        .with_extension(format.extension()))
+}
+
+/// Set the ratings of the authenticated member for the given films.
+pub async fn rate_films(
+    ratings: &HashMap<String, f32>,
+    client: &letterboxd::Client,
+) -> letterboxd::Result<()> {
+    let requests = ratings.iter().map(|(id, rating)| async move {
+        let request = letterboxd::FilmRelationshipUpdateRequest {
+            rating: Some(*rating),
+            ..Default::default()
+        };
+        with_retry(|| client.update_film_relationship(id, &request)).await
+    });
+    stream::iter(requests)
+        .buffer_unordered(max_concurrency())
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! Canonical names for the movie files whose films were not found by an exact search, suggested by
+//! `sync --suggest-renames` and applied with `--apply-renames`, so that later syncs find the films
+//! of the renamed files by an exact search.
+//!
+//! The names are rendered from a template like `{title} ({year})`. The files keep their
+//! extension and folder, and the parts of multi-part movies keep their part number.
+
+use log::warn;
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::duplicates::part_number;
+use crate::films::FilmMetadata;
+use crate::notes::render_notes;
+use crate::resolve::{MatchSource, ResolvedFilms};
+
+/// Template of the suggested file names unless another one is given.
+pub const DEFAULT_RENAME_TEMPLATE: &str = "{title} ({year})";
+
+/// A movie file and its suggested name.
+#[derive(Debug, Clone)]
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Whether the film of a movie was found other than by an exact search or an external id, like by
+/// a fuzzy search, TMDb, an override or by picking. Films from the cache are left out, as it is
+/// not known how they were found.
+fn found_inexactly(source: Option<MatchSource>, confidence: Option<f64>) -> bool {
+    match source {
+        Some(MatchSource::Override) | Some(MatchSource::Tmdb) | Some(MatchSource::Picked) => true,
+        Some(MatchSource::Search) => confidence.is_some_and(|confidence| confidence < 1.0),
+        Some(MatchSource::Cache) | Some(MatchSource::ExternalId) | None => false,
+    }
+}
+
+/// Make a file name of `name`: `Mission: Impossible` becomes `Mission - Impossible`, characters
+/// not allowed in file names on Windows are dropped and so are empty brackets of unknown values.
+fn sanitize_file_name(name: &str) -> String {
+    let name: String = name
+        .replace(':', " -")
+        .chars()
+        .filter(|c| !matches!(c, '/' | '\\' | '*' | '?' | '"' | '<' | '>' | '|') && !c.is_control())
+        .collect();
+    let name = name.replace("()", "").replace("[]", "");
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    name.trim_end_matches('.').to_string()
+}
+
+/// Name without extension of the files of a film, rendered from `template`.
+fn canonical_name(template: &str, film: &FilmMetadata) -> Option<String> {
+    let values = HashMap::from([
+        ("title", film.name.clone()),
+        ("year", film.year.map(|y| y.to_string()).unwrap_or_default()),
+        ("director", film.directors.join(", ")),
+    ]);
+    Some(sanitize_file_name(&render_notes(template, &values)?)).filter(|name| !name.is_empty())
+}
+
+/// Suggest canonical names for the files of the movies whose films were not found by an exact
+/// search, ordered by path. `films` has the details of the films.
+///
+/// Files already named canonically are left out, and so are files which would get the same name as
+/// another file, like several copies of a movie.
+pub fn suggest_renames(
+    resolved: &ResolvedFilms,
+    films: &HashMap<String, FilmMetadata>,
+    template: &str,
+) -> Vec<Rename> {
+    let mut renames = Vec::new();
+    for (movie, id) in &resolved.ids {
+        let source = resolved.sources.get(movie).copied();
+        if !found_inexactly(source, resolved.confidences.get(movie).copied()) {
+            continue;
+        }
+        let name = match films
+            .get(id)
+            .and_then(|film| canonical_name(template, film))
+        {
+            Some(name) => name,
+            None => continue,
+        };
+        for file in resolved.files.get(movie).into_iter().flatten() {
+            let mut stem = name.clone();
+            if let Some(part) = part_number(&file.path) {
+                stem = format!("{} - part{}", stem, part);
+            }
+            // Titles like `Dr. Strangelove` have no extension, so it is appended.
+            let mut file_name = OsString::from(stem);
+            if let Some(extension) = file.path.extension() {
+                file_name.push(".");
+                file_name.push(extension);
+            }
+            let to = file.path.with_file_name(file_name);
+            if to != file.path {
+                renames.push(Rename {
+                    from: file.path.clone(),
+                    to,
+                });
+            }
+        }
+    }
+    let mut targets = HashSet::new();
+    let clashing: HashSet<PathBuf> = renames
+        .iter()
+        .filter(|rename| !targets.insert(&rename.to))
+        .map(|rename| rename.to.clone())
+        .collect();
+    renames.retain(|rename| !clashing.contains(&rename.to));
+    renames.sort_by(|a, b| a.from.cmp(&b.from));
+    renames
+}
+
+/// Rename the local files as suggested, returning the number of renamed files. Files whose new
+/// name is taken are not renamed.
+pub fn apply_renames(renames: &[Rename]) -> usize {
+    let mut renamed = 0;
+    for rename in renames {
+        if rename.to.exists() {
+            warn!(
+                "not renaming {}: {} exists",
+                rename.from.display(),
+                rename.to.display()
+            );
+            continue;
+        }
+        match fs::rename(&rename.from, &rename.to) {
+            Ok(()) => renamed += 1,
+            Err(err) => warn!("failed to rename {}: {}", rename.from.display(), err),
+        }
+    }
+    renamed
+}
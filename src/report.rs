@@ -0,0 +1,78 @@
+//! Report of the movies which could not be resolved to films, kept across runs.
+//!
+//! Each sync replaces the rows of its directory in the report, so that it lists the files which
+//! still need attention, e.g. an override or a pick with `resolve --interactive`.
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::resolve::ResolvedFilms;
+
+/// A movie file which could not be resolved to a film.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnresolvedRow {
+    /// Directory scanned for the file.
+    pub directory: String,
+    pub file: String,
+    /// Title guessed from the file.
+    pub title: String,
+    /// Release year guessed from the file.
+    pub year: Option<u16>,
+    pub reason: String,
+}
+
+pub fn get_report_filename() -> anyhow::Result<PathBuf> {
+    const REPORT_FILENAME: &str = "unresolved.csv";
+    Ok(env::current_dir()?.join(REPORT_FILENAME))
+}
+
+/// Rows of the files of the movies in `resolved` which were not resolved.
+pub fn unresolved_rows(directory: &str, resolved: &ResolvedFilms) -> Vec<UnresolvedRow> {
+    let mut rows = Vec::new();
+    for (movie, reason) in resolved.unresolved() {
+        for file in resolved.files.get(&movie).into_iter().flatten() {
+            rows.push(UnresolvedRow {
+                directory: directory.to_string(),
+                file: file.path.display().to_string(),
+                title: movie.clone(),
+                year: resolved.years.get(&movie).copied(),
+                reason: reason.clone(),
+            });
+        }
+    }
+    rows
+}
+
+/// Read the report at `path`. A missing report has no rows.
+pub fn read_report(path: &Path) -> anyhow::Result<Vec<UnresolvedRow>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut reader = csv::Reader::from_path(path)
+        .with_context(|| format!("failed to read report: {}", path.display()))?;
+    reader
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("failed to parse report: {}", path.display()))
+}
+
+/// Write the rows to the report at `path`, replacing it.
+pub fn write_report(rows: &[UnresolvedRow], path: &Path) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_path(path)
+        .with_context(|| format!("failed to write report: {}", path.display()))?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(writer.flush()?)
+}
+
+/// Replace the rows of `directory` in the report at `path` with `rows`.
+pub fn update_report(path: &Path, directory: &str, rows: Vec<UnresolvedRow>) -> anyhow::Result<()> {
+    let mut report = read_report(path)?;
+    report.retain(|row| row.directory != directory);
+    report.extend(rows);
+    write_report(&report, path)
+}
@@ -0,0 +1,1228 @@
+//! Extracting movie names from file names and resolving them to Letterboxd films.
+
+use anyhow::anyhow;
+use futures_util::{stream, StreamExt};
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::iter;
+use std::sync::OnceLock;
+use std::time::Instant;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization as _;
+
+use crate::cache::{CacheFlush, MovieCache};
+use crate::films::film_title;
+use crate::i18n::Message;
+use crate::metadata::MetadataSource;
+use crate::progress;
+use crate::retry::{api_calls, with_retry};
+use crate::scan::MovieFile;
+use crate::throttle::max_concurrency;
+use crate::tmdb::search_tmdb;
+
+/// Search movie on letterbox, returning at most `per_page` results.
+pub async fn search_movie(
+    client: &letterboxd::Client,
+    movie: String,
+    per_page: usize,
+) -> letterboxd::Result<letterboxd::SearchResponse> {
+    let request = letterboxd::SearchRequest {
+        cursor: None,
+        per_page: Some(per_page),
+        input: movie,
+        search_method: Some(letterboxd::SearchMethod::Autocomplete),
+        include: None,
+        contribution_type: None,
+    };
+    with_retry(|| client.search(&request)).await
+}
+
+/// Movie name and, if known, release year and external ids of a movie file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MovieGuess {
+    pub title: String,
+    pub year: Option<u16>,
+    /// IMDb id, e.g. `tt0133093`.
+    pub imdb_id: Option<String>,
+    /// TMDb id, e.g. `603`.
+    pub tmdb_id: Option<String>,
+    /// Letterboxd film id or URL the movie is pinned to by an override.
+    pub pinned: Option<String>,
+}
+
+impl MovieGuess {
+    /// Guess without external ids.
+    pub fn new(title: String, year: Option<u16>) -> Self {
+        Self {
+            title,
+            year,
+            imdb_id: None,
+            tmdb_id: None,
+            pinned: None,
+        }
+    }
+}
+
+impl fmt::Display for MovieGuess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.year {
+            Some(year) => write!(f, "{} ({})", self.title, year),
+            None => write!(f, "{}", self.title),
+        }
+    }
+}
+
+/// Extract movie names from file names with given pattern.
+///
+/// The movie name is taken from the capture group named `title`, or else from the first group.
+/// The release year is taken from the group named `year`, if any.
+pub fn extract_movie(pattern: &Regex, file_name: &str) -> Option<MovieGuess> {
+    let matches = pattern.captures(file_name)?;
+    let title = matches.name("title").or_else(|| matches.get(1))?;
+    let year = matches
+        .name("year")
+        .and_then(|year| year.as_str().parse().ok());
+    Some(MovieGuess::new(title.as_str().to_string(), year))
+}
+
+/// Take the IMDb and TMDb ids embedded in a file name, as in `Movie (2020) {imdb-tt1234567}.mkv`
+/// or `Movie (2020) [tmdbid-603].mkv`, if the guess has no ids yet.
+pub fn add_embedded_ids(movie: &mut MovieGuess, file_name: &str) {
+    static TOKEN: OnceLock<Regex> = OnceLock::new();
+    let token = TOKEN.get_or_init(|| {
+        Regex::new(r"[\[{](imdb|tmdb)(?:id)?-(tt\d+|\d+)[\]}]").expect("valid id token pattern")
+    });
+    for captures in token.captures_iter(file_name) {
+        let id = Some(captures[2].to_string());
+        match &captures[1] {
+            "imdb" if movie.imdb_id.is_none() && captures[2].starts_with("tt") => {
+                movie.imdb_id = id
+            }
+            "tmdb" if movie.tmdb_id.is_none() && !captures[2].starts_with("tt") => {
+                movie.tmdb_id = id
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Pattern of the marker of a part of a multi-part movie at the end of a name, like ` CD1`,
+/// `.part2` or ` - Disc B`.
+fn part_marker() -> &'static Regex {
+    static MARKER: OnceLock<Regex> = OnceLock::new();
+    MARKER.get_or_init(|| {
+        Regex::new(
+            r"(?i)[ _.\-]+[\[(]?(?:cd|dvd|part|pt|disc|disk)[ _.\-]*(?:\d{1,2}|[a-d])[\])]?$",
+        )
+        .expect("valid part marker pattern")
+    })
+}
+
+/// The name without the marker of the part of a multi-part movie, if it has one.
+fn strip_part_marker(name: &str) -> Option<&str> {
+    part_marker()
+        .find(name)
+        .map(|marker| &name[..marker.start()])
+}
+
+/// Take the year and ids missing in `movie` from `other`, a guess of the same movie.
+pub(crate) fn merge_guess(movie: &mut MovieGuess, other: MovieGuess) {
+    movie.year = movie.year.or(other.year);
+    movie.imdb_id = movie.imdb_id.take().or(other.imdb_id);
+    movie.tmdb_id = movie.tmdb_id.take().or(other.tmdb_id);
+    movie.pinned = movie.pinned.take().or(other.pinned);
+}
+
+/// Movies and their files by movie name.
+pub type GuessedMovies = (Vec<MovieGuess>, HashMap<String, Vec<MovieFile>>);
+
+/// Add a movie listed with its files by a catalogue, like an input file, merging it with an
+/// already listed movie of the same name.
+pub(crate) fn add_listed_movie(
+    (movies, files_by_movie): &mut GuessedMovies,
+    mut movie: MovieGuess,
+    files: Vec<MovieFile>,
+) {
+    movie.title = movie.title.nfc().collect();
+    match files_by_movie.get_mut(&movie.title) {
+        Some(known) => {
+            known.extend(files);
+            if let Some(first) = movies.iter_mut().find(|first| first.title == movie.title) {
+                merge_guess(first, movie);
+            }
+        }
+        None => {
+            files_by_movie.insert(movie.title.clone(), files);
+            movies.push(movie);
+        }
+    }
+}
+
+/// Guess the movies of the given files with `source`, e.g. a [`MetadataChain`].
+///
+/// Returns the movies and their files by movie name. Files the source knows no movie of are
+/// skipped. Each movie is returned once, even if there are multiple files of it, like editions,
+/// qualities or the parts of a multi-part movie. Files are the parts of a movie if they are in the
+/// same folder and their names only differ in a marker like `CD1` and `CD2` or `part1` and
+/// `part2`, which is dropped from the movie name.
+///
+/// Movie names are normalized to NFC, so that a name with letters composed of a letter and
+/// combining diacritics, as in file names on macOS, is the same as the one with precomposed
+/// letters.
+///
+/// [`MetadataChain`]: crate::metadata::MetadataChain
+pub fn guess_movies(files: Vec<MovieFile>, source: &dyn MetadataSource) -> GuessedMovies {
+    // A name with a part marker only marks a part if there are other parts.
+    let stack = |file: &MovieFile| {
+        let stem = file.path.file_stem()?.to_string_lossy();
+        let stem = strip_part_marker(&stem)?;
+        Some((file.path.parent()?.to_path_buf(), stem.to_string()))
+    };
+    // The names are guessed with the invalid characters replaced, so the movie may not be found.
+    for file in files.iter().filter(|file| file.path.to_str().is_none()) {
+        warn!(
+            "{} is not valid UTF-8, guessing its movie from the name with replacement characters",
+            file.path.display()
+        );
+    }
+    let mut stack_sizes: HashMap<_, usize> = HashMap::new();
+    for stack in files.iter().filter_map(stack) {
+        *stack_sizes.entry(stack).or_default() += 1;
+    }
+
+    // Guessing may read files like NFO files, so the files are guessed in parallel.
+    let guesses: Vec<Option<MovieGuess>> =
+        files.par_iter().map(|file| source.guess(file)).collect();
+
+    let mut movies: Vec<MovieGuess> = Vec::new();
+    let mut files_by_movie: HashMap<String, Vec<MovieFile>> = HashMap::new();
+    for (file, guess) in files.into_iter().zip(guesses) {
+        let mut movie = match guess {
+            Some(movie) => movie,
+            None => continue,
+        };
+        movie.title = movie.title.nfc().collect();
+        let is_part = stack(&file).is_some_and(|stack| stack_sizes[&stack] > 1);
+        if is_part {
+            if let Some(title) = strip_part_marker(&movie.title) {
+                movie.title = title.to_string();
+            }
+        }
+        match files_by_movie.get_mut(&movie.title) {
+            Some(movie_files) => {
+                debug!("{} is another file of {}", file.path.display(), movie.title);
+                movie_files.push(file);
+                if let Some(first) = movies.iter_mut().find(|first| first.title == movie.title) {
+                    merge_guess(first, movie);
+                }
+            }
+            None => {
+                files_by_movie.insert(movie.title.clone(), vec![file]);
+                movies.push(movie);
+            }
+        }
+    }
+    (movies, files_by_movie)
+}
+
+/// Compile a pattern used to extract movie names, which must contain a capture group.
+pub fn compile_pattern(pattern: &str) -> anyhow::Result<Regex> {
+    let re = Regex::new(pattern)?;
+    if re.captures_len() < 2 {
+        return Err(anyhow!(
+            "pattern '{}' has no capture group for the movie name",
+            pattern
+        ));
+    }
+    Ok(re)
+}
+
+/// Number of search results considered in strict mode to detect ambiguous matches.
+pub const STRICT_SEARCH_RESULTS: usize = 5;
+
+/// Years a film may be released before or after the year of a movie to still match it, as
+/// festival screenings and the wide release often fall in different years.
+pub const YEAR_TOLERANCE: u16 = 1;
+
+/// Rank the films released in the year of the movie first, then the ones released within
+/// [`YEAR_TOLERANCE`] or in an unknown year, keeping the order of the search otherwise.
+fn rank_by_year(movie: &MovieGuess, films: &mut [letterboxd::FilmSummary]) {
+    if let Some(year) = movie.year {
+        films.sort_by_key(|film| match film.release_year {
+            Some(film_year) if film_year == year => 0,
+            Some(film_year) if film_year.abs_diff(year) > YEAR_TOLERANCE => 2,
+            _ => 1,
+        });
+    }
+}
+
+/// Check the search results for a movie for ambiguities, returning the reason if ambiguous.
+pub fn find_ambiguity(movie: &MovieGuess, films: &[letterboxd::FilmSummary]) -> Option<String> {
+    let best = films.first()?;
+    if let (Some(year), Some(film_year)) = (movie.year, best.release_year) {
+        if year.abs_diff(film_year) > YEAR_TOLERANCE {
+            return Some(format!(
+                "year {} differs from year {} of best match '{}'",
+                year, film_year, best.name
+            ));
+        }
+    }
+
+    let close_candidates: Vec<_> = films
+        .iter()
+        .filter(|film| film.name.eq_ignore_ascii_case(&best.name))
+        .filter(|film| movie.year.is_none() || film.release_year == movie.year)
+        .collect();
+    if close_candidates.len() > 1 {
+        let years: Vec<_> = close_candidates
+            .iter()
+            .map(|film| {
+                film.release_year
+                    .map(|year| year.to_string())
+                    .unwrap_or_else(|| "?".to_string())
+            })
+            .collect();
+        return Some(format!(
+            "{} candidates named '{}' ({})",
+            close_candidates.len(),
+            best.name,
+            years.join(", ")
+        ));
+    }
+    None
+}
+
+/// Find the id of a pinned film: a film id, a `boxd.it` short link or a Letterboxd film URL.
+///
+/// Returns `None` if no film has the URL.
+async fn find_pinned(
+    client: &letterboxd::Client,
+    pinned: &str,
+) -> letterboxd::Result<Option<String>> {
+    let pinned = pinned.trim().trim_end_matches('/');
+    if let Some(path) = pinned.split("letterboxd.com/film/").nth(1) {
+        // Films cannot be looked up by URL, so search for the slug and compare the URLs.
+        let slug = path.split('/').next().unwrap_or(path);
+        let url = format!("https://letterboxd.com/film/{}", slug);
+        let response = search_movie(client, slug.replace('-', " "), STRICT_SEARCH_RESULTS).await?;
+        let film = response
+            .items
+            .into_iter()
+            .filter_map(|item| match item {
+                letterboxd::AbstractSearchItem::FilmSearchItem { film, .. } => Some(film),
+                _ => None,
+            })
+            .find(|film| {
+                film.links.iter().any(|link| {
+                    link.type_ == letterboxd::LinkType::Letterboxd
+                        && link.url.trim_end_matches('/') == url
+                })
+            });
+        return Ok(film.map(|film| film.id));
+    }
+    let id = pinned.rsplit('/').next().unwrap_or(pinned);
+    Ok(Some(id.to_string()))
+}
+
+/// Look the film up by the IMDb or TMDb id of the movie, if any.
+///
+/// Returns `None` if the movie has no external id or Letterboxd does not know it.
+async fn find_by_external_id(
+    client: &letterboxd::Client,
+    movie: &MovieGuess,
+) -> letterboxd::Result<Option<letterboxd::Film>> {
+    let ids = movie
+        .imdb_id
+        .iter()
+        .map(|id| format!("imdb:{}", id))
+        .chain(movie.tmdb_id.iter().map(|id| format!("tmdb:{}", id)));
+    for id in ids {
+        match with_retry(|| client.film(&id)).await {
+            Ok(film) => return Ok(Some(film)),
+            Err(letterboxd::Error::Server { code: 404, .. }) => {
+                debug!("No film with id {} for {}", id, movie.title)
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(None)
+}
+
+/// Confidence, between 0 and 1, that the film is the movie.
+///
+/// The similarity of the titles weighs 80%, taking the closest of the names of the film. The
+/// release year weighs 20%, where a year off by one counts half and so does an unknown year.
+pub fn match_confidence(movie: &MovieGuess, film: &letterboxd::FilmSummary) -> f64 {
+    let names = iter::once(&film.name)
+        .chain(&film.original_name)
+        .chain(film.alternative_names.iter().flatten());
+    title_confidence(movie, names, film.release_year)
+}
+
+/// Confidence, between 0 and 1, that a film with the given names and release year is the movie,
+/// see [`match_confidence`].
+fn title_confidence<'a>(
+    movie: &MovieGuess,
+    names: impl IntoIterator<Item = &'a String>,
+    release_year: Option<u16>,
+) -> f64 {
+    let title = normalize_title(&movie.title);
+    let similarity = names
+        .into_iter()
+        .map(|name| strsim::normalized_levenshtein(&title, &normalize_title(name)))
+        .fold(0.0, f64::max);
+    let year = match (movie.year, release_year) {
+        (Some(year), Some(film_year)) if year == film_year => 1.0,
+        (Some(year), Some(film_year)) if (i32::from(year) - i32::from(film_year)).abs() == 1 => 0.5,
+        (Some(_), Some(_)) => 0.0,
+        _ => 0.5,
+    };
+    0.8 * similarity + 0.2 * year
+}
+
+/// Lowercase the title and replace punctuation, e.g. dots between words, with single spaces.
+/// Diacritics are dropped, see [`fold_diacritics`].
+fn normalize_title(title: &str) -> String {
+    let title: String = fold_diacritics(title)
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .flat_map(char::to_lowercase)
+        .collect();
+    title.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Replace the Latin letters with diacritics by the letters without, like `Amélie` by `Amelie`,
+/// and ligatures like `æ` and `ß` by their letters. Combining diacritics of other letters are
+/// dropped.
+pub fn fold_diacritics(title: &str) -> String {
+    let mut folded = String::with_capacity(title.len());
+    for c in title.nfc() {
+        let replacement = match c {
+            'À'..='Å' | 'Ā' | 'Ă' | 'Ą' => "A",
+            'à'..='å' | 'ā' | 'ă' | 'ą' => "a",
+            'Æ' => "AE",
+            'æ' => "ae",
+            'Ç' | 'Ć' | 'Ĉ' | 'Ċ' | 'Č' => "C",
+            'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+            'Ď' | 'Đ' | 'Ð' => "D",
+            'ď' | 'đ' | 'ð' => "d",
+            'È'..='Ë' | 'Ē' | 'Ĕ' | 'Ė' | 'Ę' | 'Ě' => "E",
+            'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+            'Ĝ' | 'Ğ' | 'Ġ' | 'Ģ' => "G",
+            'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+            'Ì'..='Ï' | 'Ĩ' | 'Ī' | 'Į' | 'İ' => "I",
+            'ì'..='ï' | 'ĩ' | 'ī' | 'į' | 'ı' => "i",
+            'Ķ' => "K",
+            'ķ' => "k",
+            'Ĺ' | 'Ļ' | 'Ľ' | 'Ł' => "L",
+            'ĺ' | 'ļ' | 'ľ' | 'ł' => "l",
+            'Ñ' | 'Ń' | 'Ņ' | 'Ň' => "N",
+            'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+            'Ò'..='Ö' | 'Ø' | 'Ō' | 'Ő' => "O",
+            'ò'..='ö' | 'ø' | 'ō' | 'ő' => "o",
+            'Œ' => "OE",
+            'œ' => "oe",
+            'Ŕ' | 'Ř' => "R",
+            'ŕ' | 'ř' => "r",
+            'Ś' | 'Ş' | 'Š' => "S",
+            'ś' | 'ş' | 'š' => "s",
+            'ß' => "ss",
+            'Ţ' | 'Ť' => "T",
+            'ţ' | 'ť' => "t",
+            'Ù'..='Ü' | 'Ũ' | 'Ū' | 'Ů' | 'Ű' | 'Ų' => "U",
+            'ù'..='ü' | 'ũ' | 'ū' | 'ů' | 'ű' | 'ų' => "u",
+            'Ý' | 'Ÿ' => "Y",
+            'ý' | 'ÿ' => "y",
+            'Ź' | 'Ż' | 'Ž' => "Z",
+            'ź' | 'ż' | 'ž' => "z",
+            'Þ' => "Th",
+            'þ' => "th",
+            c => {
+                folded.extend(iter::once(c).nfd().filter(|c| !is_combining_mark(*c)));
+                continue;
+            }
+        };
+        folded.push_str(replacement);
+    }
+    folded
+}
+
+/// The title with the usual substitutes of letters and words in file names replaced, like `and`
+/// for `&` and `ae` for `ä` in German titles, if it has any.
+fn ascii_substitutions(title: &str) -> Vec<String> {
+    static UMLAUT: OnceLock<Regex> = OnceLock::new();
+    let umlaut = UMLAUT.get_or_init(|| {
+        // Not after a `q`, like in `Quest`. Other words like `Blue` make for variants finding no
+        // film, which are searched last.
+        Regex::new(r"(^|[^qQ])([aouAOU])e").expect("valid umlaut pattern")
+    });
+
+    let words: Vec<&str> = title.split_whitespace().collect();
+    let mut substitutions = Vec::new();
+    for (word, substitute) in &[("&", "and"), ("and", "&")] {
+        if words.iter().any(|known| known.eq_ignore_ascii_case(word)) {
+            let replaced: Vec<&str> = words
+                .iter()
+                .map(|known| {
+                    if known.eq_ignore_ascii_case(word) {
+                        *substitute
+                    } else {
+                        known
+                    }
+                })
+                .collect();
+            substitutions.push(replaced.join(" "));
+        }
+    }
+    let umlauts = umlaut.replace_all(title, |captures: &regex::Captures<'_>| {
+        let letter = match &captures[2] {
+            "a" => "ä",
+            "o" => "ö",
+            "u" => "ü",
+            "A" => "Ä",
+            "O" => "Ö",
+            _ => "Ü",
+        };
+        format!("{}{}", &captures[1], letter)
+    });
+    if umlauts != title {
+        substitutions.push(umlauts.into_owned());
+    }
+    substitutions
+}
+
+/// Leading articles of titles in English, German, French, Spanish and Italian.
+const ARTICLES: &[&str] = &[
+    "the", "a", "an", "der", "die", "das", "ein", "eine", "le", "la", "les", "un", "une", "el",
+    "los", "las", "il", "lo", "gli",
+];
+
+/// Other titles to search for a movie whose title finds no film, most similar first: the title
+/// without diacritics, without a leading article or with a trailing one moved to the front, like
+/// `Matrix, The`, without a trailing year, the parts around a dash, like of
+/// `Original Title - Local Title`, and with substitutes like `&` for `and` replaced, see
+/// [`ascii_substitutions`].
+pub fn title_variants(title: &str) -> Vec<String> {
+    static TRAILING_YEAR: OnceLock<Regex> = OnceLock::new();
+    let trailing_year = TRAILING_YEAR.get_or_init(|| {
+        Regex::new(r"^(.+?)[\s.(\[]+(?:19|20)\d{2}[)\]]?$").expect("valid year pattern")
+    });
+
+    let title = title.trim();
+    let mut variants = vec![fold_diacritics(title)];
+    if let Some((first, rest)) = title.split_once(char::is_whitespace) {
+        if ARTICLES.contains(&first.to_lowercase().as_str()) {
+            variants.push(rest.trim().to_string());
+        }
+    }
+    if let Some((rest, article)) = title.rsplit_once(", ") {
+        if ARTICLES.contains(&article.to_lowercase().as_str()) {
+            variants.push(format!("{} {}", article, rest));
+        }
+    }
+    if let Some(captures) = trailing_year.captures(title) {
+        variants.push(captures[1].to_string());
+    }
+    for dash in &[" - ", " – ", " — ", ": "] {
+        if let Some((before, after)) = title.split_once(dash) {
+            variants.push(after.trim().to_string());
+            variants.push(before.trim().to_string());
+        }
+    }
+    variants.extend(ascii_substitutions(title));
+    let mut seen = vec![title.to_string()];
+    variants.retain(|variant| {
+        let is_new = !variant.is_empty() && !seen.contains(variant);
+        seen.push(variant.clone());
+        is_new
+    });
+    variants
+}
+
+/// How to resolve movie names to films.
+#[derive(Debug, Clone, Default)]
+pub struct ResolveOptions {
+    /// Report movies with ambiguous search results instead of resolving them.
+    pub strict: bool,
+    /// Accept the best of several search results only with at least this confidence, see
+    /// [`match_confidence`]. Otherwise, the first search result is accepted.
+    pub min_confidence: Option<f64>,
+    /// API key of TMDb to search movies Letterboxd finds no film for on TMDb, see [`crate::tmdb`].
+    pub tmdb_api_key: Option<String>,
+    /// Search the titles without diacritics first, see [`fold_diacritics`], and the titles as
+    /// guessed only if these find no film.
+    pub fold_diacritics: bool,
+    /// Search no more movies once this many requests were sent to Letterboxd in the run, see
+    /// [`api_calls`]. Movies which are being searched may still send more.
+    pub max_api_calls: Option<u64>,
+}
+
+/// Best search result for a movie with too low confidence.
+#[derive(Debug, Clone)]
+pub struct LowConfidenceMatch {
+    pub movie: String,
+    /// Title and release year of the film.
+    pub film: String,
+    pub confidence: f64,
+}
+
+/// How the film of a movie was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchSource {
+    /// Taken from the cache of a previous sync.
+    Cache,
+    /// Pinned by an override.
+    Override,
+    /// Found by an IMDb or TMDb id in the file name or NFO file.
+    ExternalId,
+    /// Found by searching Letterboxd for the title.
+    Search,
+    /// Found by searching TMDb for the title, see `--tmdb-fallback`.
+    Tmdb,
+    /// Picked among the search results by the user, see `--interactive`.
+    Picked,
+}
+
+/// Film ids resolved from movie names.
+#[derive(Debug, Default)]
+pub struct ResolvedFilms {
+    /// Film ids by movie name.
+    pub ids: HashMap<String, String>,
+    /// How the films were found by movie name.
+    pub sources: HashMap<String, MatchSource>,
+    /// Confidence of the matches by movie name of the films which were not resolved from the
+    /// cache, see [`match_confidence`].
+    pub confidences: HashMap<String, f64>,
+    /// Movie names which were not resolved due to ambiguous matches, with the reason.
+    pub ambiguous: Vec<(String, String)>,
+    /// Movies which were not resolved because the best match has too low confidence.
+    pub low_confidence: Vec<LowConfidenceMatch>,
+    /// Movie names no film was found for.
+    pub not_found: Vec<String>,
+    /// Movie names whose resolution failed, e.g. due to a failed request, with the error.
+    pub errors: Vec<(String, String)>,
+    /// Movie names which were not in the cache when resolving without a client.
+    pub uncached: Vec<String>,
+    /// Movie names which were not in the cache and not searched, as the budget of requests was
+    /// spent, see [`ResolveOptions::max_api_calls`].
+    pub skipped: Vec<String>,
+    /// Search results by movie name of the ambiguous and low confidence matches.
+    pub candidates: HashMap<String, Vec<letterboxd::FilmSummary>>,
+    /// Number of movies resolved from the cache.
+    pub cache_hits: usize,
+    /// Files by movie name.
+    pub files: HashMap<String, Vec<MovieFile>>,
+    /// Release years guessed by movie name.
+    pub years: HashMap<String, u16>,
+    /// IMDb ids found in NFO files or file names by movie name.
+    pub imdb_ids: HashMap<String, String>,
+    /// Files no movie name was found for.
+    pub unguessed: Vec<MovieFile>,
+}
+
+impl ResolvedFilms {
+    /// Files by resolved film id.
+    pub fn files_by_film_id(&self) -> HashMap<&str, Vec<&MovieFile>> {
+        let mut files: HashMap<&str, Vec<&MovieFile>> = HashMap::new();
+        for (movie, id) in &self.ids {
+            if let Some(movie_files) = self.files.get(movie) {
+                files.entry(id).or_default().extend(movie_files);
+            }
+        }
+        files
+    }
+
+    /// Number of files whose movie was not resolved, including the files no movie name was found
+    /// for.
+    pub fn unresolved_files(&self) -> usize {
+        let unresolved: usize = self
+            .unresolved()
+            .iter()
+            .filter_map(|(movie, _)| self.files.get(movie))
+            .map(Vec::len)
+            .sum();
+        unresolved + self.unguessed.len()
+    }
+
+    /// Resolve a movie to the film picked by the user. Like a film pinned by an override, it is
+    /// matched with full confidence, so that it wins over found films when caches are merged.
+    pub fn pick(&mut self, movie: &str, film_id: &str) {
+        self.ids.insert(movie.to_string(), film_id.to_string());
+        self.sources.insert(movie.to_string(), MatchSource::Picked);
+        self.confidences.insert(movie.to_string(), 1.0);
+        self.ambiguous.retain(|(ambiguous, _)| ambiguous != movie);
+        self.low_confidence.retain(|low| low.movie != movie);
+    }
+
+    /// Names of the movies which were not resolved, with the reason.
+    pub fn unresolved(&self) -> Vec<(String, String)> {
+        let not_found = self
+            .not_found
+            .iter()
+            .map(|movie| (movie.clone(), "not found on Letterboxd".to_string()));
+        let low_confidence = self.low_confidence.iter().map(|low| {
+            let reason = format!(
+                "best match {} has a confidence of {:.2}",
+                low.film, low.confidence
+            );
+            (low.movie.clone(), reason)
+        });
+        let errors = self
+            .errors
+            .iter()
+            .map(|(movie, err)| (movie.clone(), format!("request failed: {}", err)));
+        let uncached = self
+            .uncached
+            .iter()
+            .map(|movie| (movie.clone(), "not in the cache".to_string()));
+        let mut unresolved: Vec<(String, String)> = self
+            .ambiguous
+            .iter()
+            .cloned()
+            .chain(low_confidence)
+            .chain(not_found)
+            .chain(errors)
+            .chain(uncached)
+            .collect();
+        unresolved.sort();
+        unresolved
+    }
+}
+
+/// Outcome of resolving a single movie.
+enum Resolution {
+    Cached(String, String),
+    Found(String, String, f64, MatchSource),
+    Ambiguous(String, String, Vec<letterboxd::FilmSummary>),
+    LowConfidence(LowConfidenceMatch, Vec<letterboxd::FilmSummary>),
+    NotFound(String),
+    Uncached(String),
+    Skipped(String),
+}
+
+/// Search Letterboxd for the films with the title, returning at most `per_page`.
+async fn search_films(
+    client: &letterboxd::Client,
+    title: &str,
+    per_page: usize,
+) -> letterboxd::Result<Vec<letterboxd::FilmSummary>> {
+    let response = search_movie(client, title.to_string(), per_page).await?;
+    Ok(response
+        .items
+        .into_iter()
+        .filter_map(|item| match item {
+            letterboxd::AbstractSearchItem::FilmSearchItem { film, .. } => Some(film),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Search Letterboxd for the films of the other titles of the movie, see [`title_variants`], and
+/// of the title as guessed unless it is the `searched` one.
+///
+/// The films found are ranked by their confidence, see [`match_confidence`], with the title they
+/// were found by.
+async fn search_title_variants(
+    client: &letterboxd::Client,
+    movie: &MovieGuess,
+    searched: &str,
+    per_page: usize,
+) -> letterboxd::Result<Vec<letterboxd::FilmSummary>> {
+    let mut scored: Vec<(f64, letterboxd::FilmSummary)> = Vec::new();
+    let titles = iter::once(movie.title.clone())
+        .chain(title_variants(&movie.title))
+        .filter(|title| title != searched);
+    for title in titles {
+        let films = search_films(client, &title, per_page).await?;
+        if !films.is_empty() {
+            debug!(
+                "Found {} films for {} as {}",
+                films.len(),
+                movie.title,
+                title
+            );
+        }
+        let variant = MovieGuess {
+            title,
+            ..movie.clone()
+        };
+        for film in films {
+            let confidence = match_confidence(&variant, &film);
+            match scored.iter_mut().find(|(_, known)| known.id == film.id) {
+                Some(known) => known.0 = known.0.max(confidence),
+                None => scored.push((confidence, film)),
+            }
+        }
+    }
+    // Equally scored films keep the order they were found in.
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    Ok(scored.into_iter().map(|(_, film)| film).collect())
+}
+
+/// Resolve the film id of a single movie, see [`resolve_film_ids`].
+async fn resolve_movie(
+    movie: MovieGuess,
+    film_ids_cache: &MovieCache,
+    client: Option<&letterboxd::Client>,
+    options: &ResolveOptions,
+) -> letterboxd::Result<Resolution> {
+    let client = match client {
+        Some(client) => client,
+        None => {
+            return Ok(match film_ids_cache.film_id(&movie.title) {
+                Some(id) => Resolution::Cached(movie.title, id.to_string()),
+                None => Resolution::Uncached(movie.title),
+            })
+        }
+    };
+    if options.max_api_calls.is_some_and(|max| api_calls() >= max) {
+        return Ok(match film_ids_cache.film_id(&movie.title) {
+            Some(id) => Resolution::Cached(movie.title, id.to_string()),
+            None => Resolution::Skipped(movie.title),
+        });
+    }
+    if let Some(pinned) = &movie.pinned {
+        match find_pinned(client, pinned).await? {
+            Some(id) => {
+                debug!("Resolved id of {} by override: {}", movie.title, id);
+                return Ok(Resolution::Found(
+                    movie.title,
+                    id,
+                    1.0,
+                    MatchSource::Override,
+                ));
+            }
+            None => warn!("{}", Message::PinnedFilmNotFound(pinned)),
+        }
+    }
+    if let Some(id) = film_ids_cache.film_id(&movie.title) {
+        return Ok(Resolution::Cached(movie.title, id.to_string()));
+    }
+    if let Some(film) = find_by_external_id(client, &movie).await? {
+        debug!("Resolved id of {} by external id: {}", movie.title, film.id);
+        return Ok(Resolution::Found(
+            movie.title,
+            film.id,
+            1.0,
+            MatchSource::ExternalId,
+        ));
+    }
+    // Several results are needed to prefer the ones of the year of the movie.
+    let per_page = if options.strict || options.min_confidence.is_some() || movie.year.is_some() {
+        STRICT_SEARCH_RESULTS
+    } else {
+        1
+    };
+    let searched = if options.fold_diacritics {
+        fold_diacritics(&movie.title)
+    } else {
+        movie.title.clone()
+    };
+    let mut films = search_films(client, &searched, per_page).await?;
+    if films.is_empty() {
+        films = search_title_variants(client, &movie, &searched, per_page).await?;
+    }
+    rank_by_year(&movie, &mut films);
+    if options.strict {
+        if let Some(reason) = find_ambiguity(&movie, &films) {
+            return Ok(Resolution::Ambiguous(movie.title, reason, films));
+        }
+    }
+    let best = match options.min_confidence {
+        Some(min_confidence) => {
+            // The earliest of equally scored films wins, as `max_by` returns the last.
+            let scored = films
+                .iter()
+                .rev()
+                .map(|film| (match_confidence(&movie, film), film))
+                .max_by(|(a, _), (b, _)| a.total_cmp(b));
+            match scored {
+                Some((confidence, film)) if confidence < min_confidence => {
+                    let film = film_title(film);
+                    warn!(
+                        "{}",
+                        Message::LowConfidence {
+                            movie: &movie.title,
+                            film: &film,
+                            confidence,
+                        }
+                    );
+                    let low = LowConfidenceMatch {
+                        movie: movie.title,
+                        film,
+                        confidence,
+                    };
+                    return Ok(Resolution::LowConfidence(low, films));
+                }
+                scored => scored.map(|(_, film)| film),
+            }
+        }
+        None => films.first(),
+    };
+    match best {
+        Some(film) => {
+            debug!("Resolved id of {}: {}", movie.title, film.id);
+            if let (Some(year), Some(film_year)) = (movie.year, film.release_year) {
+                if year != film_year {
+                    warn!(
+                        "{}",
+                        Message::YearMismatch {
+                            movie: &movie.title,
+                            year,
+                            film: &film_title(film),
+                        }
+                    );
+                }
+            }
+            let confidence = match_confidence(&movie, film);
+            Ok(Resolution::Found(
+                movie.title,
+                film.id.clone(),
+                confidence,
+                MatchSource::Search,
+            ))
+        }
+        None => {
+            if let Some(api_key) = &options.tmdb_api_key {
+                if let Some(resolution) = resolve_by_tmdb(client, api_key, &movie, options).await? {
+                    return Ok(resolution);
+                }
+            }
+            warn!("{}", Message::MovieNotFound(&movie.title));
+            Ok(Resolution::NotFound(movie.title))
+        }
+    }
+}
+
+/// Search TMDb for a movie Letterboxd found no film for and look the film up by the TMDb id of
+/// the best match.
+///
+/// Returns `None` if TMDb does not know the movie, Letterboxd does not know the TMDb id or the
+/// search fails, which is only logged.
+async fn resolve_by_tmdb(
+    client: &letterboxd::Client,
+    api_key: &str,
+    movie: &MovieGuess,
+    options: &ResolveOptions,
+) -> letterboxd::Result<Option<Resolution>> {
+    let found = match search_tmdb(api_key, movie).await {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            debug!("No movie found on TMDb for {}", movie.title);
+            return Ok(None);
+        }
+        Err(err) => {
+            warn!("failed to search TMDb for {}: {:#}", movie.title, err);
+            return Ok(None);
+        }
+    };
+    let found_title = match found.year() {
+        Some(year) => format!("{} ({})", found.title, year),
+        None => found.title.clone(),
+    };
+    let id = format!("tmdb:{}", found.id);
+    let film = match with_retry(|| client.film(&id)).await {
+        Ok(film) => film,
+        Err(letterboxd::Error::Server { code: 404, .. }) => {
+            debug!("No film with id {} for {}", id, movie.title);
+            return Ok(None);
+        }
+        Err(err) => return Err(err),
+    };
+    let names = iter::once(&found.title).chain(&found.original_title);
+    let confidence = title_confidence(movie, names, found.year());
+    if options
+        .min_confidence
+        .is_some_and(|min_confidence| confidence < min_confidence)
+    {
+        warn!(
+            "{}",
+            Message::LowConfidence {
+                movie: &movie.title,
+                film: &found_title,
+                confidence,
+            }
+        );
+        let low = LowConfidenceMatch {
+            movie: movie.title.clone(),
+            film: found_title,
+            confidence,
+        };
+        return Ok(Some(Resolution::LowConfidence(low, Vec::new())));
+    }
+    info!(
+        "{}",
+        Message::FoundOnTmdb {
+            movie: &movie.title,
+            film: &found_title,
+        }
+    );
+    Ok(Some(Resolution::Found(
+        movie.title.clone(),
+        film.id,
+        confidence,
+        MatchSource::Tmdb,
+    )))
+}
+
+/// Resolve movie ids from movie names by first looking at the film pinned by an override, then in
+/// the given cache, and then, if not found, by making a request through letterboxd api.
+///
+/// Without a client, movies are only resolved from the cache, ignoring overrides, and the others
+/// are reported in [`ResolvedFilms::uncached`]. Once the budget of requests of `options` is spent,
+/// the same goes for the remaining movies, which are reported in [`ResolvedFilms::skipped`].
+///
+/// If `flush` is given, the cache together with the ids resolved so far is saved periodically.
+/// Movies with ambiguous or too uncertain search results, depending on `options`, are not resolved
+/// but reported instead. A failed request only fails the resolution of its movie, which is
+/// reported in [`ResolvedFilms::errors`].
+pub async fn resolve_film_ids(
+    movies: impl IntoIterator<Item = MovieGuess>,
+    film_ids_cache: &MovieCache,
+    client: Option<&letterboxd::Client>,
+    flush: Option<&CacheFlush<'_>>,
+    options: &ResolveOptions,
+) -> ResolvedFilms {
+    let movies: Vec<MovieGuess> = movies.into_iter().collect();
+    let progress_message =
+        |cached, looked_up| Message::ResolvingMovies { cached, looked_up }.to_string();
+    let bar = progress::bar(movies.len() as u64, progress_message(0, 0));
+    let film_id_requests = movies.into_iter().map(|movie| async move {
+        let title = movie.title.clone();
+        resolve_movie(movie, film_ids_cache, client, options)
+            .await
+            .map_err(|err| (title, err))
+    });
+
+    let mut results = stream::iter(film_id_requests).buffer_unordered(max_concurrency());
+    let mut resolved = ResolvedFilms::default();
+    let mut unflushed = 0;
+    let mut flushed_not_found = 0;
+    let mut last_flush = Instant::now();
+    let mut done = 0;
+    while let Some(res) = results.next().await {
+        match res {
+            Ok(Resolution::Cached(movie, id)) => {
+                resolved.sources.insert(movie.clone(), MatchSource::Cache);
+                resolved.ids.insert(movie, id);
+                resolved.cache_hits += 1;
+            }
+            Ok(Resolution::Found(movie, id, confidence, source)) => {
+                resolved.sources.insert(movie.clone(), source);
+                resolved.confidences.insert(movie.clone(), confidence);
+                resolved.ids.insert(movie, id);
+                unflushed += 1;
+            }
+            Ok(Resolution::Ambiguous(movie, reason, films)) => {
+                resolved.candidates.insert(movie.clone(), films);
+                resolved.ambiguous.push((movie, reason));
+            }
+            Ok(Resolution::LowConfidence(low, films)) => {
+                resolved.candidates.insert(low.movie.clone(), films);
+                resolved.low_confidence.push(low);
+            }
+            Ok(Resolution::NotFound(movie)) => {
+                resolved.not_found.push(movie);
+                unflushed += 1;
+            }
+            Ok(Resolution::Uncached(movie)) => {
+                resolved.uncached.push(movie);
+            }
+            Ok(Resolution::Skipped(movie)) => {
+                resolved.skipped.push(movie);
+            }
+            Err((movie, err)) => {
+                debug!("Failed to resolve id of {}: {}", movie, err);
+                resolved.errors.push((movie, err.to_string()));
+            }
+        }
+        done += 1;
+        bar.inc(1);
+        bar.set_message(progress_message(
+            resolved.cache_hits,
+            done - resolved.cache_hits,
+        ));
+
+        if let Some(flush) = flush.filter(|flush| flush.is_due(unflushed, last_flush)) {
+            let mut cache = film_ids_cache.clone();
+            cache.update(&resolved);
+            match flush.flush(&cache, &resolved.not_found[flushed_not_found..]) {
+                Ok(()) => {
+                    debug!("Flushed {} newly resolved movies to cache.", unflushed);
+                    flushed_not_found = resolved.not_found.len();
+                }
+                Err(err) => warn!("failed to flush film ids to cache: {:#}", err),
+            }
+            unflushed = 0;
+            last_flush = Instant::now();
+        }
+    }
+    bar.finish_and_clear();
+    resolved.errors.sort();
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn confidence(
+        title: &str,
+        year: Option<u16>,
+        names: &[&str],
+        release_year: Option<u16>,
+    ) -> f64 {
+        let movie = MovieGuess::new(title.to_string(), year);
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        title_confidence(&movie, &names, release_year)
+    }
+
+    #[test]
+    fn same_title_and_year_match_fully() {
+        assert_close(confidence("Heat", Some(1995), &["Heat"], Some(1995)), 1.0);
+    }
+
+    #[test]
+    fn case_and_punctuation_of_titles_are_ignored() {
+        let confidence = confidence(
+            "the.dark.knight",
+            Some(2008),
+            &["The Dark Knight"],
+            Some(2008),
+        );
+        assert_close(confidence, 1.0);
+    }
+
+    #[test]
+    fn closest_name_of_the_film_counts() {
+        let names = ["Stirb langsam", "Die Hard"];
+        assert_close(confidence("Die Hard", Some(1988), &names, Some(1988)), 1.0);
+    }
+
+    #[test]
+    fn year_off_by_one_or_unknown_counts_half() {
+        assert_close(confidence("Heat", Some(1995), &["Heat"], Some(1996)), 0.9);
+        assert_close(confidence("Heat", None, &["Heat"], Some(1995)), 0.9);
+        assert_close(confidence("Heat", Some(1995), &["Heat"], None), 0.9);
+        assert_close(confidence("Heat", Some(1995), &["Heat"], Some(2003)), 0.8);
+    }
+
+    #[test]
+    fn different_titles_match_less() {
+        // Two edits of five letters.
+        assert_close(confidence("Heat", Some(1995), &["Heist"], Some(1995)), 0.68);
+    }
+
+    /// Guesses the file stem as movie name.
+    struct FileStem;
+
+    impl MetadataSource for FileStem {
+        fn guess(&self, file: &MovieFile) -> Option<MovieGuess> {
+            let stem = file.path.file_stem()?.to_str()?;
+            Some(MovieGuess::new(stem.to_string(), None))
+        }
+    }
+
+    fn movie_files(paths: &[&str]) -> Vec<MovieFile> {
+        paths
+            .iter()
+            .map(|path| MovieFile {
+                path: PathBuf::from(path),
+                size: None,
+                modified: None,
+                fingerprint: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn part_markers_are_stripped() {
+        assert_eq!(strip_part_marker("Heat CD1"), Some("Heat"));
+        assert_eq!(strip_part_marker("Heat.part2"), Some("Heat"));
+        assert_eq!(strip_part_marker("Heat - Disc B"), Some("Heat"));
+        assert_eq!(strip_part_marker("Heat (1995) [cd2]"), Some("Heat (1995)"));
+        assert_eq!(strip_part_marker("Apollo 13"), None);
+        assert_eq!(strip_part_marker("Heat"), None);
+    }
+
+    #[test]
+    fn parts_in_a_folder_are_one_movie() {
+        let files = movie_files(&[
+            "/movies/Heat/Heat CD1.mkv",
+            "/movies/Heat/Heat CD2.mkv",
+            "/movies/Alien/Alien.mkv",
+        ]);
+        let (movies, files) = guess_movies(files, &FileStem);
+        let titles: Vec<&str> = movies.iter().map(|movie| movie.title.as_str()).collect();
+        assert_eq!(titles, ["Heat", "Alien"]);
+        assert_eq!(files["Heat"].len(), 2);
+    }
+
+    #[test]
+    fn single_file_with_part_marker_keeps_its_name() {
+        let files = movie_files(&["/movies/Kill Bill Part 2.mkv", "/other/Heat CD1.mkv"]);
+        let (movies, _) = guess_movies(files, &FileStem);
+        let titles: Vec<&str> = movies.iter().map(|movie| movie.title.as_str()).collect();
+        assert_eq!(titles, ["Kill Bill Part 2", "Heat CD1"]);
+    }
+
+    #[test]
+    fn editions_of_a_movie_are_one_movie() {
+        let files = movie_files(&["/movies/Heat.mkv", "/movies/4k/Heat.mkv"]);
+        let (movies, files) = guess_movies(files, &FileStem);
+        assert_eq!(movies.len(), 1);
+        assert_eq!(files["Heat"].len(), 2);
+    }
+
+    #[test]
+    fn merged_guess_takes_missing_year_and_ids() {
+        let mut movie = MovieGuess::new("Heat".to_string(), None);
+        let mut other = MovieGuess::new("Heat".to_string(), Some(1995));
+        other.imdb_id = Some("tt0113277".to_string());
+        merge_guess(&mut movie, other);
+        assert_eq!(movie.year, Some(1995));
+        assert_eq!(movie.imdb_id.as_deref(), Some("tt0113277"));
+    }
+
+    #[test]
+    fn title_variants_move_articles() {
+        assert_eq!(title_variants("The Matrix"), ["Matrix"]);
+        assert_eq!(title_variants("Matrix, The"), ["The Matrix"]);
+    }
+
+    #[test]
+    fn title_variants_drop_diacritics_and_trailing_years() {
+        assert_eq!(title_variants("Amélie"), ["Amelie"]);
+        assert_eq!(title_variants("Heat (1995)"), ["Heat"]);
+        assert_eq!(title_variants("Heat 1995"), ["Heat"]);
+    }
+
+    #[test]
+    fn title_variants_split_titles_at_dashes() {
+        let variants = title_variants("Tiger & Dragon - Crouching Tiger, Hidden Dragon");
+        assert_eq!(variants[0], "Crouching Tiger, Hidden Dragon");
+        assert_eq!(variants[1], "Tiger & Dragon");
+    }
+
+    #[test]
+    fn plain_title_has_no_variants() {
+        assert!(title_variants("Heat").is_empty());
+    }
+}
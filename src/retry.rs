@@ -0,0 +1,126 @@
+//! Retrying Letterboxd API requests which failed due to rate limiting or server errors.
+
+use log::warn;
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::i18n::Message;
+use crate::metrics;
+use crate::throttle::throttle;
+
+static POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+static API_CALLS: AtomicU64 = AtomicU64::new(0);
+
+/// How often and how long to wait before retrying failed requests.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts of a request including the first one, 1 to never retry.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every further one.
+    pub base_delay: Duration,
+    /// Delay before the first retry of a rate limited request.
+    pub rate_limit_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            rate_limit_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the retry after the given failed attempt, starting at 1.
+    ///
+    /// The delay grows exponentially and is randomized between half and all of it, so that
+    /// concurrent requests failing together do not retry together.
+    fn delay(&self, attempt: u32, rate_limited: bool) -> Duration {
+        let base = if rate_limited {
+            self.rate_limit_delay
+        } else {
+            self.base_delay
+        };
+        let delay = base
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay);
+        let jitter = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        delay.mul_f64(0.5 + jitter / 2.0)
+    }
+}
+
+/// Set the retry policy of all requests. Only the first call has an effect.
+pub fn set_retry_policy(policy: RetryPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+fn policy() -> RetryPolicy {
+    POLICY.get().copied().unwrap_or_default()
+}
+
+/// Number of requests sent to Letterboxd in the run, including every retry.
+pub fn api_calls() -> u64 {
+    API_CALLS.load(Ordering::Relaxed)
+}
+
+/// Start counting the requests of a new run, like a sync of a long running `--watch`.
+pub fn reset_api_calls() {
+    API_CALLS.store(0, Ordering::Relaxed);
+}
+
+/// Whether the request may succeed when sent again: if rate limited or if Letterboxd failed.
+fn is_transient(err: &letterboxd::Error) -> bool {
+    matches!(err, letterboxd::Error::Server { code, .. } if *code == 429 || *code >= 500)
+}
+
+/// Send a request, retrying it with exponential backoff on transient errors, see [`RetryPolicy`].
+///
+/// Requests wait for the [`throttle`] before they are sent.
+///
+/// The client does not expose the `Retry-After` header of rate limited responses, so they are
+/// retried after a longer delay instead.
+pub async fn with_retry<T, F, Fut>(mut request: F) -> letterboxd::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = letterboxd::Result<T>>,
+{
+    let policy = policy();
+    let mut attempt = 1;
+    loop {
+        let permit = throttle().acquire().await;
+        let started = Instant::now();
+        let res = request().await;
+        let congested = res.as_ref().err().is_some_and(is_transient);
+        throttle().release(permit, congested, started.elapsed());
+        metrics::record_request(res.is_err());
+        API_CALLS.fetch_add(1, Ordering::Relaxed);
+        match res {
+            Err(err) if attempt < policy.max_attempts && is_transient(&err) => {
+                let rate_limited = matches!(err, letterboxd::Error::Server { code: 429, .. });
+                let delay = policy.delay(attempt, rate_limited);
+                warn!(
+                    "{}",
+                    Message::RetryingRequest {
+                        error: &err.to_string(),
+                        delay,
+                        attempt,
+                        max_attempts: policy.max_attempts,
+                    }
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
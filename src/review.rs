@@ -0,0 +1,163 @@
+//! Page linking the movies of the scanned files to their films on Letterboxd, to review the matches
+//! before updating a list, written by `sync --review-page`.
+//!
+//! The page is written as Markdown if its file name ends with `.md`, and as HTML otherwise. New
+//! matches come first, followed by the ones taken from the cache.
+
+use anyhow::Context as _;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::films::FilmMetadata;
+use crate::resolve::{MatchSource, ResolvedFilms};
+
+/// A resolved movie on the review page.
+struct ReviewRow {
+    movie: String,
+    files: Vec<String>,
+    film: String,
+    url: String,
+    source: Option<MatchSource>,
+    confidence: Option<f64>,
+}
+
+impl ReviewRow {
+    fn source(&self) -> &'static str {
+        match self.source {
+            Some(MatchSource::Cache) => "cache",
+            Some(MatchSource::Override) => "override",
+            Some(MatchSource::ExternalId) => "external id",
+            Some(MatchSource::Search) => "search",
+            Some(MatchSource::Tmdb) => "TMDb",
+            Some(MatchSource::Picked) => "picked",
+            None => "",
+        }
+    }
+
+    fn confidence(&self) -> String {
+        self.confidence
+            .map(|confidence| format!("{:.2}", confidence))
+            .unwrap_or_default()
+    }
+}
+
+fn review_rows(
+    resolved: &[&ResolvedFilms],
+    films: &HashMap<String, FilmMetadata>,
+) -> Vec<ReviewRow> {
+    let mut rows: Vec<ReviewRow> = resolved
+        .iter()
+        .flat_map(|resolved| {
+            resolved.ids.iter().map(move |(movie, id)| ReviewRow {
+                movie: match resolved.years.get(movie) {
+                    Some(year) => format!("{} ({})", movie, year),
+                    None => movie.clone(),
+                },
+                files: resolved
+                    .files
+                    .get(movie)
+                    .into_iter()
+                    .flatten()
+                    .map(|file| file.path.display().to_string())
+                    .collect(),
+                film: films
+                    .get(id)
+                    .map_or_else(|| id.clone(), FilmMetadata::title),
+                url: format!("https://boxd.it/{}", id),
+                source: resolved.sources.get(movie).copied(),
+                confidence: resolved.confidences.get(movie).copied(),
+            })
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        let cached = |row: &ReviewRow| row.source == Some(MatchSource::Cache);
+        cached(a)
+            .cmp(&cached(b))
+            .then_with(|| a.movie.cmp(&b.movie))
+    });
+    rows
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+fn html_page(rows: &[ReviewRow]) -> String {
+    let mut page = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Matched films</title>\n</head>\n<body>\n<table>\n\
+         <tr><th>Movie</th><th>Files</th><th>Film</th><th>Source</th><th>Confidence</th></tr>\n",
+    );
+    for row in rows {
+        let files: Vec<String> = row.files.iter().map(|file| escape_html(file)).collect();
+        let _ = writeln!(
+            page,
+            "<tr><td>{}</td><td>{}</td><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td></tr>",
+            escape_html(&row.movie),
+            files.join("<br>"),
+            row.url,
+            escape_html(&row.film),
+            row.source(),
+            row.confidence(),
+        );
+    }
+    page.push_str("</table>\n</body>\n</html>\n");
+    page
+}
+
+fn markdown_page(rows: &[ReviewRow]) -> String {
+    let mut page = String::from(
+        "# Matched films\n\n| Movie | Files | Film | Source | Confidence |\n|---|---|---|---|---|\n",
+    );
+    for row in rows {
+        let files: Vec<String> = row
+            .files
+            .iter()
+            .map(|file| format!("`{}`", file.replace('|', "\\|")))
+            .collect();
+        let _ = writeln!(
+            page,
+            "| {} | {} | [{}]({}) | {} | {} |",
+            escape_markdown(&row.movie),
+            files.join("<br>"),
+            escape_markdown(&row.film),
+            row.url,
+            row.source(),
+            row.confidence(),
+        );
+    }
+    page
+}
+
+/// Write the review page of the resolved movies to `path`, returning the number of movies on it.
+/// `films` has the titles of the films.
+pub fn write_review_page(
+    resolved: &[&ResolvedFilms],
+    films: &HashMap<String, FilmMetadata>,
+    path: &Path,
+) -> anyhow::Result<usize> {
+    let rows = review_rows(resolved, films);
+    let markdown = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("md"));
+    let page = if markdown {
+        markdown_page(&rows)
+    } else {
+        html_page(&rows)
+    };
+    fs::write(path, page)
+        .with_context(|| format!("failed to write review page '{}'", path.display()))?;
+    Ok(rows.len())
+}
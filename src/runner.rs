@@ -0,0 +1,1031 @@
+//! Syncing mappings with their lists: [`sync_mapping`] scans and resolves the movies of a mapping
+//! with [`scan_and_resolve`] and updates its list and the further lists of the [`SyncOptions`],
+//! like the unwatched, top rated and archive lists.
+//!
+//! The mappings are synced once with [`sync_mappings`], or kept in sync by [`daemon`], [`watch`]
+//! and [`serve`]. Questions to the user, like whether to remove films, are asked by the callbacks
+//! of the options, so that the binary decides how to ask them.
+
+use anyhow::{anyhow, Context as _};
+use indicatif::HumanBytes;
+use log::{debug, error, info, warn};
+use tokio::sync::mpsc;
+
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use crate::backend::{
+    apply_service_plan, plan_service_sync, resolve_service_films, service_cache_path, ListService,
+};
+use crate::cache::{load_cache, save_cache};
+use crate::config::{Config, Mapping};
+use crate::duplicates::Duplicate;
+use crate::error::SyncIncomplete;
+use crate::export::{
+    file_mapping_rows, imdb_list_rows, write_file_mapping, write_imdb_list, FileMappingRow,
+};
+use crate::films::{
+    fetch_film_metadata, fetch_ratings, fetch_watched_films, log_watched, mark_watched, sort_films,
+    FilmMetadata, SortOrder,
+};
+use crate::i18n::Message;
+use crate::metrics::{self, serve_metrics};
+use crate::notes::{entry_notes, path_tags};
+use crate::notify::notify;
+use crate::pipeline::{
+    guess_source_movies, list_mapping_files, load_film_cache, metadata_chain, scan_and_resolve,
+    CacheOptions, ResolveSettings, ScanOptions,
+};
+use crate::ratings::{get_ratings_cache_filename, rate_films, read_ratings, RATINGS_FILENAME};
+use crate::rename::{apply_renames, suggest_renames, Rename};
+use crate::resolve::ResolvedFilms;
+use crate::retry;
+use crate::scan::{MovieFile, Source};
+use crate::snapshot::snapshot_dir;
+use crate::summary::MappingSummary;
+use crate::sync::{Ranking, RemovalPolicy, SyncExecutor, SyncPlan, Target};
+use crate::watch::DirectoryWatcher;
+use crate::webhook::{serve_webhooks, EventKind};
+
+/// Asks whether to apply the changes of a plan, like on the terminal.
+pub type Confirm = fn(&SyncPlan) -> anyhow::Result<bool>;
+
+/// How to sync a mapping with its lists.
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    pub scan: ScanOptions,
+    pub cache: CacheOptions,
+    pub resolve: ResolveSettings,
+    /// Only log the changes, without updating any list.
+    pub dry_run: bool,
+    /// Asks before each update of a list, whose changes were logged already.
+    pub confirm_update: Option<Confirm>,
+    /// Asks before removing films with [`RemovalPolicy::Prompt`]. Without it, the films are kept.
+    pub confirm_removals: Option<Confirm>,
+    /// Films added and removed with one update of a list.
+    pub chunk_size: usize,
+    /// Check that the updated lists show all changes.
+    pub verify: bool,
+    /// Update no list if more files than this could not be matched to films.
+    pub max_unmatched: Option<usize>,
+    /// CSV file to export the films to in the IMDb list import format.
+    pub export_imdb: Option<PathBuf>,
+    /// Only remove films from the lists, never add any.
+    pub remove_only: bool,
+    pub removals: RemovalPolicy,
+    /// New name of the list of the mapping.
+    pub list_name: Option<String>,
+    /// New description of the list of the mapping.
+    pub description: Option<String>,
+    /// Template for the notes of added entries, see [`entry_notes`].
+    pub notes_template: Option<String>,
+    /// Leave the films already watched out of the list.
+    pub exclude_watched: bool,
+    /// Folder whose films to mark as watched.
+    pub mark_watched: Option<PathBuf>,
+    /// Log the films marked as watched as diary entries with the tags of their folders.
+    pub log_tags: bool,
+    /// CSV file with the ratings to set, instead of the one in a local directory.
+    pub ratings: Option<PathBuf>,
+    /// List to sync with the films not yet watched.
+    pub unwatched_list: Option<String>,
+    /// List to add the films removed from the list of the mapping to.
+    pub archive_removals_to: Option<String>,
+    /// Order to rank the entries of the lists in.
+    pub sort: Option<SortOrder>,
+    /// List to sync with the films rated at least `min_rating` on average.
+    pub top_rated_list: Option<String>,
+    pub min_rating: f32,
+    /// Time without further changes or webhooks to wait for before syncing again, by [`watch`] and
+    /// [`serve`].
+    pub debounce: Duration,
+}
+
+/// Outcome of the sync of a mapping.
+pub struct SyncedMapping {
+    pub resolved: ResolvedFilms,
+    /// Whether the files are on the local file system, so that they can be renamed.
+    pub local: bool,
+    /// Plans of the updated lists.
+    pub plans: Vec<SyncPlan>,
+}
+
+/// Whether to keep the films of the plan which are not in the directory on the list.
+fn skip_removals(options: &SyncOptions, add_only: bool, plan: &SyncPlan) -> anyhow::Result<bool> {
+    let removals = if add_only {
+        RemovalPolicy::Never
+    } else {
+        options.removals
+    };
+    Ok(match removals {
+        RemovalPolicy::Never => true,
+        RemovalPolicy::Prompt => {
+            !plan.to_remove.is_empty()
+                && !options.dry_run
+                && !options
+                    .confirm_removals
+                    .map_or(Ok(false), |confirm| confirm(plan))?
+        }
+        RemovalPolicy::Always => false,
+    })
+}
+
+/// Executor of the plans of a sync, saving snapshots of the lists and asking for confirmation if
+/// the options say so.
+fn sync_executor<'a>(
+    client: &'a letterboxd::Client,
+    options: &SyncOptions,
+) -> anyhow::Result<SyncExecutor<'a>> {
+    let executor = SyncExecutor::new(client, options.dry_run)
+        .with_chunk_size(options.chunk_size)
+        .with_snapshots(snapshot_dir()?);
+    Ok(match options.confirm_update {
+        Some(confirm) => executor.with_confirmation(confirm),
+        None => executor,
+    })
+}
+
+/// State of the sync of a mapping, shared by the updates of all its lists.
+struct SyncContext<'a> {
+    client: &'a letterboxd::Client,
+    options: &'a SyncOptions,
+    mapping: &'a Mapping,
+    resolved: &'a ResolvedFilms,
+}
+
+/// Update a list, or the watchlist with [`Target::Watchlist`], to contain exactly the films with
+/// the given ids.
+///
+/// If `order` is given, the entries of the list are ranked in this order. Returns the executed
+/// plan.
+async fn update_list_films(
+    ctx: &SyncContext<'_>,
+    target: Target,
+    list_id: &str,
+    ids: &HashSet<String>,
+    order: Option<&Ranking>,
+    film_cache: &mut HashMap<String, FilmMetadata>,
+) -> anyhow::Result<SyncPlan> {
+    let SyncContext {
+        client,
+        options,
+        mapping,
+        resolved,
+    } = *ctx;
+    let mut plan = match target {
+        Target::List => SyncPlan::new(client, list_id, ids, order, options.remove_only)
+            .await
+            .context("failed to fetch the list")?,
+        Target::Watchlist => SyncPlan::for_watchlist(client, ids, options.remove_only)
+            .await
+            .context("failed to fetch the watchlist")?,
+    };
+    // The overrides apply to the synced list, not to the unwatched and top rated lists.
+    if list_id == mapping.list_id {
+        if let Some(name) = &options.list_name {
+            plan.details.name = name.clone();
+        }
+        if let Some(description) = &options.description {
+            plan.details.description = Some(description.clone());
+        }
+    }
+    // The films of movies which failed to resolve would be removed despite their files.
+    let skip = if !mapping.add_only
+        && options.removals != RemovalPolicy::Never
+        && !resolved.errors.is_empty()
+        && !plan.to_remove.is_empty()
+    {
+        warn!("{}", Message::KeepingRemovals(resolved.errors.len()));
+        true
+    } else {
+        skip_removals(options, mapping.add_only, &plan)?
+    };
+    if skip {
+        plan.skip_removals();
+    }
+    if let Some(template) = &options.notes_template {
+        if target == Target::List && !plan.to_add.is_empty() {
+            let notes = entry_notes(
+                template,
+                &plan.to_add,
+                resolved,
+                &mapping.directory,
+                film_cache,
+                client,
+            )
+            .await?;
+            plan.notes.extend(notes);
+        }
+    }
+    plan.to_add.sort();
+    plan.to_remove.sort();
+    // Show the titles of added films, or else the names of their movies.
+    match fetch_film_metadata(&plan.to_add, film_cache, client).await {
+        Ok(()) => {
+            for id in &plan.to_add {
+                if let Some(film) = film_cache.get(id) {
+                    plan.titles.insert(id.clone(), film.title());
+                }
+            }
+        }
+        Err(err) => warn!("failed to fetch titles of added films: {}", err),
+    }
+    for (movie, id) in &resolved.ids {
+        if plan.to_add.contains(id) {
+            plan.titles
+                .entry(id.clone())
+                .or_insert_with(|| movie.clone());
+        }
+    }
+    let executor = sync_executor(client, options)?;
+    let updated = executor
+        .execute(&plan)
+        .await
+        .context("failed to update the list")?;
+    if !updated && !options.dry_run {
+        plan.discard();
+    }
+    if options.verify && updated {
+        verify_plan(&executor, &plan).await?;
+    }
+    Ok(plan)
+}
+
+/// Check that the list of the applied plan shows all its changes, logging the ones it does not.
+async fn verify_plan(executor: &SyncExecutor<'_>, plan: &SyncPlan) -> anyhow::Result<()> {
+    let unapplied = executor
+        .verify(plan)
+        .await
+        .context("failed to verify the list")?;
+    if unapplied.is_empty() {
+        return Ok(());
+    }
+    error!(
+        "{}",
+        Message::Unapplied {
+            list_id: &plan.list_id,
+            count: unapplied.len(),
+        }
+    );
+    for id in &unapplied.missing {
+        error!("  + {}", plan.title(id));
+    }
+    for id in &unapplied.not_removed {
+        error!("  - {}", plan.title(id));
+    }
+    Err(SyncIncomplete::NotApplied(unapplied.len()).into())
+}
+
+/// Add the films removed by `removals` to the archive list, and remove the `owned` films from it.
+async fn archive_removals(
+    client: &letterboxd::Client,
+    options: &SyncOptions,
+    archive_id: &str,
+    removals: &SyncPlan,
+    owned: &HashSet<String>,
+) -> anyhow::Result<SyncPlan> {
+    let mut plan = SyncPlan::for_archive(client, archive_id, &removals.to_remove, owned)
+        .await
+        .context("failed to fetch the archive list")?;
+    plan.to_add.sort();
+    plan.to_remove.sort();
+    for id in &plan.to_add {
+        plan.titles
+            .insert(id.clone(), removals.title(id).to_string());
+    }
+    let executor = sync_executor(client, options)?;
+    let updated = executor
+        .execute(&plan)
+        .await
+        .context("failed to update the archive list")?;
+    if !updated && !options.dry_run {
+        plan.discard();
+    }
+    if options.verify && updated {
+        verify_plan(&executor, &plan).await?;
+    }
+    Ok(plan)
+}
+
+/// Mark the films with files in `folder` as watched, unless they are `watched` already. With
+/// `log_tags`, they are logged with the tags of the folders of their files below `folder`.
+async fn mark_folder_watched(
+    client: &letterboxd::Client,
+    options: &SyncOptions,
+    source: &Source,
+    folder: &Path,
+    resolved: &ResolvedFilms,
+    watched: &HashSet<String>,
+    film_cache: &mut HashMap<String, FilmMetadata>,
+) -> anyhow::Result<()> {
+    let in_folder = |file: &&MovieFile| {
+        file.path
+            .ancestors()
+            .skip(1)
+            .any(|dir| dir.ends_with(folder))
+    };
+    // The watched folder itself makes no tag.
+    let folder_tags: HashSet<String> = folder
+        .iter()
+        .map(|name| name.to_string_lossy().trim().to_lowercase())
+        .collect();
+    let tags: HashMap<String, Vec<String>> = resolved
+        .files_by_film_id()
+        .into_iter()
+        .filter(|(id, files)| !watched.contains(*id) && files.iter().any(in_folder))
+        .map(|(id, files)| {
+            let mut tags: Vec<String> = Vec::new();
+            for tag in files
+                .into_iter()
+                .filter(in_folder)
+                .flat_map(|file| path_tags(file, source))
+            {
+                if !folder_tags.contains(&tag) && !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+            (id.to_string(), tags)
+        })
+        .collect();
+    if tags.is_empty() {
+        return Ok(());
+    }
+    let mut ids: Vec<String> = tags.keys().cloned().collect();
+    ids.sort();
+    info!("{}", Message::MarkingWatched(ids.len()));
+    if let Err(err) = fetch_film_metadata(&ids, film_cache, client).await {
+        warn!("failed to fetch film details: {}", err);
+    }
+    for id in &ids {
+        let title = film_cache
+            .get(id)
+            .map_or_else(|| id.clone(), FilmMetadata::title);
+        match tags
+            .get(id)
+            .filter(|tags| options.log_tags && !tags.is_empty())
+        {
+            Some(tags) => info!("  + {} [{}]", title, tags.join(", ")),
+            None => info!("  + {}", title),
+        }
+    }
+    if options.dry_run {
+        return Ok(());
+    }
+    if options.log_tags {
+        log_watched(&tags, client)
+            .await
+            .context("failed to log films as watched")
+    } else {
+        mark_watched(&ids, client)
+            .await
+            .context("failed to mark films as watched")
+    }
+}
+
+/// Set the ratings of the ratings file at `path` which changed since they were last set.
+async fn import_ratings(
+    client: &letterboxd::Client,
+    options: &SyncOptions,
+    config: &Config,
+    path: &Path,
+    resolved: &ResolvedFilms,
+    film_cache: &mut HashMap<String, FilmMetadata>,
+) -> anyhow::Result<()> {
+    let ratings = read_ratings(path)?.of_films(resolved);
+    let cache_path = get_ratings_cache_filename(config.cache_format)
+        .context("failed to resolve ratings cache path")?;
+    let mut applied: HashMap<String, f32> = load_cache(&cache_path).with_context(|| {
+        format!(
+            "failed to read ratings cache file at: {}",
+            cache_path.display()
+        )
+    })?;
+    let changed: HashMap<String, f32> = ratings
+        .into_iter()
+        .filter(|(id, rating)| applied.get(id) != Some(rating))
+        .collect();
+    if changed.is_empty() {
+        return Ok(());
+    }
+    info!("{}", Message::SettingRatings(changed.len()));
+    if let Err(err) = fetch_film_metadata(changed.keys(), film_cache, client).await {
+        warn!("failed to fetch film details: {}", err);
+    }
+    let mut lines: Vec<String> = changed
+        .iter()
+        .map(|(id, rating)| {
+            let title = film_cache
+                .get(id)
+                .map_or_else(|| id.clone(), FilmMetadata::title);
+            format!("  {} {}", rating, title)
+        })
+        .collect();
+    lines.sort();
+    for line in lines {
+        info!("{}", line);
+    }
+    if options.dry_run {
+        return Ok(());
+    }
+    rate_films(&changed, client)
+        .await
+        .context("failed to set ratings")?;
+    applied.extend(changed);
+    save_cache(&applied, &cache_path, options.cache.fsync)
+        .with_context(|| format!("failed to save ratings cache {}", cache_path.display()))
+}
+
+/// Sync the directory of the mapping with its list.
+pub async fn sync_mapping(
+    options: &SyncOptions,
+    mapping: &Mapping,
+    config: &Config,
+    client: &letterboxd::Client,
+) -> anyhow::Result<SyncedMapping> {
+    let resolved = scan_and_resolve(
+        mapping,
+        &options.scan,
+        &options.cache,
+        &options.resolve,
+        config,
+        Some(client),
+    )
+    .await?;
+    // The films of the movies which were not searched would be missing from the lists.
+    if !resolved.skipped.is_empty() {
+        return Err(SyncIncomplete::BudgetSpent(resolved.skipped.len()).into());
+    }
+    let film_ids = &resolved.ids;
+
+    if !resolved.ambiguous.is_empty() {
+        for (movie, reason) in &resolved.ambiguous {
+            error!("{}", Message::AmbiguousMatch { movie, reason });
+        }
+        return Err(anyhow!(
+            "strict mode: {} ambiguous matches, list was not updated",
+            resolved.ambiguous.len()
+        ));
+    }
+    let unmatched = resolved.unresolved_files();
+    if let Some(max) = options.max_unmatched.filter(|max| unmatched > *max) {
+        return Err(SyncIncomplete::TooManyUnmatched {
+            count: unmatched,
+            max,
+        }
+        .into());
+    }
+
+    let (film_cache_path, mut film_cache) = load_film_cache(config)?;
+    let film_cache_len = film_cache.len();
+
+    if let Some(export_path) = &options.export_imdb {
+        fetch_film_metadata(film_ids.values(), &mut film_cache, client)
+            .await
+            .context("failed to fetch film details for IMDb export")?;
+        let rows = imdb_list_rows(&resolved, &film_cache);
+        write_imdb_list(&rows, export_path)
+            .with_context(|| format!("failed to write IMDb list to '{}'", export_path.display()))?;
+        info!(
+            "{}",
+            Message::ExportedImdbList {
+                count: rows.len(),
+                path: &export_path.display().to_string(),
+            }
+        );
+    }
+
+    let owned: HashSet<String> = film_ids.values().cloned().collect();
+    let watched = if options.exclude_watched
+        || options.unwatched_list.is_some()
+        || options.mark_watched.is_some()
+    {
+        fetch_watched_films(client)
+            .await
+            .context("failed to fetch watched films")?
+    } else {
+        HashSet::new()
+    };
+    let unwatched: HashSet<String> = owned.difference(&watched).cloned().collect();
+
+    let ids = if options.exclude_watched {
+        info!(
+            "{}",
+            Message::ExcludingWatched(owned.len() - unwatched.len())
+        );
+        &unwatched
+    } else {
+        &owned
+    };
+    let ratings = if options.sort == Some(SortOrder::Rating) || options.top_rated_list.is_some() {
+        fetch_ratings(owned.iter().cloned(), client)
+            .await
+            .context("failed to fetch film ratings")?
+    } else {
+        HashMap::new()
+    };
+    let order = match options.sort {
+        Some(sort) => {
+            if sort.needs_metadata() {
+                fetch_film_metadata(&owned, &mut film_cache, client)
+                    .await
+                    .context("failed to fetch film details")?;
+            }
+            let modified: HashMap<&str, i64> = resolved
+                .files_by_film_id()
+                .into_iter()
+                .filter_map(|(id, files)| {
+                    Some((id, files.iter().filter_map(|file| file.modified).max()?))
+                })
+                .collect();
+            let sorted = sort_films(&owned, sort, &ratings, &film_cache, &modified);
+            Some(match sort {
+                SortOrder::Added => Ranking::Appended(sorted),
+                _ => Ranking::Sorted(sorted),
+            })
+        }
+        None => None,
+    };
+    let ctx = SyncContext {
+        client,
+        options,
+        mapping,
+        resolved: &resolved,
+    };
+    let mut plans = vec![
+        update_list_films(
+            &ctx,
+            mapping.target,
+            &mapping.list_id,
+            ids,
+            order.as_ref(),
+            &mut film_cache,
+        )
+        .await?,
+    ];
+
+    if let Some(folder) = &options.mark_watched {
+        mark_folder_watched(
+            client,
+            options,
+            &mapping.directory,
+            folder,
+            &resolved,
+            &watched,
+            &mut film_cache,
+        )
+        .await?;
+    }
+
+    let ratings_path = options
+        .ratings
+        .clone()
+        .or_else(|| match &mapping.directory {
+            Source::Local(root) => Some(root.join(RATINGS_FILENAME)).filter(|path| path.is_file()),
+            _ => None,
+        });
+    if let Some(path) = ratings_path {
+        import_ratings(client, options, config, &path, &resolved, &mut film_cache).await?;
+    }
+
+    if let Some(archive_id) = &options.archive_removals_to {
+        let plan = archive_removals(client, options, archive_id, &plans[0], &owned).await?;
+        plans.push(plan);
+    }
+
+    if let Some(unwatched_list_id) = &options.unwatched_list {
+        let plan = update_list_films(
+            &ctx,
+            Target::List,
+            unwatched_list_id,
+            &unwatched,
+            order.as_ref(),
+            &mut film_cache,
+        )
+        .await?;
+        plans.push(plan);
+    }
+
+    if let Some(top_rated_list_id) = &options.top_rated_list {
+        let top_rated: HashSet<String> = owned
+            .iter()
+            .filter(|id| {
+                ratings
+                    .get(*id)
+                    .map(|rating| *rating >= options.min_rating)
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect();
+        let plan = update_list_films(
+            &ctx,
+            Target::List,
+            top_rated_list_id,
+            &top_rated,
+            order.as_ref(),
+            &mut film_cache,
+        )
+        .await?;
+        plans.push(plan);
+    }
+
+    if film_cache.len() != film_cache_len {
+        if let Err(err) = save_cache(&film_cache, &film_cache_path, options.cache.fsync) {
+            warn!("failed to save film details to cache: {}", err);
+        }
+    }
+    if !options.dry_run && config.notifications.is_enabled() {
+        let summary = MappingSummary::new(mapping, &resolved, &plans);
+        if let Err(err) = notify(&config.notifications, &summary).await {
+            warn!("{:#}", err);
+        }
+    }
+    Ok(SyncedMapping {
+        resolved,
+        local: mapping.directories().all(Source::is_local),
+        plans,
+    })
+}
+
+/// Sync a single mapping like [`sync_mapping`], logging the number of requests sent to Letterboxd
+/// like [`sync_mappings`]. A failure is returned but not logged.
+pub async fn sync_single_mapping(
+    options: &SyncOptions,
+    mapping: &Mapping,
+    config: &Config,
+    client: &letterboxd::Client,
+) -> anyhow::Result<SyncedMapping> {
+    retry::reset_api_calls();
+    let result = sync_mapping(options, mapping, config, client).await;
+    info!("{}", Message::ApiCalls(retry::api_calls()));
+    result
+}
+
+/// Sync each of the mappings, logging failures. Returns the outcomes of the mappings in order.
+pub async fn sync_mappings(
+    options: &SyncOptions,
+    mappings: &[&Mapping],
+    config: &Config,
+    client: &letterboxd::Client,
+) -> Vec<anyhow::Result<SyncedMapping>> {
+    retry::reset_api_calls();
+    let mut results = Vec::new();
+    for mapping in mappings {
+        info!(
+            "{}",
+            Message::SyncingMapping {
+                directory: &mapping.directory.to_string(),
+                list_id: mapping.list(),
+            }
+        );
+        let result = sync_mapping(options, mapping, config, client).await;
+        match &result {
+            Ok(synced) => metrics::record_sync(&MappingSummary::new(
+                mapping,
+                &synced.resolved,
+                &synced.plans,
+            )),
+            Err(err) => {
+                metrics::record_failed_sync();
+                error!("Sync of {} failed: {:?}", mapping.directory, err);
+            }
+        }
+        results.push(result);
+    }
+    info!("{}", Message::ApiCalls(retry::api_calls()));
+    results
+}
+
+/// Sync the movies of a mapping with a list of another service than Letterboxd.
+pub async fn sync_service_mapping<S: ListService>(
+    options: &SyncOptions,
+    mapping: &Mapping,
+    config: &Config,
+    service: &S,
+) -> anyhow::Result<()> {
+    let files = list_mapping_files(mapping, &options.scan, config).await?;
+    let metadata = metadata_chain(mapping, &files, &options.scan, config)?;
+    let (movies, _) = guess_source_movies(&mapping.directory, files, &metadata).await?;
+
+    let cache_path = service_cache_path(service, &mapping.directory, config)?;
+    let titles = resolve_service_films(service, &movies, &cache_path, options.cache.fsync).await?;
+    let mut plan =
+        plan_service_sync(service, &mapping.list_id, titles, options.remove_only).await?;
+    if skip_removals(options, mapping.add_only, &plan)? {
+        plan.skip_removals();
+    }
+
+    if plan.is_empty() {
+        info!("{}", Message::ListUpToDate(&plan.list_id));
+        return Ok(());
+    }
+    info!(
+        "{}",
+        Message::UpdatingList {
+            list_id: &plan.list_id,
+            to_add: plan.to_add.len(),
+            to_remove: plan.to_remove.len(),
+            reordered: false,
+            total: plan.total,
+        }
+    );
+    for (sign, ids) in [("+", &plan.to_add), ("-", &plan.to_remove)] {
+        for id in ids {
+            info!("  {} {}", sign, plan.title(id));
+        }
+    }
+    if options.dry_run {
+        info!("{}", Message::DryRun);
+        return Ok(());
+    }
+    if let Some(confirm) = options.confirm_update {
+        if !confirm(&plan)? {
+            info!("{}", Message::UpdateDeclined(&plan.list_id));
+            return Ok(());
+        }
+    }
+    apply_service_plan(service, &plan).await
+}
+
+/// Write how the scanned files were matched to films to a CSV file at `path`.
+pub fn write_mapping(
+    resolved: &[&ResolvedFilms],
+    films: &HashMap<String, FilmMetadata>,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let rows: Vec<FileMappingRow> = resolved
+        .iter()
+        .flat_map(|resolved| file_mapping_rows(resolved, films))
+        .collect();
+    write_file_mapping(&rows, path)
+        .with_context(|| format!("failed to write mapping file '{}'", path.display()))?;
+    info!(
+        "{}",
+        Message::MappingWritten {
+            count: rows.len(),
+            path: &path.display().to_string(),
+        }
+    );
+    Ok(())
+}
+
+/// Report the suggested names of the files whose films were not found by an exact search, named
+/// after `template`, and rename the local ones if `apply` is set.
+pub fn rename_files(
+    synced: &[&SyncedMapping],
+    films: &HashMap<String, FilmMetadata>,
+    template: &str,
+    apply: bool,
+) {
+    let renames: Vec<(Rename, bool)> = synced
+        .iter()
+        .flat_map(|synced| {
+            suggest_renames(&synced.resolved, films, template)
+                .into_iter()
+                .map(move |rename| (rename, synced.local))
+        })
+        .collect();
+    if renames.is_empty() {
+        return;
+    }
+    info!("{}", Message::RenameSuggestions(renames.len()));
+    for (rename, _) in &renames {
+        info!("  {} -> {}", rename.from.display(), rename.to.display());
+    }
+    if apply {
+        if renames.iter().any(|(_, local)| !local) {
+            warn!("{}", Message::RenamesLocalOnly);
+        }
+        let local: Vec<Rename> = renames
+            .into_iter()
+            .filter(|(_, local)| *local)
+            .map(|(rename, _)| rename)
+            .collect();
+        info!("{}", Message::FilesRenamed(apply_renames(&local)));
+    }
+}
+
+/// Log the films with several copies and their files.
+pub fn report_duplicates(duplicates: &[Duplicate]) {
+    if duplicates.is_empty() {
+        return;
+    }
+    info!("{}", Message::DuplicateFilms(duplicates.len()));
+    for duplicate in duplicates {
+        info!("  {}", duplicate.movie);
+        for file in &duplicate.files {
+            let details: Vec<String> = file
+                .quality
+                .clone()
+                .into_iter()
+                .chain(file.size.map(|size| HumanBytes(size).to_string()))
+                .collect();
+            if details.is_empty() {
+                info!("    {}", file.path.display());
+            } else {
+                info!("    {} ({})", file.path.display(), details.join(", "));
+            }
+        }
+    }
+}
+
+/// Serve the metrics at `addr` in the background, if given. A failure to serve them is logged
+/// without stopping the syncs.
+pub fn spawn_metrics_server(addr: Option<SocketAddr>) {
+    if let Some(addr) = addr {
+        tokio::spawn(async move {
+            if let Err(err) = serve_metrics(addr).await {
+                error!("{:#}", err);
+            }
+        });
+    }
+}
+
+/// Sync the mappings, and again whenever a webhook announces an added, deleted or renamed movie and
+/// every `reconcile_interval`.
+///
+/// Webhooks are accepted at `listen`, with `token` as `token` query parameter if given, and
+/// collected until there were none for the debounce time. Only the mappings whose directories may
+/// hold the movies are synced again. Movies in none of the directories, e.g. since Radarr sees
+/// other paths, cause a sync of all mappings.
+pub async fn serve(
+    options: &SyncOptions,
+    mappings: &[Mapping],
+    listen: SocketAddr,
+    token: Option<String>,
+    reconcile_interval: Duration,
+    config: &Config,
+    client: &letterboxd::Client,
+) -> anyhow::Result<()> {
+    let (sender, mut events) = mpsc::unbounded_channel();
+    let mut server = tokio::spawn(serve_webhooks(listen, token, sender));
+    info!("{}", Message::ListeningForWebhooks(&listen.to_string()));
+
+    let mut reconcile_at = Instant::now();
+    loop {
+        let changed: Vec<&Mapping> = tokio::select! {
+            result = &mut server => {
+                result??;
+                return Err(anyhow!("webhook server stopped"));
+            }
+            _ = tokio::time::sleep_until(reconcile_at.into()) => {
+                reconcile_at = Instant::now() + reconcile_interval;
+                mappings.iter().collect()
+            }
+            Some(event) = events.recv() => {
+                let mut received = vec![event];
+                let debounce = options.debounce;
+                while let Ok(Some(event)) = tokio::time::timeout(debounce, events.recv()).await {
+                    received.push(event);
+                }
+                for event in &received {
+                    let movie = event.movie.to_string();
+                    match event.kind {
+                        EventKind::Added => info!("{}", Message::MovieAdded(&movie)),
+                        EventKind::Deleted => info!("{}", Message::MovieDeleted(&movie)),
+                        EventKind::Renamed => info!("{}", Message::MovieRenamed(&movie)),
+                    }
+                }
+                let affected: Vec<&Mapping> = mappings
+                    .iter()
+                    .filter(|mapping| received.iter().any(|event| event.affects(mapping)))
+                    .collect();
+                if affected.is_empty() {
+                    mappings.iter().collect()
+                } else {
+                    affected
+                }
+            }
+        };
+        sync_mappings(options, &changed, config, client).await;
+    }
+}
+
+/// Resolves once the process is asked to stop with Ctrl-C or, on Unix, SIGTERM.
+async fn shutdown_signal() -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+/// Random delay of up to `jitter`.
+fn random_delay(jitter: Duration) -> Duration {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    // Each `RandomState` is seeded randomly, which is random enough to spread syncs.
+    let random = RandomState::new().build_hasher().finish();
+    jitter.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+/// Sync the mappings every `interval`, delayed by up to `jitter`, until asked to stop.
+///
+/// A sync running when the signal to stop arrives is finished first, so no list is left half
+/// updated.
+pub async fn daemon(
+    options: &SyncOptions,
+    mappings: &[Mapping],
+    interval: Duration,
+    jitter: Option<Duration>,
+    config: &Config,
+    client: &letterboxd::Client,
+) -> anyhow::Result<()> {
+    let mappings: Vec<&Mapping> = mappings.iter().collect();
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        let started = Instant::now();
+        let cycle = sync_mappings(options, &mappings, config, client);
+        tokio::pin!(cycle);
+        tokio::select! {
+            _ = &mut cycle => {}
+            result = &mut shutdown => {
+                result?;
+                info!("{}", Message::StoppingAfterSync);
+                cycle.await;
+                return Ok(());
+            }
+        }
+
+        let next = started + interval + jitter.map(random_delay).unwrap_or_default();
+        info!(
+            "{}",
+            Message::NextSync(next.saturating_duration_since(Instant::now()))
+        );
+        tokio::select! {
+            _ = tokio::time::sleep_until(next.into()) => {}
+            result = &mut shutdown => {
+                result?;
+                info!("{}", Message::Stopped);
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Sync the mappings and again whenever files in their local directories change.
+///
+/// Changes are collected until there were none for the debounce time and at least `min_interval`
+/// passed since the last sync began, and only mappings with changed files are synced again.
+pub async fn watch(
+    options: &SyncOptions,
+    mappings: &[Mapping],
+    min_interval: Duration,
+    config: &Config,
+    client: &letterboxd::Client,
+) -> anyhow::Result<()> {
+    let mut watcher = DirectoryWatcher::new(options.debounce)?;
+    let mut watched = Vec::new();
+    for mapping in mappings {
+        for source in mapping.directories() {
+            match source {
+                Source::Local(directory) => {
+                    // Changes are reported with absolute paths.
+                    let directory = directory
+                        .canonicalize()
+                        .with_context(|| format!("failed to access {}", directory.display()))?;
+                    watcher.watch(&directory, mapping.max_depth() != Some(1))?;
+                    watched.push((directory, mapping));
+                }
+                source => warn!("{}", Message::NotWatched(&source.to_string())),
+            }
+        }
+    }
+    if watched.is_empty() {
+        return Err(anyhow!("no local directory to watch"));
+    }
+
+    let mut changed: Vec<&Mapping> = mappings.iter().collect();
+    loop {
+        let started = Instant::now();
+        sync_mappings(options, &changed, config, client).await;
+        info!("{}", Message::Watching(watched.len()));
+
+        let path = watcher
+            .changed()
+            .await
+            .ok_or_else(|| anyhow!("file watcher stopped"))?;
+        tokio::time::sleep_until((started + min_interval).into()).await;
+        let paths: Vec<PathBuf> = iter::once(path).chain(watcher.drain()).collect();
+        debug!("Changed paths: {:?}", paths);
+        changed = watched
+            .iter()
+            .filter(|(directory, _)| paths.iter().any(|path| path.starts_with(directory)))
+            .map(|(_, mapping)| *mapping)
+            .collect();
+        // The directories of a mapping are watched one after another.
+        changed.dedup_by(|a, b| ptr::eq(*a, *b));
+    }
+}
@@ -0,0 +1,263 @@
+//! Windows service running the sync periodically.
+//!
+//! The service is installed with the arguments of the `sync` subcommand, which are passed back to
+//! the binary by the service control manager as `service run`.
+
+use anyhow::{anyhow, Context as _};
+use log::{error, info};
+use structopt::StructOpt;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+use windows_sys::Win32::System::EventLog::{
+    RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+    EVENTLOG_WARNING_TYPE,
+};
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::iter;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use letterboxd_list_sync::config::load_config;
+use letterboxd_list_sync::error::ApiFailure;
+use letterboxd_list_sync::i18n::Message;
+
+use super::{sync, Args, Command, SyncArgs};
+
+const SERVICE_NAME: &str = "letterboxd-list-sync";
+const SERVICE_DISPLAY_NAME: &str = "Letterboxd List Sync";
+
+#[derive(Debug, StructOpt)]
+#[allow(clippy::large_enum_variant)]
+pub enum ServiceCommand {
+    /// Install a Windows service running `sync` with the given arguments periodically.
+    ///
+    /// The service runs as LocalSystem, so the Letterboxd credentials must be set as system
+    /// environment variables.
+    Install {
+        /// Seconds between two syncs.
+        #[structopt(long, default_value = "3600")]
+        interval: u64,
+        /// Arguments of the `sync` subcommand.
+        #[structopt(last = true)]
+        sync_args: Vec<String>,
+    },
+    /// Stop and uninstall the Windows service.
+    Uninstall,
+    /// Run as Windows service. Only to be invoked by the service control manager.
+    Run {
+        /// Seconds between two syncs.
+        #[structopt(long)]
+        interval: u64,
+        #[structopt(flatten)]
+        sync: SyncArgs,
+    },
+}
+
+pub fn run_command(command: &ServiceCommand, config: Option<&Path>) -> anyhow::Result<()> {
+    match command {
+        ServiceCommand::Install {
+            interval,
+            sync_args,
+        } => install(*interval, sync_args, config),
+        ServiceCommand::Uninstall => uninstall(),
+        ServiceCommand::Run { .. } => service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+            .context("failed to start service dispatcher"),
+    }
+}
+
+fn install(interval: u64, sync_args: &[String], config: Option<&Path>) -> anyhow::Result<()> {
+    // Fail early on arguments the service would not be able to parse.
+    SyncArgs::from_iter_safe(iter::once("sync").chain(sync_args.iter().map(String::as_str)))
+        .map_err(|err| anyhow!("invalid sync arguments: {}", err.message))?;
+
+    let mut launch_arguments: Vec<OsString> = Vec::new();
+    if let Some(config) = config {
+        let config = fs::canonicalize(config)
+            .with_context(|| format!("failed to resolve config file: {}", config.display()))?;
+        launch_arguments.extend(vec!["--config".into(), config.into_os_string()]);
+    }
+    launch_arguments.extend(vec![
+        "service".into(),
+        "run".into(),
+        "--interval".into(),
+        interval.to_string().into(),
+    ]);
+    launch_arguments.extend(sync_args.iter().map(OsString::from));
+
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .context("failed to connect to the service manager")?;
+    let service_info = ServiceInfo {
+        name: SERVICE_NAME.into(),
+        display_name: SERVICE_DISPLAY_NAME.into(),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: env::current_exe()?,
+        launch_arguments,
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&service_info, ServiceAccess::CHANGE_CONFIG)
+        .context("failed to create service")?;
+    service.set_description("Synchronizes movies in a folder with a list on Letterboxd.")?;
+    info!("Installed service {}.", SERVICE_NAME);
+    Ok(())
+}
+
+fn uninstall() -> anyhow::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .context("failed to connect to the service manager")?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .context("failed to open service")?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop().context("failed to stop service")?;
+    }
+    service.delete().context("failed to delete service")?;
+    info!("Uninstalled service {}.", SERVICE_NAME);
+    Ok(())
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(err) = run_service() {
+        error!("Service failed: {:#}", err);
+    }
+}
+
+fn service_status(state: ServiceState, controls_accepted: ServiceControlAccept) -> ServiceStatus {
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    }
+}
+
+fn run_service() -> anyhow::Result<()> {
+    // The launch arguments given at installation are the arguments of this process.
+    let args = Args::from_args();
+    let (interval, sync_args) = match &args.command {
+        Command::Service(ServiceCommand::Run { interval, sync }) => (*interval, sync),
+        _ => return Err(anyhow!("service started without `service run` arguments")),
+    };
+
+    let (shutdown_tx, shutdown_rx) = mpsc::channel();
+    let status_handle =
+        service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop => {
+                let _ = shutdown_tx.send(());
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+    status_handle.set_service_status(service_status(
+        ServiceState::Running,
+        ServiceControlAccept::STOP,
+    ))?;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    loop {
+        let res = match load_config(args.config.as_deref()) {
+            Ok(config) => runtime.block_on(sync(sync_args, &config)),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = res {
+            match ApiFailure::find(&err) {
+                Some(failure) => error!("Sync failed: {}", Message::ApiFailure(failure)),
+                None => error!("Sync failed: {:#}", err),
+            }
+        }
+        match shutdown_rx.recv_timeout(Duration::from_secs(interval)) {
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            _ => break,
+        }
+    }
+
+    status_handle.set_service_status(service_status(
+        ServiceState::Stopped,
+        ServiceControlAccept::empty(),
+    ))?;
+    Ok(())
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(iter::once(0)).collect()
+}
+
+/// Logger writing to the Application event log.
+struct EventLogger {
+    handle: isize,
+}
+
+impl log::Log for EventLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let event_type = match record.level() {
+            log::Level::Error => EVENTLOG_ERROR_TYPE,
+            log::Level::Warn => EVENTLOG_WARNING_TYPE,
+            _ => EVENTLOG_INFORMATION_TYPE,
+        };
+        let message = to_wide(&record.args().to_string());
+        let strings = [message.as_ptr()];
+        unsafe {
+            ReportEventW(
+                self.handle,
+                event_type,
+                0,
+                0,
+                ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                ptr::null(),
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Send all log messages to the Windows event log.
+pub fn init_logging() -> anyhow::Result<()> {
+    let source_name = to_wide(SERVICE_DISPLAY_NAME);
+    let handle = unsafe { RegisterEventSourceW(ptr::null(), source_name.as_ptr()) };
+    if handle == 0 {
+        return Err(io::Error::last_os_error()).context("failed to register event source");
+    }
+    log::set_logger(Box::leak(Box::new(EventLogger { handle })))
+        .map_err(|err| anyhow!("failed to set logger: {}", err))?;
+    log::set_max_level(log::LevelFilter::Info);
+    Ok(())
+}
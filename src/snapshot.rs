@@ -0,0 +1,109 @@
+//! Snapshots of the films of lists, taken before a sync updates a list, to restore a list with
+//! `rollback`.
+//!
+//! The snapshots of a list are kept in `snapshots/<list id>.json` in the cache directory, oldest
+//! first. Only the last [`SNAPSHOTS_KEPT`] snapshots of each list are kept.
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cache::{cache_dir, save_cache};
+use crate::sync::{ListDetails, SyncPlan, Target};
+
+/// Number of snapshots kept of each list.
+pub const SNAPSHOTS_KEPT: usize = 10;
+
+/// Films of a list in their order, with its name and description.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListSnapshot {
+    pub list_id: String,
+    /// Unix timestamp of the snapshot.
+    pub taken: i64,
+    /// Ids of the films on the list in their order.
+    pub films: Vec<String>,
+    /// Titles with release year of the films, by film id.
+    pub titles: HashMap<String, String>,
+    pub details: ListDetails,
+}
+
+impl ListSnapshot {
+    /// Snapshot of the list before the update of the plan, unless the films of the list before
+    /// are unknown, like for the plans of plan files of older versions, or the plan updates the
+    /// watchlist.
+    pub fn of_plan(plan: &SyncPlan) -> Option<Self> {
+        if plan.target != Target::List {
+            return None;
+        }
+        let films = plan.saved.clone()?;
+        Some(Self {
+            list_id: plan.list_id.clone(),
+            taken: chrono::Utc::now().timestamp(),
+            titles: films
+                .iter()
+                .filter_map(|id| Some((id.clone(), plan.titles.get(id)?.clone())))
+                .collect(),
+            films,
+            details: plan.saved_details().clone(),
+        })
+    }
+
+    /// Local date and time of the snapshot, like `2021-03-01 20:15`.
+    pub fn taken_at(&self) -> String {
+        chrono::DateTime::from_timestamp(self.taken, 0)
+            .map(|taken| {
+                taken
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Directory of the snapshots: `snapshots` in the cache directory.
+pub fn snapshot_dir() -> anyhow::Result<PathBuf> {
+    Ok(cache_dir()?.join("snapshots"))
+}
+
+fn snapshot_path(dir: &Path, list_id: &str) -> PathBuf {
+    dir.join(format!("{}.json", list_id))
+}
+
+/// Load the snapshots of a list, oldest first. A list without snapshots has none.
+pub fn load_snapshots(dir: &Path, list_id: &str) -> anyhow::Result<Vec<ListSnapshot>> {
+    let path = snapshot_path(dir, list_id);
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse snapshots: {}", path.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => {
+            Err(err).with_context(|| format!("failed to read snapshots: {}", path.display()))
+        }
+    }
+}
+
+/// Save the snapshots of a list, dropping all but the last [`SNAPSHOTS_KEPT`] ones.
+pub fn save_snapshots(
+    dir: &Path,
+    list_id: &str,
+    mut snapshots: Vec<ListSnapshot>,
+) -> anyhow::Result<()> {
+    let path = snapshot_path(dir, list_id);
+    let dropped = snapshots.len().saturating_sub(SNAPSHOTS_KEPT);
+    snapshots.drain(..dropped);
+    save_cache(&snapshots, &path, true)
+        .with_context(|| format!("failed to save snapshots: {}", path.display()))
+}
+
+/// Add a snapshot to the ones of its list.
+pub fn add_snapshot(dir: &Path, snapshot: ListSnapshot) -> anyhow::Result<()> {
+    let mut snapshots = load_snapshots(dir, &snapshot.list_id)?;
+    let list_id = snapshot.list_id.clone();
+    snapshots.push(snapshot);
+    save_snapshots(dir, &list_id, snapshots)
+}
@@ -0,0 +1,75 @@
+//! Statistics of the films in a directory, shown by `stats`.
+
+use serde::Serialize;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::films::FilmMetadata;
+
+/// Counts by decade, top directors, runtime and watched films of a library.
+#[derive(Debug, Default, Serialize)]
+pub struct LibraryStats {
+    /// Number of resolved films.
+    pub films: usize,
+    /// Number of files whose movie was not resolved.
+    pub unresolved_files: usize,
+    /// Number of films by decade, like 1990. Films of unknown year are left out.
+    pub decades: BTreeMap<u16, usize>,
+    /// Directors with the most films and their number of films, most first.
+    pub top_directors: Vec<(String, usize)>,
+    /// Average runtime in minutes of the films of known runtime.
+    pub average_runtime: Option<f64>,
+    /// Runtime of all films of known runtime in minutes.
+    pub total_runtime: u64,
+    /// Number of films watched by the authenticated member, if known.
+    pub watched: Option<usize>,
+}
+
+impl LibraryStats {
+    /// Statistics of the given films, of which `metadata` has the details. With `watched`, the
+    /// films watched by the member are counted.
+    pub fn new(
+        ids: &HashSet<String>,
+        metadata: &HashMap<String, FilmMetadata>,
+        watched: Option<&HashSet<String>>,
+        top: usize,
+    ) -> Self {
+        let films: Vec<&FilmMetadata> = ids.iter().filter_map(|id| metadata.get(id)).collect();
+
+        let mut decades = BTreeMap::new();
+        for year in films.iter().filter_map(|film| film.year) {
+            *decades.entry(year / 10 * 10).or_default() += 1;
+        }
+
+        let mut directors: HashMap<&str, usize> = HashMap::new();
+        for director in films.iter().flat_map(|film| &film.directors) {
+            *directors.entry(director.as_str()).or_default() += 1;
+        }
+        let mut top_directors: Vec<(String, usize)> = directors
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect();
+        top_directors.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_directors.truncate(top);
+
+        let runtimes: Vec<u64> = films
+            .iter()
+            .filter_map(|film| film.runtime)
+            .map(u64::from)
+            .collect();
+        let total_runtime: u64 = runtimes.iter().sum();
+        let average_runtime = Some(runtimes.len())
+            .filter(|n| *n > 0)
+            .map(|n| total_runtime as f64 / n as f64);
+
+        Self {
+            films: ids.len(),
+            unresolved_files: 0,
+            decades,
+            top_directors,
+            average_runtime,
+            total_runtime,
+            watched: watched.map(|watched| ids.intersection(watched).count()),
+        }
+    }
+}
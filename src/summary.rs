@@ -0,0 +1,147 @@
+//! Machine-readable summaries of a sync, for scripts and dashboards.
+
+use anyhow::anyhow;
+use serde::Serialize;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::config::Mapping;
+use crate::duplicates::{find_duplicates, Duplicate};
+use crate::resolve::ResolvedFilms;
+use crate::sync::SyncPlan;
+
+/// Format of the output of a sync on stdout. Logs are written to stderr in either format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Only logs.
+    Text,
+    /// A [`RunSummary`] as JSON.
+    Json,
+}
+
+impl OutputFormat {
+    pub const VARIANTS: &'static [&'static str] = &["text", "json"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(anyhow!("unknown output format: {}", s)),
+        }
+    }
+}
+
+/// Summary of the sync of all mappings.
+#[derive(Debug, Default, Serialize)]
+pub struct RunSummary {
+    pub mappings: Vec<MappingSummary>,
+}
+
+/// Summary of the sync of a directory with a list.
+#[derive(Debug, Default, Serialize)]
+pub struct MappingSummary {
+    pub directory: String,
+    /// ID of the list, or `watchlist`.
+    pub list_id: String,
+    /// Number of movie files scanned.
+    pub files: usize,
+    /// Number of movies resolved to films.
+    pub matched: usize,
+    /// Number of movies resolved from the cache.
+    pub cache_hits: usize,
+    /// Files and movies which were not resolved to films.
+    pub unmatched: Vec<Unmatched>,
+    /// Films added to the synced lists.
+    pub added: Vec<FilmChange>,
+    /// Films removed from the synced lists.
+    pub removed: Vec<FilmChange>,
+    /// Films with several copies in the directory.
+    pub duplicates: Vec<Duplicate>,
+    /// Error the sync failed with, like a rejected API request.
+    pub error: Option<String>,
+}
+
+/// Files which were not resolved to a film.
+#[derive(Debug, Serialize)]
+pub struct Unmatched {
+    /// Name of the movie, unless none was found in the file name.
+    pub movie: Option<String>,
+    pub files: Vec<PathBuf>,
+    pub reason: String,
+}
+
+/// A film added to or removed from a list.
+#[derive(Debug, Serialize)]
+pub struct FilmChange {
+    pub list_id: String,
+    pub film_id: String,
+    pub title: String,
+}
+
+impl MappingSummary {
+    /// Summary of the successful sync of a mapping, which resolved the films and executed the
+    /// plans.
+    pub fn new(mapping: &Mapping, resolved: &ResolvedFilms, plans: &[SyncPlan]) -> Self {
+        let unguessed = resolved.unguessed.iter().map(|file| Unmatched {
+            movie: None,
+            files: vec![file.path.clone()],
+            reason: "no movie name found".to_string(),
+        });
+        let unresolved = resolved
+            .unresolved()
+            .into_iter()
+            .map(|(movie, reason)| Unmatched {
+                files: resolved
+                    .files
+                    .get(&movie)
+                    .into_iter()
+                    .flatten()
+                    .map(|file| file.path.clone())
+                    .collect(),
+                movie: Some(movie),
+                reason,
+            });
+        let changes = |plan: &SyncPlan, ids: &[String]| -> Vec<FilmChange> {
+            ids.iter()
+                .map(|id| FilmChange {
+                    list_id: plan.list_id.clone(),
+                    film_id: id.clone(),
+                    title: plan.title(id).to_string(),
+                })
+                .collect()
+        };
+        Self {
+            directory: mapping.directory.to_string(),
+            list_id: mapping.list().to_string(),
+            files: resolved.files.values().map(Vec::len).sum::<usize>() + resolved.unguessed.len(),
+            matched: resolved.ids.len(),
+            cache_hits: resolved.cache_hits,
+            unmatched: unguessed.chain(unresolved).collect(),
+            added: plans
+                .iter()
+                .flat_map(|plan| changes(plan, &plan.to_add))
+                .collect(),
+            removed: plans
+                .iter()
+                .flat_map(|plan| changes(plan, &plan.to_remove))
+                .collect(),
+            duplicates: find_duplicates(resolved),
+            error: None,
+        }
+    }
+
+    /// Summary of the failed sync of a mapping.
+    pub fn failed(mapping: &Mapping, err: &anyhow::Error) -> Self {
+        Self {
+            directory: mapping.directory.to_string(),
+            list_id: mapping.list().to_string(),
+            error: Some(format!("{:#}", err)),
+            ..Self::default()
+        }
+    }
+}
@@ -0,0 +1,925 @@
+//! Planning and applying the changes which sync a list with a set of films.
+
+use anyhow::{anyhow, Context as _};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::cache::{cache_dir, save_cache};
+use crate::films::film_title;
+use crate::i18n::Message;
+use crate::progress;
+use crate::retry::with_retry;
+use crate::snapshot::{add_snapshot, ListSnapshot};
+use crate::throttle::max_concurrency;
+
+/// Name shown for the watchlist where lists are shown by id.
+pub const WATCHLIST: &str = "watchlist";
+
+/// Number of films added or removed by one update of a list, unless configured otherwise.
+pub const DEFAULT_CHUNK_SIZE: usize = 500;
+
+/// Attempts to apply a chunk of an update whose films did not all end up on the list.
+const CHUNK_ATTEMPTS: usize = 3;
+
+/// Get film ids response of list entries request.
+pub fn film_id_set_from_response(entries: &[letterboxd::ListEntry]) -> HashSet<String> {
+    entries.iter().map(|entry| entry.film.id.clone()).collect()
+}
+
+/// Fetch all entries of a list by paging through them.
+pub async fn fetch_list_entries(
+    list_id: &str,
+    client: &letterboxd::Client,
+) -> letterboxd::Result<Vec<letterboxd::ListEntry>> {
+    let mut request = letterboxd::ListEntriesRequest {
+        per_page: Some(100),
+        ..Default::default()
+    };
+    let mut entries = Vec::new();
+    let spinner = progress::spinner(Message::FetchingList { list_id, count: 0 }.to_string());
+    loop {
+        let response = with_retry(|| client.list_entries(list_id, &request)).await?;
+        entries.extend(response.items);
+        spinner.set_message(
+            Message::FetchingList {
+                list_id,
+                count: entries.len(),
+            }
+            .to_string(),
+        );
+        request.cursor = response.next;
+        if request.cursor.is_none() {
+            break;
+        }
+    }
+    spinner.finish_and_clear();
+    Ok(entries)
+}
+
+/// Film on a list as needed to plan a sync, cached between runs by [`fetch_saved_entries`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedEntry {
+    pub film_id: String,
+    /// Title of the film with its year, see [`film_title`].
+    pub title: String,
+    /// Notes of the entry in LBML, or else as text.
+    pub notes: Option<String>,
+}
+
+impl From<&letterboxd::ListEntry> for SavedEntry {
+    fn from(entry: &letterboxd::ListEntry) -> Self {
+        Self {
+            notes: entry.notes_lbml.clone().or_else(|| entry.notes.clone()),
+            ..Self::from(&entry.film)
+        }
+    }
+}
+
+impl From<&letterboxd::FilmSummary> for SavedEntry {
+    fn from(film: &letterboxd::FilmSummary) -> Self {
+        Self {
+            film_id: film.id.clone(),
+            title: film_title(film),
+            notes: None,
+        }
+    }
+}
+
+/// Entries of a list at a version of the list.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedEntries {
+    version: usize,
+    entries: Vec<SavedEntry>,
+}
+
+/// Path of the cached entries of a list.
+fn list_cache_path(list_id: &str) -> anyhow::Result<PathBuf> {
+    Ok(cache_dir()?
+        .join("lists")
+        .join(list_id)
+        .with_extension("json"))
+}
+
+/// Cached entries of a list if they are of the given version of the list.
+fn read_cached_entries(list_id: &str, version: usize) -> anyhow::Result<Option<Vec<SavedEntry>>> {
+    let path = list_cache_path(list_id)?;
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let cached: CachedEntries = serde_json::from_reader(io::BufReader::new(file))
+        .with_context(|| format!("failed to parse cached list at: {}", path.display()))?;
+    Ok((cached.version == version).then_some(cached.entries))
+}
+
+/// Entries of a list as needed to plan a sync, in the order of the list.
+///
+/// Letterboxd raises the version of a list on every change, so the entries are cached by the
+/// version of `list`. A list which did not change since the last run is not paged through again.
+pub async fn fetch_saved_entries(
+    list: &letterboxd::List,
+    client: &letterboxd::Client,
+) -> letterboxd::Result<Vec<SavedEntry>> {
+    match read_cached_entries(&list.id, list.version) {
+        Ok(Some(entries)) => {
+            debug!(
+                "Using the cached entries of list {} at version {}",
+                list.id, list.version
+            );
+            return Ok(entries);
+        }
+        Ok(None) => (),
+        Err(err) => warn!(
+            "failed to read the cached entries of list {}: {:#}",
+            list.id, err
+        ),
+    }
+    let entries: Vec<SavedEntry> = fetch_list_entries(&list.id, client)
+        .await?
+        .iter()
+        .map(SavedEntry::from)
+        .collect();
+    let cached = CachedEntries {
+        version: list.version,
+        entries,
+    };
+    if let Err(err) = list_cache_path(&list.id).and_then(|path| save_cache(&cached, path, false)) {
+        warn!("failed to cache the entries of list {}: {:#}", list.id, err);
+    }
+    Ok(cached.entries)
+}
+
+/// Fetch all films in the watchlist of the authenticated member by paging through them.
+pub async fn fetch_watchlist(
+    client: &letterboxd::Client,
+) -> letterboxd::Result<Vec<letterboxd::FilmSummary>> {
+    let me = with_retry(|| client.me()).await?;
+    let mut request = letterboxd::FilmsRequest {
+        per_page: Some(100),
+        member: Some(me.member.id),
+        member_relationship: Some(letterboxd::FilmMemberRelationship::InWatchlist),
+        ..Default::default()
+    };
+    let mut films = Vec::new();
+    loop {
+        let response = with_retry(|| client.films(&request)).await?;
+        films.extend(response.items);
+        request.cursor = response.next;
+        if request.cursor.is_none() {
+            break;
+        }
+    }
+    Ok(films)
+}
+
+/// What the movies of a directory are synced with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// A list of the authenticated member.
+    #[default]
+    List,
+    /// The watchlist of the authenticated member, which is neither ranked nor has notes.
+    Watchlist,
+}
+
+impl Target {
+    pub const VARIANTS: &'static [&'static str] = &["list", "watchlist"];
+}
+
+impl FromStr for Target {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "list" => Ok(Target::List),
+            "watchlist" => Ok(Target::Watchlist),
+            _ => Err(anyhow!("unknown sync target: {}", s)),
+        }
+    }
+}
+
+/// How to handle films on a list which are not in the directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalPolicy {
+    /// Keep them on the list.
+    Never,
+    /// Ask before removing them.
+    Prompt,
+    /// Remove them from the list.
+    Always,
+}
+
+impl RemovalPolicy {
+    pub const VARIANTS: &'static [&'static str] = &["never", "prompt", "always"];
+}
+
+impl FromStr for RemovalPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "never" => Ok(RemovalPolicy::Never),
+            "prompt" => Ok(RemovalPolicy::Prompt),
+            "always" => Ok(RemovalPolicy::Always),
+            _ => Err(anyhow!("unknown removal policy: {}", s)),
+        }
+    }
+}
+
+/// Order of the entries of a list after a sync.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ranking {
+    /// All entries in this order.
+    Sorted(Vec<String>),
+    /// Entries already on the list keep their order, and added entries follow in this order.
+    Appended(Vec<String>),
+}
+
+/// Metadata of a list which is kept when the list is updated.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListDetails {
+    pub name: String,
+    /// Description in LBML, the markup of Letterboxd.
+    pub description: Option<String>,
+    pub published: bool,
+    pub tags: Vec<String>,
+}
+
+impl From<&letterboxd::List> for ListDetails {
+    fn from(list: &letterboxd::List) -> Self {
+        Self {
+            name: list.name.clone(),
+            description: list.description_lbml.clone(),
+            published: list.published,
+            tags: list.tags.clone(),
+        }
+    }
+}
+
+/// Changes turning a list into one containing exactly a given set of films.
+///
+/// Plans are saved to plan files as JSON, see [`save_plans`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncPlan {
+    /// Whether a list or the watchlist is updated.
+    pub target: Target,
+    /// ID of the list, or [`WATCHLIST`] for the watchlist.
+    pub list_id: String,
+    /// Ids of the films to add.
+    pub to_add: Vec<String>,
+    /// Ids of the films to remove.
+    pub to_remove: Vec<String>,
+    /// Ids of all films of the list in their new order, if the entries are ranked anew.
+    pub ranked: Option<Vec<String>>,
+    /// Notes of the entries by film id: the ones of the added entries, and the ones of the entries
+    /// on the list, which are sent along when the entries are ranked anew so they are kept.
+    pub notes: HashMap<String, String>,
+    /// Titles with release year of the films on the list before the sync, by film id. Added
+    /// films may be titled by their movie names.
+    pub titles: HashMap<String, String>,
+    /// Number of films on the list after the sync.
+    pub total: usize,
+    /// Name, description and visibility of the list after the sync.
+    pub details: ListDetails,
+    /// Name, description and visibility of the list before the sync.
+    #[serde(default)]
+    saved_details: ListDetails,
+    /// Ids of the films on the list before the sync in their order, unless unknown like for the
+    /// lists of other services or plan files of older versions.
+    #[serde(default)]
+    pub saved: Option<Vec<String>>,
+}
+
+impl SyncPlan {
+    /// Plan the update of a list to contain exactly the films with the given ids.
+    ///
+    /// If `order` is given, the entries of the list are ranked in this order. In `remove_only`
+    /// mode, films not on the list yet are never added.
+    pub async fn new(
+        client: &letterboxd::Client,
+        list_id: &str,
+        ids: &HashSet<String>,
+        order: Option<&Ranking>,
+        remove_only: bool,
+    ) -> letterboxd::Result<Self> {
+        let list = with_retry(|| client.list(list_id)).await?;
+        let saved_entries = fetch_saved_entries(&list, client).await?;
+        Ok(Self::from_entries(
+            list_id,
+            ListDetails::from(&list),
+            &saved_entries,
+            ids,
+            order,
+            remove_only,
+        ))
+    }
+
+    /// Plan the update of the watchlist to contain exactly the films with the given ids.
+    ///
+    /// In `remove_only` mode, films not in the watchlist yet are never added.
+    pub async fn for_watchlist(
+        client: &letterboxd::Client,
+        ids: &HashSet<String>,
+        remove_only: bool,
+    ) -> letterboxd::Result<Self> {
+        let saved_films = fetch_watchlist(client).await?;
+        let saved_entries: Vec<SavedEntry> = saved_films.iter().map(SavedEntry::from).collect();
+        Ok(Self {
+            target: Target::Watchlist,
+            ..Self::from_entries(
+                WATCHLIST,
+                ListDetails::default(),
+                &saved_entries,
+                ids,
+                None,
+                remove_only,
+            )
+        })
+    }
+
+    /// Plan the update of an archive list to also contain the `archived` films, and no longer the
+    /// `owned` ones, which are back in the directory.
+    pub async fn for_archive(
+        client: &letterboxd::Client,
+        list_id: &str,
+        archived: &[String],
+        owned: &HashSet<String>,
+    ) -> letterboxd::Result<Self> {
+        let list = with_retry(|| client.list(list_id)).await?;
+        let saved_entries = fetch_saved_entries(&list, client).await?;
+        let ids: HashSet<String> = saved_entries
+            .iter()
+            .map(|entry| entry.film_id.clone())
+            .chain(archived.iter().cloned())
+            .filter(|id| !owned.contains(id))
+            .collect();
+        Ok(Self::from_entries(
+            list_id,
+            ListDetails::from(&list),
+            &saved_entries,
+            &ids,
+            None,
+            false,
+        ))
+    }
+
+    /// Plan the update of a list to contain the films of the snapshot again, in their order if the
+    /// list is ranked, and to have the name and description of the snapshot.
+    pub async fn for_snapshot(
+        client: &letterboxd::Client,
+        snapshot: &ListSnapshot,
+    ) -> letterboxd::Result<Self> {
+        let list = with_retry(|| client.list(&snapshot.list_id)).await?;
+        let saved_entries = fetch_saved_entries(&list, client).await?;
+        let ids: HashSet<String> = snapshot.films.iter().cloned().collect();
+        let order = Some(Ranking::Sorted(snapshot.films.clone())).filter(|_| list.ranked);
+        let mut plan = Self::from_entries(
+            &snapshot.list_id,
+            ListDetails::from(&list),
+            &saved_entries,
+            &ids,
+            order.as_ref(),
+            false,
+        );
+        plan.details = snapshot.details.clone();
+        for (id, title) in &snapshot.titles {
+            plan.titles
+                .entry(id.clone())
+                .or_insert_with(|| title.clone());
+        }
+        Ok(plan)
+    }
+
+    /// Plan the update of a list of another service than Letterboxd, which has the films with the
+    /// given titles by id, to contain exactly the films with the given ids. Such lists have no
+    /// details and are not ranked.
+    pub fn for_service(
+        list_id: &str,
+        saved_titles: HashMap<String, String>,
+        ids: &HashSet<String>,
+        remove_only: bool,
+    ) -> Self {
+        let to_add: Vec<String> = if remove_only {
+            Vec::new()
+        } else {
+            ids.iter()
+                .filter(|id| !saved_titles.contains_key(*id))
+                .cloned()
+                .collect()
+        };
+        let to_remove: Vec<String> = saved_titles
+            .keys()
+            .filter(|id| !ids.contains(*id))
+            .cloned()
+            .collect();
+        Self {
+            list_id: list_id.to_string(),
+            total: saved_titles.len() + to_add.len() - to_remove.len(),
+            to_add,
+            to_remove,
+            titles: saved_titles,
+            ..Self::default()
+        }
+    }
+
+    /// Plan the update of a list with the given details and entries, see [`SyncPlan::new`].
+    pub fn from_entries(
+        list_id: &str,
+        details: ListDetails,
+        saved_entries: &[SavedEntry],
+        ids: &HashSet<String>,
+        order: Option<&Ranking>,
+        remove_only: bool,
+    ) -> Self {
+        let saved_film_ids: HashSet<String> = saved_entries
+            .iter()
+            .map(|entry| entry.film_id.clone())
+            .collect();
+
+        // Never add anything in remove-only mode.
+        let kept_ids: HashSet<String>;
+        let ids = if remove_only {
+            kept_ids = ids.intersection(&saved_film_ids).cloned().collect();
+            &kept_ids
+        } else {
+            ids
+        };
+
+        // Get disjunction of films to save and films to remove.
+        let to_add: Vec<String> = ids.difference(&saved_film_ids).cloned().collect();
+        let to_remove: Vec<String> = saved_film_ids.difference(ids).cloned().collect();
+
+        // Rank all films if the current order differs.
+        let saved_order = || {
+            saved_entries
+                .iter()
+                .map(|entry| &entry.film_id)
+                .filter(|id| ids.contains(*id))
+        };
+        let ranked: Option<Vec<String>> = order
+            .map(|order| match order {
+                Ranking::Sorted(order) => order
+                    .iter()
+                    .filter(|id| ids.contains(*id))
+                    .cloned()
+                    .collect(),
+                Ranking::Appended(order) => {
+                    let added = order
+                        .iter()
+                        .filter(|id| ids.contains(*id) && !saved_film_ids.contains(*id));
+                    saved_order().chain(added).cloned().collect()
+                }
+            })
+            .filter(|ranked: &Vec<String>| !ranked.iter().eq(saved_order()));
+
+        Self {
+            target: Target::List,
+            list_id: list_id.to_string(),
+            to_add,
+            to_remove,
+            ranked,
+            notes: saved_entries
+                .iter()
+                .filter_map(|entry| Some((entry.film_id.clone(), entry.notes.clone()?)))
+                .collect(),
+            titles: saved_entries
+                .iter()
+                .map(|entry| (entry.film_id.clone(), entry.title.clone()))
+                .collect(),
+            total: ids.len(),
+            saved_details: details.clone(),
+            details,
+            saved: Some(
+                saved_entries
+                    .iter()
+                    .map(|entry| entry.film_id.clone())
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Name, description and visibility of the list before the sync.
+    pub fn saved_details(&self) -> &ListDetails {
+        &self.saved_details
+    }
+
+    /// Title of the film with the given id, or the id if the title is unknown.
+    pub fn title<'a>(&'a self, id: &'a str) -> &'a str {
+        self.titles.get(id).map_or(id, String::as_str)
+    }
+
+    /// Titles of the given films, sorted.
+    fn sorted_titles<'a>(&'a self, ids: &'a [String]) -> Vec<&'a str> {
+        let mut titles: Vec<&str> = ids.iter().map(|id| self.title(id)).collect();
+        titles.sort_unstable();
+        titles
+    }
+
+    /// Split the plan into plans adding and removing at most `size` films each, which are applied
+    /// in turn. Removals come first. If the entries are ranked anew, a last plan ranks them once
+    /// all films are on the list.
+    pub fn chunks(&self, size: usize) -> Vec<SyncPlan> {
+        let size = size.max(1);
+        let mut total = (self.total + self.to_remove.len()).saturating_sub(self.to_add.len());
+        let removals = self
+            .to_remove
+            .chunks(size)
+            .map(|ids| (Vec::new(), ids.to_vec()));
+        let additions = self
+            .to_add
+            .chunks(size)
+            .map(|ids| (ids.to_vec(), Vec::new()));
+        let mut chunks: Vec<SyncPlan> = removals
+            .chain(additions)
+            .map(|(to_add, to_remove)| {
+                total = (total + to_add.len()).saturating_sub(to_remove.len());
+                SyncPlan {
+                    to_add,
+                    to_remove,
+                    ranked: None,
+                    total,
+                    ..self.clone()
+                }
+            })
+            .collect();
+        if self.ranked.is_some() {
+            chunks.push(SyncPlan {
+                to_add: Vec::new(),
+                to_remove: Vec::new(),
+                ..self.clone()
+            });
+        }
+        chunks
+    }
+
+    /// Keep the films which are to be removed on the list instead.
+    pub fn skip_removals(&mut self) {
+        self.total += self.to_remove.len();
+        self.to_remove.clear();
+    }
+
+    /// Drop all changes of the plan, like when the update of the list was declined.
+    pub fn discard(&mut self) {
+        self.skip_removals();
+        self.total = self.total.saturating_sub(self.to_add.len());
+        self.to_add.clear();
+        self.ranked = None;
+        self.details = self.saved_details.clone();
+    }
+
+    /// Whether the list is up to date already.
+    pub fn is_empty(&self) -> bool {
+        self.to_add.is_empty()
+            && self.to_remove.is_empty()
+            && self.ranked.is_none()
+            && self.details == self.saved_details
+    }
+
+    /// Request updating the list according to this plan.
+    pub fn request(&self) -> letterboxd::ListUpdateRequest {
+        let entry = |id: &String| letterboxd::ListUpdateEntry {
+            notes: self.notes.get(id).cloned(),
+            ..letterboxd::ListUpdateEntry::new(id.clone())
+        };
+        let entries = match &self.ranked {
+            Some(ranked) => ranked
+                .iter()
+                .enumerate()
+                .map(|(idx, id)| letterboxd::ListUpdateEntry {
+                    rank: Some(idx + 1),
+                    ..entry(id)
+                })
+                .collect(),
+            None => self.to_add.iter().map(entry).collect(),
+        };
+        // The update replaces the details of the list, so the current ones are sent along.
+        letterboxd::ListUpdateRequest {
+            published: Some(self.details.published),
+            ranked: self.ranked.as_ref().map(|_| true),
+            description: self.details.description.clone(),
+            tags: Some(self.details.tags.clone()),
+            entries,
+            films_to_remove: self.to_remove.clone(),
+            ..letterboxd::ListUpdateRequest::new(self.details.name.clone())
+        }
+    }
+}
+
+/// Save plans as JSON to a plan file, which can be reviewed and edited before applying it.
+pub fn save_plans(plans: &[SyncPlan], path: &Path) -> anyhow::Result<()> {
+    let file = fs::File::create(path)
+        .with_context(|| format!("failed to create plan file: {}", path.display()))?;
+    serde_json::to_writer_pretty(io::BufWriter::new(file), plans)
+        .with_context(|| format!("failed to write plan file: {}", path.display()))
+}
+
+/// Load the plans of a plan file written by [`save_plans`].
+pub fn load_plans(path: &Path) -> anyhow::Result<Vec<SyncPlan>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read plan file: {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("failed to parse plan file: {}", path.display()))
+}
+
+/// Changes of a plan which are missing from the list after applying it, see
+/// [`SyncExecutor::verify`].
+#[derive(Debug, Default)]
+pub struct Unapplied {
+    /// Films to add which are not on the list.
+    pub missing: Vec<String>,
+    /// Films to remove which are still on the list.
+    pub not_removed: Vec<String>,
+}
+
+impl Unapplied {
+    /// Number of changes which were not applied.
+    pub fn len(&self) -> usize {
+        self.missing.len() + self.not_removed.len()
+    }
+
+    /// Whether all changes were applied.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Applies sync plans to the lists on Letterboxd.
+pub struct SyncExecutor<'a> {
+    client: &'a letterboxd::Client,
+    dry_run: bool,
+    chunk_size: usize,
+    confirm: Option<fn(&SyncPlan) -> anyhow::Result<bool>>,
+    snapshots: Option<PathBuf>,
+}
+
+impl<'a> SyncExecutor<'a> {
+    /// Create an executor. In `dry_run` mode, plans are logged but lists are not updated.
+    pub fn new(client: &'a letterboxd::Client, dry_run: bool) -> Self {
+        Self {
+            client,
+            dry_run,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            confirm: None,
+            snapshots: None,
+        }
+    }
+
+    /// Save a snapshot of each list to the directory before updating it, see
+    /// [`crate::snapshot`].
+    pub fn with_snapshots(self, dir: PathBuf) -> Self {
+        Self {
+            snapshots: Some(dir),
+            ..self
+        }
+    }
+
+    /// Update a list only if `confirm` accepts the logged changes of its plan.
+    pub fn with_confirmation(self, confirm: fn(&SyncPlan) -> anyhow::Result<bool>) -> Self {
+        Self {
+            confirm: Some(confirm),
+            ..self
+        }
+    }
+
+    /// Add and remove at most `chunk_size` films with one update of a list, see
+    /// [`SyncPlan::chunks`].
+    pub fn with_chunk_size(self, chunk_size: usize) -> Self {
+        Self { chunk_size, ..self }
+    }
+
+    /// Update the list of the plan. Returns whether the list was updated, which it is not if it
+    /// is up to date already, in `dry_run` mode or if the update was not confirmed.
+    pub async fn execute(&self, plan: &SyncPlan) -> anyhow::Result<bool> {
+        if plan.is_empty() {
+            info!("{}", Message::ListUpToDate(&plan.list_id));
+            return Ok(false);
+        }
+
+        info!(
+            "{}",
+            Message::UpdatingList {
+                list_id: &plan.list_id,
+                to_add: plan.to_add.len(),
+                to_remove: plan.to_remove.len(),
+                reordered: plan.ranked.is_some(),
+                total: plan.total,
+            }
+        );
+        for title in plan.sorted_titles(&plan.to_add) {
+            info!("  + {}", title);
+        }
+        for title in plan.sorted_titles(&plan.to_remove) {
+            info!("  - {}", title);
+        }
+        if self.dry_run {
+            info!("{}", Message::DryRun);
+            return Ok(false);
+        }
+        if let Some(confirm) = self.confirm {
+            if !confirm(plan)? {
+                info!("{}", Message::UpdateDeclined(&plan.list_id));
+                return Ok(false);
+            }
+        }
+        if let (Some(dir), Some(snapshot)) = (&self.snapshots, ListSnapshot::of_plan(plan)) {
+            add_snapshot(dir, snapshot).context("failed to save a snapshot of the list")?;
+        }
+        match plan.target {
+            Target::List if plan.to_add.len() + plan.to_remove.len() > self.chunk_size => {
+                self.update_list_in_chunks(plan).await?
+            }
+            Target::List => {
+                let request = plan.request();
+                with_retry(|| self.client.update_list(&plan.list_id, &request)).await?;
+            }
+            Target::Watchlist => self.update_watchlist(plan).await?,
+        }
+        Ok(true)
+    }
+
+    /// Fetch the films of the list of the applied plan again, and return the changes of the plan
+    /// the list does not show, like films the API silently dropped.
+    pub async fn verify(&self, plan: &SyncPlan) -> anyhow::Result<Unapplied> {
+        info!("{}", Message::VerifyingList(&plan.list_id));
+        let ids: HashSet<String> = match plan.target {
+            Target::List => {
+                film_id_set_from_response(&fetch_list_entries(&plan.list_id, self.client).await?)
+            }
+            Target::Watchlist => fetch_watchlist(self.client)
+                .await?
+                .into_iter()
+                .map(|film| film.id)
+                .collect(),
+        };
+        Ok(Unapplied {
+            missing: plan
+                .to_add
+                .iter()
+                .filter(|id| !ids.contains(*id))
+                .cloned()
+                .collect(),
+            not_removed: plan
+                .to_remove
+                .iter()
+                .filter(|id| ids.contains(*id))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Update the list in chunks, checking the number of films on the list after each chunk and
+    /// sending a chunk again if films are missing.
+    async fn update_list_in_chunks(&self, plan: &SyncPlan) -> anyhow::Result<()> {
+        let chunks = plan.chunks(self.chunk_size);
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let request = chunk.request();
+            let mut attempt = 1;
+            loop {
+                let response = with_retry(|| self.client.update_list(&plan.list_id, &request))
+                    .await
+                    .with_context(|| {
+                        format!("failed to apply update {} of {}", idx + 1, chunks.len())
+                    })?;
+                if response.data.film_count == chunk.total {
+                    break;
+                }
+                if attempt == CHUNK_ATTEMPTS {
+                    return Err(anyhow!(
+                        "update {} of {} was not applied: the list has {} films instead of {}",
+                        idx + 1,
+                        chunks.len(),
+                        response.data.film_count,
+                        chunk.total
+                    ));
+                }
+                warn!(
+                    "list has {} films instead of {} after update {} of {}, sending it again",
+                    response.data.film_count,
+                    chunk.total,
+                    idx + 1,
+                    chunks.len()
+                );
+                attempt += 1;
+            }
+            info!(
+                "{}",
+                Message::AppliedChunk {
+                    done: idx + 1,
+                    total: chunks.len(),
+                }
+            );
+        }
+        Ok(())
+    }
+
+    /// Add and remove the films of the plan to and from the watchlist, one film at a time.
+    async fn update_watchlist(&self, plan: &SyncPlan) -> letterboxd::Result<()> {
+        let changes = plan
+            .to_add
+            .iter()
+            .map(|id| (id, true))
+            .chain(plan.to_remove.iter().map(|id| (id, false)));
+        let requests = changes.map(|(id, in_watchlist)| async move {
+            let request = letterboxd::FilmRelationshipUpdateRequest {
+                in_watchlist: Some(in_watchlist),
+                ..Default::default()
+            };
+            with_retry(|| self.client.update_film_relationship(id, &request)).await
+        });
+        stream::iter(requests)
+            .buffer_unordered(max_concurrency())
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn service_plan_adds_and_removes_films() {
+        let saved = HashMap::from([
+            ("a".to_string(), "A (2001)".to_string()),
+            ("b".to_string(), "B (2002)".to_string()),
+        ]);
+        let wanted: HashSet<String> = ids(&["b", "c"]).into_iter().collect();
+
+        let plan = SyncPlan::for_service("list", saved.clone(), &wanted, false);
+        assert_eq!(plan.to_add, ids(&["c"]));
+        assert_eq!(plan.to_remove, ids(&["a"]));
+        assert_eq!(plan.total, 2);
+        assert_eq!(plan.title("a"), "A (2001)");
+
+        let plan = SyncPlan::for_service("list", saved, &wanted, true);
+        assert!(plan.to_add.is_empty());
+        assert_eq!(plan.to_remove, ids(&["a"]));
+        assert_eq!(plan.total, 1);
+    }
+
+    #[test]
+    fn chunks_remove_first_and_count_the_films() {
+        // The list has 10 films before the sync.
+        let plan = SyncPlan {
+            list_id: "list".to_string(),
+            to_add: ids(&["a", "b", "c"]),
+            to_remove: ids(&["x", "y"]),
+            total: 11,
+            ..SyncPlan::default()
+        };
+        let chunks: Vec<_> = plan
+            .chunks(2)
+            .into_iter()
+            .map(|chunk| (chunk.to_add, chunk.to_remove, chunk.total))
+            .collect();
+        assert_eq!(
+            chunks,
+            [
+                (ids(&[]), ids(&["x", "y"]), 8),
+                (ids(&["a", "b"]), ids(&[]), 10),
+                (ids(&["c"]), ids(&[]), 11),
+            ]
+        );
+    }
+
+    #[test]
+    fn ranked_plan_is_ranked_by_the_last_chunk() {
+        let plan = SyncPlan {
+            to_add: ids(&["a", "b"]),
+            ranked: Some(ids(&["b", "a"])),
+            total: 2,
+            ..SyncPlan::default()
+        };
+        let chunks = plan.chunks(1);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[..2].iter().all(|chunk| chunk.ranked.is_none()));
+        let last = &chunks[2];
+        assert!(last.to_add.is_empty() && last.to_remove.is_empty());
+        assert_eq!(last.ranked, plan.ranked);
+        assert_eq!(last.total, 2);
+    }
+
+    #[test]
+    fn chunks_hold_at_least_one_film() {
+        let plan = SyncPlan {
+            to_add: ids(&["a", "b"]),
+            total: 2,
+            ..SyncPlan::default()
+        };
+        assert_eq!(plan.chunks(0).len(), 2);
+    }
+}
@@ -0,0 +1,100 @@
+//! Adaptive limit of the concurrent Letterboxd requests.
+//!
+//! The limit starts at the configured concurrency. It is halved whenever a request is rate
+//! limited, fails on the server or is slow, and grows by one again after as many fast requests as
+//! the limit allows at once, up to the configured concurrency.
+
+use log::debug;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::REQUESTS_CONCURRENCY;
+
+/// Responses taking longer count as a sign of an overloaded API or network.
+const SLOW_RESPONSE: Duration = Duration::from_secs(5);
+
+static THROTTLE: OnceLock<Throttle> = OnceLock::new();
+
+pub struct Throttle {
+    semaphore: Semaphore,
+    max: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    limit: usize,
+    /// Fast requests since the last change of the limit.
+    successes: usize,
+    /// Permits to forget when they are released, to lower the limit.
+    debt: usize,
+}
+
+impl Throttle {
+    fn new(max: usize) -> Self {
+        Self {
+            semaphore: Semaphore::new(max),
+            max,
+            state: Mutex::new(State {
+                limit: max,
+                ..State::default()
+            }),
+        }
+    }
+
+    /// Wait until another request may be sent.
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.semaphore
+            .acquire()
+            .await
+            .expect("semaphore is never closed")
+    }
+
+    /// Release the permit of a finished request, lowering the limit if the request was
+    /// `congested`, i.e. rate limited or failed on the server, or took longer than
+    /// [`SLOW_RESPONSE`].
+    pub fn release(&self, permit: SemaphorePermit<'_>, congested: bool, elapsed: Duration) {
+        let mut state = self.state.lock().expect("throttle state is not poisoned");
+        if congested || elapsed > SLOW_RESPONSE {
+            let limit = (state.limit / 2).max(1);
+            if limit < state.limit {
+                debug!("Reducing concurrent requests to {}", limit);
+                state.debt += state.limit - limit;
+                state.limit = limit;
+            }
+            state.successes = 0;
+        } else {
+            state.successes += 1;
+            if state.successes >= state.limit && state.limit < self.max {
+                state.limit += 1;
+                state.successes = 0;
+                match state.debt {
+                    0 => self.semaphore.add_permits(1),
+                    _ => state.debt -= 1,
+                }
+                debug!("Raising concurrent requests to {}", state.limit);
+            }
+        }
+        if state.debt > 0 {
+            state.debt -= 1;
+            permit.forget();
+        }
+    }
+}
+
+/// Set the maximum number of concurrent requests. Only the first call has an effect.
+pub fn set_concurrency(max: usize) {
+    let _ = THROTTLE.set(Throttle::new(max.max(1)));
+}
+
+/// The throttle of all requests.
+pub fn throttle() -> &'static Throttle {
+    THROTTLE.get_or_init(|| Throttle::new(REQUESTS_CONCURRENCY))
+}
+
+/// Maximum number of concurrent requests.
+pub fn max_concurrency() -> usize {
+    throttle().max
+}
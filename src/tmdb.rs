@@ -0,0 +1,75 @@
+//! Search of The Movie Database, whose search is more forgiving of foreign titles and typos than
+//! the one of Letterboxd. Letterboxd looks films up by their TMDb id, so the movies found on TMDb
+//! resolve to films exactly.
+
+use anyhow::Context as _;
+use serde::Deserialize;
+
+use std::sync::OnceLock;
+
+use crate::resolve::MovieGuess;
+
+const SEARCH_URL: &str = "https://api.themoviedb.org/3/search/movie";
+
+/// A movie found on TMDb.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TmdbMovie {
+    pub id: u64,
+    pub title: String,
+    pub original_title: Option<String>,
+    /// Date of the first release, like `1999-03-30`, or empty if unknown.
+    #[serde(default)]
+    release_date: String,
+}
+
+impl TmdbMovie {
+    pub fn year(&self) -> Option<u16> {
+        self.release_date.get(..4)?.parse().ok()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    results: Vec<TmdbMovie>,
+}
+
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+async fn search(api_key: &str, title: &str, year: Option<u16>) -> anyhow::Result<Vec<TmdbMovie>> {
+    let mut query = vec![
+        ("api_key", api_key.to_string()),
+        ("query", title.to_string()),
+    ];
+    if let Some(year) = year {
+        query.push(("year", year.to_string()));
+    }
+    let body = client()
+        .get(SEARCH_URL)
+        .query(&query)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let response: SearchResponse =
+        serde_json::from_str(&body).context("invalid TMDb search response")?;
+    Ok(response.results)
+}
+
+/// Search TMDb for the movie, returning the most relevant result.
+///
+/// Movies with a year are searched in this year first, and then in any year, since the year of a
+/// release may differ from the year of the first release known to TMDb.
+pub async fn search_tmdb(api_key: &str, movie: &MovieGuess) -> anyhow::Result<Option<TmdbMovie>> {
+    if movie.year.is_some() {
+        let results = search(api_key, &movie.title, movie.year).await?;
+        if let Some(found) = results.into_iter().next() {
+            return Ok(Some(found));
+        }
+    }
+    let results = search(api_key, &movie.title, None).await?;
+    Ok(results.into_iter().next())
+}
@@ -0,0 +1,210 @@
+//! Lists and the collection of a member of Trakt, synced instead of Letterboxd lists with
+//! `--backend trakt`.
+//!
+//! Films are identified by their TMDb ids, which Trakt accepts when adding films. Requests are
+//! authorized by the id of a Trakt API app and an OAuth access token of the member, read from
+//! `TRAKT_CLIENT_ID` and `TRAKT_ACCESS_TOKEN`.
+
+use anyhow::{anyhow, Context as _};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::env;
+
+use crate::backend::ListService;
+use crate::resolve::MovieGuess;
+
+const API_URL: &str = "https://api.trakt.tv";
+
+/// List id of the collection of the member, instead of one of their lists.
+pub const COLLECTION: &str = "collection";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TraktIds {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tmdb: Option<u64>,
+    #[serde(skip_serializing)]
+    imdb: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TraktMovie {
+    title: String,
+    year: Option<u16>,
+    ids: TraktIds,
+}
+
+impl TraktMovie {
+    fn title(&self) -> String {
+        match self.year {
+            Some(year) => format!("{} ({})", self.title, year),
+            None => self.title.clone(),
+        }
+    }
+}
+
+/// Search result, list item or collected movie.
+#[derive(Debug, Deserialize)]
+struct TraktItem {
+    movie: Option<TraktMovie>,
+}
+
+#[derive(Debug, Serialize)]
+struct MovieRef {
+    ids: TraktIds,
+}
+
+#[derive(Debug, Serialize)]
+struct MoviesRequest {
+    movies: Vec<MovieRef>,
+}
+
+/// Client of the Trakt API.
+pub struct TraktClient {
+    http: reqwest::Client,
+    client_id: String,
+    access_token: String,
+}
+
+impl TraktClient {
+    /// Client authorized by `TRAKT_CLIENT_ID` and `TRAKT_ACCESS_TOKEN`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let var = |name: &str| env::var(name).map_err(|_| anyhow!("{} is not set", name));
+        Ok(Self {
+            http: reqwest::Client::new(),
+            client_id: var("TRAKT_CLIENT_ID")?,
+            access_token: var("TRAKT_ACCESS_TOKEN")?,
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, format!("{}{}", API_URL, path))
+            .header("trakt-api-version", "2")
+            .header("trakt-api-key", &self.client_id)
+            .bearer_auth(&self.access_token)
+    }
+
+    async fn get_items(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> anyhow::Result<Vec<TraktItem>> {
+        let body = self
+            .request(reqwest::Method::GET, path)
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        serde_json::from_str(&body).with_context(|| format!("invalid Trakt response of {}", path))
+    }
+
+    async fn post_movies(&self, path: &str, ids: &[String]) -> anyhow::Result<()> {
+        let movies = ids
+            .iter()
+            .map(|id| {
+                let tmdb = id.parse().map_err(|_| anyhow!("invalid TMDb id: {}", id))?;
+                Ok(MovieRef {
+                    ids: TraktIds {
+                        tmdb: Some(tmdb),
+                        imdb: None,
+                    },
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        self.request(reqwest::Method::POST, path)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&MoviesRequest { movies })?)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn items_path(list_id: &str) -> String {
+        match list_id {
+            COLLECTION => "/sync/collection".to_string(),
+            list_id => format!("/users/me/lists/{}/items", list_id),
+        }
+    }
+}
+
+impl ListService for TraktClient {
+    fn name(&self) -> &'static str {
+        "Trakt"
+    }
+
+    /// The movie by its TMDb id, by its IMDb id, or else the first result of searching its title
+    /// and year.
+    async fn resolve(&self, movie: &MovieGuess) -> anyhow::Result<Option<String>> {
+        if let Some(tmdb_id) = &movie.tmdb_id {
+            return Ok(Some(tmdb_id.clone()));
+        }
+        let results = match &movie.imdb_id {
+            Some(imdb_id) => {
+                let path = format!("/search/imdb/{}", imdb_id);
+                self.get_items(&path, &[("type", "movie".to_string())])
+                    .await?
+            }
+            None => {
+                let mut query = vec![("query", movie.title.clone())];
+                if let Some(year) = movie.year {
+                    query.push(("years", year.to_string()));
+                }
+                self.get_items("/search/movie", &query).await?
+            }
+        };
+        let found = results.into_iter().find_map(|item| item.movie);
+        if let Some(found) = &found {
+            debug!("Found {} on Trakt for {}", found.title(), movie);
+        }
+        Ok(found
+            .and_then(|found| found.ids.tmdb)
+            .map(|id| id.to_string()))
+    }
+
+    async fn list_films(&self, list_id: &str) -> anyhow::Result<HashMap<String, String>> {
+        let path = match list_id {
+            COLLECTION => "/sync/collection/movies".to_string(),
+            list_id => format!("/users/me/lists/{}/items/movie", list_id),
+        };
+        let items = self.get_items(&path, &[]).await?;
+        Ok(items
+            .into_iter()
+            .filter_map(|item| {
+                let movie = item.movie?;
+                if movie.ids.tmdb.is_none() {
+                    debug!(
+                        "Ignoring {} without TMDb id ({:?})",
+                        movie.title(),
+                        movie.ids.imdb
+                    );
+                }
+                Some((movie.ids.tmdb?.to_string(), movie.title()))
+            })
+            .collect())
+    }
+
+    async fn update_list(
+        &self,
+        list_id: &str,
+        to_add: &[String],
+        to_remove: &[String],
+    ) -> anyhow::Result<()> {
+        let path = Self::items_path(list_id);
+        if !to_add.is_empty() {
+            self.post_movies(&path, to_add)
+                .await
+                .context("failed to add films")?;
+        }
+        if !to_remove.is_empty() {
+            self.post_movies(&format!("{}/remove", path), to_remove)
+                .await
+                .context("failed to remove films")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+//! Watching local movie directories for added, renamed and deleted files.
+
+use anyhow::Context as _;
+use log::warn;
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use tokio::sync::mpsc;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Reports the paths changed in a set of directories.
+///
+/// Changes of a path are reported once it did not change for the debounce timeout, so copying a
+/// large movie file results in a single change.
+pub struct DirectoryWatcher {
+    debouncer: Debouncer<RecommendedWatcher>,
+    changes: mpsc::UnboundedReceiver<PathBuf>,
+}
+
+impl DirectoryWatcher {
+    /// Create a watcher reporting changes after `debounce` without further changes.
+    pub fn new(debounce: Duration) -> anyhow::Result<Self> {
+        let (sender, changes) = mpsc::unbounded_channel();
+        let debouncer = new_debouncer(debounce, move |result: DebounceEventResult| match result {
+            Ok(events) => {
+                for event in events {
+                    let _ = sender.send(event.path);
+                }
+            }
+            Err(err) => warn!("failed to watch for changes: {}", err),
+        })
+        .context("failed to create file watcher")?;
+        Ok(Self { debouncer, changes })
+    }
+
+    /// Watch the directory, including its subdirectories if `recursive` is set.
+    pub fn watch(&mut self, directory: &Path, recursive: bool) -> anyhow::Result<()> {
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        self.debouncer
+            .watcher()
+            .watch(directory, mode)
+            .with_context(|| format!("failed to watch {}", directory.display()))
+    }
+
+    /// Wait for the next changed path.
+    pub async fn changed(&mut self) -> Option<PathBuf> {
+        self.changes.recv().await
+    }
+
+    /// Take the paths changed so far without waiting.
+    pub fn drain(&mut self) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        while let Ok(path) = self.changes.try_recv() {
+            paths.push(path);
+        }
+        paths
+    }
+}
@@ -0,0 +1,551 @@
+//! Webhooks of Radarr, Plex and Jellyfin announcing added and deleted movies, received by `serve`.
+//!
+//! Radarr sends its events as JSON, Plex as the `payload` field of a multipart form and the
+//! webhook plugin of Jellyfin as JSON with the fields of its default template. Events of other
+//! items than movies, like episodes or playback, are ignored.
+
+use anyhow::{anyhow, Context as _};
+use hyper::body::HttpBody as _;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, StatusCode};
+use log::{debug, warn};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::Mapping;
+use crate::resolve::MovieGuess;
+use crate::scan::Source;
+
+/// Largest request accepted, Plex sends the poster of new movies along.
+const MAX_BODY_LEN: u64 = 16 * 1024 * 1024;
+
+/// Sender of a webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Radarr,
+    Plex,
+    Jellyfin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Added,
+    Deleted,
+    Renamed,
+}
+
+/// A movie added to, deleted from or renamed in a library.
+#[derive(Debug, Clone)]
+pub struct WebhookEvent {
+    pub origin: Origin,
+    pub kind: EventKind,
+    pub movie: MovieGuess,
+    /// Folder or file of the movie, if the sender knows it.
+    pub path: Option<PathBuf>,
+}
+
+impl WebhookEvent {
+    /// Whether the movie may belong to the directory of `mapping`. Movies without a path belong to
+    /// the libraries of their server, and Radarr's movies to all local directories containing
+    /// their path.
+    pub fn affects(&self, mapping: &Mapping) -> bool {
+        match (&mapping.directory, &self.path) {
+            (Source::Local(directory), Some(path)) => path.starts_with(directory),
+            (Source::Plex(_), None) => self.origin == Origin::Plex,
+            (Source::Jellyfin(_), None) => self.origin == Origin::Jellyfin,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RadarrPayload {
+    event_type: String,
+    movie: Option<RadarrMovie>,
+    movie_file: Option<RadarrMovieFile>,
+    /// Why a movie file was deleted, like `upgrade` or `manual`.
+    delete_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RadarrMovie {
+    title: String,
+    year: Option<u16>,
+    imdb_id: Option<String>,
+    tmdb_id: Option<u64>,
+    folder_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RadarrMovieFile {
+    path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlexPayload {
+    event: String,
+    #[serde(rename = "Metadata")]
+    metadata: Option<PlexMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlexMetadata {
+    #[serde(rename = "type")]
+    type_: String,
+    title: String,
+    year: Option<u16>,
+    #[serde(rename = "Guid", default)]
+    guids: Vec<PlexGuid>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlexGuid {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JellyfinPayload {
+    #[serde(rename = "NotificationType")]
+    notification_type: String,
+    #[serde(rename = "ItemType")]
+    item_type: Option<String>,
+    #[serde(rename = "Name")]
+    name: Option<String>,
+    #[serde(rename = "Year")]
+    year: Option<u16>,
+    #[serde(rename = "Provider_imdb")]
+    imdb_id: Option<String>,
+    #[serde(rename = "Provider_tmdb")]
+    tmdb_id: Option<String>,
+}
+
+fn radarr_event(payload: RadarrPayload) -> Option<WebhookEvent> {
+    let kind = match payload.event_type.as_str() {
+        "Download" => EventKind::Added,
+        "MovieDelete" => EventKind::Deleted,
+        // The file replaced by an upgrade is followed by a download of the new one.
+        "MovieFileDelete" if payload.delete_reason.as_deref() != Some("upgrade") => {
+            EventKind::Deleted
+        }
+        "Rename" => EventKind::Renamed,
+        _ => return None,
+    };
+    let movie = payload.movie?;
+    let path = payload
+        .movie_file
+        .and_then(|file| file.path)
+        .or(movie.folder_path);
+    let mut guess = MovieGuess::new(movie.title, movie.year);
+    guess.imdb_id = movie.imdb_id.filter(|id| id.starts_with("tt"));
+    guess.tmdb_id = movie.tmdb_id.filter(|id| *id > 0).map(|id| id.to_string());
+    Some(WebhookEvent {
+        origin: Origin::Radarr,
+        kind,
+        movie: guess,
+        path: path.map(PathBuf::from),
+    })
+}
+
+fn plex_event(payload: PlexPayload) -> Option<WebhookEvent> {
+    if payload.event != "library.new" {
+        return None;
+    }
+    let metadata = payload
+        .metadata
+        .filter(|metadata| metadata.type_ == "movie")?;
+    let mut movie = MovieGuess::new(metadata.title, metadata.year);
+    for guid in &metadata.guids {
+        match guid.id.split_once("://") {
+            Some(("imdb", id)) => movie.imdb_id = Some(id.to_string()),
+            Some(("tmdb", id)) => movie.tmdb_id = Some(id.to_string()),
+            _ => {}
+        }
+    }
+    Some(WebhookEvent {
+        origin: Origin::Plex,
+        kind: EventKind::Added,
+        movie,
+        path: None,
+    })
+}
+
+fn jellyfin_event(payload: JellyfinPayload) -> Option<WebhookEvent> {
+    let kind = match payload.notification_type.as_str() {
+        "ItemAdded" => EventKind::Added,
+        "ItemDeleted" => EventKind::Deleted,
+        _ => return None,
+    };
+    if payload.item_type.as_deref() != Some("Movie") {
+        return None;
+    }
+    let mut movie = MovieGuess::new(payload.name?, payload.year);
+    movie.imdb_id = payload.imdb_id.filter(|id| id.starts_with("tt"));
+    movie.tmdb_id = payload.tmdb_id.filter(|id| !id.is_empty());
+    Some(WebhookEvent {
+        origin: Origin::Jellyfin,
+        kind,
+        movie,
+        path: None,
+    })
+}
+
+/// The content of the form field `name` of a `multipart/form-data` body.
+fn multipart_field<'a>(content_type: &str, body: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let boundary = content_type
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("boundary="))?
+        .trim_matches('"');
+    let delimiter = format!("--{}", boundary);
+    let disposition = format!("name=\"{}\"", name);
+    let mut rest = body;
+    while let Some(start) = find(rest, delimiter.as_bytes()) {
+        rest = &rest[start + delimiter.len()..];
+        let headers_end = find(rest, b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&rest[..headers_end]);
+        let content = &rest[headers_end + 4..];
+        let end = find(content, delimiter.as_bytes()).unwrap_or(content.len());
+        if headers.contains(&disposition) {
+            let content = &content[..end];
+            return Some(content.strip_suffix(b"\r\n").unwrap_or(content));
+        }
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Parse the webhook with the given content type and body, returning `None` for events which do
+/// not add or delete movies.
+pub fn parse_webhook(content_type: &str, body: &[u8]) -> anyhow::Result<Option<WebhookEvent>> {
+    if content_type.starts_with("multipart/form-data") {
+        let payload = multipart_field(content_type, body, "payload")
+            .ok_or_else(|| anyhow!("form without payload"))?;
+        let payload: PlexPayload =
+            serde_json::from_slice(payload).context("invalid Plex webhook")?;
+        return Ok(plex_event(payload));
+    }
+    let value: serde_json::Value = serde_json::from_slice(body).context("invalid JSON")?;
+    if value.get("eventType").is_some() {
+        let payload = serde_json::from_value(value).context("invalid Radarr webhook")?;
+        Ok(radarr_event(payload))
+    } else if value.get("NotificationType").is_some() {
+        let payload = serde_json::from_value(value).context("invalid Jellyfin webhook")?;
+        Ok(jellyfin_event(payload))
+    } else if value.get("event").is_some() {
+        let payload = serde_json::from_value(value).context("invalid Plex webhook")?;
+        Ok(plex_event(payload))
+    } else {
+        Err(anyhow!("unknown webhook"))
+    }
+}
+
+/// Whether the token given with a request is `token`. The hashes of the tokens are compared in
+/// full, so that the time taken tells nothing about how much of the token was guessed.
+fn is_token(given: &str, token: &str) -> bool {
+    let (given, token) = (
+        Sha256::digest(given.as_bytes()),
+        Sha256::digest(token.as_bytes()),
+    );
+    given
+        .iter()
+        .zip(token.iter())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Read the body of a request, which is `None` once it is larger than [`MAX_BODY_LEN`], also when
+/// it is sent in chunks without a length.
+async fn read_body(mut body: Body) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if (bytes.len() + chunk.len()) as u64 > MAX_BODY_LEN {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    token: &Option<String>,
+    events: &mpsc::UnboundedSender<WebhookEvent>,
+) -> anyhow::Result<StatusCode> {
+    if let Some(token) = token {
+        let given = request.uri().query().and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("token="))
+                .map(|value| percent_decode_str(value).decode_utf8_lossy())
+        });
+        if !given.is_some_and(|given| is_token(&given, token)) {
+            return Ok(StatusCode::UNAUTHORIZED);
+        }
+    }
+    if request.method() != hyper::Method::POST {
+        return Ok(StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let content_type = request
+        .headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let len = request
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+    if len.is_some_and(|len| len > MAX_BODY_LEN) {
+        return Ok(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    let body = match read_body(request.into_body()).await? {
+        Some(body) => body,
+        None => return Ok(StatusCode::PAYLOAD_TOO_LARGE),
+    };
+    match parse_webhook(&content_type, &body)? {
+        Some(event) => {
+            debug!("Received webhook {:?}", event);
+            events.send(event).map_err(|_| anyhow!("sync stopped"))?;
+            Ok(StatusCode::ACCEPTED)
+        }
+        None => Ok(StatusCode::OK),
+    }
+}
+
+/// Accept webhooks on `addr`, sending the events of movies to `events`. With a `token`, only
+/// requests with this token as `token` query parameter are accepted.
+pub async fn serve_webhooks(
+    addr: SocketAddr,
+    token: Option<String>,
+    events: mpsc::UnboundedSender<WebhookEvent>,
+) -> anyhow::Result<()> {
+    let token = Arc::new(token);
+    let make_service = make_service_fn(move |_| {
+        let token = token.clone();
+        let events = events.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let token = token.clone();
+                let events = events.clone();
+                async move {
+                    let status = match handle_request(request, &token, &events).await {
+                        Ok(status) => status,
+                        Err(err) => {
+                            warn!("rejected webhook: {:#}", err);
+                            StatusCode::BAD_REQUEST
+                        }
+                    };
+                    let mut response = Response::new(Body::empty());
+                    *response.status_mut() = status;
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+    hyper::Server::try_bind(&addr)
+        .with_context(|| format!("failed to listen on {}", addr))?
+        .serve(make_service)
+        .await
+        .context("webhook server failed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADARR_DOWNLOAD: &str = r#"{
+        "eventType": "Download",
+        "movie": {
+            "id": 1,
+            "title": "Heat",
+            "year": 1995,
+            "imdbId": "tt0113277",
+            "tmdbId": 949,
+            "folderPath": "/movies/Heat (1995)"
+        },
+        "movieFile": {
+            "id": 2,
+            "relativePath": "Heat (1995).mkv",
+            "path": "/movies/Heat (1995)/Heat (1995).mkv"
+        },
+        "isUpgrade": false
+    }"#;
+
+    const PLEX_LIBRARY_NEW: &str = r#"{
+        "event": "library.new",
+        "user": true,
+        "owner": true,
+        "Metadata": {
+            "librarySectionType": "movie",
+            "type": "movie",
+            "title": "Heat",
+            "year": 1995,
+            "Guid": [{"id": "imdb://tt0113277"}, {"id": "tmdb://949"}]
+        }
+    }"#;
+
+    const JELLYFIN_ITEM_ADDED: &str = r#"{
+        "NotificationType": "ItemAdded",
+        "ServerName": "jellyfin",
+        "ItemType": "Movie",
+        "Name": "Heat",
+        "Year": 1995,
+        "Provider_imdb": "tt0113277",
+        "Provider_tmdb": "949"
+    }"#;
+
+    const BOUNDARY: &str = "------------------------d74496d66958873e";
+
+    /// A form as sent by Plex, with the payload and the poster of the movie.
+    fn plex_form(payload: &str) -> (String, Vec<u8>) {
+        let content_type = format!("multipart/form-data; boundary={}", BOUNDARY);
+        let mut body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"payload\"\r\n\
+             Content-Type: application/json\r\n\r\n{}\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"thumb\"; filename=\"poster.jpg\"\r\n\
+             Content-Type: image/jpeg\r\n\r\n",
+            payload,
+            b = BOUNDARY
+        )
+        .into_bytes();
+        body.extend_from_slice(&[0xff, 0xd8, 0xff, 0xe0]);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+        (content_type, body)
+    }
+
+    fn assert_heat(event: &WebhookEvent, origin: Origin, kind: EventKind) {
+        assert_eq!(event.origin, origin);
+        assert_eq!(event.kind, kind);
+        assert_eq!(event.movie.title, "Heat");
+        assert_eq!(event.movie.year, Some(1995));
+        assert_eq!(event.movie.imdb_id.as_deref(), Some("tt0113277"));
+        assert_eq!(event.movie.tmdb_id.as_deref(), Some("949"));
+    }
+
+    #[test]
+    fn radarr_download_adds_the_movie_file() {
+        let event = parse_webhook("application/json", RADARR_DOWNLOAD.as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_heat(&event, Origin::Radarr, EventKind::Added);
+        assert_eq!(
+            event.path,
+            Some(PathBuf::from("/movies/Heat (1995)/Heat (1995).mkv"))
+        );
+    }
+
+    #[test]
+    fn radarr_upgrades_and_tests_are_ignored() {
+        let upgrade = r#"{
+            "eventType": "MovieFileDelete",
+            "movie": {"title": "Heat", "year": 1995, "folderPath": "/movies/Heat (1995)"},
+            "deleteReason": "upgrade"
+        }"#;
+        assert!(parse_webhook("application/json", upgrade.as_bytes())
+            .unwrap()
+            .is_none());
+        let test = r#"{"eventType": "Test"}"#;
+        assert!(parse_webhook("application/json", test.as_bytes())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn plex_form_adds_the_movie() {
+        let (content_type, body) = plex_form(PLEX_LIBRARY_NEW);
+        let event = parse_webhook(&content_type, &body).unwrap().unwrap();
+        assert_heat(&event, Origin::Plex, EventKind::Added);
+        assert_eq!(event.path, None);
+    }
+
+    #[test]
+    fn plex_playback_is_ignored() {
+        let payload = r#"{"event": "media.play", "Metadata": {"type": "movie", "title": "Heat"}}"#;
+        let (content_type, body) = plex_form(payload);
+        assert!(parse_webhook(&content_type, &body).unwrap().is_none());
+    }
+
+    #[test]
+    fn jellyfin_item_added_adds_the_movie() {
+        let event = parse_webhook("application/json", JELLYFIN_ITEM_ADDED.as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_heat(&event, Origin::Jellyfin, EventKind::Added);
+    }
+
+    #[test]
+    fn jellyfin_episodes_are_ignored() {
+        let episode = JELLYFIN_ITEM_ADDED.replace("\"Movie\"", "\"Episode\"");
+        assert!(parse_webhook("application/json", episode.as_bytes())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn unknown_webhooks_are_errors() {
+        assert!(parse_webhook("application/json", b"{\"foo\": 1}").is_err());
+        assert!(parse_webhook("application/json", b"not json").is_err());
+        let content_type = format!("multipart/form-data; boundary={}", BOUNDARY);
+        let body = format!("--{b}\r\n\r\n--{b}--\r\n", b = BOUNDARY);
+        assert!(parse_webhook(&content_type, body.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn multipart_fields_are_found_by_name() {
+        let (content_type, body) = plex_form("{}");
+        assert_eq!(
+            multipart_field(&content_type, &body, "payload"),
+            Some(&b"{}"[..])
+        );
+        assert_eq!(
+            multipart_field(&content_type, &body, "thumb"),
+            Some(&[0xff, 0xd8, 0xff, 0xe0][..])
+        );
+        assert_eq!(multipart_field(&content_type, &body, "other"), None);
+        let quoted = format!("multipart/form-data; boundary=\"{}\"", BOUNDARY);
+        assert_eq!(multipart_field(&quoted, &body, "payload"), Some(&b"{}"[..]));
+        assert_eq!(
+            multipart_field("multipart/form-data", &body, "payload"),
+            None
+        );
+    }
+
+    #[test]
+    fn only_the_token_is_accepted() {
+        assert!(is_token("secret", "secret"));
+        assert!(!is_token("secre", "secret"));
+        assert!(!is_token("", "secret"));
+    }
+
+    #[tokio::test]
+    async fn chunked_body_is_capped() {
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            let chunk = hyper::body::Bytes::from(vec![0; 1 << 20]);
+            for _ in 0..=MAX_BODY_LEN >> 20 {
+                if sender.send_data(chunk.clone()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        assert!(read_body(body).await.unwrap().is_none());
+        assert_eq!(
+            read_body(Body::from("{}")).await.unwrap(),
+            Some(b"{}".to_vec())
+        );
+    }
+}